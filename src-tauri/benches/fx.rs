@@ -0,0 +1,65 @@
+//! Throughput of the decode-side DSP, in frames per second, for each FX
+//! preset and for the resampler. Run with `cargo bench --bench fx`, and add
+//! `--features simd` to compare the vectorised filters.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use marconio_lib::bench::{AudioFxPreset, FormatConverter, FxProcessor};
+
+const SAMPLE_RATE: u32 = 44_100;
+/// One MP3 frame, the size the decoder hands over.
+const FRAME_LEN: usize = 1_152;
+
+fn program(frames: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|index| {
+            let t = index as f32 / SAMPLE_RATE as f32;
+            let left = 0.5 * (std::f32::consts::TAU * 110.0 * t).sin();
+            let right = 0.4 * (std::f32::consts::TAU * 2_500.0 * t).sin();
+            [left, right]
+        })
+        .collect()
+}
+
+fn presets(c: &mut Criterion) {
+    let input = program(FRAME_LEN);
+    let mut group = c.benchmark_group("fx");
+    group.throughput(Throughput::Elements(FRAME_LEN as u64));
+    for preset in [
+        AudioFxPreset::Clean,
+        AudioFxPreset::Cassette,
+        AudioFxPreset::Bass,
+        AudioFxPreset::Radio,
+    ] {
+        let mut processor = FxProcessor::new();
+        processor.configure(SAMPLE_RATE, preset);
+        let mut buffer = input.clone();
+        group.bench_function(BenchmarkId::from_parameter(preset.as_str()), |b| {
+            b.iter(|| {
+                buffer.copy_from_slice(input.as_slice());
+                processor.process(black_box(buffer.as_mut_slice()));
+            })
+        });
+    }
+    group.finish();
+}
+
+fn resampler(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resample");
+    for input_rate in [44_100, 48_000, 32_000] {
+        let frames = FRAME_LEN * input_rate as usize / SAMPLE_RATE as usize;
+        let input = program(frames);
+        let mut converter = FormatConverter::new(2, SAMPLE_RATE);
+        let mut out = Vec::with_capacity(FRAME_LEN * 4);
+        group.throughput(Throughput::Elements(frames as u64));
+        group.bench_function(BenchmarkId::from_parameter(input_rate), |b| {
+            b.iter(|| {
+                out.clear();
+                converter.convert(black_box(input.as_slice()), 2, input_rate, &mut out);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, presets, resampler);
+criterion_main!(benches);
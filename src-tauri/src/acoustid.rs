@@ -0,0 +1,415 @@
+use crate::audio_samples::downmix_mono;
+use crate::chromaprint;
+use crate::recognition::{RecognitionBackend, RecognitionEvent, RecognitionMatch};
+use crate::shazam::RECOGNITION_TIMEOUT;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_BASE_URL: &str = "https://api.acoustid.org";
+const USER_AGENT: &str = concat!("Marconio/", env!("CARGO_PKG_VERSION"));
+const LOOKUP_SECONDS: u32 = 8;
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+// Capturing and looking up has to finish before the attempt times out, or a
+// late match is thrown away with it.
+const _: () =
+    assert!(LOOKUP_SECONDS as u64 + LOOKUP_TIMEOUT.as_secs() < RECOGNITION_TIMEOUT.as_secs());
+const MIN_SCORE: f64 = 0.5;
+
+/// Where to look fingerprints up; read from settings when an attempt starts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AcoustIdConfig {
+    pub base_url: String,
+    pub api_key: Option<String>,
+}
+
+pub type AcoustIdConfigSource = dyn Fn() -> AcoustIdConfig + Send + Sync;
+
+#[derive(Debug, Deserialize)]
+struct LookupResponse {
+    status: String,
+    #[serde(default)]
+    error: Option<LookupError>,
+    #[serde(default)]
+    results: Vec<LookupResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupError {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupResult {
+    id: String,
+    #[serde(default)]
+    score: f64,
+    #[serde(default)]
+    recordings: Vec<LookupRecording>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupRecording {
+    id: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    artists: Vec<LookupArtist>,
+    #[serde(default)]
+    releasegroups: Vec<LookupReleaseGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupArtist {
+    name: String,
+    #[serde(default)]
+    joinphrase: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupReleaseGroup {
+    id: String,
+}
+
+pub struct AcoustIdClient {
+    base_url: String,
+    api_key: String,
+    http: reqwest::blocking::Client,
+}
+
+impl AcoustIdClient {
+    pub fn new(config: &AcoustIdConfig) -> Result<Self, String> {
+        let api_key = config
+            .api_key
+            .as_deref()
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| "Add an AcoustID API key in settings to identify songs.".to_string())?;
+        let http = reqwest::blocking::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(LOOKUP_TIMEOUT)
+            .build()
+            .map_err(|error| format!("unable to build AcoustID client: {error}"))?;
+        Ok(Self {
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            http,
+        })
+    }
+
+    /// Looks up a compressed fingerprint, returning the best-scoring
+    /// recording or `None` when nothing scores well enough.
+    pub fn lookup(
+        &self,
+        fingerprint: &str,
+        duration_seconds: u32,
+    ) -> Result<Option<RecognitionMatch>, String> {
+        let duration = duration_seconds.to_string();
+        let response = self
+            .http
+            .post(format!("{}/v2/lookup", self.base_url))
+            .form(&[
+                ("client", self.api_key.as_str()),
+                ("meta", "recordings releasegroups"),
+                ("duration", duration.as_str()),
+                ("fingerprint", fingerprint),
+            ])
+            .send()
+            .map_err(|error| format!("AcoustID lookup failed: {error}"))?;
+        let status = response.status();
+        let body = response
+            .json::<LookupResponse>()
+            .map_err(|error| format!("unable to parse AcoustID response: {error}"))?;
+        if body.status != "ok" {
+            let message = body
+                .error
+                .map(|error| error.message)
+                .filter(|message| !message.is_empty())
+                .unwrap_or_else(|| format!("status {}", status.as_u16()));
+            return Err(format!("AcoustID lookup failed: {message}"));
+        }
+
+        let best = body
+            .results
+            .into_iter()
+            .filter(|result| result.score >= MIN_SCORE)
+            .filter_map(|result| {
+                let recording = result
+                    .recordings
+                    .into_iter()
+                    .find(|recording| recording.title.is_some())?;
+                Some((result.id, result.score, recording))
+            })
+            .max_by(|lhs, rhs| lhs.1.total_cmp(&rhs.1));
+        Ok(best.map(|(acoustid, _, recording)| to_match(acoustid, recording)))
+    }
+}
+
+fn to_match(acoustid: String, recording: LookupRecording) -> RecognitionMatch {
+    let artist_count = recording.artists.len();
+    let artist = recording
+        .artists
+        .iter()
+        .enumerate()
+        .map(|(index, artist)| {
+            let joiner = match artist.joinphrase.as_deref() {
+                Some(joinphrase) => joinphrase,
+                None if index + 1 < artist_count => ", ",
+                None => "",
+            };
+            format!("{}{joiner}", artist.name)
+        })
+        .collect::<String>();
+
+    RecognitionMatch {
+        title: recording.title,
+        artist: Some(artist).filter(|artist| !artist.is_empty()),
+        artwork_url: recording.releasegroups.first().map(|group| {
+            format!(
+                "https://coverartarchive.org/release-group/{}/front-250",
+                group.id
+            )
+        }),
+        web_url: Some(format!(
+            "https://musicbrainz.org/recording/{}",
+            recording.id
+        )),
+        musicbrainz_recording_id: Some(recording.id),
+        acoustid: Some(acoustid),
+        ..RecognitionMatch::default()
+    }
+}
+
+/// Buffers tapped audio until there is enough for a Chromaprint lookup, then
+/// queries AcoustID off the audio thread.
+pub struct AcoustIdBackend {
+    events: Sender<RecognitionEvent>,
+    config: Box<AcoustIdConfigSource>,
+    client: Option<Arc<AcoustIdClient>>,
+    samples: Vec<f32>,
+    sample_rate: u32,
+    listening: bool,
+    // Bumped on every start/stop so a lookup that outlives its attempt
+    // cannot answer the next one.
+    generation: Arc<AtomicU64>,
+}
+
+impl AcoustIdBackend {
+    pub fn new(events: Sender<RecognitionEvent>, config: Box<AcoustIdConfigSource>) -> Self {
+        Self {
+            events,
+            config,
+            client: None,
+            samples: Vec::new(),
+            sample_rate: 0,
+            listening: false,
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn spawn_lookup(&mut self) {
+        self.listening = false;
+        let (Some(client), samples) = (self.client.clone(), std::mem::take(&mut self.samples))
+        else {
+            return;
+        };
+        let sample_rate = self.sample_rate;
+        let events = self.events.clone();
+        let generation = Arc::clone(&self.generation);
+        let attempt = generation.load(Ordering::Acquire);
+
+        std::thread::spawn(move || {
+            let fingerprint = chromaprint::fingerprint(samples.as_slice(), sample_rate);
+            let duration = samples.len() as u32 / sample_rate.max(1);
+            let event = match client.lookup(chromaprint::encode(&fingerprint).as_str(), duration) {
+                Ok(Some(found)) => RecognitionEvent::Match(found),
+                Ok(None) => RecognitionEvent::NoMatch,
+                Err(error) => RecognitionEvent::Error(error),
+            };
+            if generation.load(Ordering::Acquire) == attempt {
+                let _ = events.send(event);
+            }
+        });
+    }
+}
+
+impl RecognitionBackend for AcoustIdBackend {
+    fn start(&mut self) -> Result<(), String> {
+        let client = AcoustIdClient::new(&(self.config)())?;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.client = Some(Arc::new(client));
+        self.samples.clear();
+        self.listening = true;
+        Ok(())
+    }
+
+    fn feed(&mut self, samples: &[f32], channels: u16, sample_rate: u32) -> Result<(), String> {
+        if !self.listening || sample_rate == 0 {
+            return Ok(());
+        }
+        if self.sample_rate != sample_rate {
+            self.samples.clear();
+            self.sample_rate = sample_rate;
+        }
+
+        self.samples.extend(downmix_mono(samples, channels));
+        if self.samples.len() >= (LOOKUP_SECONDS * sample_rate) as usize {
+            self.spawn_lookup();
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        self.listening = false;
+        self.samples.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AcoustIdBackend, AcoustIdConfig, LOOKUP_SECONDS};
+    use crate::fingerprint::test_audio::synthesized_song;
+    use crate::recognition::{RecognitionBackend, RecognitionEvent};
+    use crate::test_support::{MockResponse, MockServer};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const LOOKUP_RESPONSE: &str = r#"{
+        "status": "ok",
+        "results": [
+            {"id": "low-score", "score": 0.2, "recordings": [{"id": "wrong", "title": "Wrong"}]},
+            {
+                "id": "9ff43b6a-4f16-427c-93c2-92307ca505e0",
+                "score": 0.94,
+                "recordings": [{
+                    "id": "cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff",
+                    "title": "Windowlicker",
+                    "artists": [
+                        {"id": "a", "name": "Aphex Twin", "joinphrase": " feat. "},
+                        {"id": "b", "name": "Someone"}
+                    ],
+                    "releasegroups": [{"id": "f0b9f8e2-0000-4000-8000-000000000000"}]
+                }]
+            }
+        ]
+    }"#;
+
+    fn backend_for(
+        server: &MockServer,
+        api_key: Option<&str>,
+    ) -> (AcoustIdBackend, mpsc::Receiver<RecognitionEvent>) {
+        let (tx, rx) = mpsc::channel();
+        let config = AcoustIdConfig {
+            base_url: server.base_url(),
+            api_key: api_key.map(str::to_string),
+        };
+        (
+            AcoustIdBackend::new(tx, Box::new(move || config.clone())),
+            rx,
+        )
+    }
+
+    fn feed_song(backend: &mut AcoustIdBackend) {
+        let song = synthesized_song(21, LOOKUP_SECONDS as f32 + 1.0);
+        for chunk in song.chunks(4_410) {
+            backend.feed(chunk, 2, 44_100).expect("feed");
+        }
+    }
+
+    #[test]
+    fn lookup_maps_best_result_to_musicbrainz_match() {
+        let server = MockServer::start(|_| MockResponse::json(LOOKUP_RESPONSE));
+        let (mut backend, events) = backend_for(&server, Some("test-key"));
+
+        backend.start().expect("start");
+        feed_song(&mut backend);
+
+        let event = events
+            .recv_timeout(Duration::from_secs(5))
+            .expect("lookup should report");
+        let RecognitionEvent::Match(found) = event else {
+            panic!("expected a match, got {event:?}");
+        };
+        assert_eq!(found.title.as_deref(), Some("Windowlicker"));
+        assert_eq!(found.artist.as_deref(), Some("Aphex Twin feat. Someone"));
+        assert_eq!(
+            found.musicbrainz_recording_id.as_deref(),
+            Some("cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff")
+        );
+        assert_eq!(
+            found.acoustid.as_deref(),
+            Some("9ff43b6a-4f16-427c-93c2-92307ca505e0")
+        );
+        assert_eq!(
+            found.web_url.as_deref(),
+            Some("https://musicbrainz.org/recording/cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff")
+        );
+        assert!(found.artwork_url.is_some());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1, "one lookup per attempt");
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path_without_query(), "/v2/lookup");
+        assert_eq!(
+            requests[0].form_param("client").as_deref(),
+            Some("test-key")
+        );
+        assert_eq!(
+            requests[0].form_param("duration"),
+            Some(LOOKUP_SECONDS.to_string())
+        );
+        let fingerprint = requests[0].form_param("fingerprint").expect("fingerprint");
+        assert!(fingerprint.starts_with("AQAA"), "{fingerprint}");
+    }
+
+    #[test]
+    fn empty_results_and_service_errors_are_reported() {
+        let server = MockServer::start(|request| {
+            if request.form_param("client").as_deref() == Some("bad-key") {
+                MockResponse::new(
+                    400,
+                    r#"{"status":"error","error":{"code":4,"message":"invalid API key"}}"#,
+                )
+            } else {
+                MockResponse::json(r#"{"status":"ok","results":[]}"#)
+            }
+        });
+
+        let (mut backend, events) = backend_for(&server, Some("test-key"));
+        backend.start().expect("start");
+        feed_song(&mut backend);
+        assert!(matches!(
+            events.recv_timeout(Duration::from_secs(5)),
+            Ok(RecognitionEvent::NoMatch)
+        ));
+
+        let (mut backend, events) = backend_for(&server, Some("bad-key"));
+        backend.start().expect("start");
+        feed_song(&mut backend);
+        match events.recv_timeout(Duration::from_secs(5)) {
+            Ok(RecognitionEvent::Error(message)) => assert!(message.contains("invalid API key")),
+            other => panic!("expected an error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn start_requires_an_api_key_and_stop_discards_pending_lookups() {
+        let server = MockServer::start(|_| {
+            std::thread::sleep(Duration::from_millis(300));
+            MockResponse::json(LOOKUP_RESPONSE)
+        });
+
+        let (mut backend, _) = backend_for(&server, Some(" "));
+        assert!(backend.start().is_err());
+
+        let (mut backend, events) = backend_for(&server, Some("test-key"));
+        backend.start().expect("start");
+        feed_song(&mut backend);
+        backend.stop();
+        assert!(events.recv_timeout(Duration::from_secs(1)).is_err());
+    }
+}
@@ -1,16 +1,25 @@
+use crate::audio_fx::FxProcessor;
+use crate::audio_output::{
+    AudioOutput, AudioVoice, CrossfadeConfig, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE, VOICE_BUFFER,
+};
+use crate::audio_tap::{AudioTapReader, AudioTapRegistry, TapPoint};
+use crate::listening::ListeningLog;
+use crate::output_device::OutputFallbackCallback;
+use crate::resampler::FormatConverter;
+use crate::scrobble::ScrobbleManager;
+use crate::tuning_noise::TuningNoiseConfig;
 use minimp3::{Decoder, Error as Mp3Error};
-use rodio::buffer::SamplesBuffer;
-use rodio::{OutputStream, Sink};
 use serde::{Deserialize, Serialize};
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
-use std::f32::consts::PI;
-use std::io::BufReader;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 use tauri::Emitter;
 #[cfg(target_os = "windows")]
@@ -23,6 +32,18 @@ pub struct NowPlayingMetadata {
     pub artist: Option<String>,
     pub album: Option<String>,
     pub artwork_url: Option<String>,
+    pub show_alias: Option<String>,
+    pub episode_alias: Option<String>,
+}
+
+impl NowPlayingMetadata {
+    /// Whether `other` is still the same show, give or take its artwork and
+    /// subtitle.
+    pub fn is_same_show(&self, other: &Self) -> bool {
+        self.title == other.title
+            && self.show_alias == other.show_alias
+            && self.episode_alias == other.episode_alias
+    }
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -40,6 +61,7 @@ pub enum AudioFxPreset {
 }
 
 impl AudioFxPreset {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Option<Self> {
         match value {
             "clean" => Some(Self::Clean),
@@ -50,6 +72,15 @@ impl AudioFxPreset {
         }
     }
 
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Clean => "clean",
+            Self::Cassette => "cassette",
+            Self::Bass => "bass",
+            Self::Radio => "radio",
+        }
+    }
+
     pub fn as_u8(self) -> u8 {
         match self {
             Self::Clean => 0,
@@ -69,361 +100,74 @@ impl AudioFxPreset {
     }
 }
 
-struct Biquad {
-    b0: f32,
-    b1: f32,
-    b2: f32,
-    a1: f32,
-    a2: f32,
-    z1: Vec<f32>,
-    z2: Vec<f32>,
-}
-
-impl Biquad {
-    fn new_normalized(
-        b0: f32,
-        b1: f32,
-        b2: f32,
-        a0: f32,
-        a1: f32,
-        a2: f32,
-        channels: usize,
-    ) -> Self {
-        Self {
-            b0: b0 / a0,
-            b1: b1 / a0,
-            b2: b2 / a0,
-            a1: a1 / a0,
-            a2: a2 / a0,
-            z1: vec![0.0; channels],
-            z2: vec![0.0; channels],
-        }
-    }
-
-    fn process(&mut self, channel: usize, x: f32) -> f32 {
-        let y = self.b0 * x + self.z1[channel];
-        self.z1[channel] = self.b1 * x - self.a1 * y + self.z2[channel];
-        self.z2[channel] = self.b2 * x - self.a2 * y;
-        y
-    }
-
-    fn lowpass(sample_rate: f32, cutoff_hz: f32, q: f32, channels: usize) -> Self {
-        let cutoff = cutoff_hz.clamp(20.0, sample_rate * 0.45);
-        let w0 = 2.0 * PI * cutoff / sample_rate;
-        let cos_w0 = w0.cos();
-        let alpha = w0.sin() / (2.0 * q.max(0.1));
-        let b0 = (1.0 - cos_w0) * 0.5;
-        let b1 = 1.0 - cos_w0;
-        let b2 = (1.0 - cos_w0) * 0.5;
-        let a0 = 1.0 + alpha;
-        let a1 = -2.0 * cos_w0;
-        let a2 = 1.0 - alpha;
-        Self::new_normalized(b0, b1, b2, a0, a1, a2, channels)
-    }
-
-    fn highpass(sample_rate: f32, cutoff_hz: f32, q: f32, channels: usize) -> Self {
-        let cutoff = cutoff_hz.clamp(20.0, sample_rate * 0.45);
-        let w0 = 2.0 * PI * cutoff / sample_rate;
-        let cos_w0 = w0.cos();
-        let alpha = w0.sin() / (2.0 * q.max(0.1));
-        let b0 = (1.0 + cos_w0) * 0.5;
-        let b1 = -(1.0 + cos_w0);
-        let b2 = (1.0 + cos_w0) * 0.5;
-        let a0 = 1.0 + alpha;
-        let a1 = -2.0 * cos_w0;
-        let a2 = 1.0 - alpha;
-        Self::new_normalized(b0, b1, b2, a0, a1, a2, channels)
-    }
-
-    fn peaking(sample_rate: f32, frequency_hz: f32, q: f32, gain_db: f32, channels: usize) -> Self {
-        let frequency = frequency_hz.clamp(20.0, sample_rate * 0.45);
-        let a = 10.0_f32.powf(gain_db / 40.0);
-        let w0 = 2.0 * PI * frequency / sample_rate;
-        let cos_w0 = w0.cos();
-        let alpha = w0.sin() / (2.0 * q.max(0.1));
-        let b0 = 1.0 + alpha * a;
-        let b1 = -2.0 * cos_w0;
-        let b2 = 1.0 - alpha * a;
-        let a0 = 1.0 + alpha / a;
-        let a1 = -2.0 * cos_w0;
-        let a2 = 1.0 - alpha / a;
-        Self::new_normalized(b0, b1, b2, a0, a1, a2, channels)
-    }
-
-    fn lowshelf(
-        sample_rate: f32,
-        frequency_hz: f32,
-        slope: f32,
-        gain_db: f32,
-        channels: usize,
-    ) -> Self {
-        let frequency = frequency_hz.clamp(20.0, sample_rate * 0.45);
-        let a = 10.0_f32.powf(gain_db / 40.0);
-        let w0 = 2.0 * PI * frequency / sample_rate;
-        let cos_w0 = w0.cos();
-        let sin_w0 = w0.sin();
-        let s = slope.max(0.1);
-        let alpha = (sin_w0 / 2.0) * (((a + 1.0 / a) * (1.0 / s - 1.0) + 2.0).sqrt());
-        let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
-
-        let b0 = a * ((a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha);
-        let b1 = 2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0);
-        let b2 = a * ((a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha);
-        let a0 = (a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha;
-        let a1 = -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0);
-        let a2 = (a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha;
-        Self::new_normalized(b0, b1, b2, a0, a1, a2, channels)
-    }
-}
-
-struct Warble {
-    sample_rate: f32,
-    channels: usize,
-    buffer: Vec<Vec<f32>>,
-    write_index: usize,
-    phase: f32,
-    wow_rate_hz: f32,
-    wow_depth_samples: f32,
-    flutter_rate_hz: f32,
-    flutter_depth_samples: f32,
-    base_delay_samples: f32,
+struct PlaybackWorker {
+    stop_tx: Sender<()>,
+    join_handle: JoinHandle<()>,
+    voice_id: u64,
 }
 
-impl Warble {
-    fn new(sample_rate: f32, channels: usize) -> Self {
-        let sr = sample_rate.max(8_000.0);
-        let ch = channels.max(1);
-        let max_delay_ms = 8.0;
-        let buffer_len = ((sr * max_delay_ms / 1000.0).ceil() as usize + 4).max(32);
-
-        Self {
-            sample_rate: sr,
-            channels: ch,
-            buffer: vec![vec![0.0; buffer_len]; ch],
-            write_index: 0,
-            phase: 0.0,
-            wow_rate_hz: 0.52,
-            wow_depth_samples: sr * (0.95 / 1000.0),
-            flutter_rate_hz: 6.7,
-            flutter_depth_samples: sr * (0.22 / 1000.0),
-            base_delay_samples: sr * (3.9 / 1000.0),
-        }
-    }
-
-    fn delay_samples(&self) -> f32 {
-        let wow = (2.0 * PI * self.wow_rate_hz * self.phase).sin();
-        let flutter = (2.0 * PI * self.flutter_rate_hz * self.phase + 0.7).sin();
-        let raw = self.base_delay_samples
-            + wow * self.wow_depth_samples
-            + flutter * self.flutter_depth_samples;
-        let max_delay = (self.buffer[0].len().saturating_sub(3)) as f32;
-        raw.clamp(1.0, max_delay.max(1.0))
-    }
-
-    fn process(&mut self, channel: usize, input: f32) -> f32 {
-        if channel >= self.channels {
-            return input;
-        }
-
-        let len = self.buffer[channel].len();
-        if len < 3 {
-            return input;
-        }
-
-        let delay = self.delay_samples();
-        let len_f = len as f32;
-        let read_position = (self.write_index as f32 - delay).rem_euclid(len_f);
-        let index_a = read_position.floor() as usize;
-        let index_b = (index_a + 1) % len;
-        let fraction = read_position - index_a as f32;
-
-        let delayed = self.buffer[channel][index_a] * (1.0 - fraction)
-            + self.buffer[channel][index_b] * fraction;
-        self.buffer[channel][self.write_index] = input;
-        delayed
-    }
-
-    fn advance_frame(&mut self) {
-        self.write_index = (self.write_index + 1) % self.buffer[0].len();
-        self.phase += 1.0 / self.sample_rate;
-        if self.phase > 60.0 {
-            self.phase = 0.0;
-        }
+impl PlaybackWorker {
+    fn stop(self) {
+        let _ = self.stop_tx.send(());
+        thread::spawn(move || {
+            let _ = self.join_handle.join();
+        });
     }
 }
 
-struct FxProcessor {
-    preset: AudioFxPreset,
-    sample_rate: u32,
-    channels: usize,
-    high_pass: Option<Biquad>,
-    low_pass: Option<Biquad>,
-    mid_peak: Option<Biquad>,
-    low_shelf: Option<Biquad>,
-    warble: Option<Warble>,
-    warble_mix: f32,
-    distortion_drive: f32,
-    saturation_mix: f32,
-    compressor_threshold: f32,
-    compressor_ratio: f32,
-    makeup_gain: f32,
-}
+/// Receives the decoded position of a file source, and `true` once it has
+/// played to the end.
+pub type PlaybackPositionCallback = dyn Fn(Duration, bool) + Send + Sync + 'static;
 
-impl FxProcessor {
-    fn new() -> Self {
-        let mut processor = Self {
-            preset: AudioFxPreset::Clean,
-            sample_rate: 44_100,
-            channels: 2,
-            high_pass: None,
-            low_pass: None,
-            mid_peak: None,
-            low_shelf: None,
-            warble: None,
-            warble_mix: 0.0,
-            distortion_drive: 1.0,
-            saturation_mix: 0.0,
-            compressor_threshold: 1.0,
-            compressor_ratio: 1.0,
-            makeup_gain: 1.0,
-        };
-        processor.rebuild_chain();
-        processor
-    }
+const POSITION_REPORT_INTERVAL: Duration = Duration::from_secs(1);
 
-    fn configure(&mut self, sample_rate: u32, channels: usize, preset: AudioFxPreset) {
-        let next_sample_rate = sample_rate.max(8_000);
-        let next_channels = channels.max(1);
-        if self.sample_rate != next_sample_rate
-            || self.channels != next_channels
-            || self.preset != preset
-        {
-            self.sample_rate = next_sample_rate;
-            self.channels = next_channels;
-            self.preset = preset;
-            self.rebuild_chain();
-        }
-    }
+pub const STREAM_ERROR_EVENT: &str = "audio-stream-error";
 
-    fn rebuild_chain(&mut self) {
-        self.high_pass = None;
-        self.low_pass = None;
-        self.mid_peak = None;
-        self.low_shelf = None;
-        self.warble = None;
-        self.warble_mix = 0.0;
-        self.distortion_drive = 1.0;
-        self.saturation_mix = 0.0;
-        self.compressor_threshold = 1.0;
-        self.compressor_ratio = 1.0;
-        self.makeup_gain = 1.0;
-
-        let sr = self.sample_rate as f32;
-        let channels = self.channels;
-        match self.preset {
-            AudioFxPreset::Clean => {}
-            AudioFxPreset::Cassette => {
-                self.high_pass = Some(Biquad::highpass(sr, 105.0, 0.75, channels));
-                self.low_pass = Some(Biquad::lowpass(sr, 6400.0, 0.82, channels));
-                self.mid_peak = Some(Biquad::peaking(sr, 2700.0, 1.35, -3.1, channels));
-                self.warble = Some(Warble::new(sr, channels));
-                self.warble_mix = 0.62;
-                self.distortion_drive = 1.42;
-                self.saturation_mix = 0.44;
-                self.compressor_threshold = 0.67;
-                self.compressor_ratio = 2.9;
-                self.makeup_gain = 1.08;
-            }
-            AudioFxPreset::Bass => {
-                self.high_pass = Some(Biquad::highpass(sr, 26.0, 0.707, channels));
-                self.low_shelf = Some(Biquad::lowshelf(sr, 92.0, 0.9, 7.4, channels));
-                self.mid_peak = Some(Biquad::peaking(sr, 180.0, 1.0, 4.0, channels));
-                self.low_pass = Some(Biquad::lowpass(sr, 9300.0, 0.8, channels));
-                self.distortion_drive = 1.36;
-                self.saturation_mix = 0.36;
-                self.compressor_threshold = 0.69;
-                self.compressor_ratio = 2.7;
-                self.makeup_gain = 1.1;
-            }
-            AudioFxPreset::Radio => {
-                self.high_pass = Some(Biquad::highpass(sr, 360.0, 0.85, channels));
-                self.low_pass = Some(Biquad::lowpass(sr, 3300.0, 0.85, channels));
-                self.mid_peak = Some(Biquad::peaking(sr, 1750.0, 1.65, 6.8, channels));
-                self.distortion_drive = 1.8;
-                self.saturation_mix = 0.58;
-                self.compressor_threshold = 0.6;
-                self.compressor_ratio = 4.4;
-                self.makeup_gain = 1.12;
-            }
-        }
-    }
-
-    fn process_buffer(&mut self, samples: &mut [f32]) {
-        if self.preset == AudioFxPreset::Clean {
-            return;
-        }
-
-        for frame in samples.chunks_mut(self.channels) {
-            for (channel, sample) in frame.iter_mut().enumerate() {
-                let mut value = *sample;
-
-                if let Some(filter) = self.high_pass.as_mut() {
-                    value = filter.process(channel, value);
-                }
-                if let Some(filter) = self.low_shelf.as_mut() {
-                    value = filter.process(channel, value);
-                }
-                if let Some(filter) = self.mid_peak.as_mut() {
-                    value = filter.process(channel, value);
-                }
-                if let Some(filter) = self.low_pass.as_mut() {
-                    value = filter.process(channel, value);
-                }
-                if let Some(warble) = self.warble.as_mut() {
-                    let warped = warble.process(channel, value);
-                    value = value + (warped - value) * self.warble_mix;
-                }
-
-                let drive = self.distortion_drive.max(0.001);
-                let saturated = (value * drive).tanh() / drive;
-                value = value + (saturated - value) * self.saturation_mix;
-                value = self.compress(value);
-                value *= self.makeup_gain;
-
-                *sample = value.clamp(-1.0, 1.0);
-            }
-
-            if let Some(warble) = self.warble.as_mut() {
-                warble.advance_frame();
-            }
-        }
-    }
-
-    fn compress(&self, sample: f32) -> f32 {
-        let threshold = self.compressor_threshold.max(0.0001);
-        let ratio = self.compressor_ratio.max(1.0);
-        let sign = sample.signum();
-        let magnitude = sample.abs();
-        if magnitude <= threshold {
-            return sample;
-        }
-        let compressed = threshold + (magnitude - threshold) / ratio;
-        sign * compressed
-    }
+/// Sent when the playing stream stops on its own because it failed, e.g. a
+/// replacement that never connected. Whatever it was replacing has faded out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamError {
+    pub message: String,
 }
 
-struct PlaybackWorker {
-    stop_tx: Sender<()>,
-    join_handle: JoinHandle<()>,
+pub type StreamErrorCallback = dyn Fn(&StreamError) + Send + Sync;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamSource {
+    /// Opened at `start_byte`, the estimated offset of `start_at`. When that
+    /// is 0 or the server ignores the `Range`, the stream is decoded from
+    /// the start and anything before `start_at` is skipped.
+    Remote {
+        url: String,
+        start_at: Duration,
+        start_byte: u64,
+    },
+    /// Seeked straight to `start_byte`, like a ranged remote.
+    File {
+        path: PathBuf,
+        start_at: Duration,
+        start_byte: u64,
+    },
+    /// Consecutive pieces of one recording, played back to back.
+    Segments(Vec<PathBuf>),
 }
 
-pub type AudioFrameTap = dyn Fn(&[f32], u16, u32) + Send + Sync + 'static;
-
 pub struct PlaybackManager {
     worker: Option<PlaybackWorker>,
+    /// Workers still playing out under a crossfade; they stop themselves
+    /// once faded.
+    fading: Vec<PlaybackWorker>,
+    crossfade: CrossfadeConfig,
     preset: Arc<AtomicU8>,
+    output: AudioOutput,
     now_playing: Option<NowPlayingMetadata>,
-    audio_frame_tap: Option<Arc<AudioFrameTap>>,
+    audio_taps: Arc<AudioTapRegistry>,
+    listening_log: Option<Arc<ListeningLog>>,
+    scrobbler: Option<Arc<ScrobbleManager>>,
+    /// Voice whose listening session and show scrobble are open, or 0.
+    session_voice: Arc<Mutex<u64>>,
+    on_stream_error: Option<Arc<StreamErrorCallback>>,
     #[cfg(any(target_os = "macos", target_os = "windows"))]
     media_controls: Option<MediaControls>,
 }
@@ -432,9 +176,16 @@ impl Default for PlaybackManager {
     fn default() -> Self {
         Self {
             worker: None,
+            fading: Vec::new(),
+            crossfade: CrossfadeConfig::default(),
             preset: Arc::new(AtomicU8::new(AudioFxPreset::Clean.as_u8())),
+            output: AudioOutput::default(),
             now_playing: None,
-            audio_frame_tap: None,
+            audio_taps: Arc::default(),
+            listening_log: None,
+            scrobbler: None,
+            session_voice: Arc::default(),
+            on_stream_error: None,
             #[cfg(any(target_os = "macos", target_os = "windows"))]
             media_controls: None,
         }
@@ -488,54 +239,221 @@ impl PlaybackManager {
     }
 
     pub fn set_preset(&self, preset: AudioFxPreset) {
-        self.preset.store(preset.as_u8(), Ordering::Relaxed);
+        let previous = self.preset.swap(preset.as_u8(), Ordering::Relaxed);
+        if previous == preset.as_u8() {
+            return;
+        }
+        // Time on each preset is logged separately.
+        let Ok(session_voice) = self.session_voice.lock() else {
+            return;
+        };
+        if *session_voice != 0 {
+            if let Some(log) = self.listening_log.as_ref() {
+                log.begin(self.now_playing.as_ref(), preset);
+            }
+        }
+    }
+
+    /// Taps added here reach the stream playing now and every later one.
+    pub fn audio_taps(&self) -> Arc<AudioTapRegistry> {
+        Arc::clone(&self.audio_taps)
+    }
+
+    /// Takes effect on a playing stream right away.
+    pub fn set_output_device(&self, device: Option<String>) {
+        self.output.set_device(device);
+    }
+
+    pub fn set_on_output_fallback(&self, callback: Arc<OutputFallbackCallback>) {
+        self.output.set_on_fallback(callback);
+    }
+
+    pub fn set_on_stream_error(&mut self, callback: Arc<StreamErrorCallback>) {
+        self.on_stream_error = Some(callback);
     }
 
-    pub fn set_audio_frame_tap(&mut self, tap: Option<Arc<AudioFrameTap>>) {
-        self.audio_frame_tap = tap;
+    pub fn set_tuning_noise(&self, config: TuningNoiseConfig) {
+        self.output.set_tuning_noise(config);
+    }
+
+    /// Applies from the next station change.
+    pub fn set_crossfade(&mut self, crossfade: CrossfadeConfig) {
+        self.crossfade = crossfade;
+    }
+
+    pub fn set_listening_log(&mut self, log: Option<Arc<ListeningLog>>) {
+        self.listening_log = log;
+    }
+
+    pub fn set_scrobbler(&mut self, scrobbler: Option<Arc<ScrobbleManager>>) {
+        self.scrobbler = scrobbler;
     }
 
     pub fn is_stream_running(&self) -> bool {
-        self.worker.is_some()
+        self.worker
+            .as_ref()
+            .is_some_and(|worker| !worker.join_handle.is_finished())
     }
 
     pub fn now_playing(&self) -> Option<NowPlayingMetadata> {
         self.now_playing.clone()
     }
 
-    pub fn start_stream(&mut self, stream_url: String, now_playing: Option<NowPlayingMetadata>) {
-        self.stop_stream();
-        if let Some(metadata) = now_playing {
-            self.now_playing = Some(metadata);
-            self.sync_media_metadata();
+    /// Follows what the playing stream is airing; a new show starts a new
+    /// listening session and show scrobble.
+    pub fn update_now_playing(&mut self, now_playing: Option<NowPlayingMetadata>) {
+        self.now_playing = now_playing;
+        self.sync_media_metadata();
+        let Ok(session_voice) = self.session_voice.lock() else {
+            return;
+        };
+        if *session_voice == 0 {
+            return;
         }
+        if let Some(log) = self.listening_log.as_ref() {
+            log.now_playing_changed(self.now_playing.as_ref());
+        }
+        if let Some(scrobbler) = self.scrobbler.as_ref() {
+            scrobbler.now_playing_changed(self.now_playing.as_ref());
+        }
+    }
+
+    pub fn start_stream(&mut self, stream_url: String, now_playing: Option<NowPlayingMetadata>) {
+        self.start_source(
+            StreamSource::Remote {
+                url: stream_url,
+                start_at: Duration::ZERO,
+                start_byte: 0,
+            },
+            now_playing,
+            None,
+        );
+    }
+
+    pub fn start_source(
+        &mut self,
+        source: StreamSource,
+        now_playing: Option<NowPlayingMetadata>,
+        on_position: Option<Arc<PlaybackPositionCallback>>,
+    ) {
+        let was_playing = self.is_stream_running();
+        let replacing = if self.crossfade.is_enabled() {
+            self.retire_worker()
+        } else {
+            self.stop_stream();
+            None
+        };
+        self.now_playing = now_playing;
+        self.sync_media_metadata();
 
         let preset = Arc::clone(&self.preset);
-        let audio_frame_tap = self.audio_frame_tap.as_ref().map(Arc::clone);
+        let voice = match replacing {
+            Some(previous) => self.output.voice_replacing(previous, self.crossfade),
+            None if was_playing => self.output.voice_after_cut(),
+            None => self.output.voice(),
+        };
+        let voice_id = voice.id();
+        self.begin_session(voice_id);
+
+        let audio_taps = self.audio_taps.reader();
+        let finish = self.worker_finisher(voice_id);
         let (stop_tx, stop_rx) = mpsc::channel::<()>();
         let join_handle = thread::spawn(move || {
-            if let Err(error) = run_stream_worker(stream_url, preset, stop_rx, audio_frame_tap) {
+            let result = run_stream_worker(source, preset, voice, stop_rx, audio_taps, on_position);
+            if let Err(error) = result.as_ref() {
                 eprintln!("[audio] worker exited with error: {}", error);
             }
+            finish(result.err());
         });
 
         self.worker = Some(PlaybackWorker {
             stop_tx,
             join_handle,
+            voice_id,
         });
         self.sync_media_playback_state(true);
     }
 
     pub fn stop_stream(&mut self) {
+        for worker in self.fading.drain(..) {
+            worker.stop();
+        }
         if let Some(worker) = self.worker.take() {
-            let _ = worker.stop_tx.send(());
-            thread::spawn(move || {
-                let _ = worker.join_handle.join();
-            });
+            worker.stop();
+            self.end_session();
         }
         self.sync_media_playback_state(false);
     }
 
+    /// Leaves the current worker playing so the next stream can crossfade
+    /// with it, and returns its voice.
+    fn retire_worker(&mut self) -> Option<u64> {
+        self.fading
+            .retain(|worker| !worker.join_handle.is_finished());
+        let worker = self.worker.take()?;
+        let voice_id = worker.voice_id;
+        self.fading.push(worker);
+        self.end_session();
+        Some(voice_id)
+    }
+
+    fn begin_session(&self, voice_id: u64) {
+        let Ok(mut session_voice) = self.session_voice.lock() else {
+            return;
+        };
+        *session_voice = voice_id;
+        if let Some(log) = self.listening_log.as_ref() {
+            log.begin(
+                self.now_playing.as_ref(),
+                AudioFxPreset::from_u8(self.preset.load(Ordering::Relaxed)),
+            );
+        }
+        if let Some(scrobbler) = self.scrobbler.as_ref() {
+            scrobbler.show_started(self.now_playing.as_ref());
+        }
+    }
+
+    fn end_session(&self) {
+        let Ok(mut session_voice) = self.session_voice.lock() else {
+            return;
+        };
+        *session_voice = 0;
+        if let Some(log) = self.listening_log.as_ref() {
+            log.end();
+        }
+        if let Some(scrobbler) = self.scrobbler.as_ref() {
+            scrobbler.show_ended();
+        }
+    }
+
+    /// Ends the session from the worker when its stream fails or runs out,
+    /// and reports the failure, unless a later stream has already taken the
+    /// session over.
+    fn worker_finisher(&self, voice_id: u64) -> impl FnOnce(Option<String>) + Send + 'static {
+        let session_voice = Arc::clone(&self.session_voice);
+        let listening_log = self.listening_log.clone();
+        let scrobbler = self.scrobbler.clone();
+        let on_stream_error = self.on_stream_error.clone();
+        move |error| {
+            let Ok(mut session_voice) = session_voice.lock() else {
+                return;
+            };
+            if *session_voice != voice_id {
+                return;
+            }
+            *session_voice = 0;
+            if let Some(log) = listening_log {
+                log.end();
+            }
+            if let Some(scrobbler) = scrobbler {
+                scrobbler.show_ended();
+            }
+            if let (Some(message), Some(callback)) = (error, on_stream_error) {
+                callback(&StreamError { message });
+            }
+        }
+    }
+
     fn sync_media_metadata(&mut self) {
         #[cfg(any(target_os = "macos", target_os = "windows"))]
         if let Some(controls) = self.media_controls.as_mut() {
@@ -597,43 +515,117 @@ impl Drop for PlaybackManager {
     }
 }
 
+impl StreamSource {
+    fn start_at(&self) -> Duration {
+        match self {
+            Self::Remote { start_at, .. } | Self::File { start_at, .. } => *start_at,
+            Self::Segments(_) => Duration::ZERO,
+        }
+    }
+}
+
+/// Returns the reader and the playback position it starts at.
+fn open_source(source: &StreamSource) -> Result<(Box<dyn Read + Send>, Duration), String> {
+    match source {
+        StreamSource::Remote {
+            url,
+            start_at,
+            start_byte,
+        } => {
+            eprintln!("[audio] opening stream {}", url);
+            let client = reqwest::blocking::Client::new();
+            let mut request = client.get(url);
+            if *start_byte > 0 {
+                request = request.header(reqwest::header::RANGE, format!("bytes={start_byte}-"));
+            }
+            let mut response = request
+                .send()
+                .map_err(|error| format!("stream request failed: {}", error))?;
+            if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                response = client
+                    .get(url)
+                    .send()
+                    .map_err(|error| format!("stream request failed: {}", error))?;
+            }
+
+            if !response.status().is_success() {
+                return Err(format!(
+                    "stream request failed with status {}",
+                    response.status().as_u16()
+                ));
+            }
+
+            let position = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+                *start_at
+            } else {
+                Duration::ZERO
+            };
+            Ok((Box::new(BufReader::new(response)), position))
+        }
+        StreamSource::File {
+            path,
+            start_at,
+            start_byte,
+        } => {
+            eprintln!("[audio] opening file {}", path.display());
+            let mut file = File::open(path)
+                .map_err(|error| format!("unable to open {}: {}", path.display(), error))?;
+            let position = if *start_byte > 0 {
+                file.seek(SeekFrom::Start(*start_byte))
+                    .map_err(|error| format!("unable to seek {}: {}", path.display(), error))?;
+                *start_at
+            } else {
+                Duration::ZERO
+            };
+            Ok((Box::new(BufReader::new(file)), position))
+        }
+        StreamSource::Segments(paths) => {
+            eprintln!("[audio] opening {} cached segments", paths.len());
+            let mut reader: Box<dyn Read + Send> = Box::new(std::io::empty());
+            for path in paths {
+                let file = File::open(path)
+                    .map_err(|error| format!("unable to open {}: {}", path.display(), error))?;
+                reader = Box::new(reader.chain(BufReader::new(file)));
+            }
+            Ok((reader, Duration::ZERO))
+        }
+    }
+}
+
 fn run_stream_worker(
-    stream_url: String,
+    source: StreamSource,
     preset: Arc<AtomicU8>,
+    mut voice: AudioVoice,
     stop_rx: Receiver<()>,
-    audio_frame_tap: Option<Arc<AudioFrameTap>>,
+    mut audio_taps: AudioTapReader,
+    on_position: Option<Arc<PlaybackPositionCallback>>,
 ) -> Result<(), String> {
-    eprintln!("[audio] opening stream {}", stream_url);
-    let response = reqwest::blocking::Client::new()
-        .get(&stream_url)
-        .send()
-        .map_err(|error| format!("stream request failed: {}", error))?;
-
-    if !response.status().is_success() {
-        return Err(format!(
-            "stream request failed with status {}",
-            response.status().as_u16()
-        ));
-    }
-
-    let reader = BufReader::new(response);
+    let start_at = source.start_at();
+    let (reader, mut position) = open_source(&source)?;
     let mut decoder = Decoder::new(reader);
-    let (_stream, stream_handle) =
-        OutputStream::try_default().map_err(|error| format!("output stream error: {}", error))?;
-    let sink = Sink::try_new(&stream_handle).map_err(|error| format!("sink error: {}", error))?;
-    sink.play();
 
+    // Everything past the decoder runs at the output format, so a stream
+    // that changes rate or channels mid-way doesn't reset the FX.
+    let mut converter = FormatConverter::new(OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE);
     let mut processor = FxProcessor::new();
+    let mut decoded = Vec::new();
+    let mut processed = Vec::new();
+    let mut last_position_report = Instant::now();
+    let mut reached_end = false;
 
     loop {
         match stop_rx.try_recv() {
             Ok(_) | Err(TryRecvError::Disconnected) => break,
+            Err(TryRecvError::Empty) if voice.is_silenced() => break,
             Err(TryRecvError::Empty) => {}
         }
 
         let frame = match decoder.next_frame() {
             Ok(frame) => frame,
-            Err(Mp3Error::Eof) => break,
+            Err(Mp3Error::Eof) => {
+                reached_end = true;
+                break;
+            }
             Err(Mp3Error::InsufficientData) => {
                 thread::sleep(Duration::from_millis(8));
                 continue;
@@ -649,32 +641,85 @@ fn run_stream_worker(
         let channels = frame.channels.max(1);
         let sample_rate = frame.sample_rate.max(8_000) as u32;
 
-        let mut processed = frame
-            .data
-            .into_iter()
-            .map(|sample| sample as f32 / i16::MAX as f32)
-            .collect::<Vec<f32>>();
-
-        processor.configure(sample_rate, channels, preset_value);
-        processor.process_buffer(&mut processed);
-
-        if let Some(tap) = audio_frame_tap.as_ref() {
-            tap(processed.as_slice(), channels as u16, sample_rate);
+        position +=
+            Duration::from_secs_f64((frame.data.len() / channels) as f64 / sample_rate as f64);
+        if position <= start_at {
+            continue;
         }
 
-        sink.append(SamplesBuffer::new(channels as u16, sample_rate, processed));
+        decoded.clear();
+        decoded.extend(
+            frame
+                .data
+                .iter()
+                .map(|&sample| sample as f32 / i16::MAX as f32),
+        );
+        processed.clear();
+        converter.convert(
+            decoded.as_slice(),
+            channels as u16,
+            sample_rate,
+            &mut processed,
+        );
+
+        audio_taps.dispatch(
+            TapPoint::PreFx,
+            processed.as_slice(),
+            OUTPUT_CHANNELS,
+            OUTPUT_SAMPLE_RATE,
+        );
+        processor.configure(OUTPUT_SAMPLE_RATE, preset_value);
+        processor.process(processed.as_mut_slice());
+        audio_taps.dispatch(
+            TapPoint::PostFx,
+            processed.as_slice(),
+            OUTPUT_CHANNELS,
+            OUTPUT_SAMPLE_RATE,
+        );
+
+        voice.push(processed.as_slice());
+
+        if let Some(callback) = on_position.as_ref() {
+            if last_position_report.elapsed() >= POSITION_REPORT_INTERVAL {
+                last_position_report = Instant::now();
+                callback(position, false);
+            }
+        }
 
-        while sink.len() > 24 {
+        while voice.queued() > VOICE_BUFFER {
             match stop_rx.try_recv() {
                 Ok(_) | Err(TryRecvError::Disconnected) => {
-                    sink.stop();
+                    if let Some(callback) = on_position.as_ref() {
+                        callback(position, false);
+                    }
                     return Ok(());
                 }
+                Err(TryRecvError::Empty) if voice.is_silenced() => break,
                 Err(TryRecvError::Empty) => thread::sleep(Duration::from_millis(10)),
             }
         }
     }
 
-    sink.stop();
+    if reached_end {
+        voice.finish();
+        while !voice.is_drained() {
+            match stop_rx.try_recv() {
+                Ok(_) | Err(TryRecvError::Disconnected) => {
+                    reached_end = false;
+                    break;
+                }
+                Err(TryRecvError::Empty) if voice.is_silenced() => {
+                    reached_end = false;
+                    break;
+                }
+                Err(TryRecvError::Empty) => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }
+
+    // Stopped while still skipping to the resume point: keep the saved one.
+    if let Some(callback) = on_position.as_ref() {
+        callback(position.max(start_at), reached_end);
+    }
     Ok(())
 }
@@ -0,0 +1,597 @@
+//! The FX chain. It works on the engine's interleaved stereo a block at a
+//! time: each block is split into left and right buffers made up front, and
+//! every stage runs over the whole block before the next, so nothing is
+//! allocated or branched on per sample.
+
+use crate::audio_engine::AudioFxPreset;
+use std::f32::consts::PI;
+
+/// Frames per pass through the chain.
+pub const BLOCK_FRAMES: usize = 256;
+/// Enough for the longest chain a preset builds.
+const MAX_FILTERS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    /// Left and right.
+    z1: [f32; 2],
+    z2: [f32; 2],
+}
+
+impl Biquad {
+    fn new_normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: [0.0; 2],
+            z2: [0.0; 2],
+        }
+    }
+
+    fn process_block(&mut self, channel: usize, samples: &mut [f32]) {
+        let (mut z1, mut z2) = (self.z1[channel], self.z2[channel]);
+        for sample in samples.iter_mut() {
+            let x = *sample;
+            let y = self.b0 * x + z1;
+            z1 = self.b1 * x - self.a1 * y + z2;
+            z2 = self.b2 * x - self.a2 * y;
+            *sample = y;
+        }
+        self.z1[channel] = z1;
+        self.z2[channel] = z2;
+    }
+
+    fn lowpass(sample_rate: f32, cutoff_hz: f32, q: f32) -> Self {
+        let cutoff = cutoff_hz.clamp(20.0, sample_rate * 0.45);
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q.max(0.1));
+        let b0 = (1.0 - cos_w0) * 0.5;
+        let b1 = 1.0 - cos_w0;
+        let b2 = (1.0 - cos_w0) * 0.5;
+        let a0 = 1.0 + alpha;
+        let a1 = -2.0 * cos_w0;
+        let a2 = 1.0 - alpha;
+        Self::new_normalized(b0, b1, b2, a0, a1, a2)
+    }
+
+    fn highpass(sample_rate: f32, cutoff_hz: f32, q: f32) -> Self {
+        let cutoff = cutoff_hz.clamp(20.0, sample_rate * 0.45);
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q.max(0.1));
+        let b0 = (1.0 + cos_w0) * 0.5;
+        let b1 = -(1.0 + cos_w0);
+        let b2 = (1.0 + cos_w0) * 0.5;
+        let a0 = 1.0 + alpha;
+        let a1 = -2.0 * cos_w0;
+        let a2 = 1.0 - alpha;
+        Self::new_normalized(b0, b1, b2, a0, a1, a2)
+    }
+
+    fn peaking(sample_rate: f32, frequency_hz: f32, q: f32, gain_db: f32) -> Self {
+        let frequency = frequency_hz.clamp(20.0, sample_rate * 0.45);
+        let a = 10.0_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * q.max(0.1));
+        let b0 = 1.0 + alpha * a;
+        let b1 = -2.0 * cos_w0;
+        let b2 = 1.0 - alpha * a;
+        let a0 = 1.0 + alpha / a;
+        let a1 = -2.0 * cos_w0;
+        let a2 = 1.0 - alpha / a;
+        Self::new_normalized(b0, b1, b2, a0, a1, a2)
+    }
+
+    fn lowshelf(sample_rate: f32, frequency_hz: f32, slope: f32, gain_db: f32) -> Self {
+        let frequency = frequency_hz.clamp(20.0, sample_rate * 0.45);
+        let a = 10.0_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos_w0 = w0.cos();
+        let sin_w0 = w0.sin();
+        let s = slope.max(0.1);
+        let alpha = (sin_w0 / 2.0) * (((a + 1.0 / a) * (1.0 / s - 1.0) + 2.0).sqrt());
+        let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let b0 = a * ((a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha);
+        let b1 = 2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0);
+        let b2 = a * ((a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha);
+        let a0 = (a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha;
+        let a1 = -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0);
+        let a2 = (a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha;
+        Self::new_normalized(b0, b1, b2, a0, a1, a2)
+    }
+}
+
+/// Runs `filters` in order over a stereo block.
+fn process_cascade(filters: &mut [Biquad], left: &mut [f32], right: &mut [f32]) {
+    #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    if filters.len() <= MAX_FILTERS {
+        simd::process_cascade(filters, left, right);
+        return;
+    }
+
+    for filter in filters.iter_mut() {
+        filter.process_block(0, left);
+        filter.process_block(1, right);
+    }
+}
+
+/// Left and right run side by side in one register, filter after filter,
+/// with the same arithmetic as the scalar path so the output is identical.
+#[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod simd {
+    use super::{Biquad, MAX_FILTERS};
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) fn process_cascade(filters: &mut [Biquad], left: &mut [f32], right: &mut [f32]) {
+        use std::arch::x86_64::{
+            __m128, _mm_add_ps, _mm_mul_ps, _mm_set1_ps, _mm_setr_ps, _mm_storeu_ps, _mm_sub_ps,
+        };
+
+        let count = filters.len();
+        // SAFETY: SSE2 is part of the x86_64 baseline, so the intrinsics are
+        // always available. Every store goes through `_mm_storeu_ps`, which
+        // takes unaligned pointers, into `lanes`, which holds exactly the
+        // four floats it writes.
+        unsafe {
+            let zero = _mm_set1_ps(0.0);
+            let mut coefficients = [[zero; 5]; MAX_FILTERS];
+            let mut z1 = [zero; MAX_FILTERS];
+            let mut z2 = [zero; MAX_FILTERS];
+            for (index, filter) in filters.iter().enumerate() {
+                coefficients[index] = [
+                    _mm_set1_ps(filter.b0),
+                    _mm_set1_ps(filter.b1),
+                    _mm_set1_ps(filter.b2),
+                    _mm_set1_ps(filter.a1),
+                    _mm_set1_ps(filter.a2),
+                ];
+                z1[index] = _mm_setr_ps(filter.z1[0], filter.z1[1], 0.0, 0.0);
+                z2[index] = _mm_setr_ps(filter.z2[0], filter.z2[1], 0.0, 0.0);
+            }
+
+            let mut lanes = [0.0f32; 4];
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                let mut x: __m128 = _mm_setr_ps(*l, *r, 0.0, 0.0);
+                for index in 0..count {
+                    let [b0, b1, b2, a1, a2] = coefficients[index];
+                    let y = _mm_add_ps(_mm_mul_ps(b0, x), z1[index]);
+                    z1[index] =
+                        _mm_add_ps(_mm_sub_ps(_mm_mul_ps(b1, x), _mm_mul_ps(a1, y)), z2[index]);
+                    z2[index] = _mm_sub_ps(_mm_mul_ps(b2, x), _mm_mul_ps(a2, y));
+                    x = y;
+                }
+                _mm_storeu_ps(lanes.as_mut_ptr(), x);
+                *l = lanes[0];
+                *r = lanes[1];
+            }
+
+            for (index, filter) in filters.iter_mut().enumerate() {
+                _mm_storeu_ps(lanes.as_mut_ptr(), z1[index]);
+                filter.z1 = [lanes[0], lanes[1]];
+                _mm_storeu_ps(lanes.as_mut_ptr(), z2[index]);
+                filter.z2 = [lanes[0], lanes[1]];
+            }
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub(super) fn process_cascade(filters: &mut [Biquad], left: &mut [f32], right: &mut [f32]) {
+        use std::arch::aarch64::{
+            float32x2_t, vadd_f32, vdup_n_f32, vld1_f32, vmul_f32, vst1_f32, vsub_f32,
+        };
+
+        let count = filters.len();
+        // SAFETY: NEON is part of the aarch64 baseline, so the intrinsics are
+        // always available. Every `vld1_f32` and `vst1_f32` reads or writes
+        // two floats through a pointer to a `[f32; 2]`, which has no
+        // alignment requirement beyond `f32`'s.
+        unsafe {
+            let zero = vdup_n_f32(0.0);
+            let mut coefficients = [[zero; 5]; MAX_FILTERS];
+            let mut z1 = [zero; MAX_FILTERS];
+            let mut z2 = [zero; MAX_FILTERS];
+            for (index, filter) in filters.iter().enumerate() {
+                coefficients[index] = [
+                    vdup_n_f32(filter.b0),
+                    vdup_n_f32(filter.b1),
+                    vdup_n_f32(filter.b2),
+                    vdup_n_f32(filter.a1),
+                    vdup_n_f32(filter.a2),
+                ];
+                z1[index] = vld1_f32(filter.z1.as_ptr());
+                z2[index] = vld1_f32(filter.z2.as_ptr());
+            }
+
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                let mut lanes = [*l, *r];
+                let mut x: float32x2_t = vld1_f32(lanes.as_ptr());
+                for index in 0..count {
+                    let [b0, b1, b2, a1, a2] = coefficients[index];
+                    let y = vadd_f32(vmul_f32(b0, x), z1[index]);
+                    z1[index] = vadd_f32(vsub_f32(vmul_f32(b1, x), vmul_f32(a1, y)), z2[index]);
+                    z2[index] = vsub_f32(vmul_f32(b2, x), vmul_f32(a2, y));
+                    x = y;
+                }
+                vst1_f32(lanes.as_mut_ptr(), x);
+                *l = lanes[0];
+                *r = lanes[1];
+            }
+
+            for (index, filter) in filters.iter_mut().enumerate() {
+                vst1_f32(filter.z1.as_mut_ptr(), z1[index]);
+                vst1_f32(filter.z2.as_mut_ptr(), z2[index]);
+            }
+        }
+    }
+}
+
+/// Tape wow and flutter: a short delay whose length wobbles.
+struct Warble {
+    sample_rate: f32,
+    /// Left and right delay lines.
+    buffer: [Vec<f32>; 2],
+    write_index: usize,
+    phase: f32,
+    wow_rate_hz: f32,
+    wow_depth_samples: f32,
+    flutter_rate_hz: f32,
+    flutter_depth_samples: f32,
+    base_delay_samples: f32,
+}
+
+impl Warble {
+    fn new(sample_rate: f32) -> Self {
+        let sr = sample_rate.max(8_000.0);
+        let max_delay_ms = 8.0;
+        let buffer_len = ((sr * max_delay_ms / 1000.0).ceil() as usize + 4).max(32);
+
+        Self {
+            sample_rate: sr,
+            buffer: [vec![0.0; buffer_len], vec![0.0; buffer_len]],
+            write_index: 0,
+            phase: 0.0,
+            wow_rate_hz: 0.52,
+            wow_depth_samples: sr * (0.95 / 1000.0),
+            flutter_rate_hz: 6.7,
+            flutter_depth_samples: sr * (0.22 / 1000.0),
+            base_delay_samples: sr * (3.9 / 1000.0),
+        }
+    }
+
+    fn delay_samples(&self) -> f32 {
+        let wow = (2.0 * PI * self.wow_rate_hz * self.phase).sin();
+        let flutter = (2.0 * PI * self.flutter_rate_hz * self.phase + 0.7).sin();
+        let raw = self.base_delay_samples
+            + wow * self.wow_depth_samples
+            + flutter * self.flutter_depth_samples;
+        let max_delay = (self.buffer[0].len().saturating_sub(3)) as f32;
+        raw.clamp(1.0, max_delay.max(1.0))
+    }
+
+    /// Blends `mix` of the delayed signal in. The delay is worked out once
+    /// per frame and shared by both sides.
+    fn process_block(&mut self, left: &mut [f32], right: &mut [f32], mix: f32) {
+        let len = self.buffer[0].len();
+        let len_f = len as f32;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let delay = self.delay_samples();
+            let read_position = (self.write_index as f32 - delay).rem_euclid(len_f);
+            let index_a = read_position.floor() as usize;
+            let index_b = (index_a + 1) % len;
+            let fraction = read_position - index_a as f32;
+
+            for (buffer, sample) in self.buffer.iter_mut().zip([l, r]) {
+                let input = *sample;
+                let delayed = buffer[index_a] * (1.0 - fraction) + buffer[index_b] * fraction;
+                buffer[self.write_index] = input;
+                *sample = input + (delayed - input) * mix;
+            }
+
+            self.write_index = (self.write_index + 1) % len;
+            self.phase += 1.0 / self.sample_rate;
+            if self.phase > 60.0 {
+                self.phase = 0.0;
+            }
+        }
+    }
+}
+
+/// Saturation, a hard-knee compressor and makeup gain.
+#[derive(Clone, Copy)]
+struct Shaper {
+    drive: f32,
+    saturation_mix: f32,
+    threshold: f32,
+    ratio: f32,
+    makeup_gain: f32,
+}
+
+impl Default for Shaper {
+    fn default() -> Self {
+        Self {
+            drive: 1.0,
+            saturation_mix: 0.0,
+            threshold: 1.0,
+            ratio: 1.0,
+            makeup_gain: 1.0,
+        }
+    }
+}
+
+impl Shaper {
+    fn process_block(&self, samples: &mut [f32]) {
+        let drive = self.drive.max(0.001);
+        let threshold = self.threshold.max(0.0001);
+        let ratio = self.ratio.max(1.0);
+        for sample in samples.iter_mut() {
+            let mut value = *sample;
+            let saturated = (value * drive).tanh() / drive;
+            value = value + (saturated - value) * self.saturation_mix;
+
+            let magnitude = value.abs();
+            if magnitude > threshold {
+                value = value.signum() * (threshold + (magnitude - threshold) / ratio);
+            }
+            *sample = (value * self.makeup_gain).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+pub struct FxProcessor {
+    preset: AudioFxPreset,
+    sample_rate: u32,
+    filters: Vec<Biquad>,
+    warble: Option<Warble>,
+    warble_mix: f32,
+    shaper: Shaper,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl Default for FxProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FxProcessor {
+    pub fn new() -> Self {
+        let mut processor = Self {
+            preset: AudioFxPreset::Clean,
+            sample_rate: 44_100,
+            filters: Vec::with_capacity(MAX_FILTERS),
+            warble: None,
+            warble_mix: 0.0,
+            shaper: Shaper::default(),
+            left: vec![0.0; BLOCK_FRAMES],
+            right: vec![0.0; BLOCK_FRAMES],
+        };
+        processor.rebuild_chain();
+        processor
+    }
+
+    /// Rebuilds the chain only when something changed, so filter state
+    /// carries over from one buffer to the next.
+    pub fn configure(&mut self, sample_rate: u32, preset: AudioFxPreset) {
+        let next_sample_rate = sample_rate.max(8_000);
+        if self.sample_rate != next_sample_rate || self.preset != preset {
+            self.sample_rate = next_sample_rate;
+            self.preset = preset;
+            self.rebuild_chain();
+        }
+    }
+
+    fn rebuild_chain(&mut self) {
+        self.filters.clear();
+        self.warble = None;
+        self.warble_mix = 0.0;
+        self.shaper = Shaper::default();
+
+        let sr = self.sample_rate as f32;
+        match self.preset {
+            AudioFxPreset::Clean => {}
+            AudioFxPreset::Cassette => {
+                self.filters.extend([
+                    Biquad::highpass(sr, 105.0, 0.75),
+                    Biquad::peaking(sr, 2700.0, 1.35, -3.1),
+                    Biquad::lowpass(sr, 6400.0, 0.82),
+                ]);
+                self.warble = Some(Warble::new(sr));
+                self.warble_mix = 0.62;
+                self.shaper = Shaper {
+                    drive: 1.42,
+                    saturation_mix: 0.44,
+                    threshold: 0.67,
+                    ratio: 2.9,
+                    makeup_gain: 1.08,
+                };
+            }
+            AudioFxPreset::Bass => {
+                self.filters.extend([
+                    Biquad::highpass(sr, 26.0, 0.707),
+                    Biquad::lowshelf(sr, 92.0, 0.9, 7.4),
+                    Biquad::peaking(sr, 180.0, 1.0, 4.0),
+                    Biquad::lowpass(sr, 9300.0, 0.8),
+                ]);
+                self.shaper = Shaper {
+                    drive: 1.36,
+                    saturation_mix: 0.36,
+                    threshold: 0.69,
+                    ratio: 2.7,
+                    makeup_gain: 1.1,
+                };
+            }
+            AudioFxPreset::Radio => {
+                self.filters.extend([
+                    Biquad::highpass(sr, 360.0, 0.85),
+                    Biquad::peaking(sr, 1750.0, 1.65, 6.8),
+                    Biquad::lowpass(sr, 3300.0, 0.85),
+                ]);
+                self.shaper = Shaper {
+                    drive: 1.8,
+                    saturation_mix: 0.58,
+                    threshold: 0.6,
+                    ratio: 4.4,
+                    makeup_gain: 1.12,
+                };
+            }
+        }
+    }
+
+    /// Processes interleaved stereo in place.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if self.preset == AudioFxPreset::Clean {
+            return;
+        }
+
+        for block in samples.chunks_mut(BLOCK_FRAMES * 2) {
+            let frames = block.len() / 2;
+            let left = &mut self.left[..frames];
+            let right = &mut self.right[..frames];
+            for ((frame, l), r) in block
+                .chunks_exact(2)
+                .zip(left.iter_mut())
+                .zip(right.iter_mut())
+            {
+                *l = frame[0];
+                *r = frame[1];
+            }
+
+            process_cascade(self.filters.as_mut_slice(), left, right);
+            if let Some(warble) = self.warble.as_mut() {
+                warble.process_block(left, right, self.warble_mix);
+            }
+            self.shaper.process_block(left);
+            self.shaper.process_block(right);
+
+            for ((frame, l), r) in block.chunks_exact_mut(2).zip(left.iter()).zip(right.iter()) {
+                frame[0] = *l;
+                frame[1] = *r;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESETS: [AudioFxPreset; 3] = [
+        AudioFxPreset::Cassette,
+        AudioFxPreset::Bass,
+        AudioFxPreset::Radio,
+    ];
+
+    fn program(frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|index| {
+                let t = index as f32 / 44_100.0;
+                let left =
+                    0.5 * (2.0 * PI * 110.0 * t).sin() + 0.3 * (2.0 * PI * 3_300.0 * t).sin();
+                let right =
+                    0.6 * (2.0 * PI * 220.0 * t).sin() + 0.2 * (2.0 * PI * 7_000.0 * t).sin();
+                [left, right]
+            })
+            .collect()
+    }
+
+    fn rms(samples: impl Iterator<Item = f32>) -> f32 {
+        let (sum, count) = samples.fold((0.0, 0), |(sum, count), sample| {
+            (sum + sample * sample, count + 1)
+        });
+        (sum / count as f32).sqrt()
+    }
+
+    #[test]
+    fn output_does_not_depend_on_buffer_sizes() {
+        let input = program(3_000);
+        for preset in PRESETS {
+            let mut whole = input.clone();
+            let mut processor = FxProcessor::new();
+            processor.configure(44_100, preset);
+            processor.process(whole.as_mut_slice());
+
+            let mut pieces = input.clone();
+            let mut processor = FxProcessor::new();
+            processor.configure(44_100, preset);
+            let mut rest = pieces.as_mut_slice();
+            for frames in [1, 37, 256, 700, 1_152].iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let take = (frames * 2).min(rest.len());
+                let (head, tail) = rest.split_at_mut(take);
+                processor.process(head);
+                rest = tail;
+            }
+            assert_eq!(whole, pieces, "{preset:?}");
+        }
+    }
+
+    #[test]
+    fn clean_passes_through() {
+        let input = program(512);
+        let mut output = input.clone();
+        let mut processor = FxProcessor::new();
+        processor.configure(44_100, AudioFxPreset::Clean);
+        processor.process(output.as_mut_slice());
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn radio_thins_out_the_low_end() {
+        let input = (0..44_100)
+            .flat_map(|index| {
+                let sample = 0.5 * (2.0 * PI * 60.0 * index as f32 / 44_100.0).sin();
+                [sample, sample]
+            })
+            .collect::<Vec<_>>();
+        let mut output = input.clone();
+        let mut processor = FxProcessor::new();
+        processor.configure(44_100, AudioFxPreset::Radio);
+        processor.process(output.as_mut_slice());
+        let before = rms(input.iter().copied());
+        let after = rms(output[8_820..].iter().copied());
+        assert!(after < before * 0.1, "{before} {after}");
+    }
+
+    #[cfg(all(feature = "simd", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    #[test]
+    fn simd_cascade_matches_scalar() {
+        let input = program(1_000);
+        let mut left = input.iter().step_by(2).copied().collect::<Vec<_>>();
+        let mut right = input.iter().skip(1).step_by(2).copied().collect::<Vec<_>>();
+        let mut scalar_left = left.clone();
+        let mut scalar_right = right.clone();
+
+        let filters = [
+            Biquad::highpass(44_100.0, 26.0, 0.707),
+            Biquad::lowshelf(44_100.0, 92.0, 0.9, 7.4),
+            Biquad::peaking(44_100.0, 180.0, 1.0, 4.0),
+            Biquad::lowpass(44_100.0, 9300.0, 0.8),
+        ];
+        let mut simd_filters = filters;
+        let mut scalar_filters = filters;
+        for _ in 0..2 {
+            simd::process_cascade(&mut simd_filters, &mut left, &mut right);
+            for filter in scalar_filters.iter_mut() {
+                filter.process_block(0, &mut scalar_left);
+                filter.process_block(1, &mut scalar_right);
+            }
+        }
+        assert_eq!(left, scalar_left);
+        assert_eq!(right, scalar_right);
+        assert_eq!(simd_filters, scalar_filters);
+    }
+}
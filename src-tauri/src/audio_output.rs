@@ -0,0 +1,892 @@
+//! The output thread. It opens the device when there's something to play,
+//! plays a mix of every voice, and closes it once everything has played
+//! out; stream workers only decode into their voice, so switching or
+//! reconnecting a stream never touches the device.
+
+use crate::output_device::{
+    spawn_device_watcher, ActiveOutput, OutputFallbackCallback, OutputSelection,
+};
+use crate::tuning_noise::{TuningNoise, TuningNoiseConfig};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+pub const OUTPUT_SAMPLE_RATE: u32 = 44_100;
+pub const OUTPUT_CHANNELS: u16 = 2;
+/// Frames mixed per buffer handed to the device, about 23ms.
+const MIX_CHUNK_FRAMES: usize = 1_024;
+/// Mixed buffers kept queued on the device.
+const DEVICE_QUEUE_CHUNKS: usize = 3;
+/// Room for every buffer in circulation, even across a device switch: the
+/// old device's queue coming back, the new one's, and one being mixed.
+const POOL_CHUNKS: usize = 2 * DEVICE_QUEUE_CHUNKS + 2;
+/// How much decoded audio a voice holds before its producer should wait.
+pub const VOICE_BUFFER: Duration = Duration::from_millis(600);
+/// What a voice can hold at most; well past `VOICE_BUFFER`, so a producer
+/// that checks between decoded frames never fills it.
+const VOICE_CAPACITY: Duration = Duration::from_secs(2);
+/// How much a replacing voice queues before its crossfade starts.
+const PREBUFFER: Duration = Duration::from_millis(400);
+const MIX_INTERVAL: Duration = Duration::from_millis(5);
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How switching from one stream to another sounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CrossfadeConfig {
+    /// Zero cuts straight to the new stream.
+    pub duration_ms: u32,
+    pub transition: CrossfadeTransition,
+}
+
+impl Default for CrossfadeConfig {
+    fn default() -> Self {
+        Self {
+            duration_ms: 1_500,
+            transition: CrossfadeTransition::Blend,
+        }
+    }
+}
+
+impl CrossfadeConfig {
+    pub const MAX_DURATION_MS: u32 = 10_000;
+
+    pub fn is_enabled(&self) -> bool {
+        self.duration_ms > 0
+    }
+
+    fn frames(&self) -> usize {
+        (self.duration_ms.min(Self::MAX_DURATION_MS) as usize * OUTPUT_SAMPLE_RATE as usize / 1_000)
+            .max(1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CrossfadeTransition {
+    Blend,
+    /// The dial sweeps through static between the two stations.
+    Static,
+}
+
+pub struct AudioOutput {
+    mixer: Arc<Mixer>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for AudioOutput {
+    fn default() -> Self {
+        Self {
+            mixer: Arc::default(),
+            thread: Mutex::new(None),
+        }
+    }
+}
+
+impl AudioOutput {
+    /// Takes effect on whatever is playing right away.
+    pub fn set_device(&self, device: Option<String>) {
+        self.mixer.selection.set(device);
+    }
+
+    pub fn set_on_fallback(&self, callback: Arc<OutputFallbackCallback>) {
+        self.mixer.selection.set_on_fallback(callback);
+    }
+
+    pub fn set_tuning_noise(&self, config: TuningNoiseConfig) {
+        if let Ok(mut mix) = self.mixer.state.lock() {
+            mix.tuning = config;
+        }
+    }
+
+    /// A new input to the mix. The output thread starts with the first one.
+    pub fn voice(&self) -> AudioVoice {
+        let voice = self.mixer.add_voice(None);
+        self.ensure_thread();
+        voice
+    }
+
+    /// A voice taking over from a stream that was just stopped, with a sweep
+    /// of the dial over the cut.
+    pub fn voice_after_cut(&self) -> AudioVoice {
+        if let Ok(mut mix) = self.mixer.state.lock() {
+            mix.start_sweep();
+        }
+        self.voice()
+    }
+
+    /// A voice that stays silent while it prebuffers, then crossfades with
+    /// `previous`, which is silenced once the fade is done. If it closes
+    /// before that, `previous` just fades out.
+    pub fn voice_replacing(&self, previous: u64, crossfade: CrossfadeConfig) -> AudioVoice {
+        let voice = self.mixer.add_voice(Some(PendingFade {
+            replaces: previous,
+            crossfade,
+        }));
+        self.ensure_thread();
+        voice
+    }
+
+    fn ensure_thread(&self) {
+        let Ok(mut thread) = self.thread.lock() else {
+            return;
+        };
+        if let Some(handle) = thread.as_ref() {
+            handle.thread().unpark();
+            return;
+        }
+        let mixer = Arc::clone(&self.mixer);
+        match thread::Builder::new()
+            .name("audio-output".to_string())
+            .spawn(move || run_output_thread(mixer))
+        {
+            Ok(handle) => {
+                spawn_device_watcher(Arc::downgrade(&self.mixer.selection));
+                *thread = Some(handle);
+            }
+            Err(error) => eprintln!("[audio] unable to start output thread: {error}"),
+        }
+    }
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        self.mixer.shutdown.store(true, Ordering::Release);
+        if let Some(handle) = self.thread.lock().ok().and_then(|mut thread| thread.take()) {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Mixer {
+    selection: Arc<OutputSelection>,
+    state: Mutex<MixState>,
+    shutdown: AtomicBool,
+}
+
+#[derive(Default)]
+struct MixState {
+    voices: Vec<MixerVoice>,
+    next_id: u64,
+    tuning: TuningNoiseConfig,
+    /// Played over a static crossfade or a hard cut.
+    sweep: Option<TuningNoise>,
+    /// Played while nothing else can be.
+    hiss: Option<TuningNoise>,
+    noise_seed: u64,
+}
+
+struct MixerVoice {
+    id: u64,
+    state: Arc<VoiceState>,
+    envelope: Envelope,
+    /// Set until the voice has prebuffered enough to fade in.
+    pending: Option<PendingFade>,
+}
+
+#[derive(Clone, Copy)]
+struct PendingFade {
+    replaces: u64,
+    crossfade: CrossfadeConfig,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Envelope {
+    Steady,
+    FadeIn { elapsed: usize, total: usize },
+    FadeOut { elapsed: usize, total: usize },
+}
+
+impl Envelope {
+    /// Equal-power curves, so the two streams together hold their loudness.
+    fn gain(&self, offset: usize) -> f32 {
+        match *self {
+            Envelope::Steady => 1.0,
+            Envelope::FadeIn { elapsed, total } => {
+                (fade_progress(elapsed + offset, total) * FRAC_PI_2).sin()
+            }
+            Envelope::FadeOut { elapsed, total } => {
+                (fade_progress(elapsed + offset, total) * FRAC_PI_2).cos()
+            }
+        }
+    }
+
+    /// Fades out from wherever the gain is now.
+    fn fade_out(self, total: usize) -> Self {
+        match self {
+            Envelope::Steady => Envelope::FadeOut { elapsed: 0, total },
+            Envelope::FadeIn {
+                elapsed,
+                total: fade_in,
+            } => Envelope::FadeOut {
+                elapsed: ((1.0 - fade_progress(elapsed, fade_in)) * total as f32) as usize,
+                total,
+            },
+            fade_out @ Envelope::FadeOut { .. } => fade_out,
+        }
+    }
+
+    fn advance(&mut self, frames: usize) {
+        match self {
+            Envelope::Steady => {}
+            Envelope::FadeIn { elapsed, total } => {
+                *elapsed += frames;
+                if *elapsed >= *total {
+                    *self = Envelope::Steady;
+                }
+            }
+            Envelope::FadeOut { elapsed, .. } => *elapsed += frames,
+        }
+    }
+
+    fn is_silent(&self) -> bool {
+        matches!(*self, Envelope::FadeOut { elapsed, total } if elapsed >= total)
+    }
+}
+
+fn fade_progress(elapsed: usize, total: usize) -> f32 {
+    (elapsed as f32 / total.max(1) as f32).min(1.0)
+}
+
+impl Mixer {
+    fn add_voice(&self, pending: Option<PendingFade>) -> AudioVoice {
+        let state = Arc::new(VoiceState::default());
+        let mut id = 0;
+        if let Ok(mut mix) = self.state.lock() {
+            mix.next_id += 1;
+            id = mix.next_id;
+            mix.voices.push(MixerVoice {
+                id,
+                state: Arc::clone(&state),
+                envelope: Envelope::Steady,
+                pending,
+            });
+        }
+        AudioVoice { id, state }
+    }
+
+    /// No voices, and no noise left to play out.
+    fn is_idle(&self) -> bool {
+        self.state
+            .lock()
+            .map(|mix| mix.voices.is_empty() && mix.sweep.is_none() && mix.hiss.is_none())
+            .unwrap_or(true)
+    }
+
+    /// Sums every voice into `out`, interleaved at the output format. Waits
+    /// until some voice can fill the whole chunk, or has finished, so a
+    /// stream that's still buffering doesn't play as a string of clicks.
+    fn mix(&self, out: &mut [f32]) -> bool {
+        let Ok(mut guard) = self.state.lock() else {
+            return false;
+        };
+        let mix = &mut *guard;
+        mix.start_fades(out.len());
+
+        let voices_ready = mix.voices.iter().any(|voice| {
+            let queued = voice.state.queued_samples();
+            voice.pending.is_none()
+                && (queued >= out.len()
+                    || (queued > 0 && voice.state.finished.load(Ordering::Acquire)))
+        });
+        // Something is connecting or rebuffering; a stream that ended isn't.
+        let waiting = !voices_ready
+            && mix.tuning.while_buffering
+            && mix
+                .voices
+                .iter()
+                .any(|voice| !voice.state.finished.load(Ordering::Acquire));
+        match mix.hiss.as_mut() {
+            Some(hiss) if !waiting => hiss.release(),
+            Some(_) => {}
+            None if waiting => {
+                let seed = mix.next_noise_seed();
+                mix.hiss = Some(TuningNoise::hiss(&mix.tuning, seed, OUTPUT_SAMPLE_RATE));
+            }
+            None => {}
+        }
+        if !voices_ready && mix.hiss.is_none() && mix.sweep.is_none() {
+            return false;
+        }
+
+        let frames = out.len() / OUTPUT_CHANNELS as usize;
+        out.fill(0.0);
+        for voice in mix.voices.iter_mut() {
+            if !voices_ready || voice.pending.is_some() {
+                continue;
+            }
+            let gain = voice.state.gain();
+            let envelope = voice.envelope;
+            voice.state.queue.pop(out.len(), |index, sample| {
+                out[index] += sample * gain * envelope.gain(index / OUTPUT_CHANNELS as usize);
+            });
+            voice.envelope.advance(frames);
+        }
+        for noise in [mix.sweep.as_mut(), mix.hiss.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            for frame in out.chunks_exact_mut(OUTPUT_CHANNELS as usize) {
+                let sample = noise.next_sample();
+                for slot in frame {
+                    *slot += sample;
+                }
+            }
+        }
+        if mix.sweep.as_ref().is_some_and(TuningNoise::is_done) {
+            mix.sweep = None;
+        }
+        if mix.hiss.as_ref().is_some_and(TuningNoise::is_done) {
+            mix.hiss = None;
+        }
+        for sample in out.iter_mut() {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+        true
+    }
+}
+
+impl MixState {
+    /// Drops closed and faded-out voices, and starts the crossfade for any
+    /// pending voice that has enough audio queued.
+    fn start_fades(&mut self, chunk_samples: usize) {
+        let mut fades = Vec::new();
+        self.voices.retain(|voice| {
+            if voice.envelope.is_silent() {
+                voice.state.silenced.store(true, Ordering::Release);
+                return false;
+            }
+            if !voice.state.closed.load(Ordering::Acquire) {
+                return true;
+            }
+            if let Some(pending) = voice.pending {
+                fades.push(pending);
+            }
+            false
+        });
+
+        let prebuffer = prebuffer_samples().max(chunk_samples);
+        for voice in self.voices.iter_mut() {
+            let Some(pending) = voice.pending else {
+                continue;
+            };
+            let queued = voice.state.queued_samples();
+            if queued >= prebuffer || (queued > 0 && voice.state.finished.load(Ordering::Acquire)) {
+                voice.pending = None;
+                voice.envelope = Envelope::FadeIn {
+                    elapsed: 0,
+                    total: pending.crossfade.frames(),
+                };
+                fades.push(pending);
+            }
+        }
+
+        for pending in fades {
+            let total = pending.crossfade.frames();
+            self.fade_out(pending.replaces, total);
+            if pending.crossfade.transition == CrossfadeTransition::Static {
+                self.start_sweep();
+            }
+        }
+    }
+
+    fn start_sweep(&mut self) {
+        let seed = self.next_noise_seed();
+        self.sweep = Some(TuningNoise::sweep(&self.tuning, seed, OUTPUT_SAMPLE_RATE));
+    }
+
+    fn next_noise_seed(&mut self) -> u64 {
+        self.noise_seed += 1;
+        self.noise_seed
+    }
+
+    /// Fades out the voice `id` replaced. When that one was itself still
+    /// pending it was never heard, so it's silenced and the voice it was
+    /// going to replace fades out instead.
+    fn fade_out(&mut self, mut id: u64, total: usize) {
+        while let Some(voice) = self.voices.iter_mut().find(|voice| voice.id == id) {
+            match voice.pending.take() {
+                Some(pending) => {
+                    voice.envelope = Envelope::FadeOut {
+                        elapsed: total,
+                        total,
+                    };
+                    id = pending.replaces;
+                }
+                None => {
+                    voice.envelope = voice.envelope.fade_out(total);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn prebuffer_samples() -> usize {
+    (PREBUFFER.as_secs_f64() * OUTPUT_SAMPLE_RATE as f64) as usize * OUTPUT_CHANNELS as usize
+}
+
+/// A voice's queued samples: a fixed ring that its stream worker writes and
+/// the output thread reads, without either waiting on the other. Counters
+/// only grow (wrapping), and their difference is what's queued.
+struct SampleRing {
+    slots: Box<[AtomicU32]>,
+    read: AtomicUsize,
+    written: AtomicUsize,
+}
+
+impl SampleRing {
+    /// Rounds `capacity` up to a power of two, so the counters can wrap.
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: (0..capacity.next_power_of_two())
+                .map(|_| AtomicU32::new(0))
+                .collect(),
+            read: AtomicUsize::new(0),
+            written: AtomicUsize::new(0),
+        }
+    }
+
+    fn len(&self) -> usize {
+        // Read first: it never passes `written`, so the difference can't
+        // underflow.
+        let read = self.read.load(Ordering::Acquire);
+        self.written
+            .load(Ordering::Acquire)
+            .wrapping_sub(read)
+            .min(self.slots.len())
+    }
+
+    fn slot(&self, counter: usize) -> &AtomicU32 {
+        &self.slots[counter & (self.slots.len() - 1)]
+    }
+
+    /// Writes as much of `samples` as fits and returns how much that was.
+    /// Only ever called by the one producer.
+    fn push(&self, samples: &[f32]) -> usize {
+        let written = self.written.load(Ordering::Relaxed);
+        let queued = written.wrapping_sub(self.read.load(Ordering::Acquire));
+        let take = samples.len().min(self.slots.len() - queued);
+        for (offset, sample) in samples[..take].iter().enumerate() {
+            self.slot(written.wrapping_add(offset))
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        self.written
+            .store(written.wrapping_add(take), Ordering::Release);
+        take
+    }
+
+    /// Hands up to `max` samples, oldest first and with their index, to
+    /// `sink`. Only ever called by the one consumer.
+    fn pop(&self, max: usize, mut sink: impl FnMut(usize, f32)) {
+        let read = self.read.load(Ordering::Relaxed);
+        let queued = self.written.load(Ordering::Acquire).wrapping_sub(read);
+        let take = queued.min(max);
+        for offset in 0..take {
+            let bits = self.slot(read.wrapping_add(offset)).load(Ordering::Relaxed);
+            sink(offset, f32::from_bits(bits));
+        }
+        self.read.store(read.wrapping_add(take), Ordering::Release);
+    }
+}
+
+struct VoiceState {
+    queue: SampleRing,
+    gain: AtomicU32,
+    finished: AtomicBool,
+    closed: AtomicBool,
+    silenced: AtomicBool,
+}
+
+impl Default for VoiceState {
+    fn default() -> Self {
+        Self {
+            queue: SampleRing::with_capacity(
+                (VOICE_CAPACITY.as_secs_f64() * OUTPUT_SAMPLE_RATE as f64) as usize
+                    * OUTPUT_CHANNELS as usize,
+            ),
+            gain: AtomicU32::new(1.0f32.to_bits()),
+            finished: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            silenced: AtomicBool::new(false),
+        }
+    }
+}
+
+impl VoiceState {
+    fn queued_samples(&self) -> usize {
+        self.queue.len()
+    }
+
+    fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+}
+
+/// One stream's input to the mix. Dropping it takes the stream out of the
+/// mix; what's already on the device plays out.
+pub struct AudioVoice {
+    id: u64,
+    state: Arc<VoiceState>,
+}
+
+impl AudioVoice {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Queues interleaved samples, already at the output format. Producers
+    /// wait while more than `VOICE_BUFFER` is queued; anything past the
+    /// voice's capacity is dropped.
+    pub fn push(&mut self, samples: &[f32]) {
+        let queued = self.state.queue.push(samples);
+        if queued < samples.len() {
+            eprintln!(
+                "[audio] voice {} full, dropped {} samples",
+                self.id,
+                samples.len() - queued
+            );
+        }
+    }
+
+    pub fn queued(&self) -> Duration {
+        let frames = self.state.queued_samples() / OUTPUT_CHANNELS as usize;
+        Duration::from_secs_f64(frames as f64 / OUTPUT_SAMPLE_RATE as f64)
+    }
+
+    pub fn set_gain(&self, gain: f32) {
+        self.state.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// No more input is coming; the rest plays even if it's short of a chunk.
+    pub fn finish(&self) {
+        self.state.finished.store(true, Ordering::Release);
+    }
+
+    pub fn is_drained(&self) -> bool {
+        self.state.queued_samples() == 0
+    }
+
+    /// Faded out by a crossfade; nothing pushed from here on is heard.
+    pub fn is_silenced(&self) -> bool {
+        self.state.silenced.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for AudioVoice {
+    fn drop(&mut self) {
+        self.state.closed.store(true, Ordering::Release);
+    }
+}
+
+/// A mixed buffer on its way through the device. Once played it goes back
+/// to the output thread to be mixed into again, so a steady stream of chunks
+/// doesn't allocate. It's dropped on the device's callback, so the way back
+/// is a bounded channel, whose slots are allocated up front.
+struct PooledChunk {
+    samples: Vec<f32>,
+    position: usize,
+    pool: SyncSender<Vec<f32>>,
+}
+
+impl Iterator for PooledChunk {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.samples.len().saturating_sub(self.position);
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for PooledChunk {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        OUTPUT_CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        OUTPUT_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Drop for PooledChunk {
+    fn drop(&mut self) {
+        // Only full if more buffers exist than expected, and the output
+        // thread may already be gone; either way this one is just freed.
+        let _ = self.pool.try_send(std::mem::take(&mut self.samples));
+    }
+}
+
+fn pooled_buffer(pool: &Receiver<Vec<f32>>) -> Vec<f32> {
+    pool.try_recv()
+        .unwrap_or_else(|_| vec![0.0; MIX_CHUNK_FRAMES * OUTPUT_CHANNELS as usize])
+}
+
+fn run_output_thread(mixer: Arc<Mixer>) {
+    let mut output: Option<ActiveOutput> = None;
+    let (pool_tx, pool_rx) = mpsc::sync_channel(POOL_CHUNKS);
+    let mut chunk = pooled_buffer(&pool_rx);
+
+    while !mixer.shutdown.load(Ordering::Acquire) {
+        // Once the last of it has played, close the device until a voice
+        // is added.
+        if mixer.is_idle() && output.as_ref().is_none_or(|active| active.sink.empty()) {
+            output = None;
+            thread::park();
+            continue;
+        }
+
+        let active = match output.as_mut() {
+            Some(active) => active,
+            None => match ActiveOutput::open(&mixer.selection) {
+                Ok(active) => output.insert(active),
+                Err(error) => {
+                    eprintln!("[audio] unable to open output: {error}");
+                    thread::park_timeout(OPEN_RETRY_INTERVAL);
+                    continue;
+                }
+            },
+        };
+        active.maintain();
+
+        while active.sink.len() < DEVICE_QUEUE_CHUNKS && mixer.mix(&mut chunk) {
+            let samples = std::mem::replace(&mut chunk, pooled_buffer(&pool_rx));
+            active.sink.append(PooledChunk {
+                samples,
+                position: 0,
+                pool: pool_tx.clone(),
+            });
+        }
+        thread::park_timeout(MIX_INTERVAL);
+    }
+
+    if let Some(active) = output {
+        active.sink.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixes_voices_with_gain_and_drops_closed_ones() {
+        let mixer = Arc::new(Mixer::default());
+        let mut first = mixer.add_voice(None);
+        let mut second = mixer.add_voice(None);
+        first.push(&[0.5; 8]);
+        second.push(&[0.25; 8]);
+        second.set_gain(0.5);
+
+        let mut out = [0.0f32; 8];
+        assert!(mixer.mix(&mut out));
+        assert!(out.iter().all(|sample| (sample - 0.625).abs() < 1e-6));
+
+        first.push(&[0.5; 8]);
+        second.push(&[0.5; 8]);
+        drop(second);
+        assert!(mixer.mix(&mut out));
+        assert!(out.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+        assert_eq!(mixer.state.lock().unwrap().voices.len(), 1);
+    }
+
+    #[test]
+    fn a_hard_cut_sweeps_while_the_new_voice_buffers() {
+        let mixer = Arc::new(Mixer::default());
+        {
+            let mut mix = mixer.state.lock().unwrap();
+            mix.tuning.while_buffering = false;
+            mix.tuning.sweep_ms = 20;
+            mix.start_sweep();
+        }
+        let mut voice = mixer.add_voice(None);
+
+        let mut out = vec![0.0f32; 2 * 441];
+        assert!(mixer.mix(&mut out));
+        assert!(out.iter().any(|sample| *sample != 0.0));
+
+        voice.push(&vec![0.5; 2 * 441 * 4]);
+        assert!(mixer.mix(&mut out));
+        assert!(mixer.state.lock().unwrap().sweep.is_none());
+        assert!(mixer.mix(&mut out));
+        assert!(out.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn voice_queues_wrap_and_drop_past_capacity() {
+        let ring = SampleRing::with_capacity(6);
+        assert_eq!(ring.push(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), 6);
+        let mut popped = Vec::new();
+        ring.pop(4, |_, sample| popped.push(sample));
+        assert_eq!(popped, [1.0, 2.0, 3.0, 4.0]);
+
+        // Eight slots, two still queued, so six more fit.
+        assert_eq!(ring.push(&[7.0, 8.0, 9.0, 10.0, 11.0, 12.0, 13.0]), 6);
+        assert_eq!(ring.len(), 8);
+        let mut indexed = Vec::new();
+        ring.pop(usize::MAX, |index, sample| indexed.push((index, sample)));
+        assert_eq!(
+            indexed
+                .iter()
+                .map(|(_, sample)| *sample)
+                .collect::<Vec<_>>(),
+            [5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0, 12.0]
+        );
+        assert_eq!(indexed.last().map(|(index, _)| *index), Some(7));
+        assert_eq!(ring.len(), 0);
+    }
+
+    #[test]
+    fn idle_once_voices_and_noise_are_gone() {
+        let mixer = Arc::new(Mixer::default());
+        assert!(mixer.is_idle());
+        let mut voice = mixer.add_voice(None);
+        assert!(!mixer.is_idle());
+
+        // Buffering hisses, and the hiss plays out after the voice is gone.
+        voice.push(&[0.5; 4]);
+        let mut out = vec![0.0f32; 2 * 441];
+        assert!(mixer.mix(&mut out));
+        drop(voice);
+        assert!(mixer.mix(&mut out));
+        assert!(!mixer.is_idle());
+        while mixer.mix(&mut out) {}
+        assert!(mixer.is_idle());
+    }
+
+    #[test]
+    fn waits_for_a_full_chunk_unless_finished() {
+        let mixer = Arc::new(Mixer::default());
+        mixer.state.lock().unwrap().tuning.while_buffering = false;
+        let mut voice = mixer.add_voice(None);
+        voice.push(&[0.5; 4]);
+
+        let mut out = [1.0f32; 8];
+        assert!(!mixer.mix(&mut out));
+        voice.finish();
+        assert!(mixer.mix(&mut out));
+        assert_eq!(out, [0.5, 0.5, 0.5, 0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert!(voice.is_drained());
+    }
+
+    #[test]
+    fn hisses_while_a_voice_buffers() {
+        let mixer = Arc::new(Mixer::default());
+        let mut voice = mixer.add_voice(None);
+        voice.push(&[0.5; 4]);
+
+        let mut out = vec![0.0f32; 2 * 441];
+        assert!(mixer.mix(&mut out));
+        assert!(out.iter().any(|sample| *sample != 0.0));
+        assert!(out.iter().all(|sample| sample.abs() < 0.5));
+        assert_eq!(voice.queued(), Duration::from_secs_f64(2.0 / 44_100.0));
+
+        voice.push(&vec![0.5; 2 * 441 * 8]);
+        for _ in 0..4 {
+            assert!(mixer.mix(&mut out));
+        }
+        assert!(mixer.state.lock().unwrap().hiss.is_none());
+        assert!(out.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn crossfades_once_the_new_voice_has_prebuffered() {
+        let mixer = Arc::new(Mixer::default());
+        let mut old = mixer.add_voice(None);
+        old.push(&vec![0.5; 4 * OUTPUT_SAMPLE_RATE as usize]);
+        let crossfade = CrossfadeConfig {
+            duration_ms: 20,
+            transition: CrossfadeTransition::Blend,
+        };
+        let mut new = mixer.add_voice(Some(PendingFade {
+            replaces: old.id(),
+            crossfade,
+        }));
+
+        // Ten milliseconds a chunk, so the fade takes two.
+        let mut out = vec![0.0f32; 2 * 441];
+        new.push(&[0.5; 2 * 441]);
+        assert!(mixer.mix(&mut out));
+        assert!(out.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+
+        new.push(&vec![0.5; 4 * OUTPUT_SAMPLE_RATE as usize]);
+        assert!(mixer.mix(&mut out));
+        assert!((out[0] - 0.5).abs() < 1e-6);
+        assert!(mixer.mix(&mut out));
+        // Halfway through, each stream is at -3dB.
+        assert!((out[0] - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-3);
+
+        assert!(mixer.mix(&mut out));
+        assert!(old.is_silenced());
+        assert!(!new.is_silenced());
+        assert!(out.iter().all(|sample| (sample - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn a_replaced_pending_voice_is_never_heard() {
+        let mixer = Arc::new(Mixer::default());
+        let mut playing = mixer.add_voice(None);
+        playing.push(&vec![0.25; 4 * OUTPUT_SAMPLE_RATE as usize]);
+        let crossfade = CrossfadeConfig {
+            duration_ms: 20,
+            transition: CrossfadeTransition::Static,
+        };
+        mixer.state.lock().unwrap().tuning.sweep_ms = 20;
+        let mut skipped = mixer.add_voice(Some(PendingFade {
+            replaces: playing.id(),
+            crossfade,
+        }));
+        skipped.push(&[0.5; 2 * 441]);
+        let mut latest = mixer.add_voice(Some(PendingFade {
+            replaces: skipped.id(),
+            crossfade,
+        }));
+        latest.push(&vec![0.25; 4 * OUTPUT_SAMPLE_RATE as usize]);
+
+        let mut out = vec![0.0f32; 2 * 441];
+        for _ in 0..3 {
+            assert!(mixer.mix(&mut out));
+        }
+        assert!(playing.is_silenced());
+        assert!(skipped.is_silenced());
+        assert!(mixer.state.lock().unwrap().sweep.is_none());
+        assert!(out.iter().all(|sample| (sample - 0.25).abs() < 1e-6));
+    }
+
+    #[test]
+    fn played_chunks_come_back_to_the_pool() {
+        let (pool_tx, pool_rx) = mpsc::sync_channel(POOL_CHUNKS);
+        let samples = vec![0.25; 8];
+        let address = samples.as_ptr();
+        let chunk = PooledChunk {
+            samples,
+            position: 0,
+            pool: pool_tx,
+        };
+        assert_eq!(chunk.collect::<Vec<_>>(), vec![0.25; 8]);
+        let recycled = pooled_buffer(&pool_rx);
+        assert_eq!(recycled.as_ptr(), address);
+        let fresh = pooled_buffer(&pool_rx);
+        assert_eq!(fresh.len(), MIX_CHUNK_FRAMES * OUTPUT_CHANNELS as usize);
+    }
+}
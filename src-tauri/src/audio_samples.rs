@@ -0,0 +1,22 @@
+//! Helpers for the interleaved `f32` buffers passed between the decoder,
+//! the taps and the fingerprinters.
+
+/// Averages each frame's channels; a trailing partial frame is dropped.
+pub fn downmix_mono(samples: &[f32], channels: u16) -> impl Iterator<Item = f32> + '_ {
+    let channels = channels.max(1) as usize;
+    samples
+        .chunks_exact(channels)
+        .map(move |frame| frame.iter().sum::<f32>() / channels as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::downmix_mono;
+
+    #[test]
+    fn averages_channels_and_drops_a_partial_frame() {
+        let stereo = [1.0, 0.0, 0.5, 0.5, -1.0];
+        assert_eq!(downmix_mono(&stereo, 2).collect::<Vec<_>>(), [0.5, 0.5]);
+        assert_eq!(downmix_mono(&stereo, 0).count(), stereo.len());
+    }
+}
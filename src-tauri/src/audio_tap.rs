@@ -0,0 +1,418 @@
+//! Taps on the decoded audio. Taps can be added and removed at any time,
+//! including while a stream plays; the worker picks changes up on its next
+//! buffer without ever waiting on the registry.
+//!
+//! Each tap runs on a thread of its own, fed through a fixed ring of buffers.
+//! The audio thread only ever writes into the ring: a tap that falls behind
+//! loses its oldest buffers rather than holding up playback.
+
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+/// Interleaved samples, channel count and sample rate.
+pub type AudioFrameTap = dyn Fn(&[f32], u16, u32) + Send + Sync + 'static;
+
+/// Buffers a tap can fall behind by before the oldest are dropped; about a
+/// second of MP3 frames.
+const TAP_QUEUE_BUFFERS: usize = 32;
+/// One stereo MP3 frame. Longer buffers take several slots.
+const TAP_BUFFER_SAMPLES: usize = 2_304;
+/// Tap threads also wake this often, in case an unpark was missed.
+const TAP_IDLE_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TapPoint {
+    /// The decoded stream, before the FX preset.
+    PreFx,
+    /// What goes to the speakers.
+    PostFx,
+}
+
+/// Returned by [`AudioTapRegistry::add`]; pass it back to remove the tap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AudioTapHandle(u64);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AudioTapStats {
+    pub delivered: u64,
+    /// Buffers dropped because the tap fell behind.
+    pub dropped: u64,
+}
+
+#[derive(Clone)]
+struct RegisteredTap {
+    handle: AudioTapHandle,
+    point: TapPoint,
+    queue: Arc<TapQueue>,
+}
+
+#[derive(Default)]
+pub struct AudioTapRegistry {
+    taps: Mutex<Arc<[RegisteredTap]>>,
+    /// Bumped on every change, so readers know to pick up a new list.
+    generation: AtomicU64,
+    next_handle: AtomicU64,
+}
+
+impl AudioTapRegistry {
+    pub fn add(&self, point: TapPoint, tap: Arc<AudioFrameTap>) -> AudioTapHandle {
+        let handle = AudioTapHandle(self.next_handle.fetch_add(1, Ordering::Relaxed));
+        let queue = Arc::new(TapQueue::spawn(tap));
+        self.replace(|taps| {
+            taps.iter()
+                .cloned()
+                .chain(std::iter::once(RegisteredTap {
+                    handle,
+                    point,
+                    queue,
+                }))
+                .collect()
+        });
+        handle
+    }
+
+    /// Returns whether the tap was still registered. Its thread finishes
+    /// what is queued, then exits.
+    pub fn remove(&self, handle: AudioTapHandle) -> bool {
+        let mut removed = false;
+        self.replace(|taps| {
+            taps.iter()
+                .filter(|registered| {
+                    let matches = registered.handle == handle;
+                    removed |= matches;
+                    !matches
+                })
+                .cloned()
+                .collect()
+        });
+        removed
+    }
+
+    pub fn stats(&self, handle: AudioTapHandle) -> Option<AudioTapStats> {
+        let taps = self.taps.lock().ok()?;
+        taps.iter()
+            .find(|registered| registered.handle == handle)
+            .map(|registered| registered.queue.ring.stats())
+    }
+
+    pub fn reader(self: &Arc<Self>) -> AudioTapReader {
+        AudioTapReader {
+            registry: Arc::clone(self),
+            generation: u64::MAX,
+            taps: Arc::from(Vec::new()),
+        }
+    }
+
+    fn replace(&self, update: impl FnOnce(&[RegisteredTap]) -> Arc<[RegisteredTap]>) {
+        let Ok(mut taps) = self.taps.lock() else {
+            eprintln!("[audio] tap registry lock poisoned");
+            return;
+        };
+        *taps = update(&taps);
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// The worker's view of the registry: a snapshot of the taps, refreshed when
+/// the registry changes.
+pub struct AudioTapReader {
+    registry: Arc<AudioTapRegistry>,
+    generation: u64,
+    taps: Arc<[RegisteredTap]>,
+}
+
+impl AudioTapReader {
+    pub fn dispatch(&mut self, point: TapPoint, samples: &[f32], channels: u16, sample_rate: u32) {
+        self.refresh();
+        for registered in self.taps.iter() {
+            if registered.point == point {
+                registered.queue.push(samples, channels, sample_rate);
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        let generation = self.registry.generation.load(Ordering::Acquire);
+        if generation == self.generation {
+            return;
+        }
+        // Someone is mid-change; the old list does for one more buffer.
+        let Ok(taps) = self.registry.taps.try_lock() else {
+            return;
+        };
+        self.taps = Arc::clone(&taps);
+        self.generation = generation;
+    }
+}
+
+/// A tap's ring and the thread draining it; closing it stops the thread.
+struct TapQueue {
+    ring: Arc<TapRing>,
+    consumer: Thread,
+}
+
+impl TapQueue {
+    fn spawn(tap: Arc<AudioFrameTap>) -> Self {
+        let ring = Arc::new(TapRing::new());
+        let consumer = thread::spawn({
+            let ring = Arc::clone(&ring);
+            move || ring.drain(tap.as_ref())
+        })
+        .thread()
+        .clone();
+        Self { ring, consumer }
+    }
+
+    fn push(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if channels == 0 {
+            return;
+        }
+        // Whole frames per slot, so a split buffer stays interleaved.
+        let chunk = TAP_BUFFER_SAMPLES - TAP_BUFFER_SAMPLES % channels as usize;
+        if chunk == 0 {
+            return;
+        }
+        for samples in samples.chunks(chunk) {
+            self.ring.publish(samples, channels, sample_rate);
+        }
+        self.consumer.unpark();
+    }
+}
+
+impl Drop for TapQueue {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+        self.consumer.unpark();
+    }
+}
+
+/// One slot of the ring. `sequence` is odd while the producer writes it and
+/// `2 * position + 2` once buffer number `position` is complete, so a reader
+/// can tell when a slot was overwritten under it. Samples are stored as bits
+/// in atomics so that a racing read is merely stale, never undefined.
+struct TapSlot {
+    sequence: AtomicU64,
+    channels: AtomicU32,
+    sample_rate: AtomicU32,
+    len: AtomicUsize,
+    samples: Box<[AtomicU32]>,
+}
+
+struct TapRing {
+    slots: Box<[TapSlot]>,
+    /// Buffers published so far; only the audio thread writes it.
+    written: AtomicU64,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    closed: AtomicBool,
+}
+
+impl TapRing {
+    fn new() -> Self {
+        Self {
+            slots: (0..TAP_QUEUE_BUFFERS)
+                .map(|_| TapSlot {
+                    sequence: AtomicU64::new(0),
+                    channels: AtomicU32::new(0),
+                    sample_rate: AtomicU32::new(0),
+                    len: AtomicUsize::new(0),
+                    samples: (0..TAP_BUFFER_SAMPLES).map(|_| AtomicU32::new(0)).collect(),
+                })
+                .collect(),
+            written: AtomicU64::new(0),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn stats(&self) -> AudioTapStats {
+        AudioTapStats {
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Producer side; never waits. `samples` fits in a slot.
+    fn publish(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        let position = self.written.load(Ordering::Relaxed);
+        let slot = &self.slots[position as usize % TAP_QUEUE_BUFFERS];
+        slot.sequence.store(position * 2 + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.channels.store(channels as u32, Ordering::Relaxed);
+        slot.sample_rate.store(sample_rate, Ordering::Relaxed);
+        slot.len.store(samples.len(), Ordering::Relaxed);
+        for (target, sample) in slot.samples.iter().zip(samples) {
+            target.store(sample.to_bits(), Ordering::Relaxed);
+        }
+        slot.sequence.store(position * 2 + 2, Ordering::Release);
+        self.written.store(position + 1, Ordering::Release);
+    }
+
+    /// Consumer side; runs on the tap's thread until the queue is closed and
+    /// everything in it has been delivered.
+    fn drain(&self, tap: &AudioFrameTap) {
+        let mut buffer = vec![0.0_f32; TAP_BUFFER_SAMPLES];
+        let mut read = 0_u64;
+        loop {
+            let written = self.written.load(Ordering::Acquire);
+            if read == written {
+                if self.closed.load(Ordering::Acquire) {
+                    break;
+                }
+                thread::park_timeout(TAP_IDLE_WAIT);
+                continue;
+            }
+            let behind = written - read;
+            if behind > TAP_QUEUE_BUFFERS as u64 {
+                self.dropped
+                    .fetch_add(behind - TAP_QUEUE_BUFFERS as u64, Ordering::Relaxed);
+                read = written - TAP_QUEUE_BUFFERS as u64;
+            }
+
+            let position = read;
+            read += 1;
+            let slot = &self.slots[position as usize % TAP_QUEUE_BUFFERS];
+            let expected = position * 2 + 2;
+            if slot.sequence.load(Ordering::Acquire) != expected {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            let channels = slot.channels.load(Ordering::Relaxed) as u16;
+            let sample_rate = slot.sample_rate.load(Ordering::Relaxed);
+            let len = slot.len.load(Ordering::Relaxed).min(TAP_BUFFER_SAMPLES);
+            for (target, sample) in buffer.iter_mut().zip(slot.samples.iter()).take(len) {
+                *target = f32::from_bits(sample.load(Ordering::Relaxed));
+            }
+            fence(Ordering::Acquire);
+            // Overwritten while it was being copied.
+            if slot.sequence.load(Ordering::Relaxed) != expected {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            tap(&buffer[..len], channels, sample_rate);
+            self.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            eprintln!("[audio] tap closed after dropping {dropped} buffers it fell behind on");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioFrameTap, AudioTapRegistry, TapPoint, TAP_QUEUE_BUFFERS};
+    use std::sync::mpsc::{self, Receiver};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    const FRAME_SAMPLES: usize = 1_152 * 2;
+
+    fn recorder() -> (Arc<AudioFrameTap>, Receiver<f32>) {
+        let (tx, rx) = mpsc::channel();
+        let tap = Arc::new(move |samples: &[f32], _, _| {
+            let _ = tx.send(samples[0]);
+        });
+        (tap, rx)
+    }
+
+    fn received(rx: &Receiver<f32>) -> Vec<f32> {
+        let mut values = Vec::new();
+        while let Ok(value) = rx.recv_timeout(Duration::from_millis(200)) {
+            values.push(value);
+        }
+        values
+    }
+
+    #[test]
+    fn running_readers_see_taps_come_and_go() {
+        let registry = Arc::new(AudioTapRegistry::default());
+        let mut reader = registry.reader();
+        let (recognition_tap, recognition) = recorder();
+        let (loudness_tap, loudness) = recorder();
+
+        reader.dispatch(TapPoint::PostFx, &[0.0], 1, 44_100);
+        let recognition_handle = registry.add(TapPoint::PostFx, recognition_tap);
+        let loudness_handle = registry.add(TapPoint::PreFx, loudness_tap);
+        reader.dispatch(TapPoint::PreFx, &[1.0], 1, 44_100);
+        reader.dispatch(TapPoint::PostFx, &[2.0], 1, 44_100);
+
+        assert!(registry.remove(recognition_handle));
+        assert!(!registry.remove(recognition_handle));
+        reader.dispatch(TapPoint::PreFx, &[3.0], 1, 44_100);
+        reader.dispatch(TapPoint::PostFx, &[4.0], 1, 44_100);
+        assert!(registry.remove(loudness_handle));
+        reader.dispatch(TapPoint::PreFx, &[5.0], 1, 44_100);
+
+        assert_eq!(received(&recognition), vec![2.0]);
+        assert_eq!(received(&loudness), vec![1.0, 3.0]);
+    }
+
+    #[test]
+    fn slow_taps_drop_their_oldest_buffers_without_stalling_the_decoder() {
+        let registry = Arc::new(AudioTapRegistry::default());
+        let mut reader = registry.reader();
+        // The slow tap is stuck until the gate opens, however long that takes.
+        let gate = Arc::new(Mutex::new(()));
+        let closed = gate.lock().expect("gate");
+        let (slow_tx, slow) = mpsc::channel();
+        let slow_tap = Arc::new({
+            let gate = Arc::clone(&gate);
+            move |samples: &[f32], _, _| {
+                let _open = gate.lock();
+                let _ = slow_tx.send(samples[0]);
+            }
+        });
+        let (fast_tap, fast) = recorder();
+        let slow_handle = registry.add(TapPoint::PostFx, slow_tap);
+        let fast_handle = registry.add(TapPoint::PostFx, fast_tap);
+
+        // The decoder hands every frame off while the slow tap is stuck, and
+        // only moves on once the fast tap has it.
+        let frames = TAP_QUEUE_BUFFERS * 4;
+        let (done_tx, done) = mpsc::channel();
+        let decoder = thread::spawn(move || {
+            let mut buffer = vec![0.0_f32; FRAME_SAMPLES];
+            let mut heard = Vec::new();
+            for index in 0..frames {
+                buffer.fill(index as f32);
+                reader.dispatch(TapPoint::PostFx, buffer.as_slice(), 2, 44_100);
+                heard.push(fast.recv().expect("fast tap"));
+            }
+            let _ = done_tx.send(());
+            heard
+        });
+        assert!(
+            done.recv_timeout(Duration::from_secs(10)).is_ok(),
+            "dispatch waited on the slow tap"
+        );
+        drop(closed);
+
+        let fast = decoder.join().expect("decoder");
+        assert_eq!(
+            fast,
+            (0..frames).map(|index| index as f32).collect::<Vec<_>>()
+        );
+        let fast_stats = registry.stats(fast_handle).expect("stats");
+        assert_eq!(fast_stats.dropped, 0, "{fast_stats:?}");
+
+        let slow = received(&slow);
+        let stats = registry.stats(slow_handle).expect("stats");
+        // Everything past the one buffer it's stuck on and a full queue.
+        assert!(
+            stats.dropped >= (frames - TAP_QUEUE_BUFFERS - 1) as u64,
+            "{stats:?}"
+        );
+        assert_eq!(stats.delivered + stats.dropped, frames as u64, "{stats:?}");
+        assert_eq!(slow.len() as u64, stats.delivered);
+        // What survives is in order and ends with the newest buffer.
+        assert!(slow.windows(2).all(|pair| pair[0] < pair[1]), "{slow:?}");
+        assert_eq!(slow.last().copied(), Some((frames - 1) as f32));
+    }
+}
@@ -0,0 +1,175 @@
+//! The SQLite database in the app data directory. Stores open their own
+//! connection to it; the schema is brought up to date whenever one opens.
+
+use rusqlite::Connection;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+pub const DATABASE_FILE_NAME: &str = "marconio.sqlite3";
+
+/// Applied in order; a database's `user_version` counts how many have run.
+/// Never edit a migration that has shipped, add another one.
+const MIGRATIONS: &[&str] = &[
+    // 1: recognition history.
+    "CREATE TABLE history (
+        id TEXT PRIMARY KEY NOT NULL,
+        recognized_at INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        artist TEXT,
+        artwork_url TEXT,
+        apple_music_url TEXT,
+        web_url TEXT,
+        source_title TEXT,
+        source_artist TEXT,
+        shazam_id TEXT,
+        musicbrainz_recording_id TEXT,
+        acoustid TEXT,
+        show_alias TEXT,
+        favorite INTEGER NOT NULL DEFAULT 0,
+        tags TEXT NOT NULL DEFAULT '[]',
+        note TEXT
+    );
+    CREATE INDEX history_recognized_at ON history (recognized_at DESC);
+    CREATE INDEX history_show_alias ON history (show_alias COLLATE NOCASE);
+    CREATE INDEX history_favorite ON history (favorite) WHERE favorite = 1;",
+    // 2: listening sessions.
+    "CREATE TABLE listening_sessions (
+        id INTEGER PRIMARY KEY,
+        started_at INTEGER NOT NULL,
+        ended_at INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        source TEXT NOT NULL,
+        title TEXT NOT NULL,
+        artist TEXT,
+        show_alias TEXT,
+        episode_alias TEXT,
+        fx_preset TEXT NOT NULL
+    );
+    CREATE INDEX listening_sessions_started_at ON listening_sessions (started_at);",
+    // 3: scrobbles waiting to be submitted.
+    "CREATE TABLE scrobble_queue (
+        id INTEGER PRIMARY KEY,
+        service TEXT NOT NULL,
+        artist TEXT NOT NULL,
+        track TEXT NOT NULL,
+        album TEXT,
+        listened_at INTEGER NOT NULL,
+        duration_seconds INTEGER,
+        musicbrainz_recording_id TEXT,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_at INTEGER NOT NULL,
+        last_error TEXT
+    );
+    CREATE INDEX scrobble_queue_next_attempt_at ON scrobble_queue (next_attempt_at);",
+];
+
+/// Waits this long for another connection's write before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn open(path: &Path) -> Result<Connection, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|error| {
+            format!(
+                "unable to create database directory {}: {error}",
+                parent.display()
+            )
+        })?;
+    }
+    let mut connection = Connection::open(path)
+        .map_err(|error| format!("unable to open database {}: {error}", path.display()))?;
+    connection
+        .busy_timeout(BUSY_TIMEOUT)
+        .and_then(|_| connection.pragma_update(None, "journal_mode", "WAL"))
+        .and_then(|_| connection.pragma_update(None, "synchronous", "NORMAL"))
+        .map_err(|error| format!("unable to configure database {}: {error}", path.display()))?;
+    migrate(&mut connection)
+        .map_err(|error| format!("unable to migrate database {}: {error}", path.display()))?;
+    Ok(connection)
+}
+
+/// A throwaway database for tests.
+#[cfg(test)]
+pub fn open_in_memory() -> Result<Connection, String> {
+    let mut connection = Connection::open_in_memory()
+        .map_err(|error| format!("unable to open in-memory database: {error}"))?;
+    migrate(&mut connection)?;
+    Ok(connection)
+}
+
+fn migrate(connection: &mut Connection) -> Result<(), String> {
+    let version = connection
+        .query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))
+        .map_err(|error| error.to_string())?;
+    let version = usize::try_from(version).unwrap_or(0);
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "schema version {version} is from a newer version of the app"
+        ));
+    }
+    if version == MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    let transaction = connection
+        .transaction()
+        .map_err(|error| error.to_string())?;
+    for migration in &MIGRATIONS[version..] {
+        transaction
+            .execute_batch(migration)
+            .map_err(|error| error.to_string())?;
+    }
+    transaction
+        .pragma_update(None, "user_version", MIGRATIONS.len() as i64)
+        .map_err(|error| error.to_string())?;
+    transaction.commit().map_err(|error| error.to_string())?;
+    eprintln!(
+        "[database] migrated schema from version {version} to {}",
+        MIGRATIONS.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open, MIGRATIONS};
+    use crate::test_support::test_dir;
+    use std::fs;
+
+    #[test]
+    fn migrates_once_and_refuses_newer_schemas() {
+        let dir = test_dir("migrate");
+        let path = dir.join("marconio.sqlite3");
+        let user_version = |connection: &rusqlite::Connection| {
+            connection
+                .query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))
+                .expect("user_version")
+        };
+
+        let connection = open(path.as_path()).expect("open");
+        assert_eq!(user_version(&connection), MIGRATIONS.len() as i64);
+        connection
+            .execute(
+                "INSERT INTO history (id, recognized_at, title) VALUES ('a', 1, 'Kept')",
+                [],
+            )
+            .expect("insert");
+        drop(connection);
+
+        let connection = open(path.as_path()).expect("reopen");
+        let count = connection
+            .query_row("SELECT COUNT(*) FROM history", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .expect("count");
+        assert_eq!(count, 1);
+
+        connection
+            .pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1)
+            .expect("bump version");
+        drop(connection);
+        assert!(open(path.as_path()).is_err());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,214 @@
+//! Minimal date parsing and formatting for timestamps in UTC epoch seconds.
+
+pub fn parse_rfc2822(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let value = match value.split_once(',') {
+        Some((_, rest)) => rest.trim(),
+        None => value,
+    };
+
+    let mut parts = value.split_whitespace();
+    let day = parts.next()?.parse::<u32>().ok()?;
+    let month = month_from_name(parts.next()?)?;
+    let year = match parts.next()?.parse::<i64>().ok()? {
+        year @ 0..=49 => 2000 + year,
+        year @ 50..=99 => 1900 + year,
+        year => year,
+    };
+
+    let mut time = parts.next()?.split(':');
+    let hour = time.next()?.parse::<i64>().ok()?;
+    let minute = time.next()?.parse::<i64>().ok()?;
+    let second = match time.next() {
+        Some(second) => second.parse::<i64>().ok()?,
+        None => 0,
+    };
+    let offset = match parts.next() {
+        Some(zone) => zone_offset_seconds(zone)?,
+        None => 0,
+    };
+
+    to_epoch(year, month, day, hour, minute, second, offset)
+}
+
+pub fn parse_rfc3339(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+
+    let mut date_parts = date.split('-');
+    let year = date_parts.next()?.parse::<i64>().ok()?;
+    let month = date_parts.next()?.parse::<u32>().ok()?;
+    let day = date_parts.next()?.parse::<u32>().ok()?;
+
+    let (clock, offset) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else {
+        let split_at = time.rfind(['+', '-'])?;
+        let (clock, zone) = time.split_at(split_at);
+        (clock, zone_offset_seconds(zone)?)
+    };
+
+    let mut clock_parts = clock.split(':');
+    let hour = clock_parts.next()?.parse::<i64>().ok()?;
+    let minute = clock_parts.next()?.parse::<i64>().ok()?;
+    let second = match clock_parts.next() {
+        Some(second) => second.split('.').next()?.parse::<i64>().ok()?,
+        None => 0,
+    };
+
+    to_epoch(year, month, day, hour, minute, second, offset)
+}
+
+/// Formats epoch seconds as `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_rfc3339(epoch_seconds: u64) -> String {
+    let days = (epoch_seconds / 86_400) as i64;
+    let seconds = epoch_seconds % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3_600,
+        seconds % 3_600 / 60,
+        seconds % 60
+    )
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn to_epoch(
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    offset_seconds: i64,
+) -> Option<u64> {
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return None;
+    }
+
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second
+        - offset_seconds;
+    u64::try_from(seconds).ok()
+}
+
+fn month_from_name(name: &str) -> Option<u32> {
+    let month = match name.get(..3)?.to_ascii_lowercase().as_str() {
+        "jan" => 1,
+        "feb" => 2,
+        "mar" => 3,
+        "apr" => 4,
+        "may" => 5,
+        "jun" => 6,
+        "jul" => 7,
+        "aug" => 8,
+        "sep" => 9,
+        "oct" => 10,
+        "nov" => 11,
+        "dec" => 12,
+        _ => return None,
+    };
+    Some(month)
+}
+
+fn zone_offset_seconds(zone: &str) -> Option<i64> {
+    let hours = match zone.to_ascii_uppercase().as_str() {
+        "GMT" | "UT" | "UTC" | "Z" => 0,
+        "EDT" => -4,
+        "EST" | "CDT" => -5,
+        "CST" | "MDT" => -6,
+        "MST" | "PDT" => -7,
+        "PST" => -8,
+        _ => {
+            let sign = match zone.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let digits = zone[1..].replace(':', "");
+            if digits.len() != 4 || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            let hours = digits[..2].parse::<i64>().ok()?;
+            let minutes = digits[2..].parse::<i64>().ok()?;
+            return Some(sign * (hours * 3_600 + minutes * 60));
+        }
+    };
+    Some(hours * 3_600)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_rfc3339, parse_rfc2822, parse_rfc3339};
+
+    #[test]
+    fn parses_rfc2822_with_offsets_and_zone_names() {
+        assert_eq!(
+            parse_rfc2822("Tue, 03 Sep 2024 18:30:00 +0200"),
+            Some(1_725_381_000)
+        );
+        assert_eq!(
+            parse_rfc2822("20 Aug 2024 18:30:00 GMT"),
+            Some(1_724_178_600)
+        );
+        assert_eq!(
+            parse_rfc2822("Tue, 20 Aug 2024 11:30 PDT"),
+            Some(1_724_178_600)
+        );
+        assert_eq!(parse_rfc2822("sometime last week"), None);
+    }
+
+    #[test]
+    fn parses_rfc3339_with_fractions_and_offsets() {
+        assert_eq!(parse_rfc3339("2024-05-01T06:15:00Z"), Some(1_714_544_100));
+        assert_eq!(
+            parse_rfc3339("2024-05-01T07:15:00.250+01:00"),
+            Some(1_714_544_100)
+        );
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_rfc3339("2024-13-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn formats_rfc3339_round_trip() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(1_714_544_100), "2024-05-01T06:15:00Z");
+        assert_eq!(format_rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+        for epoch in [1_709_164_799, 4_102_444_800] {
+            assert_eq!(parse_rfc3339(format_rfc3339(epoch).as_str()), Some(epoch));
+        }
+    }
+}
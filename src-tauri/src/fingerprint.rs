@@ -0,0 +1,502 @@
+//! Landmark fingerprints (spectral peak pairs) for recognizing tracks the
+//! user has already identified, without a network service.
+
+use crate::shazam::RecognizedTrack;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FINGERPRINT_SAMPLE_RATE: u32 = 11_025;
+const FRAME_SIZE: usize = 1024;
+const HOP_SIZE: usize = 512;
+const PEAK_BANDS: [(usize, usize); 6] = [
+    (8, 16),
+    (16, 32),
+    (32, 64),
+    (64, 128),
+    (128, 256),
+    (256, 512),
+];
+const PEAK_MIN_MAGNITUDE: f32 = 1e-3;
+const PEAK_BAND_RATIO: f32 = 2.0;
+const FAN_OUT: usize = 4;
+const MAX_PAIR_FRAMES: u32 = 48;
+const MIN_ALIGNED_HASHES: usize = 12;
+const MAX_HASHES_PER_TRACK: usize = 8_000;
+const FINGERPRINT_TRACK_LIMIT: usize = 200;
+
+/// One landmark: a hash of two peaks and their distance, plus the frame the
+/// first peak was found in. Serialized as a `[hash, frame]` pair.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Landmark(pub u32, pub u32);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StoredFingerprint {
+    track: RecognizedTrack,
+    landmarks: Vec<Landmark>,
+}
+
+pub struct FingerprintStore {
+    path: PathBuf,
+    entries: Vec<StoredFingerprint>,
+    index: HashMap<u32, Vec<(usize, u32)>>,
+}
+
+impl FingerprintStore {
+    pub fn open(path: PathBuf) -> Self {
+        let entries = load_fingerprints(path.as_path()).unwrap_or_else(|error| {
+            eprintln!("[fingerprint] unable to load fingerprints, starting empty: {error}");
+            Vec::new()
+        });
+        let mut store = Self {
+            path,
+            entries,
+            index: HashMap::new(),
+        };
+        store.rebuild_index();
+        store
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Stores landmarks for `track`, merging them into any earlier
+    /// fingerprint of the same track so more of it becomes matchable.
+    pub fn remember(
+        &mut self,
+        track: &RecognizedTrack,
+        landmarks: Vec<Landmark>,
+    ) -> Result<(), String> {
+        if landmarks.is_empty() {
+            return Ok(());
+        }
+
+        let mut next = self.entries.clone();
+        let key = track_key(track);
+        let mut entry = match next.iter().position(|entry| track_key(&entry.track) == key) {
+            Some(position) => next.remove(position),
+            None => StoredFingerprint {
+                track: track.clone(),
+                landmarks: Vec::new(),
+            },
+        };
+        entry.track = track.clone();
+        let offset = entry
+            .landmarks
+            .iter()
+            .map(|landmark| landmark.1 + MAX_PAIR_FRAMES + 1)
+            .max()
+            .unwrap_or(0);
+        entry.landmarks.extend(
+            landmarks
+                .into_iter()
+                .map(|landmark| Landmark(landmark.0, landmark.1 + offset)),
+        );
+        if entry.landmarks.len() > MAX_HASHES_PER_TRACK {
+            let excess = entry.landmarks.len() - MAX_HASHES_PER_TRACK;
+            entry.landmarks.drain(..excess);
+        }
+        next.insert(0, entry);
+        next.truncate(FINGERPRINT_TRACK_LIMIT);
+
+        persist_fingerprints(self.path.as_path(), next.as_slice())?;
+        self.entries = next;
+        self.rebuild_index();
+        Ok(())
+    }
+
+    /// Returns the stored track whose landmarks line up best with `landmarks`,
+    /// along with how many of them agreed on a single time offset.
+    pub fn best_match(&self, landmarks: &[Landmark]) -> Option<(RecognizedTrack, usize)> {
+        let mut votes = HashMap::<(usize, i64), usize>::new();
+        for landmark in landmarks {
+            let Some(candidates) = self.index.get(&landmark.0) else {
+                continue;
+            };
+            for (entry, frame) in candidates {
+                let offset = *frame as i64 - landmark.1 as i64;
+                *votes.entry((*entry, offset)).or_default() += 1;
+            }
+        }
+
+        votes
+            .into_iter()
+            .filter(|(_, count)| *count >= MIN_ALIGNED_HASHES)
+            .max_by_key(|((entry, _), count)| (*count, std::cmp::Reverse(*entry)))
+            .map(|((entry, _), count)| (self.entries[entry].track.clone(), count))
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index.clear();
+        for (position, entry) in self.entries.iter().enumerate() {
+            for landmark in entry.landmarks.iter() {
+                self.index
+                    .entry(landmark.0)
+                    .or_default()
+                    .push((position, landmark.1));
+            }
+        }
+    }
+}
+
+/// Fingerprints mono PCM at any sample rate.
+pub fn fingerprint(mono: &[f32], sample_rate: u32) -> Vec<Landmark> {
+    let samples = resample_linear(mono, sample_rate, FINGERPRINT_SAMPLE_RATE);
+    let peaks = spectral_peaks(samples.as_slice());
+
+    let mut landmarks = Vec::new();
+    for (index, (anchor_frame, anchor_bin)) in peaks.iter().enumerate() {
+        let targets = peaks[index + 1..]
+            .iter()
+            .filter(|(frame, _)| *frame > *anchor_frame)
+            .take_while(|(frame, _)| frame - anchor_frame <= MAX_PAIR_FRAMES)
+            .take(FAN_OUT);
+        for (target_frame, target_bin) in targets {
+            if target_bin == anchor_bin {
+                continue;
+            }
+            let delta = target_frame - anchor_frame;
+            let hash = (*anchor_bin << 20) | (*target_bin << 10) | delta;
+            landmarks.push(Landmark(hash, *anchor_frame));
+        }
+    }
+    landmarks
+}
+
+pub(crate) fn resample_linear(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() || from_rate == 0 {
+        return samples.to_vec();
+    }
+
+    let step = from_rate as f64 / to_rate as f64;
+    let length = (samples.len() as f64 / step).floor() as usize;
+    (0..length)
+        .map(|index| {
+            // Average the span each output sample covers so downsampling
+            // does not alias high frequencies into the peak bands.
+            let start = index as f64 * step;
+            let end = ((index + 1) as f64 * step).min(samples.len() as f64);
+            let first = start.floor() as usize;
+            let last = (end.ceil() as usize).clamp(first + 1, samples.len());
+            samples[first..last].iter().sum::<f32>() / (last - first) as f32
+        })
+        .collect()
+}
+
+/// Picks the strongest bin of each band per frame where it first appears,
+/// in time order.
+fn spectral_peaks(samples: &[f32]) -> Vec<(u32, u32)> {
+    if samples.len() < FRAME_SIZE {
+        return Vec::new();
+    }
+
+    let window = (0..FRAME_SIZE)
+        .map(|index| 0.5 - 0.5 * (2.0 * PI * index as f32 / FRAME_SIZE as f32).cos())
+        .collect::<Vec<f32>>();
+    let mut real = vec![0.0_f32; FRAME_SIZE];
+    let mut imag = vec![0.0_f32; FRAME_SIZE];
+    let mut magnitudes = vec![0.0_f32; FRAME_SIZE / 2];
+    let mut peaks = Vec::new();
+    let mut previous_bins = Vec::new();
+    let mut current_bins = Vec::new();
+
+    for (frame, start) in (0..=samples.len() - FRAME_SIZE)
+        .step_by(HOP_SIZE)
+        .enumerate()
+    {
+        for index in 0..FRAME_SIZE {
+            real[index] = samples[start + index] * window[index];
+            imag[index] = 0.0;
+        }
+        fft_in_place(real.as_mut_slice(), imag.as_mut_slice());
+        for (bin, magnitude) in magnitudes.iter_mut().enumerate() {
+            *magnitude = (real[bin] * real[bin] + imag[bin] * imag[bin]).sqrt() / FRAME_SIZE as f32;
+        }
+
+        for (low, high) in PEAK_BANDS {
+            let band = &magnitudes[low..high];
+            let mean = band.iter().sum::<f32>() / band.len() as f32;
+            let Some((offset, peak)) = band
+                .iter()
+                .enumerate()
+                .max_by(|lhs, rhs| lhs.1.total_cmp(rhs.1))
+            else {
+                continue;
+            };
+            if *peak < PEAK_MIN_MAGNITUDE || *peak < mean * PEAK_BAND_RATIO {
+                continue;
+            }
+
+            // A partial that was already a peak in the previous frame is a
+            // sustained sound, not a new landmark.
+            let bin = (low + offset) as u32;
+            current_bins.push(bin);
+            if !previous_bins
+                .iter()
+                .any(|previous: &u32| previous.abs_diff(bin) <= 1)
+            {
+                peaks.push((frame as u32, bin));
+            }
+        }
+        std::mem::swap(&mut previous_bins, &mut current_bins);
+        current_bins.clear();
+    }
+    peaks
+}
+
+/// Iterative radix-2 FFT; `real.len()` must be a power of two.
+pub(crate) fn fft_in_place(real: &mut [f32], imag: &mut [f32]) {
+    let n = real.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imag.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        let (w_imag, w_real) = angle.sin_cos();
+        for start in (0..n).step_by(length) {
+            let (mut cur_real, mut cur_imag) = (1.0_f32, 0.0_f32);
+            for offset in 0..length / 2 {
+                let a = start + offset;
+                let b = a + length / 2;
+                let t_real = real[b] * cur_real - imag[b] * cur_imag;
+                let t_imag = real[b] * cur_imag + imag[b] * cur_real;
+                real[b] = real[a] - t_real;
+                imag[b] = imag[a] - t_imag;
+                real[a] += t_real;
+                imag[a] += t_imag;
+                let next_real = cur_real * w_real - cur_imag * w_imag;
+                cur_imag = cur_real * w_imag + cur_imag * w_real;
+                cur_real = next_real;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+fn track_key(track: &RecognizedTrack) -> String {
+    match (
+        track.shazam_id.as_ref(),
+        track.musicbrainz_recording_id.as_ref(),
+    ) {
+        (Some(id), _) => format!("id:{id}"),
+        (None, Some(id)) => format!("mbid:{id}"),
+        (None, None) => format!(
+            "{}\n{}",
+            track.title.to_lowercase(),
+            track.artist.as_deref().unwrap_or_default().to_lowercase()
+        ),
+    }
+}
+
+fn load_fingerprints(path: &Path) -> Result<Vec<StoredFingerprint>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(format!(
+                "unable to read fingerprints from {}: {error}",
+                path.display()
+            ))
+        }
+    };
+
+    serde_json::from_str::<Vec<StoredFingerprint>>(contents.as_str()).map_err(|error| {
+        format!(
+            "unable to parse fingerprints from {}: {error}",
+            path.display()
+        )
+    })
+}
+
+fn persist_fingerprints(path: &Path, entries: &[StoredFingerprint]) -> Result<(), String> {
+    let bytes = serde_json::to_vec(entries)
+        .map_err(|error| format!("unable to serialize fingerprints: {error}"))?;
+    fs::write(path, bytes).map_err(|error| {
+        format!(
+            "unable to write fingerprints to {}: {error}",
+            path.display()
+        )
+    })
+}
+
+#[cfg(test)]
+pub mod test_audio {
+    /// A deterministic "song": a seeded sequence of three-note chords with
+    /// varying lengths, as interleaved stereo at 44.1 kHz.
+    pub fn synthesized_song(seed: u64, seconds: f32) -> Vec<f32> {
+        const SAMPLE_RATE: f32 = 44_100.0;
+        let mut state = seed;
+        let mut next = move || {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            (state >> 33) as u32
+        };
+
+        let total = (seconds * SAMPLE_RATE) as usize;
+        let mut samples = Vec::with_capacity(total * 2);
+        while samples.len() < total * 2 {
+            let length = (SAMPLE_RATE * (0.15 + (next() % 26) as f32 / 100.0)) as usize;
+            let chord = [next(), next(), next()]
+                .map(|value| 82.4 * 2.0_f32.powf((value % 48) as f32 / 12.0));
+            for index in 0..length {
+                let time = index as f32 / SAMPLE_RATE;
+                let value = chord
+                    .iter()
+                    .map(|frequency| (2.0 * std::f32::consts::PI * frequency * time).sin())
+                    .sum::<f32>()
+                    * 0.2;
+                samples.push(value);
+                samples.push(value);
+            }
+        }
+        samples.truncate(total * 2);
+        samples
+    }
+
+    /// Adds seeded white noise at the given amplitude.
+    pub fn with_noise(samples: &[f32], seed: u64, amplitude: f32) -> Vec<f32> {
+        let mut state = seed;
+        samples
+            .iter()
+            .map(|sample| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                let noise = ((state >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0;
+                sample + noise * amplitude
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_audio::{synthesized_song, with_noise};
+    use super::{fingerprint, FingerprintStore};
+    use crate::audio_samples::downmix_mono;
+    use crate::shazam::RecognizedTrack;
+    use crate::test_support::test_dir;
+    use std::fs;
+
+    fn track(id: &str, title: &str) -> RecognizedTrack {
+        RecognizedTrack {
+            shazam_id: Some(id.to_string()),
+            title: title.to_string(),
+            artist: Some("Artist".to_string()),
+            artwork_url: None,
+            apple_music_url: None,
+            web_url: None,
+            recognized_at: 1,
+            source_title: None,
+            source_artist: None,
+            musicbrainz_recording_id: None,
+            acoustid: None,
+        }
+    }
+
+    fn clip(stereo: &[f32], from_seconds: f32, to_seconds: f32) -> Vec<f32> {
+        let start = (from_seconds * 44_100.0) as usize * 2;
+        let end = (to_seconds * 44_100.0) as usize * 2;
+        downmix_mono(&stereo[start..end], 2).collect()
+    }
+
+    #[test]
+    fn matches_a_noisy_unaligned_excerpt_of_a_remembered_track() {
+        let dir = test_dir("match");
+        let song = synthesized_song(7, 30.0);
+        let other = synthesized_song(99, 30.0);
+
+        let mut store = FingerprintStore::open(dir.join("fingerprints.json"));
+        store
+            .remember(
+                &track("1", "Song"),
+                fingerprint(&clip(&song, 2.0, 16.0), 44_100),
+            )
+            .expect("remember should succeed");
+        store
+            .remember(
+                &track("2", "Other"),
+                fingerprint(&clip(&other, 2.0, 16.0), 44_100),
+            )
+            .expect("remember should succeed");
+
+        let noisy = with_noise(&song, 3, 0.05);
+        let query = fingerprint(&clip(&noisy, 6.013, 14.0), 44_100);
+        let (matched, score) = store.best_match(&query).expect("expected a local match");
+        assert_eq!(matched.title, "Song");
+        assert!(score >= 12, "score was {score}");
+
+        let reloaded = FingerprintStore::open(dir.join("fingerprints.json"));
+        assert_eq!(
+            reloaded.best_match(&query).map(|(track, _)| track.title),
+            Some("Song".to_string())
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn does_not_match_unknown_audio_or_silence() {
+        let dir = test_dir("no-match");
+        let song = synthesized_song(7, 20.0);
+        let mut store = FingerprintStore::open(dir.join("fingerprints.json"));
+        store
+            .remember(
+                &track("1", "Song"),
+                fingerprint(&clip(&song, 0.0, 15.0), 44_100),
+            )
+            .expect("remember should succeed");
+
+        let unknown = synthesized_song(1234, 12.0);
+        assert!(store
+            .best_match(&fingerprint(&clip(&unknown, 0.0, 10.0), 44_100))
+            .is_none());
+        assert!(fingerprint(&vec![0.0; 44_100 * 5], 44_100).is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn remembering_a_track_again_extends_its_fingerprint() {
+        let dir = test_dir("merge");
+        let song = synthesized_song(11, 40.0);
+        let mut store = FingerprintStore::open(dir.join("fingerprints.json"));
+        store
+            .remember(
+                &track("1", "Song"),
+                fingerprint(&clip(&song, 0.0, 10.0), 44_100),
+            )
+            .expect("remember should succeed");
+
+        let later = fingerprint(&clip(&song, 25.0, 35.0), 44_100);
+        assert!(store.best_match(&later).is_none());
+
+        store
+            .remember(
+                &track("1", "Song"),
+                fingerprint(&clip(&song, 22.0, 38.0), 44_100),
+            )
+            .expect("remember should succeed");
+        assert_eq!(store.entries.len(), 1);
+        assert!(store.best_match(&later).is_some());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,890 @@
+//! The user's recognition history, with their own favorites, tags and notes.
+
+use crate::database;
+use crate::shazam::{
+    is_duplicate, RecognizedTrack, DUPLICATE_CHECK_DEPTH, DUPLICATE_WINDOW_SECONDS,
+};
+use rusqlite::types::{Type, Value as SqlValue};
+use rusqlite::{params, params_from_iter, Connection, Params, Row, Transaction};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Newest version of the JSON history files the app used to keep; imports
+/// still read them. Version 0 was a bare array of tracks; version 1 wraps
+/// entries that carry an id and the user's annotations.
+const HISTORY_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: String,
+    #[serde(flatten)]
+    pub track: RecognizedTrack,
+    /// The show that was on air, when the source knew it.
+    #[serde(default)]
+    pub show_alias: Option<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// Fields left out are unchanged; an empty note removes it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryEntryUpdate {
+    pub favorite: Option<bool>,
+    pub tags: Option<Vec<String>>,
+    pub note: Option<String>,
+}
+
+/// Every field set narrows the results. Dates are epoch seconds, inclusive.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryQuery {
+    pub text: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub show: Option<String>,
+    pub tag: Option<String>,
+    pub favorites_only: bool,
+}
+
+impl HistoryEntry {
+    fn new(track: RecognizedTrack, show_alias: Option<String>) -> Self {
+        Self {
+            id: generate_entry_id(),
+            track,
+            show_alias,
+            favorite: false,
+            tags: Vec::new(),
+            note: None,
+        }
+    }
+
+    /// Takes on favorites, tags and a note from another copy of the same
+    /// recognition, returning whether anything changed.
+    fn merge_annotations(&mut self, other: &HistoryEntry) -> bool {
+        let before = (self.favorite, self.tags.len(), self.note.is_some());
+        self.favorite |= other.favorite;
+        self.tags = normalize_tags(self.tags.iter().chain(other.tags.iter()).cloned().collect());
+        if self.note.is_none() {
+            self.note = other.note.clone();
+        }
+        before != (self.favorite, self.tags.len(), self.note.is_some())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryImportSummary {
+    pub added: usize,
+    /// Already in the history, but brought favorites, tags or a note along.
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryFile {
+    version: u32,
+    entries: Vec<HistoryEntry>,
+}
+
+/// Columns read back into a `HistoryEntry`, in the order `entry_from_row`
+/// expects.
+const ENTRY_COLUMNS: &str = "id, recognized_at, title, artist, artwork_url, apple_music_url, \
+     web_url, source_title, source_artist, shazam_id, musicbrainz_recording_id, acoustid, \
+     show_alias, favorite, tags, note";
+/// Newest first; entries recognized in the same second keep the order they
+/// were added in.
+const NEWEST_FIRST: &str = "ORDER BY recognized_at DESC, rowid DESC";
+
+pub struct HistoryStore {
+    connection: Connection,
+    /// `None` keeps everything.
+    limit: Option<usize>,
+}
+
+impl HistoryStore {
+    /// Opens the history in the database at `path`. The first time, entries
+    /// are moved over from the JSON history at `legacy_path`, which is then
+    /// renamed out of the way.
+    pub fn open(path: &Path, legacy_path: &Path) -> Result<Self, String> {
+        let mut store = Self {
+            connection: database::open(path)?,
+            limit: None,
+        };
+        if let Err(error) = store.migrate_legacy(legacy_path) {
+            eprintln!("[history] unable to move history into the database: {error}");
+        }
+        Ok(store)
+    }
+
+    /// Newest first.
+    pub fn entries(&self) -> Result<Vec<HistoryEntry>, String> {
+        select_entries(&self.connection, NEWEST_FIRST, [])
+    }
+
+    pub fn search(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, String> {
+        let mut conditions = Vec::new();
+        let mut params = Vec::<SqlValue>::new();
+        if query.favorites_only {
+            conditions.push("favorite = 1");
+        }
+        if let Some(from) = query.from {
+            conditions.push("recognized_at >= ?");
+            params.push(SqlValue::Integer(sql_integer(from)));
+        }
+        if let Some(to) = query.to {
+            conditions.push("recognized_at <= ?");
+            params.push(SqlValue::Integer(sql_integer(to)));
+        }
+        if let Some(tag) = non_empty(query.tag.as_deref()) {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM json_each(history.tags) \
+                 WHERE json_each.value = ? COLLATE NOCASE)",
+            );
+            params.push(SqlValue::Text(tag.to_string()));
+        }
+        if let Some(show) = non_empty(query.show.as_deref()) {
+            conditions.push(
+                "(show_alias = ? COLLATE NOCASE \
+                 OR source_title LIKE ? ESCAPE '\\' OR source_artist LIKE ? ESCAPE '\\')",
+            );
+            params.push(SqlValue::Text(show.to_string()));
+            params.extend([like_pattern(show), like_pattern(show)]);
+        }
+        if let Some(text) = non_empty(query.text.as_deref()) {
+            conditions.push(
+                "(title LIKE ? ESCAPE '\\' OR artist LIKE ? ESCAPE '\\' \
+                 OR source_title LIKE ? ESCAPE '\\' OR source_artist LIKE ? ESCAPE '\\' \
+                 OR note LIKE ? ESCAPE '\\' OR EXISTS (SELECT 1 FROM json_each(history.tags) \
+                 WHERE json_each.value LIKE ? ESCAPE '\\'))",
+            );
+            params.extend(std::iter::repeat_n(like_pattern(text), 6));
+        }
+
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        select_entries(
+            &self.connection,
+            format!("{filter} {NEWEST_FIRST}").as_str(),
+            params_from_iter(params),
+        )
+    }
+
+    /// Drops the oldest entries beyond `limit` straight away.
+    pub fn set_limit(&mut self, limit: Option<usize>) -> Result<(), String> {
+        validate_limit(limit)?;
+        apply_limit(&self.connection, limit)?;
+        self.limit = limit;
+        Ok(())
+    }
+
+    /// Returns the new entry, or `None` if the track was just recognized.
+    pub fn add(
+        &mut self,
+        track: RecognizedTrack,
+        show_alias: Option<String>,
+    ) -> Result<Option<HistoryEntry>, String> {
+        let recent = select_entries(
+            &self.connection,
+            format!("{NEWEST_FIRST} LIMIT {DUPLICATE_CHECK_DEPTH}").as_str(),
+            [],
+        )?;
+        if is_duplicate(recent.iter().map(|entry| &entry.track), &track) {
+            return Ok(None);
+        }
+
+        let entry = HistoryEntry::new(track, show_alias);
+        let limit = self.limit;
+        let transaction = self.transaction()?;
+        insert_entry(&transaction, &entry)?;
+        apply_limit(&transaction, limit)?;
+        commit(transaction)?;
+        Ok(Some(entry))
+    }
+
+    /// Merges entries from another history. Copies of a recognition already
+    /// here (the same entry, or the same track heard within the duplicate
+    /// window) are skipped, keeping any favorite, tags or note they carry.
+    /// Nothing is written unless the whole import succeeds.
+    pub fn import(&mut self, imported: Vec<HistoryEntry>) -> Result<HistoryImportSummary, String> {
+        let limit = self.limit;
+        let transaction = self.transaction()?;
+        let mut summary = HistoryImportSummary::default();
+        for mut entry in imported {
+            match find_copy(&transaction, &entry)? {
+                Some(mut existing) => {
+                    if existing.merge_annotations(&entry) {
+                        update_annotations(&transaction, &existing)?;
+                        summary.updated += 1;
+                    } else {
+                        summary.skipped += 1;
+                    }
+                }
+                None => {
+                    if entry.id.trim().is_empty() {
+                        entry.id = generate_entry_id();
+                    }
+                    entry.tags = normalize_tags(entry.tags);
+                    insert_entry(&transaction, &entry)?;
+                    summary.added += 1;
+                }
+            }
+        }
+        apply_limit(&transaction, limit)?;
+        commit(transaction)?;
+        Ok(summary)
+    }
+
+    pub fn update(&mut self, id: &str, update: HistoryEntryUpdate) -> Result<HistoryEntry, String> {
+        let mut entry = select_entries(&self.connection, "WHERE id = ?1", [id])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("History entry {id} not found."))?;
+        if let Some(favorite) = update.favorite {
+            entry.favorite = favorite;
+        }
+        if let Some(tags) = update.tags {
+            entry.tags = normalize_tags(tags);
+        }
+        if let Some(note) = update.note {
+            entry.note = non_empty(Some(note.as_str())).map(str::to_string);
+        }
+
+        update_annotations(&self.connection, &entry)?;
+        Ok(entry)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<bool, String> {
+        let removed = self
+            .connection
+            .execute("DELETE FROM history WHERE id = ?1", [id])
+            .map_err(|error| format!("unable to delete history entry {id}: {error}"))?;
+        Ok(removed > 0)
+    }
+
+    pub fn clear(&mut self) -> Result<(), String> {
+        self.connection
+            .execute("DELETE FROM history", [])
+            .map_err(|error| format!("unable to clear Shazam history: {error}"))?;
+        Ok(())
+    }
+
+    fn transaction(&mut self) -> Result<Transaction<'_>, String> {
+        self.connection
+            .transaction()
+            .map_err(|error| format!("unable to start history transaction: {error}"))
+    }
+
+    fn migrate_legacy(&mut self, legacy_path: &Path) -> Result<(), String> {
+        let contents = match fs::read_to_string(legacy_path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => {
+                return Err(format!(
+                    "unable to read Shazam history from {}: {error}",
+                    legacy_path.display()
+                ))
+            }
+        };
+        let (entries, version) = parse_history(contents.as_str()).map_err(|error| {
+            format!(
+                "unable to parse Shazam history from {}: {error}",
+                legacy_path.display()
+            )
+        })?;
+        let summary = self.import(entries)?;
+
+        // Kept rather than deleted, in case anything went missing on the way.
+        let migrated = legacy_path.with_extension(format!("v{version}.migrated.json"));
+        fs::rename(legacy_path, migrated.as_path()).map_err(|error| {
+            format!(
+                "unable to move {} to {}: {error}",
+                legacy_path.display(),
+                migrated.display()
+            )
+        })?;
+        eprintln!(
+            "[history] moved {} entries from {} into the database",
+            summary.added,
+            legacy_path.display()
+        );
+        Ok(())
+    }
+}
+
+fn select_entries(
+    connection: &Connection,
+    clauses: &str,
+    params: impl Params,
+) -> Result<Vec<HistoryEntry>, String> {
+    let mut statement = connection
+        .prepare_cached(format!("SELECT {ENTRY_COLUMNS} FROM history {clauses}").as_str())
+        .map_err(|error| format!("unable to query Shazam history: {error}"))?;
+    let entries = statement
+        .query_map(params, entry_from_row)
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .map_err(|error| format!("unable to read Shazam history: {error}"))?;
+    Ok(entries)
+}
+
+fn entry_from_row(row: &Row<'_>) -> rusqlite::Result<HistoryEntry> {
+    let tags = row.get::<_, String>(14)?;
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        track: RecognizedTrack {
+            shazam_id: row.get(9)?,
+            title: row.get(2)?,
+            artist: row.get(3)?,
+            artwork_url: row.get(4)?,
+            apple_music_url: row.get(5)?,
+            web_url: row.get(6)?,
+            recognized_at: row.get(1)?,
+            source_title: row.get(7)?,
+            source_artist: row.get(8)?,
+            musicbrainz_recording_id: row.get(10)?,
+            acoustid: row.get(11)?,
+        },
+        show_alias: row.get(12)?,
+        favorite: row.get(13)?,
+        tags: serde_json::from_str(tags.as_str()).map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(14, Type::Text, Box::new(error))
+        })?,
+        note: row.get(15)?,
+    })
+}
+
+fn insert_entry(connection: &Connection, entry: &HistoryEntry) -> Result<(), String> {
+    let track = &entry.track;
+    connection
+        .prepare_cached(
+            format!(
+                "INSERT INTO history ({ENTRY_COLUMNS}) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)"
+            )
+            .as_str(),
+        )
+        .and_then(|mut statement| {
+            statement.execute(params![
+                entry.id,
+                sql_integer(track.recognized_at),
+                track.title,
+                track.artist,
+                track.artwork_url,
+                track.apple_music_url,
+                track.web_url,
+                track.source_title,
+                track.source_artist,
+                track.shazam_id,
+                track.musicbrainz_recording_id,
+                track.acoustid,
+                entry.show_alias,
+                entry.favorite,
+                tags_json(entry.tags.as_slice()),
+                entry.note,
+            ])
+        })
+        .map_err(|error| format!("unable to save history entry {}: {error}", entry.id))?;
+    Ok(())
+}
+
+fn update_annotations(connection: &Connection, entry: &HistoryEntry) -> Result<(), String> {
+    connection
+        .execute(
+            "UPDATE history SET favorite = ?2, tags = ?3, note = ?4 WHERE id = ?1",
+            params![
+                entry.id,
+                entry.favorite,
+                tags_json(entry.tags.as_slice()),
+                entry.note
+            ],
+        )
+        .map_err(|error| format!("unable to save history entry {}: {error}", entry.id))?;
+    Ok(())
+}
+
+/// The entry already in the history that `entry` is a copy of, if any.
+fn find_copy(
+    connection: &Connection,
+    entry: &HistoryEntry,
+) -> Result<Option<HistoryEntry>, String> {
+    let recognized_at = entry.track.recognized_at;
+    let candidates = select_entries(
+        connection,
+        "WHERE id = ?1 OR recognized_at BETWEEN ?2 AND ?3",
+        params![
+            entry.id,
+            sql_integer(recognized_at.saturating_sub(DUPLICATE_WINDOW_SECONDS)),
+            sql_integer(recognized_at.saturating_add(DUPLICATE_WINDOW_SECONDS)),
+        ],
+    )?;
+    Ok(candidates.into_iter().find(|item| {
+        item.id == entry.id || is_duplicate(std::iter::once(&item.track), &entry.track)
+    }))
+}
+
+pub(crate) fn validate_limit(limit: Option<usize>) -> Result<(), String> {
+    if limit == Some(0) {
+        return Err("History limit must be at least 1.".to_string());
+    }
+    Ok(())
+}
+
+fn apply_limit(connection: &Connection, limit: Option<usize>) -> Result<(), String> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    connection
+        .execute(
+            format!(
+                "DELETE FROM history WHERE id NOT IN \
+                 (SELECT id FROM history {NEWEST_FIRST} LIMIT ?1)"
+            )
+            .as_str(),
+            [i64::try_from(limit).unwrap_or(i64::MAX)],
+        )
+        .map_err(|error| format!("unable to trim Shazam history: {error}"))?;
+    Ok(())
+}
+
+fn commit(transaction: Transaction<'_>) -> Result<(), String> {
+    transaction
+        .commit()
+        .map_err(|error| format!("unable to save Shazam history: {error}"))
+}
+
+/// SQLite integers are signed; epoch seconds stay far below the cutoff.
+fn sql_integer(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn tags_json(tags: &[String]) -> String {
+    serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// Matches `text` anywhere, with LIKE's wildcards taken literally.
+fn like_pattern(text: &str) -> SqlValue {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    SqlValue::Text(format!("%{escaped}%"))
+}
+
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim();
+        if !tag.is_empty() && !normalized.iter().any(|item| item.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|value| !value.is_empty())
+}
+
+fn generate_entry_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_nanos())
+        .unwrap_or(0);
+    let sequence = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("history-{nanos:x}-{sequence}")
+}
+
+/// Reads a history file of any version, returning its entries and the
+/// version it was written in. Version 0 tracks are given new ids.
+pub(crate) fn parse_history(contents: &str) -> Result<(Vec<HistoryEntry>, u32), String> {
+    let value = serde_json::from_str::<Value>(contents).map_err(|error| error.to_string())?;
+    if value.is_array() {
+        let tracks = serde_json::from_value::<Vec<RecognizedTrack>>(value)
+            .map_err(|error| error.to_string())?;
+        let entries = tracks
+            .into_iter()
+            .map(|track| HistoryEntry::new(track, None))
+            .collect();
+        return Ok((entries, 0));
+    }
+
+    let file = serde_json::from_value::<HistoryFile>(value).map_err(|error| error.to_string())?;
+    if file.version > HISTORY_VERSION {
+        return Err(format!(
+            "version {} is from a newer version of the app",
+            file.version
+        ));
+    }
+    Ok((file.entries, file.version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        HistoryEntry, HistoryEntryUpdate, HistoryImportSummary, HistoryQuery, HistoryStore,
+    };
+    use crate::shazam::RecognizedTrack;
+    use crate::test_support::test_dir;
+    use std::fs;
+    use std::path::Path;
+
+    fn open_store(dir: &Path) -> HistoryStore {
+        HistoryStore::open(
+            dir.join("marconio.sqlite3").as_path(),
+            dir.join("shazam-history.json").as_path(),
+        )
+        .expect("open history")
+    }
+
+    fn sample_track() -> RecognizedTrack {
+        RecognizedTrack {
+            shazam_id: Some("123".to_string()),
+            title: "Track".to_string(),
+            artist: Some("Artist".to_string()),
+            artwork_url: None,
+            apple_music_url: None,
+            web_url: None,
+            recognized_at: 1,
+            source_title: Some("Source".to_string()),
+            source_artist: Some("Source Artist".to_string()),
+            musicbrainz_recording_id: None,
+            acoustid: None,
+        }
+    }
+
+    fn track(id: &str, title: &str, recognized_at: u64, source_title: &str) -> RecognizedTrack {
+        RecognizedTrack {
+            shazam_id: Some(id.to_string()),
+            title: title.to_string(),
+            recognized_at,
+            source_title: Some(source_title.to_string()),
+            ..sample_track()
+        }
+    }
+
+    fn sample_entry() -> HistoryEntry {
+        HistoryEntry {
+            id: "history-1".to_string(),
+            track: sample_track(),
+            show_alias: None,
+            favorite: false,
+            tags: Vec::new(),
+            note: None,
+        }
+    }
+
+    #[test]
+    fn leaves_unreadable_json_history_in_place() {
+        let dir = test_dir("invalid-json");
+        let legacy_path = dir.join("shazam-history.json");
+        fs::write(legacy_path.as_path(), "{ invalid json")
+            .expect("failed to write invalid history file");
+
+        let store = open_store(&dir);
+        assert!(store.entries().expect("entries").is_empty());
+        assert!(legacy_path.exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn moves_json_history_into_the_database_once() {
+        let dir = test_dir("migrate");
+        let legacy_path = dir.join("shazam-history.json");
+        let legacy = serde_json::to_string(&vec![
+            track("2", "Newer", 200, "Poolside"),
+            track("1", "Older", 100, "Poolside"),
+        ])
+        .expect("serialize");
+        fs::write(legacy_path.as_path(), legacy.as_str()).expect("write legacy history");
+
+        let store = open_store(&dir);
+        let entries = store.entries().expect("entries");
+        let titles = entries
+            .iter()
+            .map(|entry| entry.track.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Newer", "Older"]);
+        assert!(entries.iter().all(|entry| !entry.id.is_empty()));
+        assert!(!legacy_path.exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("shazam-history.v0.migrated.json")).expect("backup"),
+            legacy
+        );
+        drop(store);
+
+        let reopened = open_store(&dir).entries().expect("entries");
+        let ids = |entries: &[HistoryEntry]| {
+            entries
+                .iter()
+                .map(|entry| entry.id.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&reopened), ids(&entries));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn clear_persists_an_empty_history() {
+        let dir = test_dir("clear");
+        let mut store = open_store(&dir);
+        store.add(sample_track(), None).expect("add");
+        assert_eq!(open_store(&dir).entries().expect("entries").len(), 1);
+
+        store.clear().expect("expected clear to succeed");
+        assert!(store.entries().expect("entries").is_empty());
+        assert!(open_store(&dir).entries().expect("entries").is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn clear_leaves_the_history_alone_when_it_fails() {
+        let dir = test_dir("clear-fail");
+        let mut store = open_store(&dir);
+        store.add(sample_track(), None).expect("add");
+        store
+            .connection
+            .execute_batch(
+                "CREATE TEMP TRIGGER refuse_delete BEFORE DELETE ON history \
+                 BEGIN SELECT RAISE(ABORT, 'disk is read-only'); END",
+            )
+            .expect("trigger");
+
+        assert!(store.clear().is_err());
+        assert_eq!(store.entries().expect("entries").len(), 1);
+        assert_eq!(open_store(&dir).entries().expect("entries").len(), 1);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unreadable_tags_are_an_error_not_an_empty_list() {
+        let dir = test_dir("corrupt-tags");
+        let mut store = open_store(&dir);
+        store.add(sample_track(), None).expect("add");
+        store
+            .connection
+            .execute("UPDATE history SET tags = '{not json'", [])
+            .expect("corrupt tags");
+
+        let error = store.entries().expect_err("corrupt tags should fail");
+        assert!(error.contains("unable to read Shazam history"), "{error}");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn annotates_searches_and_deletes_entries() {
+        let dir = test_dir("annotate");
+        let mut store = open_store(&dir);
+        let first = store
+            .add(
+                track("1", "Sunrise", 1_000, "Early Bird"),
+                Some("early-bird".to_string()),
+            )
+            .expect("add")
+            .expect("new entry");
+        store
+            .add(track("2", "Moonrise", 5_000, "Night Moves"), None)
+            .expect("add");
+        assert!(store
+            .add(track("2", "Moonrise", 5_010, "Night Moves"), None)
+            .expect("add")
+            .is_none());
+
+        let updated = store
+            .update(
+                first.id.as_str(),
+                HistoryEntryUpdate {
+                    favorite: Some(true),
+                    tags: Some(vec![
+                        " dub ".to_string(),
+                        "Dub".to_string(),
+                        "".to_string(),
+                        "morning".to_string(),
+                    ]),
+                    note: Some("Played twice".to_string()),
+                },
+            )
+            .expect("update");
+        assert_eq!(updated.tags, vec!["dub", "morning"]);
+        assert!(store
+            .update("missing", HistoryEntryUpdate::default())
+            .is_err());
+
+        let titles = |query: HistoryQuery| {
+            store
+                .search(&query)
+                .expect("search")
+                .into_iter()
+                .map(|entry| entry.track.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(HistoryQuery::default()), vec!["Moonrise", "Sunrise"]);
+        assert_eq!(
+            titles(HistoryQuery {
+                text: Some("TWICE".to_string()),
+                ..HistoryQuery::default()
+            }),
+            vec!["Sunrise"]
+        );
+        assert_eq!(
+            titles(HistoryQuery {
+                from: Some(2_000),
+                ..HistoryQuery::default()
+            }),
+            vec!["Moonrise"]
+        );
+        assert_eq!(
+            titles(HistoryQuery {
+                show: Some("early-bird".to_string()),
+                ..HistoryQuery::default()
+            }),
+            vec!["Sunrise"]
+        );
+        assert_eq!(
+            titles(HistoryQuery {
+                show: Some("night".to_string()),
+                to: Some(4_000),
+                ..HistoryQuery::default()
+            }),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            titles(HistoryQuery {
+                tag: Some("DUB".to_string()),
+                favorites_only: true,
+                ..HistoryQuery::default()
+            }),
+            vec!["Sunrise"]
+        );
+        assert_eq!(
+            titles(HistoryQuery {
+                text: Some("%".to_string()),
+                ..HistoryQuery::default()
+            }),
+            Vec::<String>::new()
+        );
+
+        assert!(store.remove(first.id.as_str()).expect("remove"));
+        assert!(!store.remove(first.id.as_str()).expect("remove"));
+        let reopened = open_store(&dir).entries().expect("entries");
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened[0].track.title, "Moonrise");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn import_merges_without_duplicates() {
+        let dir = test_dir("import");
+        let mut store = open_store(&dir);
+        let local = store
+            .add(track("1", "Sunrise", 1_000, "Early Bird"), None)
+            .expect("add")
+            .expect("new entry");
+        store
+            .add(track("2", "Moonrise", 9_000, "Night Moves"), None)
+            .expect("add");
+
+        let imported = |id: &str, track: RecognizedTrack| HistoryEntry {
+            id: id.to_string(),
+            track,
+            ..sample_entry()
+        };
+        let summary = store
+            .import(vec![
+                // The same entry, copied from this machine.
+                HistoryEntry {
+                    favorite: true,
+                    tags: vec!["dub".to_string()],
+                    ..imported(
+                        local.id.as_str(),
+                        track("1", "Sunrise", 1_000, "Early Bird"),
+                    )
+                },
+                // Heard on another machine a minute later.
+                imported("remote-1", track("2", "Moonrise", 9_060, "Night Moves")),
+                // The same song on another day is a new recognition.
+                imported("remote-2", track("1", "Sunrise", 5_000, "Early Bird")),
+                imported("remote-3", track("3", "Noon", 6_000, "Lunch")),
+                imported("remote-3", track("3", "Noon", 6_000, "Lunch")),
+            ])
+            .expect("import");
+        assert_eq!(
+            summary,
+            HistoryImportSummary {
+                added: 2,
+                updated: 1,
+                skipped: 2,
+            }
+        );
+
+        let reopened = open_store(&dir).entries().expect("entries");
+        let order = reopened
+            .iter()
+            .map(|entry| (entry.track.title.as_str(), entry.track.recognized_at))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![
+                ("Moonrise", 9_000),
+                ("Noon", 6_000),
+                ("Sunrise", 5_000),
+                ("Sunrise", 1_000)
+            ]
+        );
+        let merged = &reopened[3];
+        assert_eq!(merged.id, local.id);
+        assert!(merged.favorite);
+        assert_eq!(merged.tags, vec!["dub"]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn limit_keeps_the_newest_entries() {
+        let dir = test_dir("limit");
+        let mut store = open_store(&dir);
+        for index in 0..5 {
+            store
+                .add(
+                    track(&index.to_string(), "Track", index * 1_000, "Source"),
+                    None,
+                )
+                .expect("add");
+        }
+        assert_eq!(store.entries().expect("entries").len(), 5);
+        assert!(store.set_limit(Some(0)).is_err());
+
+        store.set_limit(Some(3)).expect("limit");
+        store
+            .add(track("5", "Track", 5_000, "Source"), None)
+            .expect("add");
+        let ids = store
+            .entries()
+            .expect("entries")
+            .iter()
+            .filter_map(|entry| entry.track.shazam_id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["5", "4", "3"]);
+        assert_eq!(open_store(&dir).entries().expect("entries").len(), 3);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
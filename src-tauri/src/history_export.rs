@@ -0,0 +1,414 @@
+//! Recognition history in formats other tools import. The field mapping for
+//! each format is documented in `docs/history-export.md`.
+
+use crate::datetime::format_rfc3339;
+use crate::history::HistoryEntry;
+use quick_xml::escape::escape;
+use serde::Deserialize;
+
+const PLAYLIST_TITLE: &str = "Marconio HITS";
+const SERATO_CRATE_VERSION: &str = "1.0/Serato ScratchLive Crate";
+const SERATO_COLUMNS: [&str; 3] = ["song", "artist", "comment"];
+const CSV_COLUMNS: [&str; 16] = [
+    "id",
+    "recognized_at",
+    "title",
+    "artist",
+    "source_title",
+    "source_artist",
+    "show_alias",
+    "favorite",
+    "tags",
+    "note",
+    "shazam_id",
+    "musicbrainz_recording_id",
+    "acoustid",
+    "web_url",
+    "apple_music_url",
+    "artwork_url",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HistoryExportFormat {
+    Csv,
+    JsonLines,
+    M3u,
+    Xspf,
+    RekordboxXml,
+    SeratoCrate,
+}
+
+/// Renders `entries` in the order given, newest first as the history keeps them.
+/// Binary formats are refused; use [`export_bytes`] for those.
+pub fn export(entries: &[HistoryEntry], format: HistoryExportFormat) -> Result<String, String> {
+    match format {
+        HistoryExportFormat::Csv => Ok(export_csv(entries)),
+        HistoryExportFormat::JsonLines => export_json_lines(entries),
+        HistoryExportFormat::M3u => Ok(export_m3u(entries)),
+        HistoryExportFormat::Xspf => Ok(export_xspf(entries)),
+        HistoryExportFormat::RekordboxXml => Ok(export_rekordbox_xml(entries)),
+        HistoryExportFormat::SeratoCrate => {
+            Err("a Serato crate is binary; save it to a file instead".to_string())
+        }
+    }
+}
+
+/// Renders `entries` in any format, as the bytes of the file to save.
+pub fn export_bytes(
+    entries: &[HistoryEntry],
+    format: HistoryExportFormat,
+) -> Result<Vec<u8>, String> {
+    match format {
+        HistoryExportFormat::SeratoCrate => Ok(export_serato_crate(entries)),
+        format => export(entries, format).map(String::into_bytes),
+    }
+}
+
+fn export_csv(entries: &[HistoryEntry]) -> String {
+    let mut text = CSV_COLUMNS.join(",");
+    text.push('\n');
+    for entry in entries {
+        let track = &entry.track;
+        let fields = [
+            entry.id.clone(),
+            format_rfc3339(track.recognized_at),
+            track.title.clone(),
+            track.artist.clone().unwrap_or_default(),
+            track.source_title.clone().unwrap_or_default(),
+            track.source_artist.clone().unwrap_or_default(),
+            entry.show_alias.clone().unwrap_or_default(),
+            entry.favorite.to_string(),
+            entry.tags.join(";"),
+            entry.note.clone().unwrap_or_default(),
+            track.shazam_id.clone().unwrap_or_default(),
+            track.musicbrainz_recording_id.clone().unwrap_or_default(),
+            track.acoustid.clone().unwrap_or_default(),
+            track.web_url.clone().unwrap_or_default(),
+            track.apple_music_url.clone().unwrap_or_default(),
+            track.artwork_url.clone().unwrap_or_default(),
+        ];
+        let row = fields
+            .iter()
+            .map(|field| csv_field(field))
+            .collect::<Vec<_>>();
+        text.push_str(row.join(",").as_str());
+        text.push('\n');
+    }
+    text
+}
+
+/// Quotes a field when it holds a separator, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn export_json_lines(entries: &[HistoryEntry]) -> Result<String, String> {
+    let mut text = String::new();
+    for entry in entries {
+        let line = serde_json::to_string(entry)
+            .map_err(|error| format!("unable to serialize history entry: {error}"))?;
+        text.push_str(line.as_str());
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// The link a playlist entry opens: the web page, else Apple Music.
+fn playlist_url(entry: &HistoryEntry) -> Option<&str> {
+    entry
+        .track
+        .web_url
+        .as_deref()
+        .or(entry.track.apple_music_url.as_deref())
+}
+
+fn display_name(entry: &HistoryEntry) -> String {
+    match entry.track.artist.as_deref() {
+        Some(artist) => format!("{artist} - {}", entry.track.title),
+        None => entry.track.title.clone(),
+    }
+}
+
+fn export_m3u(entries: &[HistoryEntry]) -> String {
+    let mut lines = vec!["#EXTM3U".to_string(), format!("#PLAYLIST:{PLAYLIST_TITLE}")];
+    for entry in entries {
+        // Entries without a link have nothing to play.
+        let Some(url) = playlist_url(entry) else {
+            continue;
+        };
+        lines.push(format!("#EXTINF:-1,{}", display_name(entry)));
+        lines.push(url.to_string());
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn export_xspf(entries: &[HistoryEntry]) -> String {
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+        r#"<playlist version="1" xmlns="http://xspf.org/ns/0/">"#.to_string(),
+        format!("  <title>{PLAYLIST_TITLE}</title>"),
+        "  <trackList>".to_string(),
+    ];
+    for entry in entries {
+        let track = &entry.track;
+        lines.push("    <track>".to_string());
+        for url in [track.web_url.as_deref(), track.apple_music_url.as_deref()]
+            .into_iter()
+            .flatten()
+        {
+            lines.push(format!("      <location>{}</location>", escape(url)));
+        }
+        lines.push(format!(
+            "      <title>{}</title>",
+            escape(track.title.as_str())
+        ));
+        if let Some(artist) = track.artist.as_deref() {
+            lines.push(format!("      <creator>{}</creator>", escape(artist)));
+        }
+        if let Some(note) = entry.note.as_deref() {
+            lines.push(format!("      <annotation>{}</annotation>", escape(note)));
+        }
+        if let Some(artwork_url) = track.artwork_url.as_deref() {
+            lines.push(format!("      <image>{}</image>", escape(artwork_url)));
+        }
+        lines.push("    </track>".to_string());
+    }
+    lines.push("  </trackList>".to_string());
+    lines.push("</playlist>".to_string());
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// A collection plus one playlist, in the shape of rekordbox's
+/// "Export Collection in xml format".
+fn export_rekordbox_xml(entries: &[HistoryEntry]) -> String {
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string(),
+        r#"<DJ_PLAYLISTS Version="1.0.0">"#.to_string(),
+        r#"  <PRODUCT Name="Marconio"/>"#.to_string(),
+        format!(r#"  <COLLECTION Entries="{}">"#, entries.len()),
+    ];
+    for (index, entry) in entries.iter().enumerate() {
+        let track = &entry.track;
+        // Comments are a single line in rekordbox.
+        let mut comments = entry
+            .note
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !entry.tags.is_empty() {
+            let tags = entry
+                .tags
+                .iter()
+                .map(|tag| format!("#{tag}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            comments = [comments, tags]
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+        let attributes = [
+            ("TrackID", (index + 1).to_string()),
+            ("Name", track.title.clone()),
+            ("Artist", track.artist.clone().unwrap_or_default()),
+            ("Album", String::new()),
+            ("Comments", comments),
+            (
+                "DateAdded",
+                format_rfc3339(track.recognized_at)
+                    .chars()
+                    .take(10)
+                    .collect(),
+            ),
+            // rekordbox stores five stars as 255.
+            (
+                "Rating",
+                if entry.favorite { "255" } else { "0" }.to_string(),
+            ),
+            (
+                "Location",
+                playlist_url(entry).unwrap_or_default().to_string(),
+            ),
+        ];
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| format!("{name}=\"{}\"", escape(value.as_str())))
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(format!("    <TRACK {attributes}/>"));
+    }
+    lines.push("  </COLLECTION>".to_string());
+    lines.push("  <PLAYLISTS>".to_string());
+    lines.push(r#"    <NODE Type="0" Name="ROOT" Count="1">"#.to_string());
+    lines.push(format!(
+        r#"      <NODE Name="{PLAYLIST_TITLE}" Type="1" KeyType="0" Entries="{}">"#,
+        entries.len()
+    ));
+    for index in 0..entries.len() {
+        lines.push(format!(r#"        <TRACK Key="{}"/>"#, index + 1));
+    }
+    lines.push("      </NODE>".to_string());
+    lines.push("    </NODE>".to_string());
+    lines.push("  </PLAYLISTS>".to_string());
+    lines.push("</DJ_PLAYLISTS>".to_string());
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// A crate in Serato's `Subcrates/*.crate` layout: a run of tags, each a
+/// four-letter name, a big-endian `u32` length and the payload. Text is
+/// UTF-16BE.
+fn export_serato_crate(entries: &[HistoryEntry]) -> Vec<u8> {
+    let mut bytes = serato_tag(b"vrsn", serato_text(SERATO_CRATE_VERSION).as_slice());
+
+    let mut sort = serato_tag(b"tvcn", serato_text("song").as_slice());
+    sort.extend(serato_tag(b"brev", &[0]));
+    bytes.extend(serato_tag(b"osrt", sort.as_slice()));
+    for column in SERATO_COLUMNS {
+        let mut definition = serato_tag(b"tvcn", serato_text(column).as_slice());
+        definition.extend(serato_tag(b"tvcw", serato_text("0").as_slice()));
+        bytes.extend(serato_tag(b"ovct", definition.as_slice()));
+    }
+
+    for entry in entries {
+        let track = serato_tag(b"ptrk", serato_text(serato_path(entry).as_str()).as_slice());
+        bytes.extend(serato_tag(b"otrk", track.as_slice()));
+    }
+    bytes
+}
+
+fn serato_tag(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut tag = Vec::with_capacity(8 + payload.len());
+    tag.extend_from_slice(name);
+    tag.extend_from_slice((payload.len() as u32).to_be_bytes().as_slice());
+    tag.extend_from_slice(payload);
+    tag
+}
+
+fn serato_text(value: &str) -> Vec<u8> {
+    value.encode_utf16().flat_map(u16::to_be_bytes).collect()
+}
+
+/// Crates can only point at files, so each entry becomes the file a DJ would
+/// buy: `Artist - Title.mp3` at the root of the drive. Serato lists it as
+/// missing until the file is there.
+fn serato_path(entry: &HistoryEntry) -> String {
+    let name = display_name(entry).replace(['/', '\\', ':'], "-");
+    format!("{name}.mp3")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, export_bytes, HistoryExportFormat};
+    use crate::history::HistoryEntry;
+    use crate::shazam::RecognizedTrack;
+
+    const CSV_GOLDEN: &str = include_str!("../fixtures/history_export/history.csv");
+    const JSON_LINES_GOLDEN: &str = include_str!("../fixtures/history_export/history.jsonl");
+    const M3U_GOLDEN: &str = include_str!("../fixtures/history_export/history.m3u");
+    const XSPF_GOLDEN: &str = include_str!("../fixtures/history_export/history.xspf");
+    const REKORDBOX_GOLDEN: &str = include_str!("../fixtures/history_export/rekordbox.xml");
+    const SERATO_GOLDEN: &[u8] = include_bytes!("../fixtures/history_export/serato.crate");
+
+    fn entries() -> Vec<HistoryEntry> {
+        vec![
+            HistoryEntry {
+                id: "history-2".to_string(),
+                track: RecognizedTrack {
+                    shazam_id: Some("1440857781".to_string()),
+                    title: "Rain, \"Live\" & Loud".to_string(),
+                    artist: Some("Ana <Mota>".to_string()),
+                    artwork_url: Some("https://images.example/rain.jpg".to_string()),
+                    apple_music_url: Some("https://music.apple.com/us/song/1440857781".to_string()),
+                    web_url: Some("https://www.shazam.com/track/1440857781".to_string()),
+                    recognized_at: 1_792_324_800,
+                    source_title: Some("Channel 1".to_string()),
+                    source_artist: Some("Early Bird Show".to_string()),
+                    musicbrainz_recording_id: None,
+                    acoustid: None,
+                },
+                show_alias: Some("early-bird".to_string()),
+                favorite: true,
+                tags: vec!["dub".to_string(), "to buy".to_string()],
+                note: Some("Heard twice,\nboth mornings".to_string()),
+            },
+            HistoryEntry {
+                id: "history-1".to_string(),
+                track: RecognizedTrack {
+                    shazam_id: None,
+                    title: "Untitled".to_string(),
+                    artist: None,
+                    artwork_url: None,
+                    apple_music_url: None,
+                    web_url: None,
+                    recognized_at: 1_792_238_400,
+                    source_title: None,
+                    source_artist: None,
+                    musicbrainz_recording_id: Some(
+                        "b1a9c0e9-d987-4042-ae91-78d6a3267d69".to_string(),
+                    ),
+                    acoustid: Some("9ff43b6a-4f16-427c-93c2-92307ca505e0".to_string()),
+                },
+                show_alias: None,
+                favorite: false,
+                tags: Vec::new(),
+                note: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn exports_match_golden_files() {
+        let entries = entries();
+        let render = |format| export(entries.as_slice(), format).expect("export");
+
+        assert_eq!(render(HistoryExportFormat::Csv), CSV_GOLDEN);
+        assert_eq!(render(HistoryExportFormat::JsonLines), JSON_LINES_GOLDEN);
+        assert_eq!(render(HistoryExportFormat::M3u), M3U_GOLDEN);
+        assert_eq!(render(HistoryExportFormat::Xspf), XSPF_GOLDEN);
+        assert_eq!(render(HistoryExportFormat::RekordboxXml), REKORDBOX_GOLDEN);
+        assert_eq!(
+            export_bytes(entries.as_slice(), HistoryExportFormat::SeratoCrate).expect("export"),
+            SERATO_GOLDEN
+        );
+    }
+
+    #[test]
+    fn binary_formats_are_not_rendered_as_text() {
+        assert!(export(entries().as_slice(), HistoryExportFormat::SeratoCrate).is_err());
+        assert_eq!(
+            export_bytes(entries().as_slice(), HistoryExportFormat::Csv).expect("export"),
+            CSV_GOLDEN.as_bytes()
+        );
+    }
+
+    #[test]
+    fn json_lines_round_trip_to_entries() {
+        let entries = entries();
+        let text = export(entries.as_slice(), HistoryExportFormat::JsonLines).expect("export");
+        let parsed = text
+            .lines()
+            .map(|line| serde_json::from_str::<HistoryEntry>(line).expect("entry"))
+            .collect::<Vec<_>>();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].track.title, entries[0].track.title);
+        assert_eq!(parsed[0].tags, entries[0].tags);
+        assert_eq!(parsed[1].track.acoustid, entries[1].track.acoustid);
+    }
+}
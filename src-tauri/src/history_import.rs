@@ -0,0 +1,239 @@
+//! Reads recognition history brought over from another machine: a copied
+//! `shazam-history.json` of any version, or a CSV or JSON Lines export.
+
+use crate::datetime::parse_rfc3339;
+use crate::history::{parse_history, HistoryEntry};
+use crate::shazam::RecognizedTrack;
+
+/// Entries found in `contents`, and how many records couldn't be read.
+pub fn parse_import(contents: &str) -> Result<(Vec<HistoryEntry>, usize), String> {
+    let trimmed = contents.trim_start_matches('\u{feff}').trim();
+    if trimmed.is_empty() {
+        return Ok((Vec::new(), 0));
+    }
+    if trimmed.starts_with('[') {
+        return parse_history(trimmed).map(|(entries, _)| (entries, 0));
+    }
+    if trimmed.starts_with('{') {
+        // A whole history file is one JSON document with a `version`; an
+        // export is one entry per line, which may be a single line.
+        let document = serde_json::from_str::<serde_json::Value>(trimmed).ok();
+        if document.is_some_and(|document| document.get("version").is_some()) {
+            return parse_history(trimmed).map(|(entries, _)| (entries, 0));
+        }
+        return Ok(parse_json_lines(trimmed));
+    }
+    parse_csv(trimmed)
+}
+
+fn parse_json_lines(contents: &str) -> (Vec<HistoryEntry>, usize) {
+    let mut entries = Vec::new();
+    let mut unreadable = 0;
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<HistoryEntry>(line) {
+            Ok(entry) => entries.push(entry),
+            Err(error) => {
+                eprintln!("[history] skipping unreadable import line: {error}");
+                unreadable += 1;
+            }
+        }
+    }
+    (entries, unreadable)
+}
+
+/// Reads the columns `history_export` writes, by header name; other columns
+/// are ignored and missing ones left empty.
+fn parse_csv(contents: &str) -> Result<(Vec<HistoryEntry>, usize), String> {
+    let mut records = csv_records(contents).into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| "CSV import has no header row".to_string())?;
+    let column = |name: &str| header.iter().position(|item| item.trim() == name);
+    let (Some(title_column), Some(recognized_at_column)) =
+        (column("title"), column("recognized_at"))
+    else {
+        return Err("CSV import needs title and recognized_at columns".to_string());
+    };
+    let columns = [
+        "id",
+        "artist",
+        "source_title",
+        "source_artist",
+        "show_alias",
+        "favorite",
+        "tags",
+        "note",
+        "shazam_id",
+        "musicbrainz_recording_id",
+        "acoustid",
+        "web_url",
+        "apple_music_url",
+        "artwork_url",
+    ]
+    .map(column);
+
+    let mut entries = Vec::new();
+    let mut unreadable = 0;
+    for record in records {
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        let title = field(Some(title_column));
+        let recognized_at =
+            field(Some(recognized_at_column)).and_then(|value| parse_rfc3339(&value));
+        let (Some(title), Some(recognized_at)) = (title, recognized_at) else {
+            unreadable += 1;
+            continue;
+        };
+        let [id, artist, source_title, source_artist, show_alias, favorite, tags, note, shazam_id, musicbrainz_recording_id, acoustid, web_url, apple_music_url, artwork_url] =
+            columns.map(field);
+
+        entries.push(HistoryEntry {
+            id: id.unwrap_or_default(),
+            track: RecognizedTrack {
+                shazam_id,
+                title,
+                artist,
+                artwork_url,
+                apple_music_url,
+                web_url,
+                recognized_at,
+                source_title,
+                source_artist,
+                musicbrainz_recording_id,
+                acoustid,
+            },
+            show_alias,
+            favorite: favorite.is_some_and(|value| value.eq_ignore_ascii_case("true")),
+            tags: tags
+                .map(|value| value.split(';').map(str::to_string).collect())
+                .unwrap_or_default(),
+            note,
+        });
+    }
+    Ok((entries, unreadable))
+}
+
+/// Splits RFC 4180 CSV into records, honouring quoted separators and line
+/// breaks. Blank lines are dropped.
+fn csv_records(contents: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = contents.chars().peekable();
+    while let Some(character) = chars.next() {
+        match character {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|value| !value.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+            }
+            character => field.push(character),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|value| !value.is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_import;
+    use crate::history_export::{export, HistoryExportFormat};
+
+    const CSV_EXPORT: &str = include_str!("../fixtures/history_export/history.csv");
+    const JSON_LINES_EXPORT: &str = include_str!("../fixtures/history_export/history.jsonl");
+
+    #[test]
+    fn reads_csv_exports() {
+        let (entries, unreadable) = parse_import(CSV_EXPORT).expect("parse");
+        assert_eq!(unreadable, 0);
+        assert_eq!(entries.len(), 2);
+
+        let first = &entries[0];
+        assert_eq!(first.id, "history-2");
+        assert_eq!(first.track.title, "Rain, \"Live\" & Loud");
+        assert_eq!(first.track.recognized_at, 1_792_324_800);
+        assert_eq!(first.note.as_deref(), Some("Heard twice,\nboth mornings"));
+        assert_eq!(first.tags, vec!["dub", "to buy"]);
+        assert!(first.favorite);
+        assert_eq!(first.show_alias.as_deref(), Some("early-bird"));
+
+        let second = &entries[1];
+        assert_eq!(second.track.artist, None);
+        assert_eq!(
+            second.track.acoustid.as_deref(),
+            Some("9ff43b6a-4f16-427c-93c2-92307ca505e0")
+        );
+    }
+
+    #[test]
+    fn reads_json_lines_and_history_files() {
+        let (entries, unreadable) =
+            parse_import(format!("{JSON_LINES_EXPORT}{{not json}}\n").as_str()).expect("parse");
+        assert_eq!(unreadable, 1);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tags, vec!["dub", "to buy"]);
+
+        let unversioned = r#"[{"title":"Old","recognizedAt":5}]"#;
+        let (entries, _) = parse_import(unversioned).expect("parse");
+        assert_eq!(entries.len(), 1);
+        assert!(!entries[0].id.is_empty());
+
+        let versioned = r#"{
+            "version": 1,
+            "entries": [{"id": "history-9", "title": "New", "recognizedAt": 9, "favorite": true}]
+        }"#;
+        let (entries, _) = parse_import(versioned).expect("parse");
+        assert_eq!(entries[0].id, "history-9");
+        assert!(entries[0].favorite);
+
+        assert!(parse_import(r#"{"version": 99, "entries": []}"#).is_err());
+    }
+
+    #[test]
+    fn reads_a_one_entry_json_lines_export() {
+        let (entries, _) = parse_import(JSON_LINES_EXPORT).expect("parse");
+        let text = export(&entries[..1], HistoryExportFormat::JsonLines).expect("export");
+        assert_eq!(text.lines().count(), 1);
+
+        let (imported, unreadable) = parse_import(text.as_str()).expect("parse");
+        assert_eq!(unreadable, 0);
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].id, entries[0].id);
+        assert_eq!(imported[0].note, entries[0].note);
+    }
+
+    #[test]
+    fn skips_csv_rows_without_a_title_or_date() {
+        let csv = "title,recognized_at,artist\r\n\
+                   Kept,2026-10-18T12:00:00Z,Someone\r\n\
+                   ,2026-10-18T12:00:00Z,Nobody\r\n\
+                   Undated,yesterday,Someone\r\n";
+        let (entries, unreadable) = parse_import(csv).expect("parse");
+        assert_eq!(unreadable, 2);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].track.artist.as_deref(), Some("Someone"));
+        assert!(entries[0].id.is_empty());
+
+        assert!(parse_import("name,when\nA,B\n").is_err());
+    }
+}
@@ -1,20 +1,79 @@
+mod acoustid;
 mod audio_engine;
+mod audio_fx;
+mod audio_output;
+mod audio_samples;
+mod audio_tap;
+mod chromaprint;
+mod database;
+mod datetime;
+mod fingerprint;
+mod history;
+mod history_export;
+mod history_import;
+mod listening;
+mod offline_cache;
+mod output_device;
+mod podcast;
+mod radio_browser;
+mod recognition;
+mod resampler;
+mod scrobble;
+mod settings;
 mod shazam;
+mod spectrum;
+mod station;
+#[cfg(test)]
+mod test_support;
+mod track_boundary;
+mod tracklist;
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 mod tray_icon;
+mod tuning_noise;
 
-use crate::audio_engine::{AudioFxPreset, NowPlayingMetadata, PlaybackManager};
-use crate::shazam::{RecognizedTrack, ShazamManager};
+/// What the benchmarks in `benches/` drive; not part of the app's API.
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::audio_engine::AudioFxPreset;
+    pub use crate::audio_fx::FxProcessor;
+    pub use crate::resampler::FormatConverter;
+}
+
+use crate::audio_engine::{
+    AudioFxPreset, NowPlayingMetadata, PlaybackManager, StreamError, STREAM_ERROR_EVENT,
+};
+use crate::audio_output::CrossfadeConfig;
+use crate::audio_tap::TapPoint;
+use crate::history::{HistoryEntry, HistoryEntryUpdate, HistoryImportSummary, HistoryQuery};
+use crate::history_export::HistoryExportFormat;
+use crate::listening::{ListeningLog, ListeningQuery, ListeningSession, ListeningStats};
+use crate::offline_cache::{OfflineCacheManager, OfflineCacheStatus};
+use crate::output_device::{OutputDeviceInfo, OutputFallback, OUTPUT_FALLBACK_EVENT};
+use crate::podcast::{PodcastManager, PodcastSubscription};
+use crate::radio_browser::{DirectorySearch, DirectoryStation, RadioBrowserClient, StreamHealth};
+use crate::scrobble::{
+    LastFmAuthRequest, LastFmConfig, ListenBrainzConfig, ScrobbleManager, ScrobbleService,
+    ScrobbleStatus,
+};
+use crate::settings::{PresetAssignment, Settings, SettingsStore};
+use crate::shazam::{AutoIdConfig, ShazamManager};
+use crate::spectrum::{SpectrumFeed, SPECTRUM_EVENT};
+use crate::station::{Station, StationInput, StationNowPlaying};
+use crate::track_boundary::{TrackBoundaryFeed, TRACK_BOUNDARY_EVENT};
+use crate::tracklist::Tracklist;
+use crate::tuning_noise::TuningNoiseConfig;
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tauri::{Emitter, Manager};
+#[cfg(target_os = "macos")]
+use tauri::ActivationPolicy;
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 use tauri::{
     menu::{MenuBuilder, MenuItemBuilder, PredefinedMenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
 };
-#[cfg(target_os = "macos")]
-use tauri::ActivationPolicy;
+use tauri::{Emitter, Manager};
 
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 const TRAY_ID: &str = "main";
@@ -38,6 +97,30 @@ struct ShazamState {
     manager: Arc<ShazamManager>,
 }
 
+struct SettingsState {
+    store: Arc<SettingsStore>,
+}
+
+struct PodcastState {
+    manager: Arc<PodcastManager>,
+}
+
+struct OfflineCacheState {
+    manager: Arc<OfflineCacheManager>,
+}
+
+struct ListeningState {
+    log: Arc<ListeningLog>,
+}
+
+struct ScrobbleState {
+    manager: Arc<ScrobbleManager>,
+}
+
+struct SpectrumState {
+    feed: Arc<SpectrumFeed>,
+}
+
 #[tauri::command]
 async fn nts_get(path: &str) -> Result<Value, String> {
     eprintln!("[nts_get] start path={}", path);
@@ -80,14 +163,50 @@ async fn nts_get(path: &str) -> Result<Value, String> {
 
 #[tauri::command]
 fn start_native_stream(
-    stream_url: String,
+    stream_url: Option<String>,
+    station_id: Option<String>,
+    now_playing: Option<NowPlayingMetadata>,
+    playback: tauri::State<'_, Mutex<PlaybackManager>>,
+    settings: tauri::State<'_, SettingsState>,
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<(), String> {
+    let (stream_url, now_playing) = match station_id {
+        Some(station_id) => {
+            let station = settings
+                .store
+                .snapshot()
+                .station(station_id.as_str())
+                .cloned()
+                .ok_or_else(|| format!("unknown station: {station_id}"))?;
+            let now_playing = now_playing.or_else(|| Some(station.now_playing()));
+            (station.stream_url, now_playing)
+        }
+        None => (
+            stream_url.ok_or_else(|| "a stream URL or station is required".to_string())?,
+            now_playing,
+        ),
+    };
+
+    let local_source = offline.manager.local_source(stream_url.as_str());
+    let mut manager = playback
+        .lock()
+        .map_err(|_| "audio engine state lock poisoned".to_string())?;
+    match local_source {
+        Some(source) => manager.start_source(source, now_playing, None),
+        None => manager.start_stream(stream_url, now_playing),
+    }
+    Ok(())
+}
+
+#[tauri::command]
+fn update_native_now_playing(
     now_playing: Option<NowPlayingMetadata>,
     playback: tauri::State<'_, Mutex<PlaybackManager>>,
 ) -> Result<(), String> {
     let mut manager = playback
         .lock()
         .map_err(|_| "audio engine state lock poisoned".to_string())?;
-    manager.start_stream(stream_url, now_playing);
+    manager.update_now_playing(now_playing);
     Ok(())
 }
 
@@ -114,6 +233,466 @@ fn set_audio_fx_preset(
     Ok(())
 }
 
+#[tauri::command]
+fn set_audio_spectrum_enabled(enabled: bool, spectrum: tauri::State<'_, SpectrumState>) {
+    spectrum.feed.set_enabled(enabled);
+}
+
+#[tauri::command]
+async fn list_audio_output_devices() -> Result<Vec<OutputDeviceInfo>, String> {
+    tauri::async_runtime::spawn_blocking(output_device::list_output_devices)
+        .await
+        .map_err(|error| error.to_string())?
+}
+
+#[tauri::command]
+fn get_audio_output_device(settings: tauri::State<'_, SettingsState>) -> Option<String> {
+    settings.store.snapshot().output_device
+}
+
+#[tauri::command]
+fn set_audio_output_device(
+    device: Option<String>,
+    playback: tauri::State<'_, Mutex<PlaybackManager>>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    let device = device.filter(|device| !device.trim().is_empty());
+    settings.store.update(|settings| {
+        settings.output_device = device.clone();
+        Ok(())
+    })?;
+    let manager = playback
+        .lock()
+        .map_err(|_| "audio engine state lock poisoned".to_string())?;
+    manager.set_output_device(device);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_audio_crossfade(settings: tauri::State<'_, SettingsState>) -> CrossfadeConfig {
+    settings.store.snapshot().crossfade
+}
+
+#[tauri::command]
+fn set_audio_crossfade(
+    crossfade: CrossfadeConfig,
+    playback: tauri::State<'_, Mutex<PlaybackManager>>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    if crossfade.duration_ms > CrossfadeConfig::MAX_DURATION_MS {
+        return Err(format!(
+            "crossfade can be at most {}ms",
+            CrossfadeConfig::MAX_DURATION_MS
+        ));
+    }
+    settings.store.update(|settings| {
+        settings.crossfade = crossfade;
+        Ok(())
+    })?;
+    let mut manager = playback
+        .lock()
+        .map_err(|_| "audio engine state lock poisoned".to_string())?;
+    manager.set_crossfade(crossfade);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_tuning_noise(settings: tauri::State<'_, SettingsState>) -> TuningNoiseConfig {
+    settings.store.snapshot().tuning_noise
+}
+
+#[tauri::command]
+fn set_tuning_noise(
+    config: TuningNoiseConfig,
+    playback: tauri::State<'_, Mutex<PlaybackManager>>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    config.validate()?;
+    settings.store.update(|settings| {
+        settings.tuning_noise = config;
+        Ok(())
+    })?;
+    let manager = playback
+        .lock()
+        .map_err(|_| "audio engine state lock poisoned".to_string())?;
+    manager.set_tuning_noise(config);
+    Ok(())
+}
+
+#[tauri::command]
+fn station_list(settings: tauri::State<'_, SettingsState>) -> Result<Vec<Station>, String> {
+    Ok(settings.store.snapshot().stations)
+}
+
+#[tauri::command]
+fn station_save(
+    station: StationInput,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<Station, String> {
+    let station = Station::from_input(station)?;
+    settings.store.update(|current| {
+        current.upsert_station(station.clone());
+        Ok(())
+    })?;
+    Ok(station)
+}
+
+#[tauri::command]
+fn station_remove(
+    station_id: String,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    settings.store.update(|current| {
+        if current.remove_station(station_id.as_str()) {
+            Ok(())
+        } else {
+            Err(format!("unknown station: {station_id}"))
+        }
+    })
+}
+
+#[tauri::command]
+async fn station_now_playing(
+    station_id: String,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<Option<StationNowPlaying>, String> {
+    let station = settings
+        .store
+        .snapshot()
+        .station(station_id.as_str())
+        .cloned()
+        .ok_or_else(|| format!("unknown station: {station_id}"))?;
+    station::fetch_now_playing(&station).await
+}
+
+#[tauri::command]
+fn preset_get_assignments(
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<BTreeMap<u8, PresetAssignment>, String> {
+    Ok(settings.store.snapshot().presets)
+}
+
+#[tauri::command]
+fn preset_assign(
+    slot: u8,
+    assignment: Option<PresetAssignment>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    settings
+        .store
+        .update(|current| current.assign_preset(slot, assignment))
+}
+
+fn radio_browser_client(settings: &SettingsState) -> Result<RadioBrowserClient, String> {
+    RadioBrowserClient::new(settings.store.snapshot().radio_browser_base_url())
+}
+
+#[tauri::command]
+async fn radio_browser_search(
+    search: DirectorySearch,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<Vec<DirectoryStation>, String> {
+    radio_browser_client(&settings)?.search(&search).await
+}
+
+#[tauri::command]
+async fn radio_browser_check_stream(
+    stream_url: String,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<StreamHealth, String> {
+    Ok(radio_browser_client(&settings)?
+        .check_stream(stream_url.as_str())
+        .await)
+}
+
+#[tauri::command]
+async fn radio_browser_save_preset(
+    station: DirectoryStation,
+    slot: Option<u8>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<Station, String> {
+    let client = radio_browser_client(&settings)?;
+    let stream_url = match client.resolve(station.uuid.as_str()).await {
+        Ok(url) => url,
+        Err(error) => {
+            eprintln!("[radio-browser] resolve failed, using listed URL: {error}");
+            station.stream_url.clone()
+        }
+    };
+
+    let health = client.check_stream(stream_url.as_str()).await;
+    if !health.ok {
+        return Err(health
+            .message
+            .unwrap_or_else(|| "station stream is not playable".to_string()));
+    }
+
+    let station = station.into_station(stream_url)?;
+    settings.store.update(|current| {
+        current.upsert_station(station.clone());
+        if let Some(slot) = slot {
+            current.assign_preset(
+                slot,
+                Some(PresetAssignment::Station {
+                    station_id: station.id.clone(),
+                }),
+            )?;
+        }
+        Ok(())
+    })?;
+    Ok(station)
+}
+
+#[tauri::command]
+fn podcast_list(
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<Vec<PodcastSubscription>, String> {
+    Ok(podcasts.manager.subscriptions())
+}
+
+#[tauri::command]
+async fn podcast_subscribe(
+    feed_url: String,
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<PodcastSubscription, String> {
+    let manager = Arc::clone(&podcasts.manager);
+    tauri::async_runtime::spawn_blocking(move || manager.subscribe(feed_url.as_str()))
+        .await
+        .map_err(|error| error.to_string())?
+}
+
+#[tauri::command]
+fn podcast_unsubscribe(
+    subscription_id: String,
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<(), String> {
+    podcasts.manager.unsubscribe(subscription_id.as_str())
+}
+
+#[tauri::command]
+async fn podcast_refresh(
+    subscription_id: Option<String>,
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<usize, String> {
+    let manager = Arc::clone(&podcasts.manager);
+    tauri::async_runtime::spawn_blocking(move || manager.refresh(subscription_id.as_deref()))
+        .await
+        .map_err(|error| error.to_string())?
+}
+
+#[tauri::command]
+fn podcast_download_episode(
+    episode_id: String,
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<(), String> {
+    podcasts.manager.download_episode(episode_id.as_str())
+}
+
+#[tauri::command]
+fn podcast_delete_download(
+    episode_id: String,
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<(), String> {
+    podcasts.manager.delete_download(episode_id.as_str())
+}
+
+#[tauri::command]
+fn podcast_play_episode(
+    episode_id: String,
+    playback: tauri::State<'_, Mutex<PlaybackManager>>,
+    podcasts: tauri::State<'_, PodcastState>,
+) -> Result<(), String> {
+    let (source, now_playing, on_position) = podcasts.manager.playback_for(episode_id.as_str())?;
+    let mut manager = playback
+        .lock()
+        .map_err(|_| "audio engine state lock poisoned".to_string())?;
+    manager.start_source(source, Some(now_playing), Some(on_position));
+    Ok(())
+}
+
+#[tauri::command]
+fn offline_cache_status(
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<OfflineCacheStatus, String> {
+    Ok(offline.manager.status())
+}
+
+#[tauri::command]
+fn offline_cache_save(
+    stream_url: String,
+    title: Option<String>,
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<(), String> {
+    offline.manager.save(stream_url.as_str(), title)
+}
+
+#[tauri::command]
+fn offline_cache_start_capture(
+    stream_url: String,
+    title: Option<String>,
+    hours: u32,
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<(), String> {
+    offline
+        .manager
+        .start_capture(stream_url.as_str(), title, hours)
+}
+
+#[tauri::command]
+fn offline_cache_stop_capture(
+    stream_url: String,
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<(), String> {
+    offline.manager.stop_capture(stream_url.as_str())
+}
+
+#[tauri::command]
+fn offline_cache_remove(
+    stream_url: String,
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<(), String> {
+    offline.manager.remove(stream_url.as_str())
+}
+
+#[tauri::command]
+fn offline_cache_set_quota(
+    quota_bytes: u64,
+    offline: tauri::State<'_, OfflineCacheState>,
+) -> Result<(), String> {
+    offline.manager.set_quota(quota_bytes)
+}
+
+#[tauri::command]
+fn listening_get_sessions(
+    query: Option<ListeningQuery>,
+    listening: tauri::State<'_, ListeningState>,
+) -> Result<Vec<ListeningSession>, String> {
+    listening.log.sessions(&query.unwrap_or_default())
+}
+
+#[tauri::command]
+fn listening_get_stats(
+    query: Option<ListeningQuery>,
+    listening: tauri::State<'_, ListeningState>,
+) -> Result<ListeningStats, String> {
+    listening.log.stats(&query.unwrap_or_default())
+}
+
+#[tauri::command]
+fn listening_clear(listening: tauri::State<'_, ListeningState>) -> Result<(), String> {
+    listening.log.clear()
+}
+
+#[tauri::command]
+fn scrobble_get_status(scrobble: tauri::State<'_, ScrobbleState>) -> ScrobbleStatus {
+    scrobble.manager.status()
+}
+
+#[tauri::command]
+fn scrobble_set_shows(
+    enabled: bool,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    settings.store.update(|settings| {
+        settings.scrobble.scrobble_shows = enabled;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+async fn scrobble_connect_listenbrainz(
+    token: String,
+    base_url: Option<String>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<String, String> {
+    let mut config = ListenBrainzConfig {
+        base_url,
+        token: Some(token.trim().to_string()),
+        username: None,
+    };
+    let (config, username) = tauri::async_runtime::spawn_blocking(move || {
+        let username = scrobble::listenbrainz_username(&config)?;
+        config.username = Some(username.clone());
+        Ok::<_, String>((config, username))
+    })
+    .await
+    .map_err(|error| error.to_string())??;
+    settings.store.update(|settings| {
+        settings.scrobble.listenbrainz = config;
+        Ok(())
+    })?;
+    Ok(username)
+}
+
+#[tauri::command]
+async fn scrobble_begin_lastfm_auth(
+    api_key: String,
+    api_secret: String,
+    base_url: Option<String>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<LastFmAuthRequest, String> {
+    let config = LastFmConfig {
+        base_url,
+        api_key: Some(api_key.trim().to_string()),
+        api_secret: Some(api_secret.trim().to_string()),
+        session_key: None,
+        username: None,
+    };
+    let (config, request) = tauri::async_runtime::spawn_blocking(move || {
+        let request = scrobble::lastfm_begin_auth(&config)?;
+        Ok::<_, String>((config, request))
+    })
+    .await
+    .map_err(|error| error.to_string())??;
+    settings.store.update(|settings| {
+        settings.scrobble.lastfm = config;
+        Ok(())
+    })?;
+    Ok(request)
+}
+
+#[tauri::command]
+async fn scrobble_complete_lastfm_auth(
+    token: String,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<String, String> {
+    let config = settings.store.snapshot().scrobble.lastfm;
+    let (username, session_key) = tauri::async_runtime::spawn_blocking(move || {
+        scrobble::lastfm_complete_auth(&config, token.as_str())
+    })
+    .await
+    .map_err(|error| error.to_string())??;
+    settings.store.update(|settings| {
+        settings.scrobble.lastfm.session_key = Some(session_key);
+        settings.scrobble.lastfm.username = Some(username.clone());
+        Ok(())
+    })?;
+    Ok(username)
+}
+
+#[tauri::command]
+fn scrobble_disconnect(
+    service: ScrobbleService,
+    scrobble: tauri::State<'_, ScrobbleState>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    settings.store.update(|settings| {
+        match service {
+            ScrobbleService::ListenBrainz => {
+                settings.scrobble.listenbrainz = ListenBrainzConfig::default();
+            }
+            ScrobbleService::LastFm => settings.scrobble.lastfm = LastFmConfig::default(),
+        }
+        Ok(())
+    })?;
+    scrobble.manager.forget(service)
+}
+
+#[tauri::command]
+fn scrobble_retry_now(scrobble: tauri::State<'_, ScrobbleState>) -> Result<(), String> {
+    scrobble.manager.retry_now()
+}
+
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 fn reveal_main_window<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
     #[cfg(target_os = "macos")]
@@ -211,15 +790,129 @@ fn shazam_identify_now(
 }
 
 #[tauri::command]
-fn shazam_get_history(shazam: tauri::State<'_, ShazamState>) -> Result<Vec<RecognizedTrack>, String> {
+fn shazam_get_history(shazam: tauri::State<'_, ShazamState>) -> Result<Vec<HistoryEntry>, String> {
     Ok(shazam.manager.get_history())
 }
 
+#[tauri::command]
+fn shazam_search_history(
+    query: HistoryQuery,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Vec<HistoryEntry> {
+    shazam.manager.search_history(&query)
+}
+
+#[tauri::command]
+fn shazam_update_history_entry(
+    id: String,
+    update: HistoryEntryUpdate,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<HistoryEntry, String> {
+    shazam.manager.update_history_entry(id.as_str(), update)
+}
+
+#[tauri::command]
+fn shazam_delete_history_entry(
+    id: String,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<bool, String> {
+    shazam.manager.delete_history_entry(id.as_str())
+}
+
+#[tauri::command]
+fn shazam_export_history(
+    format: HistoryExportFormat,
+    query: Option<HistoryQuery>,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<String, String> {
+    let entries = match query {
+        Some(query) => shazam.manager.search_history(&query),
+        None => shazam.manager.get_history(),
+    };
+    history_export::export(entries.as_slice(), format)
+}
+
+#[tauri::command]
+fn shazam_save_history_export(
+    format: HistoryExportFormat,
+    query: Option<HistoryQuery>,
+    path: String,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<(), String> {
+    let entries = match query {
+        Some(query) => shazam.manager.search_history(&query),
+        None => shazam.manager.get_history(),
+    };
+    let bytes = history_export::export_bytes(entries.as_slice(), format)?;
+    std::fs::write(path.as_str(), bytes)
+        .map_err(|error| format!("unable to write history export to {path}: {error}"))
+}
+
+#[tauri::command]
+fn shazam_import_history(
+    path: String,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<HistoryImportSummary, String> {
+    shazam.manager.import_history(Path::new(path.as_str()))
+}
+
+#[tauri::command]
+fn shazam_set_history_limit(
+    limit: Option<usize>,
+    shazam: tauri::State<'_, ShazamState>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    settings
+        .store
+        .update(|settings| settings.set_history_limit(limit))?;
+    shazam.manager.set_history_limit(limit)
+}
+
 #[tauri::command]
 fn shazam_clear_history(shazam: tauri::State<'_, ShazamState>) -> Result<(), String> {
     shazam.manager.clear_history()
 }
 
+#[tauri::command]
+fn shazam_get_auto_id(shazam: tauri::State<'_, ShazamState>) -> AutoIdConfig {
+    shazam.manager.auto_id_config()
+}
+
+#[tauri::command]
+fn shazam_set_auto_id(
+    config: AutoIdConfig,
+    shazam: tauri::State<'_, ShazamState>,
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<(), String> {
+    settings.store.update(|settings| {
+        settings.auto_id = config.clone();
+        Ok(())
+    })?;
+    shazam.manager.set_auto_id(config);
+    Ok(())
+}
+
+#[tauri::command]
+fn shazam_list_tracklists(shazam: tauri::State<'_, ShazamState>) -> Vec<Tracklist> {
+    shazam.manager.tracklists()
+}
+
+#[tauri::command]
+fn shazam_export_tracklist(
+    key: String,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<String, String> {
+    shazam.manager.export_tracklist(key.as_str())
+}
+
+#[tauri::command]
+fn shazam_delete_tracklist(
+    key: String,
+    shazam: tauri::State<'_, ShazamState>,
+) -> Result<bool, String> {
+    shazam.manager.delete_tracklist(key.as_str())
+}
+
 #[cfg(any(target_os = "macos", target_os = "windows"))]
 #[tauri::command]
 fn set_tray_preset(slot: Option<u8>, app: tauri::AppHandle) -> Result<(), String> {
@@ -259,18 +952,16 @@ fn build_tray_menu<R: tauri::Runtime>(
         None => "Not Playing".to_string(),
     };
 
-    let title_item =
-        MenuItemBuilder::with_id(TRAY_MENU_NOW_PLAYING_TITLE_ID, title_text)
-            .enabled(false)
-            .build(app)
-            .map_err(|e| e.to_string())?;
+    let title_item = MenuItemBuilder::with_id(TRAY_MENU_NOW_PLAYING_TITLE_ID, title_text)
+        .enabled(false)
+        .build(app)
+        .map_err(|e| e.to_string())?;
 
     let sep1 = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
 
-    let settings_item =
-        MenuItemBuilder::with_id(TRAY_MENU_SETTINGS_ID, "Settings\u{2026}")
-            .build(app)
-            .map_err(|e| e.to_string())?;
+    let settings_item = MenuItemBuilder::with_id(TRAY_MENU_SETTINGS_ID, "Settings\u{2026}")
+        .build(app)
+        .map_err(|e| e.to_string())?;
 
     let open_item = MenuItemBuilder::with_id(TRAY_MENU_OPEN_ID, "Open Marconio")
         .build(app)
@@ -287,11 +978,10 @@ fn build_tray_menu<R: tauri::Runtime>(
     builder = builder.item(&title_item);
 
     if let Some(sub) = subtitle {
-        let subtitle_item =
-            MenuItemBuilder::with_id(TRAY_MENU_NOW_PLAYING_SUBTITLE_ID, sub)
-                .enabled(false)
-                .build(app)
-                .map_err(|e| e.to_string())?;
+        let subtitle_item = MenuItemBuilder::with_id(TRAY_MENU_NOW_PLAYING_SUBTITLE_ID, sub)
+            .enabled(false)
+            .build(app)
+            .map_err(|e| e.to_string())?;
         builder = builder.item(&subtitle_item);
     }
 
@@ -382,24 +1072,151 @@ pub fn run() {
         .manage(Mutex::new(PlaybackManager::default()))
         .manage(Mutex::new(UiState::default()))
         .setup(|app| {
+            let settings_store = Arc::new(
+                SettingsStore::new(app.handle())
+                    .map_err(|error| format!("[settings] init failed: {error}"))?,
+            );
+            app.manage(SettingsState {
+                store: Arc::clone(&settings_store),
+            });
+
+            let Settings {
+                auto_id,
+                history_limit,
+                output_device,
+                crossfade,
+                tuning_noise,
+                ..
+            } = settings_store.snapshot();
+            let scrobble_manager = Arc::new(
+                ScrobbleManager::new(
+                    app.handle(),
+                    Box::new({
+                        let settings_store = Arc::clone(&settings_store);
+                        move || settings_store.snapshot().scrobble
+                    }),
+                )
+                .map_err(|error| format!("[scrobble] init failed: {error}"))?,
+            );
+            app.manage(ScrobbleState {
+                manager: Arc::clone(&scrobble_manager),
+            });
+
             let shazam_manager = Arc::new(
-                ShazamManager::new(app.handle().clone())
-                    .map_err(|error| format!("[shazam] init failed: {error}"))?,
+                ShazamManager::new(
+                    app.handle().clone(),
+                    Box::new(move || settings_store.snapshot().acoustid_config()),
+                )
+                .map_err(|error| format!("[shazam] init failed: {error}"))?,
             );
+            shazam_manager.set_auto_id(auto_id);
+            if let Err(error) = shazam_manager.set_history_limit(history_limit) {
+                eprintln!("[shazam] ignoring history limit from settings: {error}");
+            }
+            shazam_manager.set_now_playing_source(Box::new({
+                let app = app.handle().clone();
+                move || {
+                    let playback = app.state::<Mutex<PlaybackManager>>();
+                    let manager = playback.lock().ok()?;
+                    manager
+                        .now_playing()
+                        .filter(|_| manager.is_stream_running())
+                }
+            }));
+            shazam_manager.set_on_match(Box::new({
+                let scrobble_manager = Arc::clone(&scrobble_manager);
+                move |track| scrobble_manager.track_recognized(track)
+            }));
             app.manage(ShazamState {
                 manager: Arc::clone(&shazam_manager),
             });
 
+            let podcast_manager = Arc::new(
+                PodcastManager::new(app.handle().clone())
+                    .map_err(|error| format!("[podcast] init failed: {error}"))?,
+            );
+            app.manage(PodcastState {
+                manager: podcast_manager,
+            });
+
+            let offline_manager = Arc::new(
+                OfflineCacheManager::new(app.handle().clone())
+                    .map_err(|error| format!("[offline] init failed: {error}"))?,
+            );
+            app.manage(OfflineCacheState {
+                manager: offline_manager,
+            });
+
+            let listening_log = Arc::new(
+                ListeningLog::new(app.handle())
+                    .map_err(|error| format!("[listening] init failed: {error}"))?,
+            );
+            app.manage(ListeningState {
+                log: Arc::clone(&listening_log),
+            });
+
             let playback_state = app.state::<Mutex<PlaybackManager>>();
+            let audio_taps = playback_state
+                .lock()
+                .map(|manager| manager.audio_taps())
+                .map_err(|_| "[audio] init failed: state lock poisoned".to_string())?;
+            app.manage(SpectrumState {
+                feed: Arc::new(SpectrumFeed::new(
+                    Arc::clone(&audio_taps),
+                    Box::new({
+                        let app_handle = app.handle().clone();
+                        move |frame| {
+                            let _ = app_handle.emit(SPECTRUM_EVENT, frame);
+                        }
+                    }),
+                )),
+            });
+
             match playback_state.lock() {
                 Ok(mut manager) => {
                     manager.initialize_media_controls(app.handle().clone());
-                    manager.set_audio_frame_tap(Some(Arc::new({
+                    manager.set_listening_log(Some(listening_log));
+                    manager.set_scrobbler(Some(scrobble_manager));
+                    manager.set_output_device(output_device);
+                    manager.set_crossfade(crossfade);
+                    manager.set_tuning_noise(tuning_noise);
+                    manager.set_on_stream_error(Arc::new({
+                        let app_handle = app.handle().clone();
+                        move |error: &StreamError| {
+                            let _ = app_handle.emit(STREAM_ERROR_EVENT, error);
+                        }
+                    }));
+                    manager.set_on_output_fallback(Arc::new({
+                        let app_handle = app.handle().clone();
+                        move |fallback: &OutputFallback| {
+                            let _ = app_handle.emit(OUTPUT_FALLBACK_EVENT, fallback);
+                        }
+                    }));
+                    audio_taps.add(
+                        TapPoint::PostFx,
+                        Arc::new({
+                            let shazam_manager = Arc::clone(&shazam_manager);
+                            move |samples, channels, sample_rate| {
+                                shazam_manager.ingest_audio(samples, channels, sample_rate);
+                            }
+                        }),
+                    );
+                    let track_boundaries = TrackBoundaryFeed::new(Box::new({
                         let shazam_manager = Arc::clone(&shazam_manager);
-                        move |samples, channels, sample_rate| {
-                            shazam_manager.ingest_audio(samples, channels, sample_rate);
+                        let app_handle = app.handle().clone();
+                        move |boundary| {
+                            if let Err(error) = app_handle.emit(TRACK_BOUNDARY_EVENT, boundary) {
+                                eprintln!("[audio] unable to emit track boundary: {error}");
+                            }
+                            shazam_manager.notify_track_transition();
                         }
-                    })));
+                    }));
+                    audio_taps.add(
+                        TapPoint::PostFx,
+                        Arc::new(move |samples, channels, sample_rate| {
+                            track_boundaries.push(samples, channels, sample_rate);
+                        }),
+                    );
                 }
                 Err(_) => {
                     eprintln!("[audio] unable to initialize media controls: state lock poisoned")
@@ -435,14 +1252,76 @@ pub fn run() {
             nts_get,
             start_native_stream,
             stop_native_stream,
+            update_native_now_playing,
             set_audio_fx_preset,
+            set_audio_spectrum_enabled,
+            list_audio_output_devices,
+            get_audio_output_device,
+            set_audio_output_device,
+            get_audio_crossfade,
+            set_audio_crossfade,
+            get_tuning_noise,
+            set_tuning_noise,
             set_menu_bar_mode,
             set_tray_preset,
             update_tray_menu,
             shazam_identify_now,
             shazam_get_history,
-            shazam_clear_history
+            shazam_search_history,
+            shazam_update_history_entry,
+            shazam_delete_history_entry,
+            shazam_export_history,
+            shazam_save_history_export,
+            shazam_import_history,
+            shazam_set_history_limit,
+            shazam_clear_history,
+            shazam_get_auto_id,
+            shazam_set_auto_id,
+            shazam_list_tracklists,
+            shazam_export_tracklist,
+            shazam_delete_tracklist,
+            station_list,
+            station_save,
+            station_remove,
+            station_now_playing,
+            preset_get_assignments,
+            preset_assign,
+            radio_browser_search,
+            radio_browser_check_stream,
+            radio_browser_save_preset,
+            podcast_list,
+            podcast_subscribe,
+            podcast_unsubscribe,
+            podcast_refresh,
+            podcast_download_episode,
+            podcast_delete_download,
+            podcast_play_episode,
+            offline_cache_status,
+            offline_cache_save,
+            offline_cache_start_capture,
+            offline_cache_stop_capture,
+            offline_cache_remove,
+            offline_cache_set_quota,
+            listening_get_sessions,
+            listening_get_stats,
+            listening_clear,
+            scrobble_get_status,
+            scrobble_set_shows,
+            scrobble_connect_listenbrainz,
+            scrobble_begin_lastfm_auth,
+            scrobble_complete_lastfm_auth,
+            scrobble_disconnect,
+            scrobble_retry_now
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Managed state isn't dropped on exit, so the open session
+                // has to be closed by hand.
+                if let Some(listening) = app.try_state::<ListeningState>() {
+                    listening.log.end();
+                }
+            }
+        });
 }
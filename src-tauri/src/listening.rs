@@ -0,0 +1,596 @@
+//! A log of every listening session, and the stats built from it.
+
+use crate::audio_engine::{AudioFxPreset, NowPlayingMetadata};
+use crate::database::{self, DATABASE_FILE_NAME};
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+/// Shorter sessions are presets tuned through on the way somewhere else.
+const MIN_SESSION_SECONDS: u64 = 10;
+const TOP_SHOWS_LIMIT: usize = 10;
+const SECONDS_PER_HOUR: i64 = 3_600;
+const SECONDS_PER_DAY: i64 = 86_400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ListeningSourceKind {
+    Channel,
+    Mixtape,
+    Station,
+    /// Podcasts, and streams played without metadata.
+    Other,
+}
+
+impl ListeningSourceKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Channel => "channel",
+            Self::Mixtape => "mixtape",
+            Self::Station => "station",
+            Self::Other => "other",
+        }
+    }
+
+    fn from_str(value: &str) -> Self {
+        match value {
+            "channel" => Self::Channel,
+            "mixtape" => Self::Mixtape,
+            "station" => Self::Station,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningSession {
+    pub id: i64,
+    pub kind: ListeningSourceKind,
+    /// The channel, mixtape or station, e.g. `NTS 1`.
+    pub source: String,
+    pub title: String,
+    pub artist: Option<String>,
+    pub show_alias: Option<String>,
+    pub episode_alias: Option<String>,
+    pub fx_preset: String,
+    pub started_at: u64,
+    pub ended_at: u64,
+}
+
+/// Dates are epoch seconds; sessions crossing them are cut to fit.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ListeningQuery {
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Local time's offset from UTC, for the hour and weekday buckets.
+    pub utc_offset_minutes: i32,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShowListening {
+    pub show_alias: String,
+    /// The show's title from its most recent session.
+    pub title: String,
+    pub seconds: u64,
+    pub sessions: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceListening {
+    pub kind: ListeningSourceKind,
+    pub source: String,
+    pub seconds: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningStats {
+    pub total_seconds: u64,
+    pub sessions: usize,
+    /// Most listened first.
+    pub top_shows: Vec<ShowListening>,
+    pub sources: Vec<SourceListening>,
+    /// Seconds listened in each local hour, midnight first.
+    pub by_hour: Vec<u64>,
+    /// Seconds listened on each local weekday, Monday first.
+    pub by_weekday: Vec<u64>,
+    /// `heatmap[weekday][hour]`, in the same order as the two above.
+    pub heatmap: Vec<Vec<u64>>,
+}
+
+struct OpenSession {
+    metadata: Option<NowPlayingMetadata>,
+    fx_preset: AudioFxPreset,
+    started_at: u64,
+}
+
+pub struct ListeningLog {
+    connection: Mutex<Connection>,
+    current: Mutex<Option<OpenSession>>,
+}
+
+impl ListeningLog {
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        let app_data_dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|error| format!("unable to resolve app data directory: {error}"))?;
+        let connection = database::open(app_data_dir.join(DATABASE_FILE_NAME).as_path())?;
+        Ok(Self::with_connection(connection))
+    }
+
+    fn with_connection(connection: Connection) -> Self {
+        Self {
+            connection: Mutex::new(connection),
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Starts a session, ending the one before it.
+    pub fn begin(&self, metadata: Option<&NowPlayingMetadata>, fx_preset: AudioFxPreset) {
+        self.begin_at(metadata, fx_preset, epoch_seconds());
+    }
+
+    /// Ends the session when the stream moves on to another show; anything
+    /// else, like new artwork, is kept on the open session.
+    pub fn now_playing_changed(&self, metadata: Option<&NowPlayingMetadata>) {
+        self.now_playing_changed_at(metadata, epoch_seconds());
+    }
+
+    pub fn end(&self) {
+        self.end_at(epoch_seconds());
+    }
+
+    fn now_playing_changed_at(&self, metadata: Option<&NowPlayingMetadata>, now: u64) {
+        let fx_preset = {
+            let Ok(mut current) = self.current.lock() else {
+                return;
+            };
+            let Some(session) = current.as_mut() else {
+                return;
+            };
+            let same_show = match (session.metadata.as_ref(), metadata) {
+                (Some(open), Some(next)) => open.is_same_show(next),
+                (open, next) => open.is_none() && next.is_none(),
+            };
+            if same_show {
+                session.metadata = metadata.cloned();
+                return;
+            }
+            session.fx_preset
+        };
+        self.begin_at(metadata, fx_preset, now);
+    }
+
+    fn begin_at(&self, metadata: Option<&NowPlayingMetadata>, fx_preset: AudioFxPreset, now: u64) {
+        self.end_at(now);
+        if let Ok(mut current) = self.current.lock() {
+            *current = Some(OpenSession {
+                metadata: metadata.cloned(),
+                fx_preset,
+                started_at: now,
+            });
+        }
+    }
+
+    fn end_at(&self, now: u64) {
+        let Some(session) = self
+            .current
+            .lock()
+            .ok()
+            .and_then(|mut current| current.take())
+        else {
+            return;
+        };
+        if now.saturating_sub(session.started_at) < MIN_SESSION_SECONDS {
+            return;
+        }
+        if let Err(error) = self.insert(&session, now) {
+            eprintln!("[listening] unable to record session: {error}");
+        }
+    }
+
+    fn insert(&self, session: &OpenSession, ended_at: u64) -> Result<(), String> {
+        let metadata = session.metadata.as_ref();
+        let (kind, source) = classify(metadata);
+        self.lock_connection()?
+            .execute(
+                "INSERT INTO listening_sessions (started_at, ended_at, kind, source, title, \
+                 artist, show_alias, episode_alias, fx_preset) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    sql_integer(session.started_at),
+                    sql_integer(ended_at),
+                    kind.as_str(),
+                    source,
+                    metadata.map_or("Unknown", |item| item.title.as_str()),
+                    metadata.and_then(|item| item.artist.as_deref()),
+                    metadata.and_then(|item| non_empty(item.show_alias.as_deref())),
+                    metadata.and_then(|item| non_empty(item.episode_alias.as_deref())),
+                    session.fx_preset.as_str(),
+                ],
+            )
+            .map_err(|error| error.to_string())?;
+        Ok(())
+    }
+
+    /// Newest first, cut to the query's dates.
+    pub fn sessions(&self, query: &ListeningQuery) -> Result<Vec<ListeningSession>, String> {
+        let from = query.from.unwrap_or(0);
+        let to = query.to.unwrap_or(u64::MAX);
+        let limit = query
+            .limit
+            .map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let connection = self.lock_connection()?;
+        let mut statement = connection
+            .prepare_cached(
+                "SELECT id, kind, source, title, artist, show_alias, episode_alias, fx_preset, \
+                 MAX(started_at, ?1), MIN(ended_at, ?2) FROM listening_sessions \
+                 WHERE started_at < ?2 AND ended_at > ?1 \
+                 ORDER BY started_at DESC, id DESC LIMIT ?3",
+            )
+            .map_err(|error| format!("unable to query listening log: {error}"))?;
+        let sessions = statement
+            .query_map(
+                params![sql_integer(from), sql_integer(to), limit],
+                session_from_row,
+            )
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|error| format!("unable to read listening log: {error}"))?;
+        Ok(sessions)
+    }
+
+    pub fn stats(&self, query: &ListeningQuery) -> Result<ListeningStats, String> {
+        let sessions = self.sessions(&ListeningQuery {
+            limit: None,
+            ..query.clone()
+        })?;
+        Ok(build_stats(sessions.as_slice(), query.utc_offset_minutes))
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        if let Ok(mut current) = self.current.lock() {
+            *current = None;
+        }
+        self.lock_connection()?
+            .execute("DELETE FROM listening_sessions", [])
+            .map_err(|error| format!("unable to clear listening log: {error}"))?;
+        Ok(())
+    }
+
+    fn lock_connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>, String> {
+        self.connection
+            .lock()
+            .map_err(|_| "listening log lock poisoned".to_string())
+    }
+}
+
+/// Works out what kind of source is playing from the metadata the frontend
+/// sends: NTS channels come in as album `NTS <channel>`, mixtapes as
+/// `NTS Mixtape`, and saved stations are titled after themselves.
+fn classify(metadata: Option<&NowPlayingMetadata>) -> (ListeningSourceKind, String) {
+    let Some(metadata) = metadata else {
+        return (ListeningSourceKind::Other, "Unknown".to_string());
+    };
+    match metadata.album.as_deref() {
+        Some("NTS Mixtape") => (ListeningSourceKind::Mixtape, metadata.title.clone()),
+        Some(album) if album.starts_with("NTS ") => {
+            (ListeningSourceKind::Channel, album.to_string())
+        }
+        Some(album) if album == metadata.title => (ListeningSourceKind::Station, album.to_string()),
+        Some(album) => (ListeningSourceKind::Other, album.to_string()),
+        None => (ListeningSourceKind::Other, metadata.title.clone()),
+    }
+}
+
+fn session_from_row(row: &Row<'_>) -> rusqlite::Result<ListeningSession> {
+    Ok(ListeningSession {
+        id: row.get(0)?,
+        kind: ListeningSourceKind::from_str(row.get::<_, String>(1)?.as_str()),
+        source: row.get(2)?,
+        title: row.get(3)?,
+        artist: row.get(4)?,
+        show_alias: row.get(5)?,
+        episode_alias: row.get(6)?,
+        fx_preset: row.get(7)?,
+        started_at: row.get(8)?,
+        ended_at: row.get(9)?,
+    })
+}
+
+fn build_stats(sessions: &[ListeningSession], utc_offset_minutes: i32) -> ListeningStats {
+    let mut stats = ListeningStats {
+        sessions: sessions.len(),
+        by_hour: vec![0; 24],
+        by_weekday: vec![0; 7],
+        heatmap: vec![vec![0; 24]; 7],
+        ..ListeningStats::default()
+    };
+    let mut shows = HashMap::<String, ShowListening>::new();
+    let mut sources = HashMap::<(ListeningSourceKind, String), u64>::new();
+    let offset = i64::from(utc_offset_minutes) * 60;
+
+    // Newest first, so the first title seen for a show is its latest.
+    for session in sessions {
+        let seconds = session.ended_at.saturating_sub(session.started_at);
+        stats.total_seconds += seconds;
+        *sources
+            .entry((session.kind, session.source.clone()))
+            .or_default() += seconds;
+        if let Some(show_alias) = session.show_alias.as_ref() {
+            let show = shows
+                .entry(show_alias.clone())
+                .or_insert_with(|| ShowListening {
+                    show_alias: show_alias.clone(),
+                    title: session.title.clone(),
+                    seconds: 0,
+                    sessions: 0,
+                });
+            show.seconds += seconds;
+            show.sessions += 1;
+        }
+
+        // Split the session at each local hour it crosses.
+        let mut at = sql_integer(session.started_at) + offset;
+        let end = sql_integer(session.ended_at) + offset;
+        while at < end {
+            let next_hour = (at.div_euclid(SECONDS_PER_HOUR) + 1) * SECONDS_PER_HOUR;
+            let slice = (next_hour.min(end) - at) as u64;
+            let day = at.div_euclid(SECONDS_PER_DAY);
+            let hour = (at.rem_euclid(SECONDS_PER_DAY) / SECONDS_PER_HOUR) as usize;
+            // 1970-01-01 was a Thursday.
+            let weekday = (day + 3).rem_euclid(7) as usize;
+            stats.by_hour[hour] += slice;
+            stats.by_weekday[weekday] += slice;
+            stats.heatmap[weekday][hour] += slice;
+            at = next_hour;
+        }
+    }
+
+    let mut top_shows = shows.into_values().collect::<Vec<_>>();
+    top_shows.sort_by(|lhs, rhs| {
+        rhs.seconds
+            .cmp(&lhs.seconds)
+            .then_with(|| lhs.show_alias.cmp(&rhs.show_alias))
+    });
+    top_shows.truncate(TOP_SHOWS_LIMIT);
+    stats.top_shows = top_shows;
+
+    let mut sources = sources
+        .into_iter()
+        .map(|((kind, source), seconds)| SourceListening {
+            kind,
+            source,
+            seconds,
+        })
+        .collect::<Vec<_>>();
+    sources.sort_by(|lhs, rhs| {
+        rhs.seconds
+            .cmp(&lhs.seconds)
+            .then_with(|| lhs.source.cmp(&rhs.source))
+    });
+    stats.sources = sources;
+    stats
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|value| !value.is_empty())
+}
+
+/// SQLite integers are signed; epoch seconds stay far below the cutoff.
+fn sql_integer(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ListeningLog, ListeningQuery, ListeningSourceKind, SourceListening};
+    use crate::audio_engine::{AudioFxPreset, NowPlayingMetadata};
+    use crate::database;
+
+    // Monday 2026-10-12, 00:00 UTC.
+    const MONDAY: u64 = 1_791_763_200;
+    const HOUR: u64 = 3_600;
+
+    fn log() -> ListeningLog {
+        ListeningLog::with_connection(database::open_in_memory().expect("database"))
+    }
+
+    fn channel(name: &str, show: &str, title: &str) -> NowPlayingMetadata {
+        NowPlayingMetadata {
+            title: title.to_string(),
+            artist: Some("NTS Radio".to_string()),
+            album: Some(format!("NTS {name}")),
+            artwork_url: None,
+            show_alias: Some(show.to_string()),
+            episode_alias: None,
+        }
+    }
+
+    fn station(name: &str) -> NowPlayingMetadata {
+        NowPlayingMetadata {
+            title: name.to_string(),
+            artist: None,
+            album: Some(name.to_string()),
+            artwork_url: None,
+            show_alias: None,
+            episode_alias: None,
+        }
+    }
+
+    #[test]
+    fn records_sessions_and_drops_brief_ones() {
+        let log = log();
+        let early_bird = channel("1", "early-bird", "Early Bird");
+        log.begin_at(Some(&early_bird), AudioFxPreset::Clean, MONDAY);
+        // Switching FX starts a new session on the same channel.
+        log.begin_at(Some(&early_bird), AudioFxPreset::Cassette, MONDAY + 600);
+        // Tuning straight past a station doesn't count.
+        log.begin_at(Some(&station("FIP")), AudioFxPreset::Cassette, MONDAY + 900);
+        log.begin_at(None, AudioFxPreset::Clean, MONDAY + 905);
+        log.end_at(MONDAY + 1_000);
+        log.end_at(MONDAY + 2_000);
+
+        let sessions = log.sessions(&ListeningQuery::default()).expect("sessions");
+        let summary = sessions
+            .iter()
+            .map(|session| {
+                (
+                    session.kind,
+                    session.source.as_str(),
+                    session.fx_preset.as_str(),
+                    session.ended_at - session.started_at,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (ListeningSourceKind::Other, "Unknown", "clean", 95),
+                (ListeningSourceKind::Channel, "NTS 1", "cassette", 300),
+                (ListeningSourceKind::Channel, "NTS 1", "clean", 600),
+            ]
+        );
+        assert_eq!(sessions[1].show_alias.as_deref(), Some("early-bird"));
+
+        let clipped = log
+            .sessions(&ListeningQuery {
+                from: Some(MONDAY + 300),
+                to: Some(MONDAY + 700),
+                limit: Some(1),
+                ..ListeningQuery::default()
+            })
+            .expect("sessions");
+        assert_eq!(clipped.len(), 1);
+        assert_eq!(clipped[0].started_at, MONDAY + 600);
+        assert_eq!(clipped[0].ended_at, MONDAY + 700);
+
+        log.clear().expect("clear");
+        assert!(log
+            .sessions(&ListeningQuery::default())
+            .expect("sessions")
+            .is_empty());
+    }
+
+    #[test]
+    fn a_new_show_on_the_same_stream_starts_a_new_session() {
+        let log = log();
+        let early_bird = channel("1", "early-bird", "Early Bird");
+        log.begin_at(Some(&early_bird), AudioFxPreset::Bass, MONDAY);
+        let new_artwork = NowPlayingMetadata {
+            artwork_url: Some("https://images.example/early-bird.jpg".to_string()),
+            ..early_bird.clone()
+        };
+        log.now_playing_changed_at(Some(&new_artwork), MONDAY + 600);
+        log.now_playing_changed_at(
+            Some(&channel("1", "lunch-break", "Lunch Break")),
+            MONDAY + HOUR,
+        );
+        log.end_at(MONDAY + 2 * HOUR);
+
+        let sessions = log.sessions(&ListeningQuery::default()).expect("sessions");
+        let summary = sessions
+            .iter()
+            .map(|session| {
+                (
+                    session.show_alias.as_deref(),
+                    session.fx_preset.as_str(),
+                    session.started_at,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("lunch-break"), "bass", MONDAY + HOUR),
+                (Some("early-bird"), "bass", MONDAY),
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_rank_shows_and_split_time_by_local_hour() {
+        let log = log();
+        // Monday 23:30 to Tuesday 01:00 UTC.
+        log.begin_at(
+            Some(&channel("1", "night-moves", "Night Moves")),
+            AudioFxPreset::Clean,
+            MONDAY + 23 * HOUR + 1_800,
+        );
+        log.end_at(MONDAY + 25 * HOUR);
+        log.begin_at(
+            Some(&channel("2", "early-bird", "Early Bird")),
+            AudioFxPreset::Clean,
+            MONDAY + 30 * HOUR,
+        );
+        log.end_at(MONDAY + 30 * HOUR + 600);
+        log.begin_at(
+            Some(&channel("1", "night-moves", "Night Moves (Repeat)")),
+            AudioFxPreset::Bass,
+            MONDAY + 48 * HOUR,
+        );
+        log.end_at(MONDAY + 48 * HOUR + 900);
+
+        let stats = log
+            .stats(&ListeningQuery {
+                // UTC+2.
+                utc_offset_minutes: 120,
+                ..ListeningQuery::default()
+            })
+            .expect("stats");
+        assert_eq!(stats.sessions, 3);
+        assert_eq!(stats.total_seconds, 5_400 + 600 + 900);
+
+        let shows = stats
+            .top_shows
+            .iter()
+            .map(|show| (show.show_alias.as_str(), show.title.as_str(), show.seconds))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shows,
+            vec![
+                ("night-moves", "Night Moves (Repeat)", 6_300),
+                ("early-bird", "Early Bird", 600)
+            ]
+        );
+        assert_eq!(
+            stats.sources,
+            vec![
+                SourceListening {
+                    kind: ListeningSourceKind::Channel,
+                    source: "NTS 1".to_string(),
+                    seconds: 6_300,
+                },
+                SourceListening {
+                    kind: ListeningSourceKind::Channel,
+                    source: "NTS 2".to_string(),
+                    seconds: 600,
+                },
+            ]
+        );
+
+        // In local time the first session runs Tuesday 01:30 to 03:00.
+        assert_eq!(stats.by_hour[1], 1_800);
+        assert_eq!(stats.by_hour[2], 3_600 + 900);
+        assert_eq!(stats.by_hour[8], 600);
+        assert_eq!(stats.by_weekday, vec![0, 6_000, 900, 0, 0, 0, 0]);
+        assert_eq!(stats.heatmap[1][2], 3_600);
+        assert_eq!(stats.heatmap[2][2], 900);
+        assert_eq!(stats.by_hour.iter().sum::<u64>(), stats.total_seconds);
+    }
+}
@@ -0,0 +1,770 @@
+use crate::audio_engine::StreamSource;
+use crate::podcast::{download_with_resume, fnv1a};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+const OFFLINE_CACHE_DIR_NAME: &str = "offline";
+const OFFLINE_INDEX_FILE_NAME: &str = "offline-cache.json";
+const OFFLINE_CACHE_EVENT: &str = "offline-cache";
+const DEFAULT_QUOTA_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const CAPTURE_SEGMENT_DURATION: Duration = Duration::from_secs(5 * 60);
+const MAX_CAPTURE_HOURS: u32 = 12;
+const CAPTURE_CHUNK_SIZE: usize = 16 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Blocking reads each time out on their own, so a stalled stream is noticed
+/// and reconnected rather than waited on forever.
+const READ_TIMEOUT: Duration = Duration::from_secs(15);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OfflineEntryKind {
+    /// A complete copy of a finite archive episode.
+    Download,
+    /// The most recent hours of an endless mixtape stream, as rolling segments.
+    Capture,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfflineEntry {
+    pub id: String,
+    pub stream_url: String,
+    pub title: Option<String>,
+    pub kind: OfflineEntryKind,
+    /// Audio files in playback order; captures list their oldest segment first.
+    pub files: Vec<PathBuf>,
+    pub size_bytes: u64,
+    pub created_at: u64,
+    pub last_accessed_at: u64,
+    #[serde(default)]
+    pub capture_hours: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfflineCacheStatus {
+    pub entries: Vec<OfflineEntry>,
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+    pub active_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct OfflineIndex {
+    quota_bytes: Option<u64>,
+    entries: Vec<OfflineEntry>,
+}
+
+impl OfflineIndex {
+    fn quota_bytes(&self) -> u64 {
+        self.quota_bytes.unwrap_or(DEFAULT_QUOTA_BYTES)
+    }
+
+    fn used_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size_bytes).sum()
+    }
+
+    fn entry_mut(&mut self, id: &str) -> Option<&mut OfflineEntry> {
+        self.entries.iter_mut().find(|entry| entry.id == id)
+    }
+
+    /// Drops least recently used entries until the cache fits its quota.
+    /// Entries that are still being written are never evicted.
+    fn evict_to_fit(&mut self, protected: &HashSet<String>) -> Vec<OfflineEntry> {
+        let quota = self.quota_bytes();
+        let mut evicted = Vec::new();
+        while self.used_bytes() > quota {
+            let Some(index) = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| !protected.contains(entry.id.as_str()))
+                .min_by_key(|(_, entry)| entry.last_accessed_at)
+                .map(|(index, _)| index)
+            else {
+                break;
+            };
+            evicted.push(self.entries.remove(index));
+        }
+        evicted
+    }
+}
+
+pub struct OfflineCacheManager {
+    inner: Arc<OfflineCacheInner>,
+}
+
+struct OfflineCacheInner {
+    app: AppHandle,
+    index_path: PathBuf,
+    cache_dir: PathBuf,
+    index: Mutex<OfflineIndex>,
+    downloads: Mutex<HashSet<String>>,
+    captures: Mutex<HashMap<String, Sender<()>>>,
+    http: reqwest::blocking::Client,
+}
+
+impl OfflineCacheManager {
+    pub fn new(app: AppHandle) -> Result<Self, String> {
+        let app_data_dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|error| format!("unable to resolve app data directory: {error}"))?;
+        let cache_dir = app_data_dir.join(OFFLINE_CACHE_DIR_NAME);
+        fs::create_dir_all(cache_dir.as_path())
+            .map_err(|error| format!("unable to create offline cache directory: {error}"))?;
+        let http = reqwest::blocking::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(READ_TIMEOUT)
+            .build()
+            .map_err(|error| format!("unable to build offline cache client: {error}"))?;
+        let index_path = app_data_dir.join(OFFLINE_INDEX_FILE_NAME);
+        let index = load_index(index_path.as_path()).unwrap_or_else(|error| {
+            eprintln!("[offline] unable to load cache index, starting empty: {error}");
+            OfflineIndex::default()
+        });
+
+        Ok(Self {
+            inner: Arc::new(OfflineCacheInner {
+                app,
+                index_path,
+                cache_dir,
+                index: Mutex::new(index),
+                downloads: Mutex::new(HashSet::new()),
+                captures: Mutex::new(HashMap::new()),
+                http,
+            }),
+        })
+    }
+
+    pub fn status(&self) -> OfflineCacheStatus {
+        self.inner.status()
+    }
+
+    pub fn set_quota(&self, quota_bytes: u64) -> Result<(), String> {
+        if quota_bytes == 0 {
+            return Err("The offline cache quota must be greater than zero.".to_string());
+        }
+        self.inner.update_index(|index| {
+            index.quota_bytes = Some(quota_bytes);
+            Ok(())
+        })
+    }
+
+    /// Downloads a finite recording (an archive episode) in the background.
+    pub fn save(&self, stream_url: &str, title: Option<String>) -> Result<(), String> {
+        self.inner.start_download(stream_url.trim(), title)
+    }
+
+    /// Starts recording the last `hours` of a live mixtape stream.
+    pub fn start_capture(
+        &self,
+        stream_url: &str,
+        title: Option<String>,
+        hours: u32,
+    ) -> Result<(), String> {
+        if !(1..=MAX_CAPTURE_HOURS).contains(&hours) {
+            return Err(format!(
+                "Captures can keep between 1 and {MAX_CAPTURE_HOURS} hours."
+            ));
+        }
+        self.inner.start_capture(stream_url.trim(), title, hours)
+    }
+
+    /// Signals the capture to stop; it stays active until its thread has
+    /// finished writing.
+    pub fn stop_capture(&self, stream_url: &str) -> Result<(), String> {
+        let id = entry_id(stream_url.trim());
+        let sender = self
+            .inner
+            .captures
+            .lock()
+            .map_err(|_| "offline capture state lock poisoned".to_string())?
+            .get(id.as_str())
+            .cloned();
+        match sender {
+            Some(sender) => {
+                let _ = sender.send(());
+                Ok(())
+            }
+            None => Err("This stream is not being captured.".to_string()),
+        }
+    }
+
+    pub fn remove(&self, stream_url: &str) -> Result<(), String> {
+        let id = entry_id(stream_url.trim());
+        if self.inner.active_ids().contains(id.as_str()) {
+            return Err("Stop the download or capture before removing it.".to_string());
+        }
+        let removed = self.inner.update_index(|index| {
+            let position = index
+                .entries
+                .iter()
+                .position(|entry| entry.id == id)
+                .ok_or_else(|| format!("no offline copy of {stream_url}"))?;
+            Ok(index.entries.remove(position))
+        })?;
+        remove_entry_files(&removed);
+        Ok(())
+    }
+
+    /// Returns a playable local copy of `stream_url`, if one is finished.
+    pub fn local_source(&self, stream_url: &str) -> Option<StreamSource> {
+        let active = self.inner.active_ids();
+        let mut index = self.inner.index.lock().ok()?;
+        let source = local_source_for(&index, stream_url, &active)?;
+
+        if let Some(entry) = index.entry_mut(entry_id(stream_url.trim()).as_str()) {
+            entry.last_accessed_at = epoch_seconds();
+        }
+        if let Err(error) = persist_index(self.inner.index_path.as_path(), &index) {
+            eprintln!("[offline] unable to record cache access: {error}");
+        }
+        Some(source)
+    }
+}
+
+impl OfflineCacheInner {
+    fn lock_index(&self) -> Result<std::sync::MutexGuard<'_, OfflineIndex>, String> {
+        self.index
+            .lock()
+            .map_err(|_| "offline cache index lock poisoned".to_string())
+    }
+
+    fn active_ids(&self) -> HashSet<String> {
+        let mut active = self
+            .downloads
+            .lock()
+            .map(|downloads| downloads.clone())
+            .unwrap_or_default();
+        if let Ok(captures) = self.captures.lock() {
+            active.extend(captures.keys().cloned());
+        }
+        active
+    }
+
+    fn status(&self) -> OfflineCacheStatus {
+        let index = match self.index.lock() {
+            Ok(index) => index.clone(),
+            Err(_) => OfflineIndex::default(),
+        };
+        let mut active_ids = self.active_ids().into_iter().collect::<Vec<_>>();
+        active_ids.sort();
+        OfflineCacheStatus {
+            used_bytes: index.used_bytes(),
+            quota_bytes: index.quota_bytes(),
+            entries: index.entries,
+            active_ids,
+        }
+    }
+
+    /// Applies `f`, evicts anything over quota, and persists the result.
+    fn update_index<T>(
+        &self,
+        f: impl FnOnce(&mut OfflineIndex) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let active = self.active_ids();
+        let (value, evicted) = {
+            let mut index = self.lock_index()?;
+            let mut next = index.clone();
+            let value = f(&mut next)?;
+            let evicted = next.evict_to_fit(&active);
+            persist_index(self.index_path.as_path(), &next)?;
+            *index = next;
+            (value, evicted)
+        };
+
+        for entry in evicted.iter() {
+            eprintln!("[offline] evicted {} to stay under quota", entry.stream_url);
+            remove_entry_files(entry);
+        }
+        self.emit_status();
+        Ok(value)
+    }
+
+    fn begin(&self, id: &str) -> Result<(), String> {
+        if self.active_ids().contains(id) {
+            return Err("This stream is already being saved.".to_string());
+        }
+        Ok(())
+    }
+
+    fn start_download(
+        self: &Arc<Self>,
+        stream_url: &str,
+        title: Option<String>,
+    ) -> Result<(), String> {
+        let id = entry_id(stream_url);
+        self.begin(id.as_str())?;
+        self.downloads
+            .lock()
+            .map_err(|_| "offline download state lock poisoned".to_string())?
+            .insert(id.clone());
+        self.emit_status();
+
+        let inner = Arc::clone(self);
+        let stream_url = stream_url.to_string();
+        std::thread::spawn(move || {
+            let final_path = inner.cache_dir.join(format!("{id}.mp3"));
+            let part_path = final_path.with_extension("part");
+            let result = download_with_resume(
+                &inner.http,
+                stream_url.as_str(),
+                part_path.as_path(),
+                |_, _| {},
+            )
+            .and_then(|size_bytes| {
+                fs::rename(part_path.as_path(), final_path.as_path()).map_err(|error| {
+                    format!("unable to finalize {}: {error}", final_path.display())
+                })?;
+                Ok(size_bytes)
+            });
+
+            if let Ok(mut downloads) = inner.downloads.lock() {
+                downloads.remove(id.as_str());
+            }
+
+            let result = result.and_then(|size_bytes| {
+                let now = epoch_seconds();
+                inner.update_index(|index| {
+                    index.entries.retain(|entry| entry.id != id);
+                    index.entries.push(OfflineEntry {
+                        id: id.clone(),
+                        stream_url: stream_url.clone(),
+                        title,
+                        kind: OfflineEntryKind::Download,
+                        files: vec![final_path.clone()],
+                        size_bytes,
+                        created_at: now,
+                        last_accessed_at: now,
+                        capture_hours: None,
+                    });
+                    Ok(())
+                })
+            });
+            if let Err(error) = result {
+                eprintln!("[offline] download failed for {stream_url}: {error}");
+                inner.emit_status();
+            }
+        });
+
+        Ok(())
+    }
+
+    fn start_capture(
+        self: &Arc<Self>,
+        stream_url: &str,
+        title: Option<String>,
+        hours: u32,
+    ) -> Result<(), String> {
+        let id = entry_id(stream_url);
+        self.begin(id.as_str())?;
+
+        let now = epoch_seconds();
+        self.update_index(|index| {
+            match index.entry_mut(id.as_str()) {
+                Some(entry) if entry.kind == OfflineEntryKind::Capture => {
+                    entry.capture_hours = Some(hours);
+                    if title.is_some() {
+                        entry.title = title;
+                    }
+                }
+                Some(_) => {
+                    return Err("This stream is already saved as a download.".to_string());
+                }
+                None => index.entries.push(OfflineEntry {
+                    id: id.clone(),
+                    stream_url: stream_url.to_string(),
+                    title,
+                    kind: OfflineEntryKind::Capture,
+                    files: Vec::new(),
+                    size_bytes: 0,
+                    created_at: now,
+                    last_accessed_at: now,
+                    capture_hours: Some(hours),
+                }),
+            }
+            Ok(())
+        })?;
+
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        self.captures
+            .lock()
+            .map_err(|_| "offline capture state lock poisoned".to_string())?
+            .insert(id.clone(), stop_tx);
+        self.emit_status();
+
+        let inner = Arc::clone(self);
+        let stream_url = stream_url.to_string();
+        std::thread::spawn(move || {
+            if let Err(error) = inner.run_capture(id.as_str(), stream_url.as_str(), hours, stop_rx)
+            {
+                eprintln!("[offline] capture of {stream_url} stopped: {error}");
+            }
+            if let Ok(mut captures) = inner.captures.lock() {
+                captures.remove(id.as_str());
+            }
+            inner.emit_status();
+        });
+
+        Ok(())
+    }
+
+    fn run_capture(
+        &self,
+        id: &str,
+        stream_url: &str,
+        hours: u32,
+        stop_rx: Receiver<()>,
+    ) -> Result<(), String> {
+        let directory = self.cache_dir.join(id);
+        fs::create_dir_all(directory.as_path())
+            .map_err(|error| format!("unable to create {}: {error}", directory.display()))?;
+
+        // Only the first connection has to succeed; after that the capture
+        // rides out network hiccups until it is stopped.
+        let mut response = Some(self.open_stream(stream_url)?);
+        let mut reconnect_delay = MIN_RECONNECT_DELAY;
+
+        let keep_segments = segments_for_hours(hours);
+        let mut buffer = vec![0_u8; CAPTURE_CHUNK_SIZE];
+        let mut segment: Option<(PathBuf, File, u64, Instant)> = None;
+        let result = loop {
+            match stop_rx.try_recv() {
+                Ok(_) | Err(TryRecvError::Disconnected) => break Ok(()),
+                Err(TryRecvError::Empty) => {}
+            }
+
+            let Some(stream) = response.as_mut() else {
+                match stop_rx.recv_timeout(reconnect_delay) {
+                    Ok(_) | Err(RecvTimeoutError::Disconnected) => break Ok(()),
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                match self.open_stream(stream_url) {
+                    Ok(reopened) => response = Some(reopened),
+                    Err(error) => eprintln!("[offline] unable to reconnect {stream_url}: {error}"),
+                }
+                continue;
+            };
+
+            let read = match stream.read(&mut buffer) {
+                Ok(0) => {
+                    eprintln!("[offline] {stream_url} ended, reconnecting");
+                    response = None;
+                    continue;
+                }
+                Ok(read) => read,
+                Err(error) => {
+                    eprintln!("[offline] reading {stream_url} failed, reconnecting: {error}");
+                    response = None;
+                    continue;
+                }
+            };
+            reconnect_delay = MIN_RECONNECT_DELAY;
+
+            if segment.is_none() {
+                let path = directory.join(format!("segment-{:x}.mp3", epoch_nanos()));
+                let file = File::create(path.as_path())
+                    .map_err(|error| format!("unable to create {}: {error}", path.display()))?;
+                segment = Some((path, file, 0, Instant::now()));
+            }
+            let Some((path, file, written, started)) = segment.as_mut() else {
+                continue;
+            };
+            file.write_all(&buffer[..read])
+                .map_err(|error| format!("unable to write {}: {error}", path.display()))?;
+            *written += read as u64;
+
+            if started.elapsed() >= CAPTURE_SEGMENT_DURATION {
+                if let Some((path, _, written, _)) = segment.take() {
+                    self.commit_segment(id, path, written, keep_segments)?;
+                }
+            }
+        };
+
+        if let Some((path, mut file, written, _)) = segment.take() {
+            let _ = file.flush();
+            if written > 0 {
+                self.commit_segment(id, path, written, keep_segments)?;
+            } else {
+                let _ = fs::remove_file(path);
+            }
+        }
+        result
+    }
+
+    fn open_stream(&self, stream_url: &str) -> Result<reqwest::blocking::Response, String> {
+        let response = self
+            .http
+            .get(stream_url)
+            .send()
+            .map_err(|error| format!("stream request failed: {error}"))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "stream request failed with status {}",
+                response.status().as_u16()
+            ));
+        }
+        Ok(response)
+    }
+
+    fn commit_segment(
+        &self,
+        id: &str,
+        path: PathBuf,
+        size_bytes: u64,
+        keep_segments: usize,
+    ) -> Result<(), String> {
+        let pruned = self.update_index(|index| {
+            let quota = index.quota_bytes();
+            let entry = index
+                .entry_mut(id)
+                .ok_or_else(|| "capture was removed from the offline cache".to_string())?;
+            entry.files.push(path.clone());
+            entry.size_bytes += size_bytes;
+
+            let mut pruned = Vec::new();
+            while entry.files.len() > 1
+                && (entry.files.len() > keep_segments || entry.size_bytes > quota)
+            {
+                let oldest = entry.files.remove(0);
+                entry.size_bytes = entry.size_bytes.saturating_sub(file_size(oldest.as_path()));
+                pruned.push(oldest);
+            }
+            Ok(pruned)
+        })?;
+
+        for path in pruned {
+            if let Err(error) = fs::remove_file(path.as_path()) {
+                eprintln!("[offline] unable to remove {}: {error}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_status(&self) {
+        if let Err(error) = self.app.emit(OFFLINE_CACHE_EVENT, self.status()) {
+            eprintln!("[offline] failed to emit cache event: {error}");
+        }
+    }
+}
+
+fn local_source_for(
+    index: &OfflineIndex,
+    stream_url: &str,
+    active: &HashSet<String>,
+) -> Option<StreamSource> {
+    let id = entry_id(stream_url.trim());
+    if active.contains(id.as_str()) {
+        return None;
+    }
+    let entry = index.entries.iter().find(|entry| entry.id == id)?;
+    let files = entry
+        .files
+        .iter()
+        .filter(|path| path.exists())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    match entry.kind {
+        OfflineEntryKind::Download => files.into_iter().next().map(|path| StreamSource::File {
+            path,
+            start_at: Duration::ZERO,
+            start_byte: 0,
+        }),
+        OfflineEntryKind::Capture if !files.is_empty() => Some(StreamSource::Segments(files)),
+        OfflineEntryKind::Capture => None,
+    }
+}
+
+fn segments_for_hours(hours: u32) -> usize {
+    let segment_seconds = CAPTURE_SEGMENT_DURATION.as_secs().max(1);
+    (hours as u64 * 3_600).div_ceil(segment_seconds) as usize
+}
+
+fn remove_entry_files(entry: &OfflineEntry) {
+    for path in entry.files.iter() {
+        if let Err(error) = fs::remove_file(path.as_path()) {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("[offline] unable to remove {}: {error}", path.display());
+            }
+        }
+    }
+    if entry.kind == OfflineEntryKind::Capture {
+        if let Some(directory) = entry.files.first().and_then(|path| path.parent()) {
+            let _ = fs::remove_dir(directory);
+        }
+    }
+}
+
+fn entry_id(stream_url: &str) -> String {
+    format!("offline-{:016x}", fnv1a(stream_url.as_bytes()))
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+fn load_index(path: &Path) -> Result<OfflineIndex, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(OfflineIndex::default())
+        }
+        Err(error) => {
+            return Err(format!(
+                "unable to read offline cache index from {}: {error}",
+                path.display()
+            ))
+        }
+    };
+
+    serde_json::from_str::<OfflineIndex>(contents.as_str()).map_err(|error| {
+        format!(
+            "unable to parse offline cache index from {}: {error}",
+            path.display()
+        )
+    })
+}
+
+fn persist_index(path: &Path, index: &OfflineIndex) -> Result<(), String> {
+    let bytes = serde_json::to_vec_pretty(index)
+        .map_err(|error| format!("unable to serialize offline cache index: {error}"))?;
+    fs::write(path, bytes).map_err(|error| {
+        format!(
+            "unable to write offline cache index to {}: {error}",
+            path.display()
+        )
+    })
+}
+
+fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or(0)
+}
+
+fn epoch_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_nanos())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        entry_id, local_source_for, segments_for_hours, OfflineEntry, OfflineEntryKind,
+        OfflineIndex,
+    };
+    use crate::audio_engine::StreamSource;
+    use crate::test_support::test_dir;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn entry(
+        url: &str,
+        kind: OfflineEntryKind,
+        files: Vec<PathBuf>,
+        size: u64,
+        accessed: u64,
+    ) -> OfflineEntry {
+        OfflineEntry {
+            id: entry_id(url),
+            stream_url: url.to_string(),
+            title: None,
+            kind,
+            files,
+            size_bytes: size,
+            created_at: 0,
+            last_accessed_at: accessed,
+            capture_hours: None,
+        }
+    }
+
+    #[test]
+    fn evict_to_fit_drops_least_recently_used_unprotected_entries() {
+        let mut index = OfflineIndex {
+            quota_bytes: Some(250),
+            entries: vec![
+                entry("https://a", OfflineEntryKind::Download, Vec::new(), 100, 10),
+                entry("https://b", OfflineEntryKind::Download, Vec::new(), 100, 5),
+                entry("https://c", OfflineEntryKind::Capture, Vec::new(), 100, 1),
+            ],
+        };
+        let protected = HashSet::from([entry_id("https://c")]);
+
+        let evicted = index.evict_to_fit(&protected);
+        assert_eq!(
+            evicted
+                .iter()
+                .map(|entry| entry.stream_url.as_str())
+                .collect::<Vec<_>>(),
+            vec!["https://b"]
+        );
+        assert_eq!(index.used_bytes(), 200);
+    }
+
+    #[test]
+    fn local_source_prefers_finished_copies_that_exist_on_disk() {
+        let dir = test_dir("local-source");
+        let episode = dir.join("episode.mp3");
+        let segment = dir.join("segment-1.mp3");
+        fs::write(episode.as_path(), b"mp3").expect("failed to write episode");
+        fs::write(segment.as_path(), b"mp3").expect("failed to write segment");
+
+        let index = OfflineIndex {
+            quota_bytes: None,
+            entries: vec![
+                entry(
+                    "https://episode",
+                    OfflineEntryKind::Download,
+                    vec![episode.clone()],
+                    3,
+                    0,
+                ),
+                entry(
+                    "https://mixtape",
+                    OfflineEntryKind::Capture,
+                    vec![dir.join("segment-0.mp3"), segment.clone()],
+                    3,
+                    0,
+                ),
+            ],
+        };
+
+        let none = HashSet::new();
+        assert!(matches!(
+            local_source_for(&index, "https://episode", &none),
+            Some(StreamSource::File { path, .. }) if path == episode
+        ));
+        assert!(matches!(
+            local_source_for(&index, "https://mixtape", &none),
+            Some(StreamSource::Segments(files)) if files == vec![segment.clone()]
+        ));
+        let recording = HashSet::from([entry_id("https://mixtape")]);
+        assert!(local_source_for(&index, "https://mixtape", &recording).is_none());
+        assert!(local_source_for(&index, "https://unknown", &none).is_none());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn segments_for_hours_covers_the_requested_window() {
+        assert_eq!(segments_for_hours(1), 12);
+        assert_eq!(segments_for_hours(3), 36);
+    }
+}
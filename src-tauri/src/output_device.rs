@@ -0,0 +1,402 @@
+//! Output devices: listing them, playing through the one the user picked,
+//! and moving playback when that changes or the device goes away.
+
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{Device, OutputStream, Sink};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+pub const OUTPUT_FALLBACK_EVENT: &str = "audio-output-fallback";
+/// How often the device watcher checks on the device in use.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<OutputConfigRange>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputConfigRange {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: String,
+}
+
+/// Sent when the selected device can't be used and playback moved to the
+/// default one.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputFallback {
+    pub requested: String,
+    pub device: Option<String>,
+}
+
+pub type OutputFallbackCallback = dyn Fn(&OutputFallback) + Send + Sync;
+
+pub fn list_output_devices() -> Result<Vec<OutputDeviceInfo>, String> {
+    let host = rodio::cpal::default_host();
+    let default_name = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+    let devices = host
+        .output_devices()
+        .map_err(|error| format!("unable to list output devices: {error}"))?;
+
+    Ok(devices
+        .filter_map(|device| {
+            let name = device.name().ok()?;
+            let configs = device
+                .supported_output_configs()
+                .map(|configs| {
+                    configs
+                        .map(|config| OutputConfigRange {
+                            channels: config.channels(),
+                            min_sample_rate: config.min_sample_rate().0,
+                            max_sample_rate: config.max_sample_rate().0,
+                            sample_format: config.sample_format().to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(OutputDeviceInfo {
+                is_default: default_name.as_deref() == Some(name.as_str()),
+                name,
+                configs,
+            })
+        })
+        .collect())
+}
+
+/// The device to play through; `None` follows the system default. Read by
+/// the output thread, which reopens its device whenever the generation
+/// moves: when the selection changes, or when the device watcher finds the
+/// device in use is stale.
+#[derive(Default)]
+pub struct OutputSelection {
+    device: Mutex<Option<String>>,
+    generation: AtomicU64,
+    /// Where the output thread is playing now; `None` while it's closed.
+    playing: Mutex<Option<OutputTarget>>,
+    /// The selected device the user was last told is unavailable.
+    fallen_back: Mutex<Option<String>>,
+    on_fallback: Mutex<Option<Arc<OutputFallbackCallback>>>,
+}
+
+impl OutputSelection {
+    pub fn get(&self) -> Option<String> {
+        self.device.lock().ok().and_then(|device| device.clone())
+    }
+
+    pub fn set(&self, device: Option<String>) {
+        if let Ok(mut current) = self.device.lock() {
+            *current = device;
+        }
+        if let Ok(mut fallen_back) = self.fallen_back.lock() {
+            *fallen_back = None;
+        }
+        self.bump();
+    }
+
+    pub fn set_on_fallback(&self, callback: Arc<OutputFallbackCallback>) {
+        if let Ok(mut on_fallback) = self.on_fallback.lock() {
+            *on_fallback = Some(callback);
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    fn bump(&self) {
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    fn set_playing(&self, target: Option<OutputTarget>) {
+        if let Ok(mut playing) = self.playing.lock() {
+            *playing = target;
+        }
+    }
+
+    /// Run by the device watcher; enumerating devices can take a while, so
+    /// the output thread never does it.
+    fn check_devices(&self) {
+        let Some(playing) = self.playing.lock().ok().and_then(|playing| playing.clone()) else {
+            return;
+        };
+        if playing.is_stale(self.get().as_deref(), &DeviceSnapshot::take().0) {
+            self.bump();
+        }
+    }
+
+    /// Whether opening `target` for `requested` is news: playback just moved
+    /// off the selected device. Reopening on the same fallback, after a
+    /// device switch or an idle close, isn't.
+    fn is_new_fallback(&self, requested: Option<&str>, target: &OutputTarget) -> bool {
+        let Ok(mut fallen_back) = self.fallen_back.lock() else {
+            return false;
+        };
+        match (requested, target) {
+            (Some(requested), OutputTarget::Default(_)) => {
+                if fallen_back.as_deref() == Some(requested) {
+                    return false;
+                }
+                *fallen_back = Some(requested.to_string());
+                true
+            }
+            _ => {
+                *fallen_back = None;
+                false
+            }
+        }
+    }
+
+    fn notify_fallback(&self, fallback: &OutputFallback) {
+        eprintln!(
+            "[audio] output device {} unavailable, playing through {}",
+            fallback.requested,
+            fallback.device.as_deref().unwrap_or("the default device")
+        );
+        let callback = self
+            .on_fallback
+            .lock()
+            .ok()
+            .and_then(|callback| callback.as_ref().map(Arc::clone));
+        if let Some(callback) = callback {
+            callback(fallback);
+        }
+    }
+}
+
+/// Checks on the output device every `DEVICE_CHECK_INTERVAL` while one is
+/// open, until the selection is dropped.
+pub(crate) fn spawn_device_watcher(selection: Weak<OutputSelection>) {
+    let spawned = thread::Builder::new()
+        .name("audio-devices".to_string())
+        .spawn(move || loop {
+            thread::sleep(DEVICE_CHECK_INTERVAL);
+            let Some(selection) = selection.upgrade() else {
+                return;
+            };
+            selection.check_devices();
+        });
+    if let Err(error) = spawned {
+        eprintln!("[audio] unable to start device watcher: {error}");
+    }
+}
+
+/// The output devices present at one moment.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct DeviceSnapshot {
+    names: Vec<String>,
+    default: Option<String>,
+}
+
+impl DeviceSnapshot {
+    /// The snapshot, and the devices behind `names` in the same order.
+    fn take() -> (Self, Vec<Device>) {
+        let host = rodio::cpal::default_host();
+        let (names, devices) = host
+            .output_devices()
+            .map(|devices| {
+                devices
+                    .filter_map(|device| Some((device.name().ok()?, device)))
+                    .unzip()
+            })
+            .unwrap_or_default();
+        let snapshot = Self {
+            names,
+            default: host
+                .default_output_device()
+                .and_then(|device| device.name().ok()),
+        };
+        (snapshot, devices)
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|item| item == name)
+    }
+}
+
+/// Where the output is playing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum OutputTarget {
+    Selected(String),
+    /// The system default, by the name it had when opened.
+    Default(Option<String>),
+}
+
+impl OutputTarget {
+    /// The selected device when it's present, otherwise the default.
+    fn choose(selected: Option<&str>, devices: &DeviceSnapshot) -> Self {
+        match selected {
+            Some(name) if devices.contains(name) => Self::Selected(name.to_string()),
+            _ => Self::Default(devices.default.clone()),
+        }
+    }
+
+    /// Whether playback should move: the device in use went away, the
+    /// default changed, or a selected device that went away is back.
+    fn is_stale(&self, selected: Option<&str>, devices: &DeviceSnapshot) -> bool {
+        Self::choose(selected, devices) != *self
+    }
+}
+
+/// The output thread's device. Switching devices replaces the sink; the
+/// little audio queued in the old one is dropped, the mix keeps going.
+pub(crate) struct ActiveOutput {
+    _stream: OutputStream,
+    pub sink: Sink,
+    selection: Arc<OutputSelection>,
+    generation: u64,
+}
+
+impl ActiveOutput {
+    pub fn open(selection: &Arc<OutputSelection>) -> Result<Self, String> {
+        let generation = selection.generation();
+        let (stream, sink) = open_sink(selection)?;
+        Ok(Self {
+            _stream: stream,
+            sink,
+            selection: Arc::clone(selection),
+            generation,
+        })
+    }
+
+    /// Moves to another device when the generation moved. Cheap: it never
+    /// looks at the devices itself.
+    pub fn maintain(&mut self) {
+        let generation = self.selection.generation();
+        if generation == self.generation {
+            return;
+        }
+
+        self.generation = generation;
+        match open_sink(&self.selection) {
+            Ok((stream, sink)) => {
+                self.sink.stop();
+                self._stream = stream;
+                self.sink = sink;
+            }
+            Err(error) => eprintln!("[audio] unable to switch output device: {error}"),
+        }
+    }
+}
+
+impl Drop for ActiveOutput {
+    fn drop(&mut self) {
+        self.selection.set_playing(None);
+    }
+}
+
+/// Opens the selected device, or the default one when it can't be, and
+/// records where it ended up.
+fn open_sink(selection: &OutputSelection) -> Result<(OutputStream, Sink), String> {
+    let requested = selection.get();
+    let (devices, mut handles) = DeviceSnapshot::take();
+    let mut opened = None;
+    if let OutputTarget::Selected(name) = OutputTarget::choose(requested.as_deref(), &devices) {
+        let index = devices.names.iter().position(|item| *item == name);
+        match index.map(|index| OutputStream::try_from_device(&handles.swap_remove(index))) {
+            Some(Ok((stream, handle))) => {
+                let sink =
+                    Sink::try_new(&handle).map_err(|error| format!("sink error: {}", error))?;
+                opened = Some((stream, sink, OutputTarget::Selected(name)));
+            }
+            Some(Err(error)) => eprintln!("[audio] unable to open output device {name}: {error}"),
+            None => {}
+        }
+    }
+    let (stream, sink, target) = match opened {
+        Some(opened) => opened,
+        None => {
+            let (stream, handle) = OutputStream::try_default()
+                .map_err(|error| format!("output stream error: {}", error))?;
+            let sink = Sink::try_new(&handle).map_err(|error| format!("sink error: {}", error))?;
+            (stream, sink, OutputTarget::Default(devices.default.clone()))
+        }
+    };
+
+    if selection.is_new_fallback(requested.as_deref(), &target) {
+        if let Some(requested) = requested {
+            selection.notify_fallback(&OutputFallback {
+                requested,
+                device: devices.default,
+            });
+        }
+    }
+    selection.set_playing(Some(target));
+    Ok((stream, sink))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceSnapshot, OutputSelection, OutputTarget};
+
+    fn devices(names: &[&str], default: Option<&str>) -> DeviceSnapshot {
+        DeviceSnapshot {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            default: default.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn follows_the_default_device_when_nothing_is_selected() {
+        let speakers = devices(&["Speakers", "Headphones"], Some("Speakers"));
+        let target = OutputTarget::choose(None, &speakers);
+        assert_eq!(target, OutputTarget::Default(Some("Speakers".to_string())));
+        assert!(!target.is_stale(None, &speakers));
+
+        // Headphones plugged in and made the default.
+        let headphones = devices(&["Speakers", "Headphones"], Some("Headphones"));
+        assert!(target.is_stale(None, &headphones));
+        // The default went away with nothing to replace it.
+        assert!(target.is_stale(None, &devices(&[], None)));
+    }
+
+    #[test]
+    fn falls_back_to_the_default_until_the_selected_device_is_back() {
+        let unplugged = devices(&["Speakers"], Some("Speakers"));
+        let target = OutputTarget::choose(Some("USB DAC"), &unplugged);
+        assert_eq!(target, OutputTarget::Default(Some("Speakers".to_string())));
+        assert!(!target.is_stale(Some("USB DAC"), &unplugged));
+
+        let plugged_in = devices(&["Speakers", "USB DAC"], Some("Speakers"));
+        assert!(target.is_stale(Some("USB DAC"), &plugged_in));
+        let target = OutputTarget::choose(Some("USB DAC"), &plugged_in);
+        assert_eq!(target, OutputTarget::Selected("USB DAC".to_string()));
+        // A selected device stays put when the default changes...
+        assert!(!target.is_stale(
+            Some("USB DAC"),
+            &devices(&["Speakers", "USB DAC"], Some("USB DAC"))
+        ));
+        // ...and moves when it's unplugged again.
+        assert!(target.is_stale(Some("USB DAC"), &unplugged));
+    }
+
+    #[test]
+    fn a_fallback_is_reported_once_until_the_selected_device_is_back() {
+        let selection = OutputSelection::default();
+        selection.set(Some("USB DAC".to_string()));
+        let speakers = OutputTarget::Default(Some("Speakers".to_string()));
+        let dac = OutputTarget::Selected("USB DAC".to_string());
+
+        assert!(selection.is_new_fallback(Some("USB DAC"), &speakers));
+        // Reopened on the same fallback, e.g. after closing while idle.
+        assert!(!selection.is_new_fallback(Some("USB DAC"), &speakers));
+        assert!(!selection.is_new_fallback(Some("USB DAC"), &dac));
+        assert!(selection.is_new_fallback(Some("USB DAC"), &speakers));
+
+        // Picking the device again reports it again.
+        selection.set(Some("USB DAC".to_string()));
+        assert!(selection.is_new_fallback(Some("USB DAC"), &speakers));
+        assert!(!selection.is_new_fallback(None, &speakers));
+    }
+}
@@ -0,0 +1,1245 @@
+use crate::audio_engine::{NowPlayingMetadata, PlaybackPositionCallback, StreamSource};
+use crate::datetime;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+
+const PODCAST_LIBRARY_FILE_NAME: &str = "podcasts.json";
+const PODCAST_DOWNLOAD_DIR_NAME: &str = "podcasts";
+const PODCAST_LIBRARY_EVENT: &str = "podcast-library";
+const PODCAST_DOWNLOAD_EVENT: &str = "podcast-download";
+const POLL_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const POSITION_PERSIST_INTERVAL: Duration = Duration::from_secs(15);
+const DOWNLOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParsedFeed {
+    pub title: String,
+    pub description: Option<String>,
+    pub link: Option<String>,
+    pub author: Option<String>,
+    pub image_url: Option<String>,
+    pub episodes: Vec<ParsedEpisode>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParsedEpisode {
+    pub guid: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub published_at: Option<u64>,
+    pub enclosure_url: String,
+    pub enclosure_type: Option<String>,
+    pub enclosure_length: Option<u64>,
+    pub duration_seconds: Option<u64>,
+    pub image_url: Option<String>,
+    pub episode_number: Option<u32>,
+    pub season_number: Option<u32>,
+    pub explicit: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodcastEpisode {
+    pub id: String,
+    pub guid: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub published_at: Option<u64>,
+    pub enclosure_url: String,
+    pub enclosure_type: Option<String>,
+    pub enclosure_length: Option<u64>,
+    pub duration_seconds: Option<u64>,
+    pub image_url: Option<String>,
+    pub episode_number: Option<u32>,
+    pub season_number: Option<u32>,
+    pub explicit: Option<bool>,
+    #[serde(default)]
+    pub download_path: Option<PathBuf>,
+    #[serde(default)]
+    pub resume_position_ms: u64,
+    #[serde(default)]
+    pub played: bool,
+    #[serde(default)]
+    pub is_new: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodcastSubscription {
+    pub id: String,
+    pub feed_url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub link: Option<String>,
+    pub author: Option<String>,
+    pub image_url: Option<String>,
+    pub subscribed_at: u64,
+    pub last_polled_at: Option<u64>,
+    pub episodes: Vec<PodcastEpisode>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct PodcastLibrary {
+    subscriptions: Vec<PodcastSubscription>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PodcastLibraryPayload {
+    subscriptions: Vec<PodcastSubscription>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PodcastDownloadPayload {
+    episode_id: String,
+    state: String,
+    downloaded_bytes: u64,
+    total_bytes: Option<u64>,
+    message: Option<String>,
+}
+
+pub struct PodcastManager {
+    inner: Arc<PodcastInner>,
+}
+
+struct PodcastInner {
+    app: AppHandle,
+    library_path: PathBuf,
+    download_dir: PathBuf,
+    library: Mutex<PodcastLibrary>,
+    downloads: Mutex<HashSet<String>>,
+    last_position_persist: Mutex<Option<Instant>>,
+    /// Wakes the writer that saves resume positions, so the playback worker
+    /// never waits on the disk.
+    position_writes: Sender<()>,
+    http: reqwest::blocking::Client,
+}
+
+impl PodcastManager {
+    pub fn new(app: AppHandle) -> Result<Self, String> {
+        let app_data_dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|error| format!("unable to resolve app data directory: {error}"))?;
+        let download_dir = app_data_dir.join(PODCAST_DOWNLOAD_DIR_NAME);
+        fs::create_dir_all(download_dir.as_path())
+            .map_err(|error| format!("unable to create podcast directory: {error}"))?;
+        let library_path = app_data_dir.join(PODCAST_LIBRARY_FILE_NAME);
+        let library = load_library(library_path.as_path()).unwrap_or_else(|error| {
+            eprintln!("[podcast] unable to load library, starting empty: {error}");
+            PodcastLibrary::default()
+        });
+
+        let (position_writes, position_writes_rx) = mpsc::channel();
+        let inner = Arc::new(PodcastInner {
+            app,
+            library_path,
+            download_dir,
+            library: Mutex::new(library),
+            downloads: Mutex::new(HashSet::new()),
+            last_position_persist: Mutex::new(None),
+            position_writes,
+            http: reqwest::blocking::Client::new(),
+        });
+        spawn_poller(Arc::downgrade(&inner));
+        spawn_position_writer(Arc::downgrade(&inner), position_writes_rx);
+
+        Ok(Self { inner })
+    }
+
+    pub fn subscriptions(&self) -> Vec<PodcastSubscription> {
+        self.inner.subscriptions()
+    }
+
+    pub fn subscribe(&self, feed_url: &str) -> Result<PodcastSubscription, String> {
+        let feed_url = feed_url.trim();
+        let parsed = self.inner.fetch_feed(feed_url)?;
+
+        let mut library = self.inner.lock_library()?;
+        if let Some(existing) = library
+            .subscriptions
+            .iter()
+            .find(|subscription| subscription.feed_url == feed_url)
+        {
+            return Ok(existing.clone());
+        }
+
+        let mut subscription = PodcastSubscription {
+            id: format!("podcast-{:016x}", fnv1a(feed_url.as_bytes())),
+            feed_url: feed_url.to_string(),
+            subscribed_at: epoch_seconds(),
+            ..PodcastSubscription::default()
+        };
+        apply_feed(&mut subscription, parsed);
+        for episode in subscription.episodes.iter_mut() {
+            episode.is_new = false;
+        }
+        subscription.last_polled_at = Some(epoch_seconds());
+
+        let mut next = library.clone();
+        next.subscriptions.push(subscription.clone());
+        persist_library(self.inner.library_path.as_path(), &next)?;
+        *library = next;
+        drop(library);
+
+        self.inner.emit_library();
+        Ok(subscription)
+    }
+
+    pub fn unsubscribe(&self, subscription_id: &str) -> Result<(), String> {
+        self.inner.update_library(|library| {
+            let before = library.subscriptions.len();
+            library
+                .subscriptions
+                .retain(|subscription| subscription.id != subscription_id);
+            if library.subscriptions.len() == before {
+                return Err(format!("unknown podcast subscription: {subscription_id}"));
+            }
+            Ok(())
+        })?;
+
+        let directory = self.inner.download_dir.join(subscription_id);
+        if directory.exists() {
+            if let Err(error) = fs::remove_dir_all(directory.as_path()) {
+                eprintln!("[podcast] unable to remove downloads for {subscription_id}: {error}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Polls one subscription, or all of them, returning how many new
+    /// episodes were found.
+    pub fn refresh(&self, subscription_id: Option<&str>) -> Result<usize, String> {
+        self.inner.refresh(subscription_id)
+    }
+
+    pub fn download_episode(&self, episode_id: &str) -> Result<(), String> {
+        self.inner.start_download(episode_id)
+    }
+
+    pub fn delete_download(&self, episode_id: &str) -> Result<(), String> {
+        let path = self.inner.update_library(|library| {
+            let (_, episode) = find_episode_mut(library, episode_id)?;
+            Ok(episode.download_path.take())
+        })?;
+        if let Some(path) = path {
+            if let Err(error) = fs::remove_file(path.as_path()) {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    return Err(format!("unable to delete {}: {error}", path.display()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn playback_for(
+        &self,
+        episode_id: &str,
+    ) -> Result<
+        (
+            StreamSource,
+            NowPlayingMetadata,
+            Arc<PlaybackPositionCallback>,
+        ),
+        String,
+    > {
+        let (subscription, episode) = {
+            let library = self.inner.lock_library()?;
+            let (subscription, episode) = find_episode(&library, episode_id)?;
+            (subscription.clone(), episode.clone())
+        };
+
+        if !is_mp3_enclosure(&episode) {
+            return Err("Only MP3 podcast episodes can be played.".to_string());
+        }
+
+        let source = episode_source(&episode);
+
+        let now_playing = NowPlayingMetadata {
+            title: episode.title.clone(),
+            artist: subscription.author.clone(),
+            album: Some(subscription.title.clone()),
+            artwork_url: episode.image_url.clone().or(subscription.image_url.clone()),
+            show_alias: None,
+            episode_alias: None,
+        };
+
+        let weak = Arc::downgrade(&self.inner);
+        let episode_id = episode.id.clone();
+        let on_position: Arc<PlaybackPositionCallback> = Arc::new(move |position, finished| {
+            if let Some(inner) = weak.upgrade() {
+                inner.record_position(episode_id.as_str(), position, finished);
+            }
+        });
+
+        Ok((source, now_playing, on_position))
+    }
+}
+
+impl PodcastInner {
+    fn lock_library(&self) -> Result<std::sync::MutexGuard<'_, PodcastLibrary>, String> {
+        self.library
+            .lock()
+            .map_err(|_| "podcast library lock poisoned".to_string())
+    }
+
+    fn subscriptions(&self) -> Vec<PodcastSubscription> {
+        match self.library.lock() {
+            Ok(library) => library.subscriptions.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn update_library<T>(
+        &self,
+        f: impl FnOnce(&mut PodcastLibrary) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let value = {
+            let mut library = self.lock_library()?;
+            let mut next = library.clone();
+            let value = f(&mut next)?;
+            persist_library(self.library_path.as_path(), &next)?;
+            *library = next;
+            value
+        };
+        self.emit_library();
+        Ok(value)
+    }
+
+    fn fetch_feed(&self, feed_url: &str) -> Result<ParsedFeed, String> {
+        let response = self
+            .http
+            .get(feed_url)
+            .send()
+            .map_err(|error| format!("podcast feed request failed: {error}"))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "podcast feed request failed with status {}",
+                response.status().as_u16()
+            ));
+        }
+        let body = response
+            .text()
+            .map_err(|error| format!("unable to read podcast feed: {error}"))?;
+        parse_feed(body.as_str())
+    }
+
+    fn refresh(&self, subscription_id: Option<&str>) -> Result<usize, String> {
+        let targets = self
+            .subscriptions()
+            .into_iter()
+            .filter(|subscription| subscription_id.is_none_or(|id| subscription.id == id))
+            .map(|subscription| (subscription.id, subscription.feed_url))
+            .collect::<Vec<_>>();
+        if let Some(id) = subscription_id {
+            if targets.is_empty() {
+                return Err(format!("unknown podcast subscription: {id}"));
+            }
+        }
+
+        let mut fetched = Vec::new();
+        for (id, feed_url) in targets {
+            match self.fetch_feed(feed_url.as_str()) {
+                Ok(parsed) => fetched.push((id, parsed)),
+                Err(error) => eprintln!("[podcast] refresh failed for {feed_url}: {error}"),
+            }
+        }
+
+        self.update_library(|library| {
+            let mut new_episodes = 0;
+            for (id, parsed) in fetched {
+                if let Some(subscription) = library
+                    .subscriptions
+                    .iter_mut()
+                    .find(|subscription| subscription.id == id)
+                {
+                    new_episodes += apply_feed(subscription, parsed);
+                    subscription.last_polled_at = Some(epoch_seconds());
+                }
+            }
+            Ok(new_episodes)
+        })
+    }
+
+    fn start_download(self: &Arc<Self>, episode_id: &str) -> Result<(), String> {
+        let (subscription_id, episode) = {
+            let library = self.lock_library()?;
+            let (subscription, episode) = find_episode(&library, episode_id)?;
+            (subscription.id.clone(), episode.clone())
+        };
+
+        {
+            let mut downloads = self
+                .downloads
+                .lock()
+                .map_err(|_| "podcast download state lock poisoned".to_string())?;
+            if !downloads.insert(episode.id.clone()) {
+                return Err("This episode is already downloading.".to_string());
+            }
+        }
+
+        let directory = self.download_dir.join(subscription_id.as_str());
+        let final_path = directory.join(format!(
+            "{}.{}",
+            episode.id,
+            enclosure_extension(episode.enclosure_url.as_str())
+        ));
+        let part_path = final_path.with_extension("part");
+
+        let inner = Arc::clone(self);
+        std::thread::spawn(move || {
+            let result = fs::create_dir_all(directory.as_path())
+                .map_err(|error| format!("unable to create {}: {error}", directory.display()))
+                .and_then(|_| {
+                    let mut last_emit = Instant::now();
+                    download_with_resume(
+                        &inner.http,
+                        episode.enclosure_url.as_str(),
+                        part_path.as_path(),
+                        |downloaded, total| {
+                            if last_emit.elapsed() >= DOWNLOAD_PROGRESS_INTERVAL {
+                                last_emit = Instant::now();
+                                inner.emit_download(
+                                    episode.id.as_str(),
+                                    "progress",
+                                    downloaded,
+                                    total,
+                                    None,
+                                );
+                            }
+                        },
+                    )
+                })
+                .and_then(|downloaded| {
+                    fs::rename(part_path.as_path(), final_path.as_path()).map_err(|error| {
+                        format!("unable to finalize {}: {error}", final_path.display())
+                    })?;
+                    inner.update_library(|library| {
+                        let (_, stored) = find_episode_mut(library, episode.id.as_str())?;
+                        stored.download_path = Some(final_path.clone());
+                        Ok(())
+                    })?;
+                    Ok(downloaded)
+                });
+
+            match result {
+                Ok(downloaded) => inner.emit_download(
+                    episode.id.as_str(),
+                    "complete",
+                    downloaded,
+                    Some(downloaded),
+                    None,
+                ),
+                Err(error) => {
+                    eprintln!("[podcast] download failed for {}: {error}", episode.id);
+                    inner.emit_download(episode.id.as_str(), "error", 0, None, Some(error));
+                }
+            }
+
+            if let Ok(mut downloads) = inner.downloads.lock() {
+                downloads.remove(episode.id.as_str());
+            }
+        });
+
+        Ok(())
+    }
+
+    fn record_position(&self, episode_id: &str, position: Duration, finished: bool) {
+        let Ok(mut library) = self.library.lock() else {
+            return;
+        };
+        let Ok((_, episode)) = find_episode_mut(&mut library, episode_id) else {
+            return;
+        };
+        if finished {
+            episode.played = true;
+            episode.resume_position_ms = 0;
+        } else {
+            episode.resume_position_ms = position.as_millis() as u64;
+        }
+        episode.is_new = false;
+
+        let should_persist = {
+            let mut last = match self.last_position_persist.lock() {
+                Ok(last) => last,
+                Err(_) => return,
+            };
+            let due = last.is_none_or(|instant| instant.elapsed() >= POSITION_PERSIST_INTERVAL);
+            if due || finished {
+                *last = Some(Instant::now());
+            }
+            due || finished
+        };
+        drop(library);
+        if should_persist {
+            let _ = self.position_writes.send(());
+        }
+    }
+
+    fn emit_library(&self) {
+        let payload = PodcastLibraryPayload {
+            subscriptions: self.subscriptions(),
+        };
+        if let Err(error) = self.app.emit(PODCAST_LIBRARY_EVENT, payload) {
+            eprintln!("[podcast] failed to emit library event: {error}");
+        }
+    }
+
+    fn emit_download(
+        &self,
+        episode_id: &str,
+        state: &str,
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
+        message: Option<String>,
+    ) {
+        let payload = PodcastDownloadPayload {
+            episode_id: episode_id.to_string(),
+            state: state.to_string(),
+            downloaded_bytes,
+            total_bytes,
+            message,
+        };
+        if let Err(error) = self.app.emit(PODCAST_DOWNLOAD_EVENT, payload) {
+            eprintln!("[podcast] failed to emit download event: {error}");
+        }
+    }
+}
+
+fn spawn_poller(weak: Weak<PodcastInner>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let Some(inner) = weak.upgrade() else {
+            break;
+        };
+        match inner.refresh(None) {
+            Ok(0) => {}
+            Ok(count) => eprintln!("[podcast] found {count} new episodes"),
+            Err(error) => eprintln!("[podcast] scheduled refresh failed: {error}"),
+        }
+    });
+}
+
+fn spawn_position_writer(weak: Weak<PodcastInner>, writes: Receiver<()>) {
+    std::thread::spawn(move || {
+        while writes.recv().is_ok() {
+            // Several requests queued up only need one write.
+            while writes.try_recv().is_ok() {}
+            let Some(inner) = weak.upgrade() else {
+                break;
+            };
+            // Written from a copy so the playback worker never waits on disk.
+            let result = inner
+                .lock_library()
+                .map(|library| library.clone())
+                .and_then(|library| persist_library(inner.library_path.as_path(), &library));
+            if let Err(error) = result {
+                eprintln!("[podcast] unable to persist resume position: {error}");
+            }
+        }
+    });
+}
+
+/// Plays the download when there is one, otherwise streams the enclosure;
+/// either way from where the listener left off.
+fn episode_source(episode: &PodcastEpisode) -> StreamSource {
+    let start_at = Duration::from_millis(episode.resume_position_ms);
+    match episode.download_path.as_ref().filter(|path| path.exists()) {
+        Some(path) => StreamSource::File {
+            path: path.clone(),
+            start_at,
+            start_byte: estimated_offset(
+                start_at,
+                fs::metadata(path).ok().map(|meta| meta.len()),
+                episode.duration_seconds,
+            ),
+        },
+        None => StreamSource::Remote {
+            url: episode.enclosure_url.clone(),
+            start_at,
+            start_byte: estimated_offset(
+                start_at,
+                episode.enclosure_length,
+                episode.duration_seconds,
+            ),
+        },
+    }
+}
+
+/// Where `start_at` falls in `length` bytes lasting `duration_seconds`,
+/// assuming a constant bitrate; 0 when either is unknown.
+fn estimated_offset(start_at: Duration, length: Option<u64>, duration_seconds: Option<u64>) -> u64 {
+    let (Some(length), Some(duration_seconds)) = (length, duration_seconds) else {
+        return 0;
+    };
+    if duration_seconds == 0 {
+        return 0;
+    }
+    let offset = length as u128 * start_at.as_millis() / (duration_seconds as u128 * 1_000);
+    if offset >= length as u128 {
+        return 0;
+    }
+    offset as u64
+}
+
+/// Downloads `url` into `part_path`, continuing from whatever is already on
+/// disk with a `Range` request. Returns the final size in bytes.
+pub fn download_with_resume(
+    http: &reqwest::blocking::Client,
+    url: &str,
+    part_path: &Path,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> Result<u64, String> {
+    let existing = fs::metadata(part_path).map(|meta| meta.len()).unwrap_or(0);
+    let mut request = http.get(url);
+    if existing > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={existing}-"));
+    }
+    let mut response = request
+        .send()
+        .map_err(|error| format!("episode download failed: {error}"))?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && existing > 0 {
+        return Ok(existing);
+    }
+    if !status.is_success() {
+        return Err(format!(
+            "episode download failed with status {}",
+            status.as_u16()
+        ));
+    }
+
+    let resumed = status == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut downloaded = if resumed { existing } else { 0 };
+    let total = response.content_length().map(|length| length + downloaded);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part_path)
+        .map_err(|error| format!("unable to open {}: {error}", part_path.display()))?;
+
+    let mut buffer = vec![0_u8; DOWNLOAD_CHUNK_SIZE];
+    loop {
+        let read = response
+            .read(&mut buffer)
+            .map_err(|error| format!("episode download interrupted: {error}"))?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])
+            .map_err(|error| format!("unable to write {}: {error}", part_path.display()))?;
+        downloaded += read as u64;
+        on_progress(downloaded, total);
+    }
+    file.flush()
+        .map_err(|error| format!("unable to write {}: {error}", part_path.display()))?;
+
+    if let Some(total) = total {
+        if downloaded < total {
+            return Err(format!(
+                "episode download ended early ({downloaded} of {total} bytes)"
+            ));
+        }
+    }
+    Ok(downloaded)
+}
+
+/// Merges a freshly parsed feed into a subscription, keeping per-episode
+/// state. Returns the number of episodes that were not seen before.
+fn apply_feed(subscription: &mut PodcastSubscription, parsed: ParsedFeed) -> usize {
+    subscription.title = parsed.title;
+    subscription.description = parsed.description;
+    subscription.link = parsed.link;
+    subscription.author = parsed.author;
+    subscription.image_url = parsed.image_url;
+
+    let mut new_episodes = 0;
+    for parsed_episode in parsed.episodes {
+        let id = format!(
+            "episode-{:016x}",
+            fnv1a(format!("{}\n{}", subscription.feed_url, parsed_episode.guid).as_bytes())
+        );
+        let existing = subscription
+            .episodes
+            .iter_mut()
+            .find(|episode| episode.id == id);
+        let episode = match existing {
+            Some(episode) => episode,
+            None => {
+                new_episodes += 1;
+                subscription.episodes.push(PodcastEpisode {
+                    id: id.clone(),
+                    is_new: true,
+                    ..PodcastEpisode::default()
+                });
+                subscription
+                    .episodes
+                    .last_mut()
+                    .expect("episode was just pushed")
+            }
+        };
+
+        episode.guid = parsed_episode.guid;
+        episode.title = parsed_episode.title;
+        episode.description = parsed_episode.description;
+        episode.published_at = parsed_episode.published_at;
+        episode.enclosure_url = parsed_episode.enclosure_url;
+        episode.enclosure_type = parsed_episode.enclosure_type;
+        episode.enclosure_length = parsed_episode.enclosure_length;
+        episode.duration_seconds = parsed_episode.duration_seconds;
+        episode.image_url = parsed_episode.image_url;
+        episode.episode_number = parsed_episode.episode_number;
+        episode.season_number = parsed_episode.season_number;
+        episode.explicit = parsed_episode.explicit;
+    }
+
+    subscription
+        .episodes
+        .sort_by_key(|episode| std::cmp::Reverse(episode.published_at));
+    new_episodes
+}
+
+#[derive(Default)]
+struct FeedBuilder {
+    feed: ParsedFeed,
+    episode: Option<ParsedEpisode>,
+    episode_link: Option<String>,
+    path: Vec<String>,
+    text: String,
+}
+
+impl FeedBuilder {
+    fn in_episode(&self) -> bool {
+        self.episode.is_some()
+    }
+
+    fn parent(&self) -> Option<&str> {
+        self.path.iter().rev().nth(1).map(String::as_str)
+    }
+
+    fn start(&mut self, name: &str, element: &BytesStart<'_>) {
+        self.path.push(name.to_string());
+        self.text.clear();
+
+        match name {
+            "item" | "entry" => {
+                self.episode = Some(ParsedEpisode::default());
+                self.episode_link = None;
+            }
+            "enclosure" => {
+                if let Some(episode) = self.episode.as_mut() {
+                    episode.enclosure_url = attribute(element, "url").unwrap_or_default();
+                    episode.enclosure_type = attribute(element, "type");
+                    episode.enclosure_length = attribute(element, "length")
+                        .and_then(|value| value.parse().ok())
+                        .filter(|length| *length > 0);
+                }
+            }
+            "itunes:image" => {
+                let href = attribute(element, "href");
+                match self.episode.as_mut() {
+                    Some(episode) => episode.image_url = href,
+                    None => {
+                        if href.is_some() {
+                            self.feed.image_url = href;
+                        }
+                    }
+                }
+            }
+            "link" => {
+                let rel = attribute(element, "rel").unwrap_or_else(|| "alternate".to_string());
+                let Some(href) = attribute(element, "href") else {
+                    return;
+                };
+                match self.episode.as_mut() {
+                    Some(episode) if rel == "enclosure" => {
+                        episode.enclosure_url = href;
+                        episode.enclosure_type = attribute(element, "type");
+                        episode.enclosure_length = attribute(element, "length")
+                            .and_then(|value| value.parse().ok())
+                            .filter(|length| *length > 0);
+                    }
+                    Some(_) if rel == "alternate" => self.episode_link = Some(href),
+                    None if rel == "alternate" => self.feed.link = Some(href),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self) {
+        let Some(name) = self.path.last().cloned() else {
+            return;
+        };
+        let text = self.text.trim().to_string();
+        let value = (!text.is_empty()).then_some(text);
+
+        if name == "item" || name == "entry" {
+            if let Some(mut episode) = self.episode.take() {
+                if episode.guid.is_empty() {
+                    episode.guid = [
+                        Some(episode.enclosure_url.clone()),
+                        self.episode_link.take(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .find(|value| !value.is_empty())
+                    .unwrap_or_else(|| episode.title.clone());
+                }
+                if !episode.enclosure_url.is_empty() {
+                    self.feed.episodes.push(episode);
+                }
+            }
+        } else if let Some(value) = value {
+            let parent = self.parent().unwrap_or_default().to_string();
+            if let Some(episode) = self.episode.as_mut() {
+                match name.as_str() {
+                    "title" => episode.title = value,
+                    "guid" | "id" => episode.guid = value,
+                    "description" | "summary" | "content" | "content:encoded"
+                    | "itunes:summary" => {
+                        episode.description.get_or_insert(value);
+                    }
+                    "pubDate" => episode.published_at = datetime::parse_rfc2822(value.as_str()),
+                    "published" => episode.published_at = datetime::parse_rfc3339(value.as_str()),
+                    "updated" if episode.published_at.is_none() => {
+                        episode.published_at = datetime::parse_rfc3339(value.as_str());
+                    }
+                    "itunes:duration" => episode.duration_seconds = parse_duration(value.as_str()),
+                    "itunes:episode" => episode.episode_number = value.parse().ok(),
+                    "itunes:season" => episode.season_number = value.parse().ok(),
+                    "itunes:explicit" => episode.explicit = parse_explicit(value.as_str()),
+                    _ => {}
+                }
+            } else {
+                match (name.as_str(), parent.as_str()) {
+                    ("title", "channel" | "feed") => self.feed.title = value,
+                    ("description" | "subtitle", "channel" | "feed") => {
+                        self.feed.description = Some(value)
+                    }
+                    ("itunes:summary", "channel") => {
+                        self.feed.description.get_or_insert(value);
+                    }
+                    ("link", "channel") => self.feed.link = Some(value),
+                    ("itunes:author", "channel") => self.feed.author = Some(value),
+                    ("name", "author") => {
+                        self.feed.author.get_or_insert(value);
+                    }
+                    ("url", "image") | ("logo" | "icon", "feed") => {
+                        self.feed.image_url.get_or_insert(value);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.path.pop();
+        self.text.clear();
+    }
+}
+
+/// Parses an RSS 2.0 or Atom podcast feed, including the common `itunes:`
+/// tags. Entries without an audio enclosure are skipped.
+pub fn parse_feed(xml: &str) -> Result<ParsedFeed, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut builder = FeedBuilder::default();
+    let mut saw_root = false;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|error| format!("invalid podcast feed XML: {error}"))?;
+        match event {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                saw_root |= name == "rss" || name == "feed";
+                builder.start(name.as_str(), &element);
+            }
+            Event::Empty(element) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                builder.start(name.as_str(), &element);
+                builder.end();
+            }
+            Event::End(_) => builder.end(),
+            Event::Text(text) => {
+                let text = text
+                    .xml_content()
+                    .map_err(|error| format!("invalid podcast feed text: {error}"))?;
+                builder.text.push_str(text.as_ref());
+            }
+            Event::CData(data) => {
+                let text = data
+                    .decode()
+                    .map_err(|error| format!("invalid podcast feed text: {error}"))?;
+                builder.text.push_str(text.as_ref());
+            }
+            Event::GeneralRef(reference) => {
+                if let Ok(Some(character)) = reference.resolve_char_ref() {
+                    builder.text.push(character);
+                } else if let Ok(name) = reference.decode() {
+                    if let Some(value) = quick_xml::escape::resolve_predefined_entity(name.as_ref())
+                    {
+                        builder.text.push_str(value);
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !saw_root {
+        return Err("document is not an RSS or Atom feed".to_string());
+    }
+    if builder.feed.title.is_empty() {
+        builder.feed.title = "Untitled Podcast".to_string();
+    }
+    Ok(builder.feed)
+}
+
+fn attribute(element: &BytesStart<'_>, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.as_ref() == name.as_bytes())
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_duration(value: &str) -> Option<u64> {
+    let mut seconds = 0_u64;
+    for part in value.trim().split(':') {
+        let part = part.split('.').next()?.trim().parse::<u64>().ok()?;
+        seconds = seconds * 60 + part;
+    }
+    Some(seconds)
+}
+
+fn parse_explicit(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "yes" | "true" | "explicit" => Some(true),
+        "no" | "false" | "clean" => Some(false),
+        _ => None,
+    }
+}
+
+fn is_mp3_enclosure(episode: &PodcastEpisode) -> bool {
+    match episode.enclosure_type.as_deref() {
+        Some(kind) => matches!(
+            kind.to_ascii_lowercase().as_str(),
+            "audio/mpeg" | "audio/mp3"
+        ),
+        None => enclosure_extension(episode.enclosure_url.as_str()) == "mp3",
+    }
+}
+
+fn enclosure_extension(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .filter(|extension| {
+            !extension.is_empty()
+                && extension.len() <= 4
+                && extension.bytes().all(|byte| byte.is_ascii_alphanumeric())
+        })
+        .unwrap_or_else(|| "mp3".to_string())
+}
+
+fn find_episode<'a>(
+    library: &'a PodcastLibrary,
+    episode_id: &str,
+) -> Result<(&'a PodcastSubscription, &'a PodcastEpisode), String> {
+    library
+        .subscriptions
+        .iter()
+        .find_map(|subscription| {
+            subscription
+                .episodes
+                .iter()
+                .find(|episode| episode.id == episode_id)
+                .map(|episode| (subscription, episode))
+        })
+        .ok_or_else(|| format!("unknown podcast episode: {episode_id}"))
+}
+
+fn find_episode_mut<'a>(
+    library: &'a mut PodcastLibrary,
+    episode_id: &str,
+) -> Result<(&'a str, &'a mut PodcastEpisode), String> {
+    library
+        .subscriptions
+        .iter_mut()
+        .find_map(|subscription| {
+            let id = subscription.id.as_str();
+            subscription
+                .episodes
+                .iter_mut()
+                .find(|episode| episode.id == episode_id)
+                .map(|episode| (id, episode))
+        })
+        .ok_or_else(|| format!("unknown podcast episode: {episode_id}"))
+}
+
+fn load_library(path: &Path) -> Result<PodcastLibrary, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(PodcastLibrary::default())
+        }
+        Err(error) => {
+            return Err(format!(
+                "unable to read podcast library from {}: {error}",
+                path.display()
+            ))
+        }
+    };
+
+    serde_json::from_str::<PodcastLibrary>(contents.as_str()).map_err(|error| {
+        format!(
+            "unable to parse podcast library from {}: {error}",
+            path.display()
+        )
+    })
+}
+
+fn persist_library(path: &Path, library: &PodcastLibrary) -> Result<(), String> {
+    let bytes = serde_json::to_vec_pretty(library)
+        .map_err(|error| format!("unable to serialize podcast library: {error}"))?;
+    fs::write(path, bytes).map_err(|error| {
+        format!(
+            "unable to write podcast library to {}: {error}",
+            path.display()
+        )
+    })
+}
+
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_feed, download_with_resume, episode_source, parse_feed, PodcastEpisode,
+        PodcastSubscription,
+    };
+    use crate::audio_engine::StreamSource;
+    use crate::test_support::test_dir;
+    use crate::test_support::{MockResponse, MockServer};
+    use std::fs;
+    use std::time::Duration;
+
+    const RSS_FIXTURE: &str = include_str!("../fixtures/podcast/rss.xml");
+    const ATOM_FIXTURE: &str = include_str!("../fixtures/podcast/atom.xml");
+
+    #[test]
+    fn parses_rss_channel_enclosures_and_itunes_tags() {
+        let feed = parse_feed(RSS_FIXTURE).expect("RSS fixture should parse");
+        assert_eq!(feed.title, "Late Night Frequencies");
+        assert_eq!(
+            feed.description.as_deref(),
+            Some("Deep cuts & slow burners, every fortnight.")
+        );
+        assert_eq!(feed.author.as_deref(), Some("Marconio Radio Club"));
+        assert_eq!(
+            feed.image_url.as_deref(),
+            Some("https://example.com/late-night/cover.jpg")
+        );
+        assert_eq!(feed.episodes.len(), 2);
+
+        let latest = &feed.episodes[0];
+        assert_eq!(latest.guid, "lnf-012");
+        assert_eq!(latest.title, "Episode 12: Tape Hiss");
+        assert_eq!(
+            latest.description.as_deref(),
+            Some("<p>Cassette culture from <b>1982</b>.</p>")
+        );
+        assert_eq!(latest.published_at, Some(1_725_381_000));
+        assert_eq!(latest.enclosure_url, "https://cdn.example.com/lnf/012.mp3");
+        assert_eq!(latest.enclosure_type.as_deref(), Some("audio/mpeg"));
+        assert_eq!(latest.enclosure_length, Some(48_213_000));
+        assert_eq!(latest.duration_seconds, Some(3_735));
+        assert_eq!(latest.episode_number, Some(12));
+        assert_eq!(latest.season_number, Some(2));
+        assert_eq!(latest.explicit, Some(false));
+        assert_eq!(
+            latest.image_url.as_deref(),
+            Some("https://example.com/late-night/012.jpg")
+        );
+
+        let older = &feed.episodes[1];
+        assert_eq!(
+            older.description.as_deref(),
+            Some("Numbers stations & ionosphere.")
+        );
+        assert_eq!(older.enclosure_length, None);
+        assert_eq!(older.duration_seconds, Some(3_120));
+        assert_eq!(older.explicit, Some(true));
+    }
+
+    #[test]
+    fn parses_atom_feed_with_enclosure_links() {
+        let feed = parse_feed(ATOM_FIXTURE).expect("Atom fixture should parse");
+        assert_eq!(feed.title, "Field Recordings Weekly");
+        assert_eq!(feed.description.as_deref(), Some("Places, as heard."));
+        assert_eq!(feed.link.as_deref(), Some("https://example.org/field"));
+        assert_eq!(feed.author.as_deref(), Some("Ada Listener"));
+        assert_eq!(
+            feed.image_url.as_deref(),
+            Some("https://example.org/field/logo.png")
+        );
+
+        assert_eq!(feed.episodes.len(), 1);
+        let episode = &feed.episodes[0];
+        assert_eq!(
+            episode.guid,
+            "urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a"
+        );
+        assert_eq!(episode.published_at, Some(1_714_544_100));
+        assert_eq!(
+            episode.enclosure_url,
+            "https://example.org/field/harbour.mp3"
+        );
+        assert_eq!(episode.enclosure_length, Some(1_048_576));
+    }
+
+    #[test]
+    fn parse_feed_rejects_non_feed_documents() {
+        assert!(parse_feed("<html><body>nope</body></html>").is_err());
+        assert!(parse_feed("<rss><channel><title>x</title></rss>").is_err());
+    }
+
+    #[test]
+    fn apply_feed_keeps_episode_state_and_counts_new_episodes() {
+        let mut subscription = PodcastSubscription {
+            id: "podcast-1".to_string(),
+            feed_url: "https://example.com/feed.xml".to_string(),
+            ..PodcastSubscription::default()
+        };
+        let mut feed = parse_feed(RSS_FIXTURE).expect("RSS fixture should parse");
+        let latest = feed.episodes.remove(0);
+
+        assert_eq!(apply_feed(&mut subscription, feed.clone()), 1);
+        subscription.episodes[0].resume_position_ms = 42_000;
+
+        feed.episodes.insert(0, latest);
+        assert_eq!(apply_feed(&mut subscription, feed), 1);
+        assert_eq!(subscription.episodes.len(), 2);
+        assert_eq!(subscription.episodes[0].guid, "lnf-012");
+        assert!(subscription.episodes[0].is_new);
+        assert_eq!(subscription.episodes[1].resume_position_ms, 42_000);
+    }
+
+    #[test]
+    fn download_with_resume_continues_partial_files() {
+        let payload = (0..=255_u8).cycle().take(10_000).collect::<Vec<u8>>();
+        let served = payload.clone();
+        let server = MockServer::start(move |request| {
+            let start = request
+                .header("range")
+                .and_then(|value| value.strip_prefix("bytes="))
+                .and_then(|value| value.trim_end_matches('-').parse::<usize>().ok());
+            match start {
+                Some(start) => MockResponse::new(206, served[start..].to_vec()).with_header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, served.len() - 1, served.len()).as_str(),
+                ),
+                None => MockResponse::new(200, served.clone()),
+            }
+        });
+
+        let dir = test_dir("resume");
+        let part_path = dir.join("episode.part");
+        fs::write(part_path.as_path(), &payload[..4_000]).expect("failed to seed partial file");
+
+        let client = reqwest::blocking::Client::new();
+        let url = format!("{}/episode.mp3", server.base_url());
+        let mut last_progress = None;
+        let total =
+            download_with_resume(&client, url.as_str(), part_path.as_path(), |done, total| {
+                last_progress = Some((done, total));
+            })
+            .expect("download should succeed");
+
+        assert_eq!(total, 10_000);
+        assert_eq!(last_progress, Some((10_000, Some(10_000))));
+        assert_eq!(fs::read(part_path.as_path()).expect("read part"), payload);
+        assert_eq!(server.requests()[0].header("range"), Some("bytes=4000-"));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn streamed_episodes_resume_from_the_saved_position() {
+        let dir = test_dir("resume");
+        let mut episode = PodcastEpisode {
+            enclosure_url: "https://cdn.example.com/show/1.mp3".to_string(),
+            enclosure_length: Some(48_000_000),
+            duration_seconds: Some(3_600),
+            resume_position_ms: 754_000,
+            ..PodcastEpisode::default()
+        };
+        let expected = StreamSource::Remote {
+            url: episode.enclosure_url.clone(),
+            start_at: Duration::from_millis(754_000),
+            start_byte: 10_053_333,
+        };
+        assert_eq!(episode_source(&episode), expected);
+
+        // A download that has since been deleted streams instead.
+        episode.download_path = Some(dir.join("gone.mp3"));
+        assert_eq!(episode_source(&episode), expected);
+
+        let path = dir.join("1.mp3");
+        fs::write(path.as_path(), vec![0; 36_000]).expect("failed to write episode");
+        episode.download_path = Some(path.clone());
+        assert_eq!(
+            episode_source(&episode),
+            StreamSource::File {
+                path: path.clone(),
+                start_at: Duration::from_millis(754_000),
+                start_byte: 7_540,
+            }
+        );
+
+        // Without a duration there's nothing to estimate from.
+        episode.duration_seconds = None;
+        assert_eq!(
+            episode_source(&episode),
+            StreamSource::File {
+                path,
+                start_at: Duration::from_millis(754_000),
+                start_byte: 0,
+            }
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::station::{Station, StationInput, StationMetadataSource};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_BASE_URL: &str = "https://all.api.radio-browser.info";
+const USER_AGENT: &str = concat!("Marconio/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+const MAX_SEARCH_LIMIT: u32 = 200;
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(8);
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DirectorySearch {
+    pub name: Option<String>,
+    pub tag: Option<String>,
+    pub country: Option<String>,
+    pub codec: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// A Radio-Browser station normalized to the fields Marconio cares about.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryStation {
+    pub uuid: String,
+    pub name: String,
+    pub stream_url: String,
+    pub homepage_url: Option<String>,
+    pub favicon_url: Option<String>,
+    pub bitrate: Option<u32>,
+    pub codec: Option<String>,
+    pub country: Option<String>,
+    pub tags: Vec<String>,
+    pub hls: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamHealth {
+    pub ok: bool,
+    pub status: Option<u16>,
+    pub content_type: Option<String>,
+    pub bitrate: Option<u32>,
+    pub icy_name: Option<String>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawStation {
+    stationuuid: String,
+    name: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    url_resolved: String,
+    #[serde(default)]
+    homepage: String,
+    #[serde(default)]
+    favicon: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    country: String,
+    #[serde(default)]
+    codec: String,
+    #[serde(default)]
+    bitrate: u32,
+    #[serde(default)]
+    hls: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawResolvedUrl {
+    ok: bool,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    url: String,
+}
+
+pub struct RadioBrowserClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl RadioBrowserClient {
+    pub fn new(base_url: &str) -> Result<Self, String> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|error| format!("unable to build Radio-Browser client: {error}"))?;
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http,
+        })
+    }
+
+    pub async fn search(&self, search: &DirectorySearch) -> Result<Vec<DirectoryStation>, String> {
+        let limit = search
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT)
+            .to_string();
+        let mut query = vec![
+            ("hidebroken", "true"),
+            ("order", "votes"),
+            ("reverse", "true"),
+            ("limit", limit.as_str()),
+        ];
+        for (key, value) in [
+            ("name", search.name.as_deref()),
+            ("tag", search.tag.as_deref()),
+            ("country", search.country.as_deref()),
+            ("codec", search.codec.as_deref()),
+        ] {
+            if let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) {
+                query.push((key, value));
+            }
+        }
+
+        let response = self
+            .http
+            .get(format!("{}/json/stations/search", self.base_url))
+            .query(query.as_slice())
+            .send()
+            .await
+            .map_err(|error| format!("Radio-Browser search failed: {error}"))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Radio-Browser search failed with status {}",
+                response.status().as_u16()
+            ));
+        }
+
+        let raw = response
+            .json::<Vec<RawStation>>()
+            .await
+            .map_err(|error| format!("unable to parse Radio-Browser search results: {error}"))?;
+        Ok(raw.into_iter().filter_map(normalize_station).collect())
+    }
+
+    /// Asks Radio-Browser for the playable URL of a station (following
+    /// playlists), which also registers a click for the station.
+    pub async fn resolve(&self, uuid: &str) -> Result<String, String> {
+        let response = self
+            .http
+            .get(format!("{}/json/url/{uuid}", self.base_url))
+            .send()
+            .await
+            .map_err(|error| format!("Radio-Browser resolve failed: {error}"))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Radio-Browser resolve failed with status {}",
+                response.status().as_u16()
+            ));
+        }
+
+        let resolved = response
+            .json::<RawResolvedUrl>()
+            .await
+            .map_err(|error| format!("unable to parse Radio-Browser resolve result: {error}"))?;
+        if !resolved.ok || resolved.url.trim().is_empty() {
+            return Err(format!(
+                "Radio-Browser could not resolve station: {}",
+                resolved.message
+            ));
+        }
+        Ok(resolved.url.trim().to_string())
+    }
+
+    pub async fn check_stream(&self, stream_url: &str) -> StreamHealth {
+        let response = match self
+            .http
+            .get(stream_url)
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(error) => {
+                return StreamHealth::failed(None, format!("stream request failed: {error}"))
+            }
+        };
+
+        let status = response.status().as_u16();
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let content_type = header("content-type");
+        let bitrate = header("icy-br").and_then(|value| value.split(',').next()?.parse().ok());
+        let icy_name = header("icy-name");
+
+        if !response.status().is_success() {
+            return StreamHealth {
+                content_type,
+                bitrate,
+                icy_name,
+                ..StreamHealth::failed(
+                    Some(status),
+                    format!("stream responded with status {status}"),
+                )
+            };
+        }
+
+        let is_audio = content_type
+            .as_deref()
+            .map(|value| {
+                let value = value.to_ascii_lowercase();
+                value.starts_with("audio/") || value == "application/ogg"
+            })
+            .unwrap_or(false);
+        let message = if is_audio {
+            None
+        } else {
+            Some("stream did not report an audio content type".to_string())
+        };
+
+        let mut response = response;
+        let has_data = match response.chunk().await {
+            Ok(Some(chunk)) => !chunk.is_empty(),
+            _ => false,
+        };
+
+        StreamHealth {
+            ok: is_audio && has_data,
+            status: Some(status),
+            content_type,
+            bitrate,
+            icy_name,
+            message: if has_data {
+                message
+            } else {
+                Some("stream did not deliver any audio data".to_string())
+            },
+        }
+    }
+}
+
+impl StreamHealth {
+    fn failed(status: Option<u16>, message: String) -> Self {
+        Self {
+            ok: false,
+            status,
+            content_type: None,
+            bitrate: None,
+            icy_name: None,
+            message: Some(message),
+        }
+    }
+}
+
+impl DirectoryStation {
+    pub fn into_station(self, stream_url: String) -> Result<Station, String> {
+        Station::from_input(StationInput {
+            id: Some(format!("radio-browser-{}", self.uuid)),
+            name: self.name,
+            stream_url,
+            artwork_url: self.favicon_url,
+            homepage_url: self.homepage_url,
+            metadata: Some(StationMetadataSource::Icy),
+        })
+    }
+}
+
+fn normalize_station(raw: RawStation) -> Option<DirectoryStation> {
+    let stream_url = [raw.url_resolved, raw.url]
+        .into_iter()
+        .map(|value| value.trim().to_string())
+        .find(|value| !value.is_empty())?;
+    let name = raw.name.trim();
+    if raw.stationuuid.trim().is_empty() || name.is_empty() {
+        return None;
+    }
+
+    Some(DirectoryStation {
+        uuid: raw.stationuuid.trim().to_string(),
+        name: name.to_string(),
+        stream_url,
+        homepage_url: non_empty(raw.homepage),
+        favicon_url: non_empty(raw.favicon),
+        bitrate: (raw.bitrate > 0).then_some(raw.bitrate),
+        codec: non_empty(raw.codec).map(|value| value.to_ascii_uppercase()),
+        country: non_empty(raw.country),
+        tags: raw
+            .tags
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect(),
+        hls: raw.hls != 0,
+    })
+}
+
+fn non_empty(value: String) -> Option<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectorySearch, RadioBrowserClient};
+    use crate::test_support::{MockResponse, MockServer};
+
+    const SEARCH_RESPONSE: &str = r#"[
+        {
+            "stationuuid": "9617a958-0601-11e8-ae97-52543be04c81",
+            "name": " dublab ",
+            "url": "https://dublab.out.airtime.pro/dublab_a.m3u",
+            "url_resolved": "https://dublab.out.airtime.pro/dublab_a",
+            "homepage": "https://dublab.com/",
+            "favicon": "",
+            "tags": "eclectic, experimental,,electronic",
+            "country": "The United States Of America",
+            "codec": "mp3",
+            "bitrate": 128,
+            "hls": 0
+        },
+        {
+            "stationuuid": "broken",
+            "name": "No URL",
+            "url": "",
+            "url_resolved": ""
+        }
+    ]"#;
+
+    #[tokio::test]
+    async fn search_sends_filters_and_normalizes_results() {
+        let server = MockServer::start(|_| MockResponse::json(SEARCH_RESPONSE));
+        let client = RadioBrowserClient::new(server.base_url().as_str()).expect("client");
+
+        let results = client
+            .search(&DirectorySearch {
+                name: Some("dublab".to_string()),
+                tag: Some(" ".to_string()),
+                country: None,
+                codec: Some("MP3".to_string()),
+                limit: Some(1_000),
+            })
+            .await
+            .expect("search should succeed");
+
+        assert_eq!(results.len(), 1);
+        let station = &results[0];
+        assert_eq!(station.name, "dublab");
+        assert_eq!(
+            station.stream_url,
+            "https://dublab.out.airtime.pro/dublab_a"
+        );
+        assert_eq!(station.favicon_url, None);
+        assert_eq!(station.bitrate, Some(128));
+        assert_eq!(station.codec.as_deref(), Some("MP3"));
+        assert_eq!(station.tags, vec!["eclectic", "experimental", "electronic"]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path_without_query(), "/json/stations/search");
+        assert_eq!(requests[0].query_param("name").as_deref(), Some("dublab"));
+        assert_eq!(requests[0].query_param("codec").as_deref(), Some("MP3"));
+        assert_eq!(requests[0].query_param("tag"), None);
+        assert_eq!(requests[0].query_param("limit").as_deref(), Some("200"));
+    }
+
+    #[tokio::test]
+    async fn resolve_returns_url_or_error() {
+        let server = MockServer::start(|request| {
+            if request.path == "/json/url/good" {
+                MockResponse::json(
+                    r#"{"ok":true,"message":"retrieved station url","url":"https://example.com/live"}"#,
+                )
+            } else {
+                MockResponse::json(
+                    r#"{"ok":false,"message":"did not find station with matching id","url":""}"#,
+                )
+            }
+        });
+        let client = RadioBrowserClient::new(server.base_url().as_str()).expect("client");
+
+        assert_eq!(
+            client.resolve("good").await.as_deref(),
+            Ok("https://example.com/live")
+        );
+        assert!(client.resolve("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn check_stream_reports_audio_streams_as_healthy() {
+        let server = MockServer::start(|request| match request.path.as_str() {
+            "/live" => MockResponse::new(200, vec![0xFF_u8; 64])
+                .with_header("Content-Type", "audio/mpeg")
+                .with_header("icy-br", "128")
+                .with_header("icy-name", "Test FM"),
+            "/html" => {
+                MockResponse::new(200, "<html></html>").with_header("Content-Type", "text/html")
+            }
+            _ => MockResponse::new(404, ""),
+        });
+        let client = RadioBrowserClient::new(server.base_url().as_str()).expect("client");
+
+        let healthy = client
+            .check_stream(format!("{}/live", server.base_url()).as_str())
+            .await;
+        assert!(healthy.ok);
+        assert_eq!(healthy.bitrate, Some(128));
+        assert_eq!(healthy.icy_name.as_deref(), Some("Test FM"));
+
+        let html = client
+            .check_stream(format!("{}/html", server.base_url()).as_str())
+            .await;
+        assert!(!html.ok);
+
+        let missing = client
+            .check_stream(format!("{}/gone", server.base_url()).as_str())
+            .await;
+        assert!(!missing.ok);
+        assert_eq!(missing.status, Some(404));
+    }
+}
@@ -0,0 +1,389 @@
+use crate::acoustid::AcoustIdConfigSource;
+use std::sync::mpsc::Sender;
+
+/// A track reported by a recognition backend.
+#[derive(Clone, Debug, Default)]
+pub struct RecognitionMatch {
+    pub shazam_id: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub artwork_url: Option<String>,
+    pub apple_music_url: Option<String>,
+    pub web_url: Option<String>,
+    pub musicbrainz_recording_id: Option<String>,
+    pub acoustid: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub enum RecognitionEvent {
+    Match(RecognitionMatch),
+    NoMatch,
+    Error(String),
+}
+
+/// A song recognition service. Backends receive PCM between `start` and
+/// `stop` and report their outcome asynchronously on the event sender they
+/// were created with.
+pub trait RecognitionBackend: Send {
+    fn start(&mut self) -> Result<(), String>;
+    fn feed(&mut self, samples: &[f32], channels: u16, sample_rate: u32) -> Result<(), String>;
+    fn stop(&mut self);
+}
+
+/// ShazamKit on macOS; everywhere else, Chromaprint lookups against AcoustID.
+pub fn default_backend(
+    events: Sender<RecognitionEvent>,
+    acoustid: Box<AcoustIdConfigSource>,
+) -> Result<Box<dyn RecognitionBackend>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        let _ = acoustid;
+        Ok(Box::new(ShazamKitBackend::new(events)?))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        Ok(Box::new(crate::acoustid::AcoustIdBackend::new(
+            events, acoustid,
+        )))
+    }
+}
+
+#[cfg(target_os = "macos")]
+struct CallbackContext {
+    tx: Sender<RecognitionEvent>,
+}
+
+#[cfg(target_os = "macos")]
+struct ShazamKitBackend {
+    // Declared before the context so the bridge is destroyed first and can
+    // no longer call back into it.
+    bridge: MacBridge,
+    _callback_context: Box<CallbackContext>,
+}
+
+#[cfg(target_os = "macos")]
+impl ShazamKitBackend {
+    fn new(events: Sender<RecognitionEvent>) -> Result<Self, String> {
+        let callback_context = Box::new(CallbackContext { tx: events });
+        let user_data =
+            callback_context.as_ref() as *const CallbackContext as *mut std::ffi::c_void;
+        let bridge = unsafe { MacBridge::create(shazam_bridge_callback, user_data)? };
+        Ok(Self {
+            bridge,
+            _callback_context: callback_context,
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl RecognitionBackend for ShazamKitBackend {
+    fn start(&mut self) -> Result<(), String> {
+        self.bridge.start()
+    }
+
+    fn feed(&mut self, samples: &[f32], channels: u16, sample_rate: u32) -> Result<(), String> {
+        self.bridge.feed(samples, channels, sample_rate)
+    }
+
+    fn stop(&mut self) {
+        self.bridge.stop();
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe extern "C" fn shazam_bridge_callback(
+    event_type: i32,
+    title: *const std::ffi::c_char,
+    artist: *const std::ffi::c_char,
+    artwork_url: *const std::ffi::c_char,
+    apple_music_url: *const std::ffi::c_char,
+    web_url: *const std::ffi::c_char,
+    error_message: *const std::ffi::c_char,
+    user_data: *mut std::ffi::c_void,
+) {
+    if user_data.is_null() {
+        return;
+    }
+    let context = &*(user_data as *const CallbackContext);
+
+    let event = match event_type {
+        ffi::SHAZAM_BRIDGE_EVENT_MATCH => RecognitionEvent::Match(RecognitionMatch {
+            shazam_id: None,
+            title: cstring_to_string(title),
+            artist: cstring_to_string(artist),
+            artwork_url: cstring_to_string(artwork_url),
+            apple_music_url: cstring_to_string(apple_music_url),
+            web_url: cstring_to_string(web_url),
+            ..RecognitionMatch::default()
+        }),
+        ffi::SHAZAM_BRIDGE_EVENT_NO_MATCH => RecognitionEvent::NoMatch,
+        ffi::SHAZAM_BRIDGE_EVENT_ERROR => {
+            let message = cstring_to_string(error_message)
+                .unwrap_or_else(|| "ShazamKit failed to identify the track.".to_string());
+            RecognitionEvent::Error(message)
+        }
+        _ => return,
+    };
+
+    let _ = context.tx.send(event);
+}
+
+#[cfg(target_os = "macos")]
+fn cstring_to_string(value: *const std::ffi::c_char) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { std::ffi::CStr::from_ptr(value) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+#[cfg(target_os = "macos")]
+struct MacBridge {
+    raw: *mut std::ffi::c_void,
+}
+
+#[cfg(target_os = "macos")]
+unsafe impl Send for MacBridge {}
+
+#[cfg(target_os = "macos")]
+impl MacBridge {
+    unsafe fn create(
+        callback: ffi::ShazamBridgeCallback,
+        user_data: *mut std::ffi::c_void,
+    ) -> Result<Self, String> {
+        let mut error_ptr: *mut std::ffi::c_char = std::ptr::null_mut();
+        let raw = ffi::shazam_bridge_create(callback, user_data, &mut error_ptr);
+        if raw.is_null() {
+            return Err(consume_bridge_error(error_ptr));
+        }
+        Ok(Self { raw })
+    }
+
+    fn start(&mut self) -> Result<(), String> {
+        let mut error_ptr: *mut std::ffi::c_char = std::ptr::null_mut();
+        let ok = unsafe { ffi::shazam_bridge_start(self.raw, &mut error_ptr) };
+        if ok {
+            Ok(())
+        } else {
+            Err(consume_bridge_error(error_ptr))
+        }
+    }
+
+    fn feed(&mut self, samples: &[f32], channels: u16, sample_rate: u32) -> Result<(), String> {
+        if channels == 0 {
+            return Ok(());
+        }
+        if samples.len() % channels as usize != 0 {
+            return Err("audio frame data was not aligned with channel count".to_string());
+        }
+
+        let frame_count = (samples.len() / channels as usize) as u32;
+        if frame_count == 0 {
+            return Ok(());
+        }
+
+        let mut error_ptr: *mut std::ffi::c_char = std::ptr::null_mut();
+        let ok = unsafe {
+            ffi::shazam_bridge_feed(
+                self.raw,
+                samples.as_ptr(),
+                frame_count,
+                channels as u32,
+                sample_rate as f64,
+                &mut error_ptr,
+            )
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(consume_bridge_error(error_ptr))
+        }
+    }
+
+    fn stop(&mut self) {
+        unsafe {
+            ffi::shazam_bridge_stop(self.raw);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Drop for MacBridge {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::shazam_bridge_destroy(self.raw);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn consume_bridge_error(error_ptr: *mut std::ffi::c_char) -> String {
+    if error_ptr.is_null() {
+        return "Unknown Shazam bridge error".to_string();
+    }
+
+    let message = unsafe { std::ffi::CStr::from_ptr(error_ptr) }
+        .to_string_lossy()
+        .into_owned();
+
+    unsafe {
+        ffi::shazam_bridge_free_error(error_ptr);
+    }
+
+    message
+}
+
+#[cfg(target_os = "macos")]
+mod ffi {
+    pub const SHAZAM_BRIDGE_EVENT_MATCH: i32 = 1;
+    pub const SHAZAM_BRIDGE_EVENT_NO_MATCH: i32 = 2;
+    pub const SHAZAM_BRIDGE_EVENT_ERROR: i32 = 3;
+
+    pub type ShazamBridgeCallback = unsafe extern "C" fn(
+        event_type: i32,
+        title: *const std::ffi::c_char,
+        artist: *const std::ffi::c_char,
+        artwork_url: *const std::ffi::c_char,
+        apple_music_url: *const std::ffi::c_char,
+        web_url: *const std::ffi::c_char,
+        error_message: *const std::ffi::c_char,
+        user_data: *mut std::ffi::c_void,
+    );
+
+    unsafe extern "C" {
+        pub fn shazam_bridge_create(
+            callback: ShazamBridgeCallback,
+            user_data: *mut std::ffi::c_void,
+            error_out: *mut *mut std::ffi::c_char,
+        ) -> *mut std::ffi::c_void;
+
+        pub fn shazam_bridge_start(
+            bridge: *mut std::ffi::c_void,
+            error_out: *mut *mut std::ffi::c_char,
+        ) -> bool;
+
+        pub fn shazam_bridge_feed(
+            bridge: *mut std::ffi::c_void,
+            samples: *const f32,
+            frame_count: u32,
+            channels: u32,
+            sample_rate: f64,
+            error_out: *mut *mut std::ffi::c_char,
+        ) -> bool;
+
+        pub fn shazam_bridge_stop(bridge: *mut std::ffi::c_void);
+        pub fn shazam_bridge_destroy(bridge: *mut std::ffi::c_void);
+        pub fn shazam_bridge_free_error(error_message: *mut std::ffi::c_char);
+    }
+}
+
+/// A scriptable backend for exercising the recognition flow in tests.
+#[cfg(test)]
+pub mod mock {
+    use super::{RecognitionBackend, RecognitionEvent, RecognitionMatch};
+    use std::sync::mpsc::Sender;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct MockState {
+        starts: usize,
+        stops: usize,
+        fed_frames: usize,
+        feeds_held: bool,
+        start_error: Option<String>,
+        events: Option<Sender<RecognitionEvent>>,
+    }
+
+    /// The test's view of a `MockBackend`, shared with the backend itself.
+    #[derive(Clone, Default)]
+    pub struct MockBackendHandle {
+        state: Arc<Mutex<MockState>>,
+    }
+
+    struct MockBackend {
+        state: Arc<Mutex<MockState>>,
+    }
+
+    impl MockBackendHandle {
+        pub fn backend(&self, events: Sender<RecognitionEvent>) -> Box<dyn RecognitionBackend> {
+            self.state.lock().expect("mock state").events = Some(events);
+            Box::new(MockBackend {
+                state: Arc::clone(&self.state),
+            })
+        }
+
+        pub fn fail_next_start(&self, message: &str) {
+            self.state.lock().expect("mock state").start_error = Some(message.to_string());
+        }
+
+        /// Feeds wait until released, like a backend that's slow to take audio.
+        pub fn hold_feeds(&self, held: bool) {
+            self.state.lock().expect("mock state").feeds_held = held;
+        }
+
+        pub fn emit(&self, event: RecognitionEvent) {
+            let events = self.state.lock().expect("mock state").events.clone();
+            if let Some(events) = events {
+                let _ = events.send(event);
+            }
+        }
+
+        pub fn emit_match(&self, shazam_id: &str, title: &str, artist: &str) {
+            self.emit(RecognitionEvent::Match(RecognitionMatch {
+                shazam_id: Some(shazam_id.to_string()),
+                title: Some(title.to_string()),
+                artist: Some(artist.to_string()),
+                ..RecognitionMatch::default()
+            }));
+        }
+
+        pub fn starts(&self) -> usize {
+            self.state.lock().expect("mock state").starts
+        }
+
+        pub fn stops(&self) -> usize {
+            self.state.lock().expect("mock state").stops
+        }
+
+        pub fn fed_frames(&self) -> usize {
+            self.state.lock().expect("mock state").fed_frames
+        }
+    }
+
+    impl RecognitionBackend for MockBackend {
+        fn start(&mut self) -> Result<(), String> {
+            let mut state = self.state.lock().expect("mock state");
+            if let Some(error) = state.start_error.take() {
+                return Err(error);
+            }
+            state.starts += 1;
+            Ok(())
+        }
+
+        fn feed(
+            &mut self,
+            samples: &[f32],
+            channels: u16,
+            _sample_rate: u32,
+        ) -> Result<(), String> {
+            loop {
+                let mut state = self.state.lock().expect("mock state");
+                if !state.feeds_held {
+                    state.fed_frames += samples.len() / channels.max(1) as usize;
+                    return Ok(());
+                }
+                drop(state);
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+
+        fn stop(&mut self) {
+            self.state.lock().expect("mock state").stops += 1;
+        }
+    }
+}
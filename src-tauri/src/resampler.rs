@@ -0,0 +1,301 @@
+//! Brings decoded audio to the engine's format: channel mapping, then a
+//! windowed-sinc resampler that carries its history across buffers and rate
+//! changes, so nothing downstream ever sees the stream's own format.
+
+use crate::audio_samples::downmix_mono;
+use std::f64::consts::PI;
+
+/// Zero crossings of the kernel on each side of the centre.
+const KERNEL_HALF_WIDTH: usize = 24;
+/// Kernel phases tabulated between two input samples.
+const KERNEL_PHASES: usize = 256;
+/// Passband edge as a fraction of the lower Nyquist frequency; the rest is
+/// the transition band.
+const PASSBAND: f64 = 0.92;
+
+/// Maps `samples` from `from` channels to `to`, appending to `out`. Mono is
+/// copied to both sides, stereo is averaged down to mono, and past stereo
+/// only the front pair is kept.
+pub fn map_channels(samples: &[f32], from: u16, to: u16, out: &mut Vec<f32>) {
+    let from = from.max(1);
+    let to = to.max(1);
+    if from == to {
+        out.extend_from_slice(samples);
+        return;
+    }
+    match (from, to) {
+        (_, 1) => out.extend(downmix_mono(samples, from)),
+        (1, _) => {
+            for &sample in samples {
+                out.extend(std::iter::repeat_n(sample, to as usize));
+            }
+        }
+        _ => {
+            for frame in samples.chunks_exact(from as usize) {
+                for channel in 0..to as usize {
+                    out.push(frame.get(channel).copied().unwrap_or(0.0));
+                }
+            }
+        }
+    }
+}
+
+/// Converts interleaved audio in any format to one fixed format.
+pub struct FormatConverter {
+    channels: u16,
+    mapped: Vec<f32>,
+    resampler: SincResampler,
+}
+
+impl FormatConverter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        Self {
+            channels: channels.max(1),
+            mapped: Vec::new(),
+            resampler: SincResampler::new(channels.max(1) as usize, sample_rate),
+        }
+    }
+
+    pub fn convert(
+        &mut self,
+        samples: &[f32],
+        channels: u16,
+        sample_rate: u32,
+        out: &mut Vec<f32>,
+    ) {
+        self.mapped.clear();
+        map_channels(samples, channels, self.channels, &mut self.mapped);
+        self.resampler
+            .process(self.mapped.as_slice(), sample_rate, out);
+    }
+}
+
+/// Streaming band-limited resampler. The kernel is a Blackman-windowed sinc
+/// tabulated at `KERNEL_PHASES` offsets and interpolated between them.
+pub struct SincResampler {
+    channels: usize,
+    output_rate: u32,
+    input_rate: u32,
+    /// Input frames still needed, interleaved, oldest first.
+    history: Vec<f32>,
+    /// Where the next output frame falls, in frames into `history`.
+    position: f64,
+    /// `KERNEL_PHASES + 1` rows of `2 * KERNEL_HALF_WIDTH` taps.
+    kernel: Vec<f32>,
+}
+
+impl SincResampler {
+    pub fn new(channels: usize, output_rate: u32) -> Self {
+        let channels = channels.max(1);
+        Self {
+            channels,
+            output_rate,
+            input_rate: 0,
+            // Silence before the first sample, so output starts right away.
+            history: vec![0.0; (KERNEL_HALF_WIDTH - 1) * channels],
+            position: (KERNEL_HALF_WIDTH - 1) as f64,
+            kernel: Vec::new(),
+        }
+    }
+
+    /// Resamples interleaved `input` at `input_rate`, appending to `out`. A
+    /// new rate retunes the kernel without dropping what's buffered.
+    pub fn process(&mut self, input: &[f32], input_rate: u32, out: &mut Vec<f32>) {
+        if input_rate == 0 {
+            return;
+        }
+        if input_rate != self.input_rate {
+            self.input_rate = input_rate;
+            self.kernel = build_kernel(input_rate, self.output_rate);
+            if input_rate == self.output_rate {
+                self.position = self.position.round();
+            }
+        }
+        self.history.extend_from_slice(input);
+
+        let channels = self.channels;
+        let taps = 2 * KERNEL_HALF_WIDTH;
+        let frames = self.history.len() / channels;
+        let step = self.input_rate as f64 / self.output_rate as f64;
+        let passthrough = self.input_rate == self.output_rate;
+
+        while (self.position as usize) + KERNEL_HALF_WIDTH < frames {
+            let centre = self.position as usize;
+            if passthrough {
+                let start = centre * channels;
+                out.extend_from_slice(&self.history[start..start + channels]);
+            } else {
+                let phase = (self.position - centre as f64) * KERNEL_PHASES as f64;
+                let row = (phase as usize).min(KERNEL_PHASES - 1);
+                let blend = (phase - row as f64) as f32;
+                let first = (centre + 1 - KERNEL_HALF_WIDTH) * channels;
+                for channel in 0..channels {
+                    let mut sum = 0.0f32;
+                    for tap in 0..taps {
+                        let before = self.kernel[row * taps + tap];
+                        let after = self.kernel[(row + 1) * taps + tap];
+                        let weight = before + (after - before) * blend;
+                        sum += self.history[first + tap * channels + channel] * weight;
+                    }
+                    out.push(sum);
+                }
+            }
+            self.position += step;
+        }
+
+        // Keep what the next output still reaches back to.
+        let keep_from = (self.position as usize + 1).saturating_sub(KERNEL_HALF_WIDTH);
+        if keep_from > 0 {
+            self.history.drain(..keep_from * channels);
+            self.position -= keep_from as f64;
+        }
+    }
+}
+
+/// Taps for each phase. Row `p` weighs the input at offsets
+/// `1 - KERNEL_HALF_WIDTH ..= KERNEL_HALF_WIDTH` from the centre, for an
+/// output `p / KERNEL_PHASES` of the way past it.
+fn build_kernel(input_rate: u32, output_rate: u32) -> Vec<f32> {
+    let taps = 2 * KERNEL_HALF_WIDTH;
+    // Downsampling moves the cutoff below the output's Nyquist frequency.
+    let cutoff = PASSBAND * (output_rate as f64 / input_rate as f64).min(1.0);
+    let half_width = KERNEL_HALF_WIDTH as f64;
+    let mut kernel = Vec::with_capacity((KERNEL_PHASES + 1) * taps);
+    for phase in 0..=KERNEL_PHASES {
+        let fraction = phase as f64 / KERNEL_PHASES as f64;
+        let row_start = kernel.len();
+        for tap in 0..taps {
+            let offset = tap as f64 + 1.0 - half_width - fraction;
+            let sinc = if offset == 0.0 {
+                1.0
+            } else {
+                (PI * cutoff * offset).sin() / (PI * cutoff * offset)
+            };
+            let window_position = (offset + half_width) / (2.0 * half_width);
+            let window = 0.42 - 0.5 * (2.0 * PI * window_position).cos()
+                + 0.08 * (4.0 * PI * window_position).cos();
+            kernel.push((cutoff * sinc * window) as f32);
+        }
+        // Unity gain at DC for every phase.
+        let sum = kernel[row_start..].iter().sum::<f32>();
+        for weight in kernel[row_start..].iter_mut() {
+            *weight /= sum;
+        }
+    }
+    kernel
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32, seconds: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * seconds) as usize)
+            .map(|index| {
+                (std::f32::consts::TAU * frequency * index as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn maps_mono_and_stereo_both_ways() {
+        let mut out = Vec::new();
+        map_channels(&[0.1, 0.2], 1, 2, &mut out);
+        assert_eq!(out, vec![0.1, 0.1, 0.2, 0.2]);
+
+        out.clear();
+        map_channels(&[0.2, 0.4, -0.5, 0.5], 2, 1, &mut out);
+        assert!((out[0] - 0.3).abs() < 1e-6 && out[1].abs() < 1e-6);
+
+        out.clear();
+        map_channels(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3, 2, &mut out);
+        assert_eq!(out, vec![0.1, 0.2, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn passes_the_engine_rate_through_untouched() {
+        let input = sine(440.0, 44_100, 0.1);
+        let mut resampler = SincResampler::new(1, 44_100);
+        let mut out = Vec::new();
+        resampler.process(input.as_slice(), 44_100, &mut out);
+        // The last few frames wait for the kernel's lookahead.
+        assert_eq!(out.len(), input.len() - KERNEL_HALF_WIDTH);
+        assert_eq!(out.as_slice(), &input[..out.len()]);
+    }
+
+    #[test]
+    fn keeps_in_band_tones_and_the_length_right() {
+        let input = sine(1_000.0, 48_000, 1.0);
+        let mut resampler = SincResampler::new(1, 44_100);
+        let mut out = Vec::new();
+        for block in input.chunks(1_152) {
+            resampler.process(block, 48_000, &mut out);
+        }
+        assert!((out.len() as i64 - 44_100).abs() <= KERNEL_HALF_WIDTH as i64);
+
+        let steady = &out[1_000..40_000];
+        assert!((rms(steady) - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+        let crossings = steady
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        let expected = 1_000.0 * steady.len() as f32 / 44_100.0;
+        assert!((crossings as f32 - expected).abs() <= 1.0);
+    }
+
+    #[test]
+    fn filters_out_what_would_alias() {
+        // Above the output's Nyquist frequency; a linear resampler folds it
+        // back down at nearly full level.
+        let input = sine(23_000.0, 48_000, 0.5);
+        let mut resampler = SincResampler::new(1, 44_100);
+        let mut out = Vec::new();
+        resampler.process(input.as_slice(), 48_000, &mut out);
+        assert!(rms(&out[1_000..]) < 0.01, "{}", rms(&out[1_000..]));
+    }
+
+    #[test]
+    fn block_size_and_rate_changes_keep_the_stream_continuous() {
+        let input = sine(500.0, 32_000, 0.2);
+        let mut whole = Vec::new();
+        SincResampler::new(2, 44_100).process(
+            &input
+                .iter()
+                .flat_map(|&sample| [sample, -sample])
+                .collect::<Vec<_>>(),
+            32_000,
+            &mut whole,
+        );
+
+        let mut converter = FormatConverter::new(2, 44_100);
+        let mut pieces = Vec::new();
+        for block in input.chunks(317) {
+            let stereo = block
+                .iter()
+                .flat_map(|&sample| [sample, -sample])
+                .collect::<Vec<_>>();
+            converter.convert(stereo.as_slice(), 2, 32_000, &mut pieces);
+        }
+        assert_eq!(pieces.len(), whole.len());
+        assert!(pieces
+            .iter()
+            .zip(whole.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-5));
+
+        // A mono 48kHz stretch after the stereo one joins without a jump.
+        let before = pieces.len();
+        converter.convert(&sine(500.0, 48_000, 0.05), 1, 48_000, &mut pieces);
+        let largest_step = pieces[before - 20..]
+            .chunks_exact(2)
+            .map(|frame| frame[0])
+            .collect::<Vec<_>>()
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs())
+            .fold(0.0f32, f32::max);
+        assert!(largest_step < 0.2, "{largest_step}");
+    }
+}
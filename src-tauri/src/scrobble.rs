@@ -0,0 +1,1272 @@
+//! Scrobbles recognized tracks, and optionally the NTS show on air, to
+//! ListenBrainz and Last.fm. Scrobbles wait in the database until the service
+//! takes them, so nothing recognized offline is lost.
+
+use crate::audio_engine::NowPlayingMetadata;
+use crate::database::{self, DATABASE_FILE_NAME};
+use crate::shazam::RecognizedTrack;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Manager};
+
+pub const LISTENBRAINZ_DEFAULT_BASE_URL: &str = "https://api.listenbrainz.org";
+pub const LASTFM_DEFAULT_BASE_URL: &str = "https://ws.audioscrobbler.com";
+const LASTFM_AUTH_URL: &str = "https://www.last.fm/api/auth/";
+const USER_AGENT: &str = concat!("Marconio/", env!("CARGO_PKG_VERSION"));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the worker looks for due retries when nothing else happens.
+const RETRY_TICK: Duration = Duration::from_secs(30);
+const RETRY_BASE_SECONDS: u64 = 60;
+const RETRY_MAX_SECONDS: u64 = 6 * 60 * 60;
+/// Scrobbles submitted per pass over the queue.
+const FLUSH_LIMIT: usize = 50;
+/// Last.fm only counts a track once it has played this long.
+const MIN_SHOW_SECONDS: u64 = 240;
+const SHOW_ARTIST: &str = "NTS Radio";
+
+/// Kept in settings.json in the app data directory, which only the user can
+/// read; the keys and tokens are in plain text there. Never send this to the
+/// webview: `ScrobbleStatus` is the frontend's view, and `Debug` redacts the
+/// secrets so they stay out of logs too.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScrobbleConfig {
+    pub listenbrainz: ListenBrainzConfig,
+    pub lastfm: LastFmConfig,
+    /// Also scrobble the NTS show on air, as a track by "NTS Radio".
+    pub scrobble_shows: bool,
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ListenBrainzConfig {
+    pub base_url: Option<String>,
+    pub token: Option<String>,
+    pub username: Option<String>,
+}
+
+impl fmt::Debug for ListenBrainzConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListenBrainzConfig")
+            .field("base_url", &self.base_url)
+            .field("token", &redacted(self.token.as_deref()))
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+impl ListenBrainzConfig {
+    fn base_url(&self) -> &str {
+        non_empty(self.base_url.as_deref()).unwrap_or(LISTENBRAINZ_DEFAULT_BASE_URL)
+    }
+
+    fn is_connected(&self) -> bool {
+        non_empty(self.token.as_deref()).is_some()
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LastFmConfig {
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    pub api_secret: Option<String>,
+    /// Set once the user has approved the app on last.fm.
+    pub session_key: Option<String>,
+    pub username: Option<String>,
+}
+
+impl fmt::Debug for LastFmConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LastFmConfig")
+            .field("base_url", &self.base_url)
+            .field("api_key", &self.api_key)
+            .field("api_secret", &redacted(self.api_secret.as_deref()))
+            .field("session_key", &redacted(self.session_key.as_deref()))
+            .field("username", &self.username)
+            .finish()
+    }
+}
+
+fn redacted(secret: Option<&str>) -> Option<&'static str> {
+    secret.map(|_| "<redacted>")
+}
+
+impl LastFmConfig {
+    fn base_url(&self) -> &str {
+        non_empty(self.base_url.as_deref()).unwrap_or(LASTFM_DEFAULT_BASE_URL)
+    }
+
+    fn is_connected(&self) -> bool {
+        non_empty(self.api_key.as_deref()).is_some()
+            && non_empty(self.api_secret.as_deref()).is_some()
+            && non_empty(self.session_key.as_deref()).is_some()
+    }
+}
+
+pub type ScrobbleConfigSource = dyn Fn() -> ScrobbleConfig + Send + Sync;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ScrobbleService {
+    ListenBrainz,
+    LastFm,
+}
+
+impl ScrobbleService {
+    const ALL: [Self; 2] = [Self::ListenBrainz, Self::LastFm];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ListenBrainz => "listenbrainz",
+            Self::LastFm => "lastfm",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|service| service.as_str() == value)
+    }
+
+    fn is_connected(self, config: &ScrobbleConfig) -> bool {
+        match self {
+            Self::ListenBrainz => config.listenbrainz.is_connected(),
+            Self::LastFm => config.lastfm.is_connected(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrobbleStatus {
+    pub listenbrainz_username: Option<String>,
+    pub lastfm_username: Option<String>,
+    pub scrobble_shows: bool,
+    pub queued: usize,
+    /// Why the oldest waiting scrobble hasn't gone through yet.
+    pub last_error: Option<String>,
+}
+
+/// The first half of Last.fm's auth: the user approves `token` at `auth_url`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastFmAuthRequest {
+    pub token: String,
+    pub auth_url: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Scrobble {
+    artist: String,
+    track: String,
+    album: Option<String>,
+    listened_at: u64,
+    duration_seconds: Option<u64>,
+    musicbrainz_recording_id: Option<String>,
+}
+
+struct QueuedScrobble {
+    id: i64,
+    service: ScrobbleService,
+    attempts: u32,
+    scrobble: Scrobble,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SubmitError {
+    /// Worth trying again later: offline, rate limited, or the service is down.
+    Retry(String),
+    /// The service will never take it.
+    Rejected(String),
+}
+
+impl SubmitError {
+    fn into_message(self) -> String {
+        match self {
+            Self::Retry(message) | Self::Rejected(message) => message,
+        }
+    }
+}
+
+enum ScrobbleJob {
+    NowPlaying(Scrobble),
+    Flush,
+}
+
+pub struct ScrobbleManager {
+    inner: Arc<ScrobbleInner>,
+}
+
+struct ScrobbleInner {
+    config: Box<ScrobbleConfigSource>,
+    connection: Mutex<Connection>,
+    show: Mutex<Option<Scrobble>>,
+    jobs: Sender<ScrobbleJob>,
+}
+
+impl ScrobbleManager {
+    pub fn new(app: &AppHandle, config: Box<ScrobbleConfigSource>) -> Result<Self, String> {
+        let app_data_dir = app
+            .path()
+            .app_data_dir()
+            .map_err(|error| format!("unable to resolve app data directory: {error}"))?;
+        let connection = database::open(app_data_dir.join(DATABASE_FILE_NAME).as_path())?;
+        let (jobs_tx, jobs_rx) = mpsc::channel();
+        let inner = Arc::new(ScrobbleInner::new(config, connection, jobs_tx));
+        spawn_scrobble_worker(Arc::downgrade(&inner), jobs_rx);
+        // Anything left over from last time.
+        inner.send(ScrobbleJob::Flush);
+        Ok(Self { inner })
+    }
+
+    pub fn track_recognized(&self, track: &RecognizedTrack) {
+        if let Some(scrobble) = track_scrobble(track) {
+            self.inner.submit(scrobble);
+        }
+    }
+
+    /// Starts the show pseudo-track for a new stream, ending the one before it.
+    pub fn show_started(&self, metadata: Option<&NowPlayingMetadata>) {
+        self.inner.show_started(metadata, epoch_seconds());
+    }
+
+    /// Ends the show pseudo-track when the stream moves on to another show.
+    pub fn now_playing_changed(&self, metadata: Option<&NowPlayingMetadata>) {
+        self.inner.now_playing_changed(metadata, epoch_seconds());
+    }
+
+    pub fn show_ended(&self) {
+        self.inner.show_ended(epoch_seconds());
+    }
+
+    pub fn status(&self) -> ScrobbleStatus {
+        self.inner.status()
+    }
+
+    pub fn retry_now(&self) -> Result<(), String> {
+        self.inner
+            .lock_connection()?
+            .execute("UPDATE scrobble_queue SET next_attempt_at = 0", [])
+            .map_err(|error| error.to_string())?;
+        self.inner.send(ScrobbleJob::Flush);
+        Ok(())
+    }
+
+    /// Drops everything still waiting for a service being disconnected.
+    pub fn forget(&self, service: ScrobbleService) -> Result<(), String> {
+        self.inner
+            .lock_connection()?
+            .execute(
+                "DELETE FROM scrobble_queue WHERE service = ?1",
+                params![service.as_str()],
+            )
+            .map_err(|error| error.to_string())?;
+        Ok(())
+    }
+}
+
+impl ScrobbleInner {
+    fn new(
+        config: Box<ScrobbleConfigSource>,
+        connection: Connection,
+        jobs: Sender<ScrobbleJob>,
+    ) -> Self {
+        Self {
+            config,
+            connection: Mutex::new(connection),
+            show: Mutex::new(None),
+            jobs,
+        }
+    }
+
+    fn send(&self, job: ScrobbleJob) {
+        let _ = self.jobs.send(job);
+    }
+
+    fn run(&self, job: ScrobbleJob, now: u64) {
+        match job {
+            ScrobbleJob::NowPlaying(scrobble) => self.send_now_playing(&scrobble),
+            ScrobbleJob::Flush => {
+                if let Err(error) = self.flush_at(now) {
+                    eprintln!("[scrobble] unable to flush queue: {error}");
+                }
+            }
+        }
+    }
+
+    fn submit(&self, scrobble: Scrobble) {
+        if let Err(error) = self.enqueue(&scrobble, scrobble.listened_at) {
+            eprintln!("[scrobble] unable to queue scrobble: {error}");
+        }
+        self.send(ScrobbleJob::NowPlaying(scrobble));
+        self.send(ScrobbleJob::Flush);
+    }
+
+    fn show_started(&self, metadata: Option<&NowPlayingMetadata>, now: u64) {
+        self.show_ended(now);
+        if !(self.config)().scrobble_shows {
+            return;
+        }
+        let Some(scrobble) = metadata.and_then(|metadata| show_scrobble(metadata, now)) else {
+            return;
+        };
+        if let Ok(mut show) = self.show.lock() {
+            *show = Some(scrobble.clone());
+        }
+        self.send(ScrobbleJob::NowPlaying(scrobble));
+    }
+
+    fn now_playing_changed(&self, metadata: Option<&NowPlayingMetadata>, now: u64) {
+        let next = metadata.and_then(|metadata| show_scrobble(metadata, now));
+        let same_show = self
+            .show
+            .lock()
+            .is_ok_and(|show| match (show.as_ref(), next) {
+                (Some(open), Some(next)) => open.track == next.track && open.album == next.album,
+                (open, next) => open.is_none() && next.is_none(),
+            });
+        if !same_show {
+            self.show_started(metadata, now);
+        }
+    }
+
+    fn show_ended(&self, now: u64) {
+        let Some(mut scrobble) = self.show.lock().ok().and_then(|mut show| show.take()) else {
+            return;
+        };
+        let played = now.saturating_sub(scrobble.listened_at);
+        if played < MIN_SHOW_SECONDS {
+            return;
+        }
+        scrobble.duration_seconds = Some(played);
+        if let Err(error) = self.enqueue(&scrobble, now) {
+            eprintln!("[scrobble] unable to queue show: {error}");
+        }
+        self.send(ScrobbleJob::Flush);
+    }
+
+    /// Queues `scrobble` once for each connected service.
+    fn enqueue(&self, scrobble: &Scrobble, due_at: u64) -> Result<(), String> {
+        let config = (self.config)();
+        let connection = self.lock_connection()?;
+        for service in ScrobbleService::ALL {
+            if !service.is_connected(&config) {
+                continue;
+            }
+            connection
+                .execute(
+                    "INSERT INTO scrobble_queue (service, artist, track, album, listened_at, \
+                     duration_seconds, musicbrainz_recording_id, next_attempt_at) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![
+                        service.as_str(),
+                        scrobble.artist,
+                        scrobble.track,
+                        scrobble.album,
+                        sql_integer(scrobble.listened_at),
+                        scrobble.duration_seconds.map(sql_integer),
+                        scrobble.musicbrainz_recording_id,
+                        sql_integer(due_at),
+                    ],
+                )
+                .map_err(|error| error.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Best effort: a missed "now playing" isn't worth retrying.
+    fn send_now_playing(&self, scrobble: &Scrobble) {
+        let config = (self.config)();
+        if config.listenbrainz.is_connected() {
+            let result = ListenBrainzClient::new(&config.listenbrainz).and_then(|client| {
+                client
+                    .submit("playing_now", scrobble)
+                    .map_err(SubmitError::into_message)
+            });
+            if let Err(error) = result {
+                eprintln!("[scrobble] ListenBrainz now playing failed: {error}");
+            }
+        }
+        if config.lastfm.is_connected() {
+            let result = LastFmClient::new(&config.lastfm).and_then(|client| {
+                client
+                    .now_playing(scrobble)
+                    .map_err(SubmitError::into_message)
+            });
+            if let Err(error) = result {
+                eprintln!("[scrobble] Last.fm now playing failed: {error}");
+            }
+        }
+    }
+
+    /// Submits the scrobbles that are due, returning how many went through.
+    /// The connection isn't held while talking to the services.
+    fn flush_at(&self, now: u64) -> Result<usize, String> {
+        let config = (self.config)();
+        let due = self.due_scrobbles(now)?;
+        let mut listenbrainz = None;
+        let mut lastfm = None;
+        let mut submitted = 0;
+        for queued in due {
+            if !queued.service.is_connected(&config) {
+                continue;
+            }
+            let result = match queued.service {
+                ScrobbleService::ListenBrainz => {
+                    let client = listenbrainz
+                        .get_or_insert_with(|| ListenBrainzClient::new(&config.listenbrainz));
+                    match client {
+                        Ok(client) => client.submit("single", &queued.scrobble),
+                        Err(error) => Err(SubmitError::Retry(error.clone())),
+                    }
+                }
+                ScrobbleService::LastFm => {
+                    let client = lastfm.get_or_insert_with(|| LastFmClient::new(&config.lastfm));
+                    match client {
+                        Ok(client) => client.scrobble(&queued.scrobble),
+                        Err(error) => Err(SubmitError::Retry(error.clone())),
+                    }
+                }
+            };
+
+            let connection = self.lock_connection()?;
+            let outcome = match result {
+                Ok(()) => {
+                    submitted += 1;
+                    connection.execute(
+                        "DELETE FROM scrobble_queue WHERE id = ?1",
+                        params![queued.id],
+                    )
+                }
+                Err(SubmitError::Rejected(error)) => {
+                    eprintln!(
+                        "[scrobble] {} rejected \"{}\": {error}",
+                        queued.service.as_str(),
+                        queued.scrobble.track
+                    );
+                    connection.execute(
+                        "DELETE FROM scrobble_queue WHERE id = ?1",
+                        params![queued.id],
+                    )
+                }
+                Err(SubmitError::Retry(error)) => connection.execute(
+                    "UPDATE scrobble_queue SET attempts = ?2, next_attempt_at = ?3, \
+                     last_error = ?4 WHERE id = ?1",
+                    params![
+                        queued.id,
+                        queued.attempts.saturating_add(1),
+                        sql_integer(now.saturating_add(retry_delay(queued.attempts))),
+                        error,
+                    ],
+                ),
+            };
+            outcome.map_err(|error| error.to_string())?;
+        }
+        Ok(submitted)
+    }
+
+    fn due_scrobbles(&self, now: u64) -> Result<Vec<QueuedScrobble>, String> {
+        let connection = self.lock_connection()?;
+        let mut statement = connection
+            .prepare(
+                "SELECT id, service, attempts, artist, track, album, listened_at, \
+                 duration_seconds, musicbrainz_recording_id FROM scrobble_queue \
+                 WHERE next_attempt_at <= ?1 ORDER BY listened_at, id LIMIT ?2",
+            )
+            .map_err(|error| error.to_string())?;
+        let rows = statement
+            .query_map(
+                params![sql_integer(now), sql_integer(FLUSH_LIMIT as u64)],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, u32>(2)?,
+                        Scrobble {
+                            artist: row.get(3)?,
+                            track: row.get(4)?,
+                            album: row.get(5)?,
+                            listened_at: row.get::<_, i64>(6)?.max(0) as u64,
+                            duration_seconds: row
+                                .get::<_, Option<i64>>(7)?
+                                .map(|value| value.max(0) as u64),
+                            musicbrainz_recording_id: row.get(8)?,
+                        },
+                    ))
+                },
+            )
+            .map_err(|error| error.to_string())?;
+
+        let mut due = Vec::new();
+        for row in rows {
+            let (id, service, attempts, scrobble) = row.map_err(|error| error.to_string())?;
+            // Rows from a service this version doesn't know are left alone.
+            if let Some(service) = ScrobbleService::from_str(service.as_str()) {
+                due.push(QueuedScrobble {
+                    id,
+                    service,
+                    attempts,
+                    scrobble,
+                });
+            }
+        }
+        Ok(due)
+    }
+
+    fn status(&self) -> ScrobbleStatus {
+        let config = (self.config)();
+        let (queued, last_error) = self
+            .lock_connection()
+            .and_then(|connection| {
+                let queued = connection
+                    .query_row("SELECT COUNT(*) FROM scrobble_queue", [], |row| {
+                        row.get::<_, i64>(0)
+                    })
+                    .map_err(|error| error.to_string())?;
+                let last_error = connection
+                    .query_row(
+                        "SELECT last_error FROM scrobble_queue WHERE last_error IS NOT NULL \
+                         ORDER BY listened_at, id LIMIT 1",
+                        [],
+                        |row| row.get::<_, String>(0),
+                    )
+                    .ok();
+                Ok((usize::try_from(queued).unwrap_or(0), last_error))
+            })
+            .unwrap_or_else(|error| (0, Some(error)));
+
+        ScrobbleStatus {
+            listenbrainz_username: config
+                .listenbrainz
+                .is_connected()
+                .then(|| config.listenbrainz.username.clone())
+                .flatten(),
+            lastfm_username: config
+                .lastfm
+                .is_connected()
+                .then(|| config.lastfm.username.clone())
+                .flatten(),
+            scrobble_shows: config.scrobble_shows,
+            queued,
+            last_error,
+        }
+    }
+
+    fn lock_connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>, String> {
+        self.connection
+            .lock()
+            .map_err(|_| "scrobble queue lock poisoned".to_string())
+    }
+}
+
+fn spawn_scrobble_worker(weak: Weak<ScrobbleInner>, jobs: Receiver<ScrobbleJob>) {
+    thread::spawn(move || loop {
+        let job = match jobs.recv_timeout(RETRY_TICK) {
+            Ok(job) => job,
+            Err(RecvTimeoutError::Timeout) => ScrobbleJob::Flush,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let Some(inner) = weak.upgrade() else {
+            break;
+        };
+        inner.run(job, epoch_seconds());
+    });
+}
+
+fn track_scrobble(track: &RecognizedTrack) -> Option<Scrobble> {
+    Some(Scrobble {
+        artist: non_empty(track.artist.as_deref())?.to_string(),
+        track: non_empty(Some(track.title.as_str()))?.to_string(),
+        album: None,
+        listened_at: track.recognized_at,
+        duration_seconds: None,
+        musicbrainz_recording_id: track.musicbrainz_recording_id.clone(),
+    })
+}
+
+/// Live NTS shows only; mixtapes and stations have no show to credit.
+fn show_scrobble(metadata: &NowPlayingMetadata, now: u64) -> Option<Scrobble> {
+    non_empty(metadata.show_alias.as_deref())?;
+    Some(Scrobble {
+        artist: SHOW_ARTIST.to_string(),
+        track: non_empty(Some(metadata.title.as_str()))?.to_string(),
+        album: non_empty(metadata.album.as_deref()).map(str::to_string),
+        listened_at: now,
+        duration_seconds: None,
+        musicbrainz_recording_id: None,
+    })
+}
+
+fn retry_delay(attempts: u32) -> u64 {
+    RETRY_BASE_SECONDS
+        .saturating_mul(1_u64 << attempts.min(16))
+        .min(RETRY_MAX_SECONDS)
+}
+
+/// Checks a ListenBrainz user token, returning the user it belongs to.
+pub fn listenbrainz_username(config: &ListenBrainzConfig) -> Result<String, String> {
+    ListenBrainzClient::new(config)?.validate_token()
+}
+
+pub fn lastfm_begin_auth(config: &LastFmConfig) -> Result<LastFmAuthRequest, String> {
+    let client = LastFmClient::new(config)?;
+    let token = client.get_token()?;
+    let auth_url = reqwest::Url::parse_with_params(
+        LASTFM_AUTH_URL,
+        &[
+            ("api_key", client.api_key.as_str()),
+            ("token", token.as_str()),
+        ],
+    )
+    .map_err(|error| error.to_string())?
+    .to_string();
+    Ok(LastFmAuthRequest { token, auth_url })
+}
+
+/// Trades an approved token for a session, returning the username and key.
+pub fn lastfm_complete_auth(
+    config: &LastFmConfig,
+    token: &str,
+) -> Result<(String, String), String> {
+    LastFmClient::new(config)?.get_session(token)
+}
+
+fn http_client() -> Result<reqwest::blocking::Client, String> {
+    reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|error| format!("unable to build scrobble client: {error}"))
+}
+
+struct ListenBrainzClient {
+    base_url: String,
+    token: String,
+    http: reqwest::blocking::Client,
+}
+
+impl ListenBrainzClient {
+    fn new(config: &ListenBrainzConfig) -> Result<Self, String> {
+        let token = non_empty(config.token.as_deref())
+            .ok_or_else(|| "Add a ListenBrainz user token to scrobble.".to_string())?;
+        Ok(Self {
+            base_url: config.base_url().trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            http: http_client()?,
+        })
+    }
+
+    fn validate_token(&self) -> Result<String, String> {
+        let response = self
+            .http
+            .get(format!("{}/1/validate-token", self.base_url))
+            .header("Authorization", format!("Token {}", self.token))
+            .send()
+            .map_err(|error| format!("ListenBrainz token check failed: {error}"))?;
+        let body = response
+            .json::<Value>()
+            .map_err(|error| format!("unable to parse ListenBrainz response: {error}"))?;
+        if body["valid"].as_bool() != Some(true) {
+            return Err("ListenBrainz didn't accept that token.".to_string());
+        }
+        body["user_name"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| "ListenBrainz didn't say whose token that is.".to_string())
+    }
+
+    /// `listen_type` is `single` for a scrobble or `playing_now`.
+    fn submit(&self, listen_type: &str, scrobble: &Scrobble) -> Result<(), SubmitError> {
+        let mut additional_info = json!({
+            "submission_client": "Marconio",
+            "submission_client_version": env!("CARGO_PKG_VERSION"),
+        });
+        if let Some(duration) = scrobble.duration_seconds {
+            additional_info["duration"] = json!(duration);
+        }
+        if let Some(recording_id) = scrobble.musicbrainz_recording_id.as_deref() {
+            additional_info["recording_mbid"] = json!(recording_id);
+        }
+        let mut track_metadata = json!({
+            "artist_name": scrobble.artist,
+            "track_name": scrobble.track,
+            "additional_info": additional_info,
+        });
+        if let Some(album) = scrobble.album.as_deref() {
+            track_metadata["release_name"] = json!(album);
+        }
+        let mut listen = json!({ "track_metadata": track_metadata });
+        if listen_type != "playing_now" {
+            listen["listened_at"] = json!(scrobble.listened_at);
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/1/submit-listens", self.base_url))
+            .header("Authorization", format!("Token {}", self.token))
+            .json(&json!({ "listen_type": listen_type, "payload": [listen] }))
+            .send()
+            .map_err(|error| SubmitError::Retry(format!("ListenBrainz is unreachable: {error}")))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let message = response
+            .json::<Value>()
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| format!("status {}", status.as_u16()));
+        let message = format!("ListenBrainz: {message}");
+        // A revoked token is fixed by reconnecting, not by dropping scrobbles.
+        if status.as_u16() == 401 || status.as_u16() == 429 || status.is_server_error() {
+            Err(SubmitError::Retry(message))
+        } else {
+            Err(SubmitError::Rejected(message))
+        }
+    }
+}
+
+struct LastFmClient {
+    base_url: String,
+    api_key: String,
+    api_secret: String,
+    session_key: Option<String>,
+    http: reqwest::blocking::Client,
+}
+
+impl LastFmClient {
+    fn new(config: &LastFmConfig) -> Result<Self, String> {
+        let (Some(api_key), Some(api_secret)) = (
+            non_empty(config.api_key.as_deref()),
+            non_empty(config.api_secret.as_deref()),
+        ) else {
+            return Err("Add a Last.fm API key and secret to scrobble.".to_string());
+        };
+        Ok(Self {
+            base_url: config.base_url().trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            api_secret: api_secret.to_string(),
+            session_key: non_empty(config.session_key.as_deref()).map(str::to_string),
+            http: http_client()?,
+        })
+    }
+
+    fn get_token(&self) -> Result<String, String> {
+        let body = self
+            .call("auth.getToken", Vec::new(), false)
+            .map_err(SubmitError::into_message)?;
+        body["token"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| "Last.fm didn't return a token.".to_string())
+    }
+
+    fn get_session(&self, token: &str) -> Result<(String, String), String> {
+        let body = self
+            .call("auth.getSession", vec![("token", token.to_string())], false)
+            .map_err(SubmitError::into_message)?;
+        let session = &body["session"];
+        match (session["name"].as_str(), session["key"].as_str()) {
+            (Some(name), Some(key)) => Ok((name.to_string(), key.to_string())),
+            _ => Err("Last.fm didn't return a session; approve the app first.".to_string()),
+        }
+    }
+
+    fn now_playing(&self, scrobble: &Scrobble) -> Result<(), SubmitError> {
+        self.call("track.updateNowPlaying", track_params(scrobble), true)
+            .map(|_| ())
+    }
+
+    fn scrobble(&self, scrobble: &Scrobble) -> Result<(), SubmitError> {
+        let mut params = track_params(scrobble);
+        params.push(("timestamp", scrobble.listened_at.to_string()));
+        params.push(("chosenByUser", "0".to_string()));
+        let body = self.call("track.scrobble", params, true)?;
+        let ignored = body["scrobbles"]["@attr"]["ignored"]
+            .as_u64()
+            .or_else(|| body["scrobbles"]["@attr"]["ignored"].as_str()?.parse().ok())
+            .unwrap_or(0);
+        if ignored > 0 {
+            return Err(SubmitError::Rejected(
+                "Last.fm ignored the scrobble".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn call(
+        &self,
+        method: &str,
+        mut params: Vec<(&'static str, String)>,
+        signed_in: bool,
+    ) -> Result<Value, SubmitError> {
+        params.push(("method", method.to_string()));
+        params.push(("api_key", self.api_key.clone()));
+        if signed_in {
+            let session_key = self
+                .session_key
+                .clone()
+                .ok_or_else(|| SubmitError::Retry("Last.fm isn't connected".to_string()))?;
+            params.push(("sk", session_key));
+        }
+        params.push((
+            "api_sig",
+            lastfm_signature(&params, self.api_secret.as_str()),
+        ));
+        params.push(("format", "json".to_string()));
+
+        let response = self
+            .http
+            .post(format!("{}/2.0/", self.base_url))
+            .form(&params)
+            .send()
+            .map_err(|error| SubmitError::Retry(format!("Last.fm is unreachable: {error}")))?;
+        let status = response.status();
+        let body = match response.json::<Value>() {
+            Ok(body) => body,
+            Err(_) if status.is_success() => {
+                return Err(SubmitError::Retry(
+                    "unable to parse Last.fm response".to_string(),
+                ))
+            }
+            Err(_) => {
+                return Err(SubmitError::Retry(format!(
+                    "Last.fm: status {}",
+                    status.as_u16()
+                )))
+            }
+        };
+        if let Some(code) = body["error"].as_u64() {
+            let message = format!(
+                "Last.fm: {}",
+                body["message"].as_str().unwrap_or("unknown error")
+            );
+            // 9: bad session, 11: offline, 16: temporary error, 29: rate limited.
+            return match code {
+                9 | 11 | 16 | 29 => Err(SubmitError::Retry(message)),
+                _ => Err(SubmitError::Rejected(message)),
+            };
+        }
+        if !status.is_success() {
+            return Err(SubmitError::Retry(format!(
+                "Last.fm: status {}",
+                status.as_u16()
+            )));
+        }
+        Ok(body)
+    }
+}
+
+fn track_params(scrobble: &Scrobble) -> Vec<(&'static str, String)> {
+    let mut params = vec![
+        ("artist", scrobble.artist.clone()),
+        ("track", scrobble.track.clone()),
+    ];
+    if let Some(album) = scrobble.album.clone() {
+        params.push(("album", album));
+    }
+    if let Some(duration) = scrobble.duration_seconds {
+        params.push(("duration", duration.to_string()));
+    }
+    if let Some(recording_id) = scrobble.musicbrainz_recording_id.clone() {
+        params.push(("mbid", recording_id));
+    }
+    params
+}
+
+/// Every parameter but `format`, sorted by name and run together as
+/// `namevalue`, then the secret, md5'd.
+fn lastfm_signature(params: &[(&'static str, String)], secret: &str) -> String {
+    let mut sorted = params
+        .iter()
+        .filter(|(name, _)| *name != "format")
+        .collect::<Vec<_>>();
+    sorted.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+    let mut input = sorted
+        .into_iter()
+        .map(|(name, value)| format!("{name}{value}"))
+        .collect::<String>();
+    input.push_str(secret);
+    format!("{:x}", md5::compute(input.as_bytes()))
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|value| !value.is_empty())
+}
+
+/// SQLite integers are signed; epoch seconds stay far below the cutoff.
+fn sql_integer(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        lastfm_begin_auth, lastfm_complete_auth, lastfm_signature, LastFmConfig,
+        ListenBrainzConfig, ScrobbleConfig, ScrobbleInner, ScrobbleJob,
+    };
+    use crate::audio_engine::NowPlayingMetadata;
+    use crate::database;
+    use crate::shazam::RecognizedTrack;
+    use crate::test_support::{MockResponse, MockServer};
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::mpsc::{self, Receiver};
+    use std::sync::Arc;
+
+    const NOW: u64 = 1_791_763_200;
+
+    fn inner_for(config: ScrobbleConfig) -> (ScrobbleInner, Receiver<ScrobbleJob>) {
+        let (tx, rx) = mpsc::channel();
+        let connection = database::open_in_memory().expect("database");
+        (
+            ScrobbleInner::new(Box::new(move || config.clone()), connection, tx),
+            rx,
+        )
+    }
+
+    /// Does what the worker thread would, without the thread.
+    fn drain(inner: &ScrobbleInner, jobs: &Receiver<ScrobbleJob>, now: u64) {
+        for job in jobs.try_iter() {
+            inner.run(job, now);
+        }
+    }
+
+    fn track(title: &str, artist: Option<&str>) -> RecognizedTrack {
+        RecognizedTrack {
+            shazam_id: None,
+            title: title.to_string(),
+            artist: artist.map(str::to_string),
+            artwork_url: None,
+            apple_music_url: None,
+            web_url: None,
+            recognized_at: NOW,
+            source_title: None,
+            source_artist: None,
+            musicbrainz_recording_id: Some("cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff".to_string()),
+            acoustid: None,
+        }
+    }
+
+    fn listenbrainz(server: &MockServer) -> ScrobbleConfig {
+        ScrobbleConfig {
+            listenbrainz: ListenBrainzConfig {
+                base_url: Some(server.base_url()),
+                token: Some("lb-token".to_string()),
+                username: Some("listener".to_string()),
+            },
+            ..ScrobbleConfig::default()
+        }
+    }
+
+    fn body_json(body: &[u8]) -> Value {
+        serde_json::from_slice(body).expect("json body")
+    }
+
+    #[test]
+    fn listenbrainz_gets_now_playing_and_a_single_listen() {
+        let server = MockServer::start(|request| match request.path_without_query() {
+            "/1/validate-token" => {
+                MockResponse::json(r#"{"code": 200, "valid": true, "user_name": "listener"}"#)
+            }
+            _ => MockResponse::json(r#"{"status": "ok"}"#),
+        });
+        let (inner, jobs) = inner_for(listenbrainz(&server));
+
+        let config = listenbrainz(&server).listenbrainz;
+        assert_eq!(
+            super::listenbrainz_username(&config).as_deref(),
+            Ok("listener")
+        );
+
+        inner.submit(
+            super::track_scrobble(&track("Windowlicker", Some("Aphex Twin"))).expect("scrobble"),
+        );
+        drain(&inner, &jobs, NOW);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|request| request.header("Authorization") == Some("Token lb-token")));
+        let submits = requests
+            .iter()
+            .filter(|request| request.path == "/1/submit-listens")
+            .map(|request| body_json(request.body.as_slice()))
+            .collect::<Vec<_>>();
+        assert_eq!(submits.len(), 2);
+        assert_eq!(submits[0]["listen_type"], "playing_now");
+        assert!(submits[0]["payload"][0].get("listened_at").is_none());
+        assert_eq!(submits[1]["listen_type"], "single");
+        let listen = &submits[1]["payload"][0];
+        assert_eq!(listen["listened_at"], NOW);
+        assert_eq!(listen["track_metadata"]["artist_name"], "Aphex Twin");
+        assert_eq!(listen["track_metadata"]["track_name"], "Windowlicker");
+        assert_eq!(
+            listen["track_metadata"]["additional_info"]["recording_mbid"],
+            "cd2e7c47-16f5-46c6-a37c-a1eb7bf599ff"
+        );
+        assert_eq!(inner.status().queued, 0);
+
+        // Nothing to scrobble without an artist.
+        assert!(super::track_scrobble(&track("Untitled", None)).is_none());
+    }
+
+    #[test]
+    fn lastfm_auth_and_scrobbles_are_signed() {
+        let server = MockServer::start(|request| match request.form_param("method").as_deref() {
+            Some("auth.getToken") => MockResponse::json(r#"{"token": "tok"}"#),
+            Some("auth.getSession") => MockResponse::json(
+                r#"{"session": {"name": "listener", "key": "session-key", "subscriber": 0}}"#,
+            ),
+            Some("track.scrobble") => {
+                MockResponse::json(r#"{"scrobbles": {"@attr": {"accepted": 1, "ignored": 0}}}"#)
+            }
+            _ => MockResponse::json(r#"{"nowplaying": {}}"#),
+        });
+        let mut lastfm = LastFmConfig {
+            base_url: Some(server.base_url()),
+            api_key: Some("key".to_string()),
+            api_secret: Some("secret".to_string()),
+            ..LastFmConfig::default()
+        };
+
+        let request = lastfm_begin_auth(&lastfm).expect("begin auth");
+        assert_eq!(request.token, "tok");
+        assert_eq!(
+            request.auth_url,
+            "https://www.last.fm/api/auth/?api_key=key&token=tok"
+        );
+        let (username, session_key) = lastfm_complete_auth(&lastfm, "tok").expect("session");
+        assert_eq!(username, "listener");
+        lastfm.session_key = Some(session_key);
+
+        let (inner, jobs) = inner_for(ScrobbleConfig {
+            lastfm,
+            ..ScrobbleConfig::default()
+        });
+        inner.submit(
+            super::track_scrobble(&track("Windowlicker", Some("Aphex Twin"))).expect("scrobble"),
+        );
+        drain(&inner, &jobs, NOW);
+
+        let requests = server.requests();
+        let session = &requests[1];
+        assert_eq!(
+            session.form_param("api_sig"),
+            Some(format!(
+                "{:x}",
+                md5::compute("api_keykeymethodauth.getSessiontokentoksecret")
+            ))
+        );
+        let scrobble = requests
+            .iter()
+            .find(|request| request.form_param("method").as_deref() == Some("track.scrobble"))
+            .expect("scrobble request");
+        assert_eq!(scrobble.path, "/2.0/");
+        assert_eq!(scrobble.form_param("sk").as_deref(), Some("session-key"));
+        assert_eq!(scrobble.form_param("format").as_deref(), Some("json"));
+        assert_eq!(scrobble.form_param("timestamp"), Some(NOW.to_string()));
+        let mut params = [
+            "artist",
+            "track",
+            "mbid",
+            "timestamp",
+            "chosenByUser",
+            "method",
+            "api_key",
+            "sk",
+        ]
+        .into_iter()
+        .filter_map(|name| Some((name, scrobble.form_param(name)?)))
+        .collect::<Vec<_>>();
+        assert_eq!(params.len(), 8);
+        params.sort();
+        assert_eq!(
+            scrobble.form_param("api_sig"),
+            Some(lastfm_signature(&params, "secret"))
+        );
+        assert_eq!(inner.status().queued, 0);
+    }
+
+    #[test]
+    fn failed_scrobbles_wait_and_retry_with_backoff() {
+        let status = Arc::new(AtomicU16::new(503));
+        let server = MockServer::start({
+            let status = Arc::clone(&status);
+            move |request| {
+                if request
+                    .body
+                    .windows(11)
+                    .any(|window| window == b"playing_now")
+                {
+                    return MockResponse::json(r#"{"status": "ok"}"#);
+                }
+                MockResponse::new(status.load(Ordering::SeqCst), r#"{"error": "down"}"#)
+            }
+        });
+        let (inner, jobs) = inner_for(listenbrainz(&server));
+
+        inner.submit(
+            super::track_scrobble(&track("Windowlicker", Some("Aphex Twin"))).expect("scrobble"),
+        );
+        drain(&inner, &jobs, NOW);
+        let pending = inner.status();
+        assert_eq!(pending.queued, 1);
+        assert_eq!(pending.last_error.as_deref(), Some("ListenBrainz: down"));
+
+        // Not due again for a minute.
+        assert_eq!(inner.flush_at(NOW + 30), Ok(0));
+        assert_eq!(server.requests().len(), 2);
+        // Then two.
+        assert_eq!(inner.flush_at(NOW + 61), Ok(0));
+        assert_eq!(inner.flush_at(NOW + 61 + 119), Ok(0));
+        assert_eq!(server.requests().len(), 3);
+
+        status.store(200, Ordering::SeqCst);
+        assert_eq!(inner.flush_at(NOW + 61 + 121), Ok(1));
+        assert_eq!(inner.status().queued, 0);
+
+        // Listens the service refuses outright aren't kept.
+        status.store(400, Ordering::SeqCst);
+        inner.submit(super::track_scrobble(&track("Flim", Some("Aphex Twin"))).expect("scrobble"));
+        drain(&inner, &jobs, NOW);
+        assert_eq!(inner.status().queued, 0);
+
+        assert_eq!(super::retry_delay(0), 60);
+        assert_eq!(super::retry_delay(30), 6 * 60 * 60);
+    }
+
+    #[test]
+    fn shows_are_scrobbled_once_they_have_played_long_enough() {
+        let server = MockServer::start(|_| MockResponse::json(r#"{"status": "ok"}"#));
+        let mut config = listenbrainz(&server);
+        config.scrobble_shows = true;
+        let (inner, jobs) = inner_for(config);
+        let show = NowPlayingMetadata {
+            title: "Floating Points".to_string(),
+            artist: None,
+            album: Some("NTS 1".to_string()),
+            artwork_url: None,
+            show_alias: Some("floating-points".to_string()),
+            episode_alias: None,
+        };
+
+        inner.show_started(Some(&show), NOW);
+        inner.show_ended(NOW + 100);
+        inner.show_started(Some(&show), NOW + 200);
+        inner.show_started(None, NOW + 200 + 3_600);
+        drain(&inner, &jobs, NOW + 200 + 3_600);
+
+        let listens = server
+            .requests()
+            .into_iter()
+            .map(|request| body_json(request.body.as_slice()))
+            .filter(|body| body["listen_type"] == "single")
+            .collect::<Vec<_>>();
+        assert_eq!(listens.len(), 1);
+        let listen = &listens[0]["payload"][0];
+        assert_eq!(listen["listened_at"], NOW + 200);
+        assert_eq!(listen["track_metadata"]["artist_name"], "NTS Radio");
+        assert_eq!(listen["track_metadata"]["track_name"], "Floating Points");
+        assert_eq!(listen["track_metadata"]["release_name"], "NTS 1");
+        assert_eq!(
+            listen["track_metadata"]["additional_info"]["duration"],
+            3_600
+        );
+    }
+
+    #[test]
+    fn a_new_show_on_the_same_stream_is_scrobbled_separately() {
+        let server = MockServer::start(|_| MockResponse::json(r#"{"status": "ok"}"#));
+        let mut config = listenbrainz(&server);
+        config.scrobble_shows = true;
+        let (inner, jobs) = inner_for(config);
+        let show = |title: &str, alias: &str| NowPlayingMetadata {
+            title: title.to_string(),
+            artist: None,
+            album: Some("NTS 1".to_string()),
+            artwork_url: None,
+            show_alias: Some(alias.to_string()),
+            episode_alias: None,
+        };
+        let floating_points = show("Floating Points", "floating-points");
+
+        inner.show_started(Some(&floating_points), NOW);
+        inner.now_playing_changed(
+            Some(&NowPlayingMetadata {
+                artwork_url: Some("https://images.example/fp.jpg".to_string()),
+                ..floating_points.clone()
+            }),
+            NOW + 600,
+        );
+        inner.now_playing_changed(
+            Some(&show("Charlie Bones", "the-do-you-breakfast-show")),
+            NOW + 3_600,
+        );
+        inner.show_ended(NOW + 7_200);
+        drain(&inner, &jobs, NOW + 7_200);
+
+        let mut listens = server
+            .requests()
+            .into_iter()
+            .map(|request| body_json(request.body.as_slice()))
+            .filter(|body| body["listen_type"] == "single")
+            .map(|body| {
+                let listen = &body["payload"][0];
+                (
+                    listen["track_metadata"]["track_name"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    listen["listened_at"].as_u64().unwrap_or_default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        listens.sort();
+        assert_eq!(
+            listens,
+            vec![
+                ("Charlie Bones".to_string(), NOW + 3_600),
+                ("Floating Points".to_string(), NOW),
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_output_leaves_credentials_out() {
+        let config = ScrobbleConfig {
+            listenbrainz: ListenBrainzConfig {
+                token: Some("lb-token".to_string()),
+                username: Some("listener".to_string()),
+                ..ListenBrainzConfig::default()
+            },
+            lastfm: LastFmConfig {
+                api_key: Some("key".to_string()),
+                api_secret: Some("shh".to_string()),
+                session_key: Some("fm-session".to_string()),
+                ..LastFmConfig::default()
+            },
+            scrobble_shows: false,
+        };
+        let debug = format!("{config:?}");
+        assert!(debug.contains("listener"), "{debug}");
+        for secret in ["lb-token", "shh", "fm-session"] {
+            assert!(!debug.contains(secret), "{debug}");
+        }
+    }
+}
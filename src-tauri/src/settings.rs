@@ -0,0 +1,363 @@
+use crate::acoustid::AcoustIdConfig;
+use crate::audio_output::CrossfadeConfig;
+use crate::scrobble::ScrobbleConfig;
+use crate::shazam::AutoIdConfig;
+use crate::station::Station;
+use crate::tuning_noise::TuningNoiseConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+const UNREADABLE_SETTINGS_EXTENSION: &str = "json.bak";
+const USER_PRESET_SLOTS: std::ops::RangeInclusive<u8> = 3..=6;
+
+/// What a user preset slot (3–6) is tuned to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PresetAssignment {
+    Mixtape {
+        alias: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Station {
+        station_id: String,
+    },
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    pub stations: Vec<Station>,
+    pub presets: BTreeMap<u8, PresetAssignment>,
+    pub radio_browser_base_url: Option<String>,
+    pub acoustid_base_url: Option<String>,
+    pub acoustid_api_key: Option<String>,
+    pub auto_id: AutoIdConfig,
+    /// Recognition history entries to keep; unset keeps them all.
+    pub history_limit: Option<usize>,
+    /// Output device name; unset follows the system default.
+    pub output_device: Option<String>,
+    /// How changing stations sounds.
+    pub crossfade: CrossfadeConfig,
+    /// The static played between stations and while buffering.
+    pub tuning_noise: TuningNoiseConfig,
+    /// ListenBrainz and Last.fm credentials, set through the connect flows.
+    pub scrobble: ScrobbleConfig,
+}
+
+impl Settings {
+    pub fn radio_browser_base_url(&self) -> &str {
+        self.radio_browser_base_url
+            .as_deref()
+            .unwrap_or(crate::radio_browser::DEFAULT_BASE_URL)
+    }
+
+    pub fn acoustid_config(&self) -> AcoustIdConfig {
+        AcoustIdConfig {
+            base_url: self
+                .acoustid_base_url
+                .clone()
+                .unwrap_or_else(|| crate::acoustid::DEFAULT_BASE_URL.to_string()),
+            api_key: self.acoustid_api_key.clone(),
+        }
+    }
+
+    pub fn station(&self, station_id: &str) -> Option<&Station> {
+        self.stations
+            .iter()
+            .find(|station| station.id == station_id)
+    }
+
+    pub fn upsert_station(&mut self, station: Station) {
+        match self.stations.iter_mut().find(|item| item.id == station.id) {
+            Some(existing) => *existing = station,
+            None => self.stations.push(station),
+        }
+    }
+
+    pub fn remove_station(&mut self, station_id: &str) -> bool {
+        let before = self.stations.len();
+        self.stations.retain(|station| station.id != station_id);
+        self.presets.retain(|_, assignment| {
+            !matches!(assignment, PresetAssignment::Station { station_id: id } if id == station_id)
+        });
+        self.stations.len() != before
+    }
+
+    pub fn assign_preset(
+        &mut self,
+        slot: u8,
+        assignment: Option<PresetAssignment>,
+    ) -> Result<(), String> {
+        if !USER_PRESET_SLOTS.contains(&slot) {
+            return Err(format!("preset slot {slot} cannot be reassigned"));
+        }
+
+        match assignment {
+            Some(PresetAssignment::Station { station_id }) => {
+                if self.station(station_id.as_str()).is_none() {
+                    return Err(format!("unknown station: {station_id}"));
+                }
+                self.presets
+                    .insert(slot, PresetAssignment::Station { station_id });
+            }
+            Some(assignment) => {
+                self.presets.insert(slot, assignment);
+            }
+            None => {
+                self.presets.remove(&slot);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_history_limit(&mut self, limit: Option<usize>) -> Result<(), String> {
+        crate::history::validate_limit(limit)?;
+        self.history_limit = limit;
+        Ok(())
+    }
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: Mutex<Settings>,
+}
+
+impl SettingsStore {
+    pub fn new(app: &AppHandle) -> Result<Self, String> {
+        let mut path = app
+            .path()
+            .app_data_dir()
+            .map_err(|error| format!("unable to resolve app data directory: {error}"))?;
+        fs::create_dir_all(path.as_path())
+            .map_err(|error| format!("unable to create app data directory: {error}"))?;
+        path.push(SETTINGS_FILE_NAME);
+        Ok(Self::open(path))
+    }
+
+    /// Falls back to defaults when the file can't be read, first moving it
+    /// aside so the next save doesn't overwrite it.
+    pub fn open(path: PathBuf) -> Self {
+        let settings = load_settings(path.as_path()).unwrap_or_else(|error| {
+            eprintln!("[settings] unable to load settings, using defaults: {error}");
+            let backup = path.with_extension(UNREADABLE_SETTINGS_EXTENSION);
+            match fs::rename(path.as_path(), backup.as_path()) {
+                Ok(()) => eprintln!(
+                    "[settings] moved unreadable settings to {}",
+                    backup.display()
+                ),
+                Err(error) => eprintln!("[settings] unable to move unreadable settings: {error}"),
+            }
+            Settings::default()
+        });
+        Self {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn snapshot(&self) -> Settings {
+        match self.settings.lock() {
+            Ok(settings) => settings.clone(),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    /// Applies `f` to a copy of the settings and only swaps it in once the
+    /// result has been written to disk.
+    pub fn update<T>(
+        &self,
+        f: impl FnOnce(&mut Settings) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut settings = self
+            .settings
+            .lock()
+            .map_err(|_| "settings state lock poisoned".to_string())?;
+        let mut next = settings.clone();
+        let value = f(&mut next)?;
+        persist_settings(self.path.as_path(), &next)?;
+        *settings = next;
+        Ok(value)
+    }
+}
+
+fn load_settings(path: &Path) -> Result<Settings, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Settings::default())
+        }
+        Err(error) => {
+            return Err(format!(
+                "unable to read settings from {}: {error}",
+                path.display()
+            ))
+        }
+    };
+
+    serde_json::from_str::<Settings>(contents.as_str())
+        .map_err(|error| format!("unable to parse settings from {}: {error}", path.display()))
+}
+
+/// Only the user can read the file, since it holds scrobbling credentials.
+fn persist_settings(path: &Path, settings: &Settings) -> Result<(), String> {
+    let bytes = serde_json::to_vec_pretty(settings)
+        .map_err(|error| format!("unable to serialize settings: {error}"))?;
+    fs::write(path, bytes)
+        .map_err(|error| format!("unable to write settings to {}: {error}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+            .map_err(|error| format!("unable to restrict access to {}: {error}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PresetAssignment, Settings, SettingsStore};
+    use crate::station::Station;
+    use crate::test_support::test_dir;
+    use std::fs;
+
+    fn sample_station() -> Station {
+        Station {
+            id: "station-1".to_string(),
+            name: "Dublab".to_string(),
+            stream_url: "https://dublab.out.airtime.pro/dublab_a".to_string(),
+            artwork_url: None,
+            homepage_url: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn assign_preset_rejects_locked_slots_and_unknown_stations() {
+        let mut settings = Settings::default();
+        let assignment = PresetAssignment::Mixtape {
+            alias: "poolside".to_string(),
+        };
+        assert!(settings.assign_preset(1, Some(assignment.clone())).is_err());
+        assert!(settings
+            .assign_preset(
+                3,
+                Some(PresetAssignment::Station {
+                    station_id: "missing".to_string(),
+                }),
+            )
+            .is_err());
+        settings
+            .assign_preset(3, Some(assignment.clone()))
+            .expect("slot 3 should accept a mixtape");
+        assert_eq!(settings.presets.get(&3), Some(&assignment));
+    }
+
+    #[test]
+    fn remove_station_clears_its_presets() {
+        let mut settings = Settings::default();
+        settings.upsert_station(sample_station());
+        settings
+            .assign_preset(
+                4,
+                Some(PresetAssignment::Station {
+                    station_id: "station-1".to_string(),
+                }),
+            )
+            .expect("assignment should succeed");
+
+        assert!(settings.remove_station("station-1"));
+        assert!(settings.stations.is_empty());
+        assert!(settings.presets.is_empty());
+    }
+
+    #[test]
+    fn update_persists_and_reloads() {
+        let dir = test_dir("roundtrip");
+        let path = dir.join("settings.json");
+        let store = SettingsStore::open(path.clone());
+        store
+            .update(|settings| {
+                settings.upsert_station(sample_station());
+                Ok(())
+            })
+            .expect("update should succeed");
+
+        let reloaded = SettingsStore::open(path.clone());
+        assert_eq!(reloaded.snapshot().stations, vec![sample_station()]);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path).expect("metadata").permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn a_rejected_history_limit_is_not_persisted() {
+        let dir = test_dir("history-limit");
+        let path = dir.join("settings.json");
+        let store = SettingsStore::open(path.clone());
+        store
+            .update(|settings| settings.set_history_limit(Some(50)))
+            .expect("a positive limit should be saved");
+
+        assert!(store
+            .update(|settings| settings.set_history_limit(Some(0)))
+            .is_err());
+        assert_eq!(store.snapshot().history_limit, Some(50));
+        assert_eq!(SettingsStore::open(path).snapshot().history_limit, Some(50));
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn unreadable_settings_are_moved_aside_before_defaults_are_saved() {
+        let dir = test_dir("unreadable");
+        let path = dir.join("settings.json");
+        fs::write(path.as_path(), "{\"stations\": [").expect("failed to write settings");
+
+        let store = SettingsStore::open(path.clone());
+        assert!(store.snapshot().stations.is_empty());
+        store
+            .update(|settings| {
+                settings.upsert_station(sample_station());
+                Ok(())
+            })
+            .expect("update should succeed");
+
+        assert_eq!(
+            fs::read_to_string(dir.join("settings.json.bak")).expect("backup should exist"),
+            "{\"stations\": ["
+        );
+        assert_eq!(
+            SettingsStore::open(path).snapshot().stations,
+            vec![sample_station()]
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn update_does_not_mutate_memory_when_persist_fails() {
+        let dir = test_dir("persist-fail");
+        let blocking_parent = dir.join("not-a-directory");
+        fs::write(blocking_parent.as_path(), "block").expect("failed to create blocking file");
+        let store = SettingsStore::open(blocking_parent.join("settings.json"));
+
+        let result = store.update(|settings| {
+            settings.upsert_station(sample_station());
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert!(store.snapshot().stations.is_empty());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
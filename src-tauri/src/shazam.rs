@@ -1,18 +1,54 @@
+use crate::acoustid::AcoustIdConfigSource;
 use crate::audio_engine::NowPlayingMetadata;
+use crate::audio_samples;
+use crate::database::DATABASE_FILE_NAME;
+use crate::fingerprint::{self, FingerprintStore};
+use crate::history::{
+    HistoryEntry, HistoryEntryUpdate, HistoryImportSummary, HistoryQuery, HistoryStore,
+};
+use crate::recognition::{self, RecognitionBackend, RecognitionEvent, RecognitionMatch};
+use crate::tracklist::{Tracklist, TracklistStore};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Emitter, Manager};
 
 const SHAZAM_STATUS_EVENT: &str = "shazam-status";
 const SHAZAM_RESULT_EVENT: &str = "shazam-result";
 const SHAZAM_HISTORY_EVENT: &str = "shazam-history";
+const SHAZAM_TRACKLIST_EVENT: &str = "shazam-tracklist";
 const HISTORY_FILE_NAME: &str = "shazam-history.json";
-const HISTORY_LIMIT: usize = 200;
-const RECOGNITION_TIMEOUT: Duration = Duration::from_secs(14);
+const FINGERPRINT_FILE_NAME: &str = "shazam-fingerprints.json";
+const TRACKLIST_FILE_NAME: &str = "shazam-tracklists.json";
+const CAPTURE_LIMIT_SECONDS: usize = 20;
+// Audio from just before an attempt starts is often what made someone ask.
+const PRE_ROLL_SECONDS: usize = 10;
+const PRE_ROLL_CHUNK_FRAMES: usize = 4_096;
+pub(crate) const RECOGNITION_TIMEOUT: Duration = Duration::from_secs(14);
+const AUTO_ID_TICK: Duration = Duration::from_secs(1);
+const AUTO_ID_MIN_INTERVAL_SECONDS: u64 = 30;
+// Give a new track a few seconds to get going before listening to it.
+const AUTO_ID_TRANSITION_DELAY: Duration = Duration::from_secs(8);
+const AUDIO_IDLE_AFTER: Duration = Duration::from_secs(2);
+/// The same title heard again within this long is the same recognition.
+pub(crate) const DUPLICATE_WINDOW_SECONDS: u64 = 180;
+/// How many of the latest entries a new recognition is checked against.
+pub(crate) const DUPLICATE_CHECK_DEPTH: usize = 12;
+
+/// Delivers a frontend event; the app handle in production, a recorder in tests.
+type ShazamEventSink = dyn Fn(&str, Value) -> Result<(), String> + Send + Sync + 'static;
+
+/// What is playing right now, for attempts auto ID starts on its own.
+pub type NowPlayingSource = dyn Fn() -> Option<NowPlayingMetadata> + Send + Sync;
+
+/// Told about each recognition that made it into the history.
+pub type MatchObserver = dyn Fn(&RecognizedTrack) + Send + Sync;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -26,6 +62,8 @@ pub struct RecognizedTrack {
     pub recognized_at: u64,
     pub source_title: Option<String>,
     pub source_artist: Option<String>,
+    pub musicbrainz_recording_id: Option<String>,
+    pub acoustid: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -45,214 +83,537 @@ struct ShazamResultPayload {
 #[derive(Clone, Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ShazamHistoryPayload {
-    history: Vec<RecognizedTrack>,
+    history: Vec<HistoryEntry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ShazamTracklistPayload {
+    tracklist: Tracklist,
+}
+
+/// Opt-in background recognition while something is playing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AutoIdConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub on_track_change: bool,
+}
+
+impl Default for AutoIdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_seconds: 90,
+            on_track_change: true,
+        }
+    }
+}
+
+impl AutoIdConfig {
+    fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_seconds.max(AUTO_ID_MIN_INTERVAL_SECONDS))
+    }
+}
+
+#[derive(Default)]
+struct AutoIdState {
+    config: AutoIdConfig,
+    last_attempt_at: Option<Instant>,
+    transition_at: Option<Instant>,
+    last_source_key: Option<String>,
 }
 
 #[derive(Default)]
 struct AttemptState {
     id: u64,
     active: bool,
+    auto: bool,
     source: Option<NowPlayingMetadata>,
 }
 
+/// The attempt being finished: what was playing, and whether auto ID
+/// started it (in which case misses stay quiet).
+struct EndedAttempt {
+    source: Option<NowPlayingMetadata>,
+    auto: bool,
+}
+
+/// A match on its way to the history, which is written off the recognition
+/// thread.
+struct PendingMatch {
+    track: RecognizedTrack,
+    ended: EndedAttempt,
+    prefix: &'static str,
+}
+
+/// Mono audio heard during the current attempt, kept for fingerprinting.
+#[derive(Default)]
+struct AttemptCapture {
+    samples: Vec<f32>,
+    sample_rate: u32,
+}
+
+impl AttemptCapture {
+    fn push(&mut self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if self.sample_rate != sample_rate {
+            self.samples.clear();
+            self.sample_rate = sample_rate;
+        }
+        let limit = CAPTURE_LIMIT_SECONDS * sample_rate as usize;
+        if self.samples.len() < limit {
+            self.samples.extend(
+                audio_samples::downmix_mono(samples, channels).take(limit - self.samples.len()),
+            );
+        }
+    }
+}
+
+/// The last few seconds of tapped audio, replayed into each new attempt.
+#[derive(Default)]
+struct PreRoll {
+    samples: VecDeque<f32>,
+    channels: u16,
+    sample_rate: u32,
+    updated_at: Option<Instant>,
+    /// An attempt is replaying what was taken; audio tapped meanwhile
+    /// queues here behind it.
+    replaying: bool,
+}
+
+impl PreRoll {
+    fn push(&mut self, samples: &[f32], channels: u16, sample_rate: u32, now: Instant) {
+        if channels != self.channels || sample_rate != self.sample_rate || self.is_stale(now) {
+            self.samples.clear();
+            self.channels = channels;
+            self.sample_rate = sample_rate;
+        }
+        self.updated_at = Some(now);
+
+        let whole_frames = samples.len() / channels as usize * channels as usize;
+        self.samples.extend(samples[..whole_frames].iter().copied());
+        let limit = PRE_ROLL_SECONDS * sample_rate as usize * channels as usize;
+        let excess = self.samples.len().saturating_sub(limit);
+        self.samples.drain(..excess);
+    }
+
+    /// Audio left over from before playback paused doesn't belong to
+    /// whatever is playing now.
+    fn is_stale(&self, now: Instant) -> bool {
+        self.updated_at
+            .is_none_or(|at| now.saturating_duration_since(at) > AUDIO_IDLE_AFTER)
+    }
+
+    fn take(&mut self, now: Instant) -> Option<(Vec<f32>, u16, u32)> {
+        let stale = self.is_stale(now);
+        let samples = std::mem::take(&mut self.samples);
+        if stale || samples.is_empty() {
+            return None;
+        }
+        Some((Vec::from(samples), self.channels, self.sample_rate))
+    }
+}
+
 pub struct ShazamManager {
     inner: Arc<ShazamInner>,
 }
 
 struct ShazamInner {
-    app: AppHandle,
-    history_path: PathBuf,
-    history: Mutex<Vec<RecognizedTrack>>,
+    emit: Box<ShazamEventSink>,
+    history: Mutex<HistoryStore>,
+    pending_matches: Sender<PendingMatch>,
     attempt: Mutex<AttemptState>,
     identifying: AtomicBool,
-    #[cfg(target_os = "macos")]
-    bridge: Mutex<Option<MacBridge>>,
-    #[cfg(target_os = "macos")]
-    callback_context: Mutex<Option<Box<CallbackContext>>>,
+    backend: Mutex<Box<dyn RecognitionBackend>>,
+    backend_listening: AtomicBool,
+    capture: Mutex<AttemptCapture>,
+    pre_roll: Mutex<PreRoll>,
+    fingerprints: Mutex<FingerprintStore>,
+    tracklists: Mutex<TracklistStore>,
+    auto_id: Mutex<AutoIdState>,
+    now_playing: Mutex<Option<Box<NowPlayingSource>>>,
+    on_match: Mutex<Option<Box<MatchObserver>>>,
+    last_audio_at: Mutex<Option<Instant>>,
+    timeout: Duration,
 }
 
 impl ShazamManager {
-    pub fn new(app: AppHandle) -> Result<Self, String> {
+    pub fn new(app: AppHandle, acoustid: Box<AcoustIdConfigSource>) -> Result<Self, String> {
         let history_path = resolve_history_path(&app)?;
-        let history = load_history_or_empty(history_path.as_path());
+        let (events_tx, events_rx) = mpsc::channel::<RecognitionEvent>();
+        let backend = recognition::default_backend(events_tx, acoustid)?;
+        let emit = Box::new(move |event: &str, payload: Value| {
+            app.emit(event, payload).map_err(|error| error.to_string())
+        });
+        let manager =
+            Self::with_backend(history_path, backend, events_rx, emit, RECOGNITION_TIMEOUT)?;
+        spawn_auto_id_ticker(Arc::downgrade(&manager.inner));
+        Ok(manager)
+    }
+
+    fn with_backend(
+        history_path: PathBuf,
+        backend: Box<dyn RecognitionBackend>,
+        events: Receiver<RecognitionEvent>,
+        emit: Box<ShazamEventSink>,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let history = HistoryStore::open(
+            history_path.with_file_name(DATABASE_FILE_NAME).as_path(),
+            history_path.as_path(),
+        )?;
+        let fingerprints =
+            FingerprintStore::open(history_path.with_file_name(FINGERPRINT_FILE_NAME));
+        let tracklists = TracklistStore::open(history_path.with_file_name(TRACKLIST_FILE_NAME));
+        let (pending_tx, pending_rx) = mpsc::channel::<PendingMatch>();
         let inner = Arc::new(ShazamInner {
-            app,
-            history_path,
+            emit,
             history: Mutex::new(history),
+            pending_matches: pending_tx,
             attempt: Mutex::new(AttemptState::default()),
             identifying: AtomicBool::new(false),
-            #[cfg(target_os = "macos")]
-            bridge: Mutex::new(None),
-            #[cfg(target_os = "macos")]
-            callback_context: Mutex::new(None),
+            backend: Mutex::new(backend),
+            backend_listening: AtomicBool::new(false),
+            capture: Mutex::new(AttemptCapture::default()),
+            pre_roll: Mutex::new(PreRoll::default()),
+            fingerprints: Mutex::new(fingerprints),
+            tracklists: Mutex::new(tracklists),
+            auto_id: Mutex::new(AutoIdState::default()),
+            now_playing: Mutex::new(None),
+            on_match: Mutex::new(None),
+            last_audio_at: Mutex::new(None),
+            timeout,
         });
-
-        #[cfg(target_os = "macos")]
-        inner.initialize_bridge()?;
+        spawn_event_pump(Arc::downgrade(&inner), events);
+        spawn_history_writer(Arc::downgrade(&inner), pending_rx);
 
         Ok(Self { inner })
     }
 
     pub fn identify_now(&self, source: Option<NowPlayingMetadata>) -> Result<(), String> {
-        self.inner.start_attempt(source)
+        self.inner.start_attempt(source, false)
+    }
+
+    pub fn set_now_playing_source(&self, source: Box<NowPlayingSource>) {
+        if let Ok(mut now_playing) = self.inner.now_playing.lock() {
+            *now_playing = Some(source);
+        }
+    }
+
+    pub fn set_on_match(&self, observer: Box<MatchObserver>) {
+        if let Ok(mut on_match) = self.inner.on_match.lock() {
+            *on_match = Some(observer);
+        }
+    }
+
+    pub fn auto_id_config(&self) -> AutoIdConfig {
+        self.inner
+            .auto_id
+            .lock()
+            .map(|auto_id| auto_id.config.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_auto_id(&self, config: AutoIdConfig) {
+        if let Ok(mut auto_id) = self.inner.auto_id.lock() {
+            *auto_id = AutoIdState {
+                config,
+                ..AutoIdState::default()
+            };
+        }
+    }
+
+    /// Schedules an auto ID attempt shortly after a track change heard in
+    /// the audio, for sources whose metadata doesn't change between songs.
+    pub fn notify_track_transition(&self) {
+        if let Ok(mut auto_id) = self.inner.auto_id.lock() {
+            if auto_id.config.enabled && auto_id.config.on_track_change {
+                auto_id.transition_at = Some(Instant::now() + AUTO_ID_TRANSITION_DELAY);
+            }
+        }
+    }
+
+    pub fn tracklists(&self) -> Vec<Tracklist> {
+        self.inner
+            .tracklists
+            .lock()
+            .map(|tracklists| tracklists.list())
+            .unwrap_or_default()
+    }
+
+    pub fn export_tracklist(&self, key: &str) -> Result<String, String> {
+        let tracklists = self
+            .inner
+            .tracklists
+            .lock()
+            .map_err(|_| "tracklist state lock poisoned".to_string())?;
+        tracklists
+            .get(key)
+            .map(crate::tracklist::export_text)
+            .ok_or_else(|| format!("unknown tracklist: {key}"))
+    }
+
+    pub fn delete_tracklist(&self, key: &str) -> Result<bool, String> {
+        self.inner
+            .tracklists
+            .lock()
+            .map_err(|_| "tracklist state lock poisoned".to_string())?
+            .remove(key)
     }
 
     pub fn ingest_audio(&self, samples: &[f32], channels: u16, sample_rate: u32) {
         self.inner.ingest_audio(samples, channels, sample_rate);
     }
 
-    pub fn get_history(&self) -> Vec<RecognizedTrack> {
-        match self.inner.history.lock() {
-            Ok(history) => history.clone(),
-            Err(_) => Vec::new(),
+    pub fn get_history(&self) -> Vec<HistoryEntry> {
+        self.inner.read_history(&HistoryQuery::default())
+    }
+
+    pub fn search_history(&self, query: &HistoryQuery) -> Vec<HistoryEntry> {
+        self.inner.read_history(query)
+    }
+
+    pub fn set_history_limit(&self, limit: Option<usize>) -> Result<(), String> {
+        self.inner.lock_history()?.set_limit(limit)?;
+        self.inner.emit_history();
+        Ok(())
+    }
+
+    pub fn update_history_entry(
+        &self,
+        id: &str,
+        update: HistoryEntryUpdate,
+    ) -> Result<HistoryEntry, String> {
+        let entry = self.inner.lock_history()?.update(id, update)?;
+        self.inner.emit_history();
+        Ok(entry)
+    }
+
+    pub fn delete_history_entry(&self, id: &str) -> Result<bool, String> {
+        let removed = self.inner.lock_history()?.remove(id)?;
+        if removed {
+            self.inner.emit_history();
         }
+        Ok(removed)
+    }
+
+    /// Merges a history file or export from `path` into the local history.
+    pub fn import_history(&self, path: &Path) -> Result<HistoryImportSummary, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("unable to read {}: {error}", path.display()))?;
+        let (entries, unreadable) = crate::history_import::parse_import(contents.as_str())
+            .map_err(|error| format!("unable to import {}: {error}", path.display()))?;
+        let mut summary = self.inner.lock_history()?.import(entries)?;
+        summary.skipped += unreadable;
+        if summary.added > 0 || summary.updated > 0 {
+            self.inner.emit_history();
+        }
+        Ok(summary)
     }
 
     pub fn clear_history(&self) -> Result<(), String> {
-        let mut history = self
-            .inner
-            .history
-            .lock()
-            .map_err(|_| "Shazam history state lock poisoned".to_string())?;
-        clear_history_with_persist(self.inner.history_path.as_path(), &mut history)?;
-        drop(history);
+        self.inner.lock_history()?.clear()?;
         self.inner.emit_history();
         Ok(())
     }
 }
 
 impl ShazamInner {
-    fn start_attempt(self: &Arc<Self>, source: Option<NowPlayingMetadata>) -> Result<(), String> {
-        #[cfg(not(target_os = "macos"))]
-        {
-            let _ = source;
-            return Err("ShazamKit recognition is only available on macOS.".to_string());
-        }
-
-        #[cfg(target_os = "macos")]
-        {
-            let attempt_id = {
-                let mut attempt = self
-                    .attempt
-                    .lock()
-                    .map_err(|_| "Shazam attempt state lock poisoned".to_string())?;
-                if attempt.active {
-                    return Err("Song recognition is already in progress.".to_string());
+    fn start_attempt(
+        self: &Arc<Self>,
+        source: Option<NowPlayingMetadata>,
+        auto: bool,
+    ) -> Result<(), String> {
+        let attempt_id = {
+            let mut attempt = self
+                .attempt
+                .lock()
+                .map_err(|_| "Shazam attempt state lock poisoned".to_string())?;
+            if attempt.active {
+                // Asking while auto ID is already listening adopts that attempt.
+                if attempt.auto && !auto {
+                    attempt.auto = false;
+                    return Ok(());
                 }
-                attempt.id = attempt.id.saturating_add(1);
-                attempt.active = true;
-                attempt.source = source;
-                attempt.id
-            };
+                return Err("Song recognition is already in progress.".to_string());
+            }
+            attempt.id = attempt.id.saturating_add(1);
+            attempt.active = true;
+            attempt.auto = auto;
+            attempt.source = source;
+            attempt.id
+        };
+
+        if let Ok(mut capture) = self.capture.lock() {
+            *capture = AttemptCapture::default();
+        }
 
+        // Tapped audio queues behind the pre-roll until it has been replayed,
+        // so the backend hears everything in order.
+        let taken = {
+            let mut pre_roll = self
+                .pre_roll
+                .lock()
+                .map_err(|_| "Shazam pre-roll lock poisoned".to_string())?;
             self.identifying.store(true, Ordering::Release);
-            if let Err(error) = self.with_bridge_mut(|bridge| bridge.start()) {
+            pre_roll.replaying = true;
+            pre_roll.take(Instant::now())
+        };
+        match self.with_backend(|backend| backend.start()) {
+            Ok(()) => self.backend_listening.store(true, Ordering::Release),
+            Err(error) if self.has_fingerprints() => {
+                eprintln!("[shazam] backend unavailable, matching locally: {error}");
+            }
+            Err(error) => {
                 self.identifying.store(false, Ordering::Release);
+                self.end_replay();
                 if let Ok(mut attempt) = self.attempt.lock() {
                     attempt.active = false;
                     attempt.source = None;
                 }
                 return Err(error);
             }
+        }
 
-            self.emit_status("listening");
-
-            let weak = Arc::downgrade(self);
-            std::thread::spawn(move || {
-                std::thread::sleep(RECOGNITION_TIMEOUT);
-                if let Some(inner) = weak.upgrade() {
-                    inner.finish_timeout(attempt_id);
+        self.emit_status("listening");
+        let mut next = taken;
+        loop {
+            if let Some((samples, channels, sample_rate)) = next {
+                if let Err(error) = self.replay_pre_roll(samples.as_slice(), channels, sample_rate)
+                {
+                    self.end_replay();
+                    self.finalize_error(error);
+                    return Ok(());
                 }
-            });
-
-            Ok(())
+            }
+            let Ok(mut pre_roll) = self.pre_roll.lock() else {
+                break;
+            };
+            next = pre_roll.take(Instant::now());
+            if next.is_none() {
+                pre_roll.replaying = false;
+                break;
+            }
         }
+
+        let weak = Arc::downgrade(self);
+        let timeout = self.timeout;
+        std::thread::spawn(move || {
+            std::thread::sleep(timeout);
+            if let Some(inner) = weak.upgrade() {
+                inner.finish_timeout(attempt_id);
+            }
+        });
+
+        Ok(())
     }
 
     fn ingest_audio(&self, samples: &[f32], channels: u16, sample_rate: u32) {
-        #[cfg(not(target_os = "macos"))]
-        {
-            let _ = (samples, channels, sample_rate);
+        let now = Instant::now();
+        if let Ok(mut last_audio_at) = self.last_audio_at.lock() {
+            *last_audio_at = Some(now);
+        }
+        if channels == 0 || samples.is_empty() {
+            return;
+        }
+        let frame_count = samples.len() / channels as usize;
+        if frame_count == 0 {
+            return;
         }
 
-        #[cfg(target_os = "macos")]
         {
-            if !self.identifying.load(Ordering::Acquire) {
+            let Ok(mut pre_roll) = self.pre_roll.lock() else {
                 return;
-            }
-            if channels == 0 || samples.is_empty() {
-                return;
-            }
-            let frame_count = samples.len() / channels as usize;
-            if frame_count == 0 {
+            };
+            if !self.identifying.load(Ordering::Acquire) || pre_roll.replaying {
+                pre_roll.push(samples, channels, sample_rate, now);
                 return;
             }
+        }
 
-            if let Err(error) = self.with_bridge_mut(|bridge| bridge.feed(samples, channels, sample_rate)) {
-                self.finalize_error(error);
-            }
+        if let Ok(mut capture) = self.capture.lock() {
+            capture.push(samples, channels, sample_rate);
+        }
+
+        if !self.backend_listening.load(Ordering::Acquire) {
+            return;
+        }
+        if let Err(error) =
+            self.with_backend(|backend| backend.feed(samples, channels, sample_rate))
+        {
+            self.finalize_error(error);
+        }
+    }
+
+    fn end_replay(&self) {
+        if let Ok(mut pre_roll) = self.pre_roll.lock() {
+            pre_roll.replaying = false;
+            pre_roll.samples.clear();
         }
     }
 
+    fn replay_pre_roll(
+        &self,
+        samples: &[f32],
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<(), String> {
+        if let Ok(mut capture) = self.capture.lock() {
+            capture.push(samples, channels, sample_rate);
+        }
+        if !self.backend_listening.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        samples
+            .chunks(PRE_ROLL_CHUNK_FRAMES * channels as usize)
+            .try_for_each(|chunk| {
+                self.with_backend(|backend| backend.feed(chunk, channels, sample_rate))
+            })
+    }
+
     fn finish_timeout(&self, attempt_id: u64) {
-        let should_finish = {
+        let ended = {
             let mut attempt = match self.attempt.lock() {
                 Ok(attempt) => attempt,
                 Err(_) => return,
             };
             if !attempt.active || attempt.id != attempt_id {
-                false
-            } else {
-                attempt.active = false;
-                attempt.source = None;
-                true
+                return;
+            }
+            attempt.active = false;
+            EndedAttempt {
+                source: attempt.source.take(),
+                auto: attempt.auto,
             }
         };
 
-        if !should_finish {
-            return;
-        }
-
-        self.identifying.store(false, Ordering::Release);
-        #[cfg(target_os = "macos")]
-        self.stop_bridge();
-        self.emit_status("idle");
-        self.emit_result("noMatch", "No match found.", None);
+        self.finish_without_match(ended, "noMatch", "No match found.");
     }
 
     fn finalize_no_match(&self) {
-        if !self.take_active_attempt() {
+        let Some(ended) = self.take_active_attempt() else {
             return;
-        }
-        self.identifying.store(false, Ordering::Release);
-        #[cfg(target_os = "macos")]
-        self.stop_bridge();
-        self.emit_status("idle");
-        self.emit_result("noMatch", "No match found.", None);
+        };
+        self.finish_without_match(ended, "noMatch", "No match found.");
     }
 
     fn finalize_error(&self, message: String) {
-        if !self.take_active_attempt() {
+        let Some(ended) = self.take_active_attempt() else {
             return;
-        }
-        self.identifying.store(false, Ordering::Release);
-        #[cfg(target_os = "macos")]
-        self.stop_bridge();
-        self.emit_status("idle");
-        self.emit_result("error", &message, None);
+        };
+        self.finish_without_match(ended, "error", &message);
     }
 
-    #[cfg(target_os = "macos")]
-    fn finalize_match(&self, payload: BridgeMatchPayload) {
-        let source = match self.take_active_attempt_with_source() {
-            Some(source) => source,
+    fn finalize_match(&self, payload: RecognitionMatch) {
+        let ended = match self.take_active_attempt() {
+            Some(ended) => ended,
             None => return,
         };
+        let source = ended.source.as_ref();
 
-        self.identifying.store(false, Ordering::Release);
-        #[cfg(target_os = "macos")]
-        self.stop_bridge();
-        self.emit_status("idle");
+        self.end_listening();
+        let landmarks = self.capture_landmarks();
 
         let track = RecognizedTrack {
             shazam_id: payload.shazam_id,
@@ -262,57 +623,252 @@ impl ShazamInner {
             apple_music_url: payload.apple_music_url,
             web_url: payload.web_url,
             recognized_at: epoch_seconds(),
-            source_title: source.as_ref().map(|item| item.title.clone()),
-            source_artist: source.as_ref().and_then(|item| item.artist.clone()),
+            source_title: source.map(|item| item.title.clone()),
+            source_artist: source.and_then(|item| item.artist.clone()),
+            musicbrainz_recording_id: payload.musicbrainz_recording_id,
+            acoustid: payload.acoustid,
         };
 
+        if let Ok(mut fingerprints) = self.fingerprints.lock() {
+            if let Err(error) = fingerprints.remember(&track, landmarks) {
+                eprintln!("[shazam] unable to store fingerprint: {error}");
+            }
+        }
+
+        self.queue_match(track, ended, "Recognized");
+    }
+
+    /// Ends an attempt the backend could not answer, falling back to the
+    /// user's own fingerprinted history before reporting `kind`.
+    fn finish_without_match(&self, ended: EndedAttempt, kind: &str, message: &str) {
+        self.end_listening();
+        let source = ended.source.as_ref();
+
+        let landmarks = self.capture_landmarks();
+        let local = self
+            .fingerprints
+            .lock()
+            .ok()
+            .and_then(|fingerprints| fingerprints.best_match(landmarks.as_slice()));
+        if let Some((track, score)) = local {
+            eprintln!(
+                "[shazam] matched {} locally ({score} landmarks)",
+                track.title
+            );
+            let track = RecognizedTrack {
+                recognized_at: epoch_seconds(),
+                source_title: source.map(|item| item.title.clone()),
+                source_artist: source.and_then(|item| item.artist.clone()),
+                ..track
+            };
+            self.queue_match(track, ended, "Recognized from your history");
+            return;
+        }
+
+        if ended.auto {
+            eprintln!("[shazam] auto ID attempt ended without a match: {message}");
+            return;
+        }
+        self.emit_result(kind, message, None);
+    }
+
+    fn queue_match(&self, track: RecognizedTrack, ended: EndedAttempt, prefix: &'static str) {
+        let pending = PendingMatch {
+            track,
+            ended,
+            prefix,
+        };
+        if self.pending_matches.send(pending).is_err() {
+            eprintln!("[shazam] history writer stopped, dropping match");
+        }
+    }
+
+    fn record_match(&self, pending: PendingMatch) {
+        let PendingMatch {
+            track,
+            ended,
+            prefix,
+        } = pending;
         let message = if let Some(artist) = track.artist.as_ref() {
-            format!("Recognized: {} — {}", track.title, artist)
+            format!("{prefix}: {} — {}", track.title, artist)
         } else {
-            format!("Recognized: {}", track.title)
+            format!("{prefix}: {}", track.title)
         };
 
-        if let Err(error) = self.push_history(track.clone()) {
-            self.emit_result("error", &error, None);
-            return;
+        if let Some(source) = ended.source.as_ref() {
+            self.push_tracklist(source, &track);
+        }
+
+        let show_alias = ended
+            .source
+            .as_ref()
+            .and_then(|source| source.show_alias.clone());
+        let added = match self.push_history(track.clone(), show_alias) {
+            Ok(added) => added,
+            Err(error) => {
+                self.emit_result("error", &error, None);
+                return;
+            }
+        };
+
+        if added {
+            if let Ok(on_match) = self.on_match.lock() {
+                if let Some(observer) = on_match.as_ref() {
+                    observer(&track);
+                }
+            }
         }
 
+        // Auto ID hears the same track several times; only news is worth a toast.
+        if ended.auto && !added {
+            return;
+        }
         self.emit_result("match", &message, Some(track));
         self.emit_history();
     }
 
-    fn push_history(&self, track: RecognizedTrack) -> Result<(), String> {
-        let mut history = self
-            .history
+    fn push_tracklist(&self, source: &NowPlayingMetadata, track: &RecognizedTrack) {
+        let updated = match self.tracklists.lock() {
+            Ok(mut tracklists) => tracklists.record(source, track),
+            Err(_) => Err("tracklist state lock poisoned".to_string()),
+        };
+        match updated {
+            Ok(Some(tracklist)) => {
+                if let Err(error) =
+                    self.emit_event(SHAZAM_TRACKLIST_EVENT, ShazamTracklistPayload { tracklist })
+                {
+                    eprintln!("[shazam] failed to emit tracklist event: {error}");
+                }
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!("[shazam] unable to update tracklist: {error}"),
+        }
+    }
+
+    /// Starts an auto ID attempt when one is due: on the interval, or shortly
+    /// after a track change, and only while audio is flowing.
+    fn auto_id_tick(self: &Arc<Self>, now: Instant) {
+        let source = self.current_source();
+        let playing = self
+            .last_audio_at
             .lock()
-            .map_err(|_| "Shazam history state lock poisoned".to_string())?;
+            .ok()
+            .and_then(|last_audio_at| *last_audio_at)
+            .is_some_and(|at| now.saturating_duration_since(at) <= AUDIO_IDLE_AFTER);
 
-        let is_duplicate = history.iter().take(12).any(|item| {
-            if let (Some(lhs), Some(rhs)) = (item.shazam_id.as_ref(), track.shazam_id.as_ref()) {
-                return lhs == rhs;
+        {
+            let Ok(mut auto_id) = self.auto_id.lock() else {
+                return;
+            };
+            if !auto_id.config.enabled {
+                return;
+            }
+            if !playing {
+                auto_id.last_attempt_at = None;
+                auto_id.transition_at = None;
+                return;
             }
 
-            item.title.eq_ignore_ascii_case(track.title.as_str())
-                && item.artist.as_deref() == track.artist.as_deref()
-                && item.recognized_at.saturating_add(180) >= track.recognized_at
-        });
+            let source_key = source
+                .as_ref()
+                .map(|item| crate::tracklist::broadcast_key(item).unwrap_or(item.title.clone()));
+            if auto_id.last_attempt_at.is_none() && auto_id.transition_at.is_none() {
+                // Playback just started.
+                auto_id.transition_at = Some(now + AUTO_ID_TRANSITION_DELAY);
+            } else if auto_id.config.on_track_change && source_key != auto_id.last_source_key {
+                auto_id.transition_at = Some(now + AUTO_ID_TRANSITION_DELAY);
+            }
+            auto_id.last_source_key = source_key;
 
-        if is_duplicate {
-            return Ok(());
+            let transition_due = auto_id.transition_at.is_some_and(|at| at <= now);
+            let interval_due = auto_id
+                .last_attempt_at
+                .is_some_and(|at| now.saturating_duration_since(at) >= auto_id.config.interval());
+            if !transition_due && !interval_due {
+                return;
+            }
+            if self
+                .attempt
+                .lock()
+                .map(|attempt| attempt.active)
+                .unwrap_or(true)
+            {
+                return;
+            }
+            auto_id.transition_at = None;
+            auto_id.last_attempt_at = Some(now);
         }
 
-        history.insert(0, track);
-        if history.len() > HISTORY_LIMIT {
-            history.truncate(HISTORY_LIMIT);
+        if let Err(error) = self.start_attempt(source, true) {
+            eprintln!("[shazam] auto ID attempt failed to start: {error}");
+        }
+    }
+
+    fn current_source(&self) -> Option<NowPlayingMetadata> {
+        let now_playing = self.now_playing.lock().ok()?;
+        now_playing.as_ref().and_then(|source| source())
+    }
+
+    fn end_listening(&self) {
+        self.identifying.store(false, Ordering::Release);
+        if self.backend_listening.swap(false, Ordering::AcqRel) {
+            self.stop_backend();
+        }
+        self.emit_status("idle");
+    }
+
+    fn capture_landmarks(&self) -> Vec<fingerprint::Landmark> {
+        let capture = match self.capture.lock() {
+            Ok(mut capture) => std::mem::take(&mut *capture),
+            Err(_) => return Vec::new(),
+        };
+        fingerprint::fingerprint(capture.samples.as_slice(), capture.sample_rate)
+    }
+
+    fn has_fingerprints(&self) -> bool {
+        self.fingerprints
+            .lock()
+            .map(|fingerprints| !fingerprints.is_empty())
+            .unwrap_or(false)
+    }
+
+    fn with_backend<T>(
+        &self,
+        f: impl FnOnce(&mut dyn RecognitionBackend) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut backend = self
+            .backend
+            .lock()
+            .map_err(|_| "Shazam backend lock poisoned".to_string())?;
+        f(backend.as_mut())
+    }
+
+    fn stop_backend(&self) {
+        if let Ok(mut backend) = self.backend.lock() {
+            backend.stop();
         }
-        persist_history(self.history_path.as_path(), history.as_slice())
+    }
+
+    /// Returns whether the track was new enough to be added.
+    fn push_history(
+        &self,
+        track: RecognizedTrack,
+        show_alias: Option<String>,
+    ) -> Result<bool, String> {
+        Ok(self.lock_history()?.add(track, show_alias)?.is_some())
+    }
+
+    fn lock_history(&self) -> Result<std::sync::MutexGuard<'_, HistoryStore>, String> {
+        self.history
+            .lock()
+            .map_err(|_| "Shazam history state lock poisoned".to_string())
     }
 
     fn emit_status(&self, status: &str) {
         let payload = ShazamStatusPayload {
             status: status.to_string(),
         };
-        if let Err(error) = self.app.emit(SHAZAM_STATUS_EVENT, payload) {
+        if let Err(error) = self.emit_event(SHAZAM_STATUS_EVENT, payload) {
             eprintln!("[shazam] failed to emit status event: {error}");
         }
     }
@@ -323,46 +879,111 @@ impl ShazamInner {
             message: message.to_string(),
             track,
         };
-        if let Err(error) = self.app.emit(SHAZAM_RESULT_EVENT, payload) {
+        if let Err(error) = self.emit_event(SHAZAM_RESULT_EVENT, payload) {
             eprintln!("[shazam] failed to emit result event: {error}");
         }
     }
 
+    fn read_history(&self, query: &HistoryQuery) -> Vec<HistoryEntry> {
+        let history = self
+            .lock_history()
+            .and_then(|history| history.search(query));
+        history.unwrap_or_else(|error| {
+            eprintln!("[shazam] unable to read history: {error}");
+            Vec::new()
+        })
+    }
+
     fn emit_history(&self) {
-        let history = match self.history.lock() {
-            Ok(history) => history.clone(),
-            Err(_) => Vec::new(),
-        };
+        let history = self.read_history(&HistoryQuery::default());
 
         let payload = ShazamHistoryPayload { history };
-        if let Err(error) = self.app.emit(SHAZAM_HISTORY_EVENT, payload) {
+        if let Err(error) = self.emit_event(SHAZAM_HISTORY_EVENT, payload) {
             eprintln!("[shazam] failed to emit history event: {error}");
         }
     }
 
-    fn take_active_attempt(&self) -> bool {
-        let mut attempt = match self.attempt.lock() {
-            Ok(attempt) => attempt,
-            Err(_) => return false,
-        };
-        if !attempt.active {
-            return false;
-        }
-        attempt.active = false;
-        attempt.source = None;
-        true
+    fn emit_event(&self, event: &str, payload: impl Serialize) -> Result<(), String> {
+        let payload = serde_json::to_value(payload).map_err(|error| error.to_string())?;
+        (self.emit)(event, payload)
     }
 
-    fn take_active_attempt_with_source(&self) -> Option<Option<NowPlayingMetadata>> {
+    fn take_active_attempt(&self) -> Option<EndedAttempt> {
         let mut attempt = self.attempt.lock().ok()?;
         if !attempt.active {
             return None;
         }
         attempt.active = false;
-        Some(attempt.source.take())
+        Some(EndedAttempt {
+            source: attempt.source.take(),
+            auto: attempt.auto,
+        })
     }
 }
 
+/// Whether `track` repeats one of the most recent entries in `recent`
+/// (newest first): the same catalog id, or the same title and artist within
+/// a few minutes.
+pub(crate) fn is_duplicate<'a>(
+    recent: impl Iterator<Item = &'a RecognizedTrack>,
+    track: &RecognizedTrack,
+) -> bool {
+    recent.take(DUPLICATE_CHECK_DEPTH).any(|item| {
+        if let (Some(lhs), Some(rhs)) = (item.shazam_id.as_ref(), track.shazam_id.as_ref()) {
+            return lhs == rhs;
+        }
+        if let (Some(lhs), Some(rhs)) = (
+            item.musicbrainz_recording_id.as_ref(),
+            track.musicbrainz_recording_id.as_ref(),
+        ) {
+            return lhs == rhs;
+        }
+
+        item.title.eq_ignore_ascii_case(track.title.as_str())
+            && item.artist.as_deref() == track.artist.as_deref()
+            && item.recognized_at.saturating_add(DUPLICATE_WINDOW_SECONDS) >= track.recognized_at
+    })
+}
+
+fn spawn_auto_id_ticker(weak: std::sync::Weak<ShazamInner>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(AUTO_ID_TICK);
+        let Some(inner) = weak.upgrade() else {
+            break;
+        };
+        inner.auto_id_tick(Instant::now());
+    });
+}
+
+fn spawn_event_pump(weak: std::sync::Weak<ShazamInner>, events: Receiver<RecognitionEvent>) {
+    std::thread::spawn(move || {
+        while let Ok(event) = events.recv() {
+            let Some(inner) = weak.upgrade() else {
+                break;
+            };
+
+            match event {
+                RecognitionEvent::Match(payload) => inner.finalize_match(payload),
+                RecognitionEvent::NoMatch => inner.finalize_no_match(),
+                RecognitionEvent::Error(message) => inner.finalize_error(message),
+            }
+        }
+    });
+}
+
+/// Writes matches to the history in the order they arrive, so the
+/// recognition thread never waits on the database.
+fn spawn_history_writer(weak: std::sync::Weak<ShazamInner>, pending: Receiver<PendingMatch>) {
+    std::thread::spawn(move || {
+        while let Ok(pending) = pending.recv() {
+            let Some(inner) = weak.upgrade() else {
+                break;
+            };
+            inner.record_match(pending);
+        }
+    });
+}
+
 fn resolve_history_path(app: &AppHandle) -> Result<PathBuf, String> {
     let mut app_data_dir = app
         .path()
@@ -374,63 +995,6 @@ fn resolve_history_path(app: &AppHandle) -> Result<PathBuf, String> {
     Ok(app_data_dir)
 }
 
-fn load_history(path: &Path) -> Result<Vec<RecognizedTrack>, String> {
-    let contents = match fs::read_to_string(path) {
-        Ok(contents) => contents,
-        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
-        Err(error) => {
-            return Err(format!(
-                "unable to read Shazam history from {}: {error}",
-                path.display()
-            ))
-        }
-    };
-
-    serde_json::from_str::<Vec<RecognizedTrack>>(contents.as_str()).map_err(|error| {
-        format!(
-            "unable to parse Shazam history from {}: {error}",
-            path.display()
-        )
-    })
-}
-
-fn load_history_or_empty(path: &Path) -> Vec<RecognizedTrack> {
-    load_history(path).unwrap_or_else(|error| {
-        eprintln!("[shazam] unable to load history, starting empty: {error}");
-        Vec::new()
-    })
-}
-
-fn persist_history(path: &Path, history: &[RecognizedTrack]) -> Result<(), String> {
-    let parent = path.parent().ok_or_else(|| {
-        format!(
-            "unable to resolve parent directory for history path {}",
-            path.display()
-        )
-    })?;
-    fs::create_dir_all(parent).map_err(|error| {
-        format!(
-            "unable to create history directory {}: {error}",
-            parent.display()
-        )
-    })?;
-
-    let bytes = serde_json::to_vec_pretty(history)
-        .map_err(|error| format!("unable to serialize Shazam history: {error}"))?;
-    fs::write(path, bytes).map_err(|error| {
-        format!(
-            "unable to write Shazam history to {}: {error}",
-            path.display()
-        )
-    })
-}
-
-fn clear_history_with_persist(path: &Path, history: &mut Vec<RecognizedTrack>) -> Result<(), String> {
-    persist_history(path, &[])?;
-    history.clear();
-    Ok(())
-}
-
 fn epoch_seconds() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -440,378 +1004,314 @@ fn epoch_seconds() -> u64 {
 
 #[cfg(test)]
 mod tests {
-    use super::{
-        clear_history_with_persist, load_history_or_empty, persist_history, RecognizedTrack,
-    };
+    use super::{AutoIdConfig, ShazamManager, AUTO_ID_TRANSITION_DELAY};
+    use crate::audio_engine::NowPlayingMetadata;
+    use crate::fingerprint::test_audio::{synthesized_song, with_noise};
+    use crate::history::HistoryStore;
+    use crate::recognition::mock::MockBackendHandle;
+    use crate::recognition::RecognitionEvent;
+    use crate::test_support::test_dir;
+    use serde_json::Value;
     use std::fs;
-    use std::path::PathBuf;
-    use std::time::{SystemTime, UNIX_EPOCH};
-
-    fn test_dir(name: &str) -> PathBuf {
-        let mut path = std::env::temp_dir();
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|value| value.as_nanos())
-            .unwrap_or(0);
-        path.push(format!(
-            "marconio-shazam-tests-{}-{}-{}",
-            name,
-            std::process::id(),
-            nanos
-        ));
-        fs::create_dir_all(path.as_path()).expect("failed to create test directory");
-        path
-    }
-
-    fn sample_track() -> RecognizedTrack {
-        RecognizedTrack {
-            shazam_id: Some("123".to_string()),
-            title: "Track".to_string(),
-            artist: Some("Artist".to_string()),
-            artwork_url: None,
-            apple_music_url: None,
-            web_url: None,
-            recognized_at: 1,
-            source_title: Some("Source".to_string()),
-            source_artist: Some("Source Artist".to_string()),
-        }
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    type EmittedEvents = Arc<Mutex<Vec<(String, Value)>>>;
+
+    fn mock_manager(
+        dir: &Path,
+        timeout: Duration,
+    ) -> (ShazamManager, MockBackendHandle, EmittedEvents) {
+        let handle = MockBackendHandle::default();
+        let (events_tx, events_rx) = mpsc::channel::<RecognitionEvent>();
+        let emitted: EmittedEvents = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&emitted);
+        let manager = ShazamManager::with_backend(
+            dir.join("shazam-history.json"),
+            handle.backend(events_tx),
+            events_rx,
+            Box::new(move |event, payload| {
+                sink.lock()
+                    .expect("emitted events")
+                    .push((event.to_string(), payload));
+                Ok(())
+            }),
+            timeout,
+        )
+        .expect("manager");
+        (manager, handle, emitted)
     }
 
-    #[test]
-    fn load_history_or_empty_returns_empty_for_invalid_json() {
-        let dir = test_dir("invalid-json");
-        let history_path = dir.join("history.json");
-        fs::write(history_path.as_path(), "{ invalid json")
-            .expect("failed to write invalid history file");
+    fn results(emitted: &EmittedEvents) -> Vec<String> {
+        emitted
+            .lock()
+            .expect("emitted events")
+            .iter()
+            .filter(|(event, _)| event == "shazam-result")
+            .filter_map(|(_, payload)| payload["kind"].as_str().map(str::to_string))
+            .collect()
+    }
 
-        let history = load_history_or_empty(history_path.as_path());
-        assert!(history.is_empty());
+    fn wait_for(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for condition");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
 
-        let _ = fs::remove_dir_all(dir);
+    fn source() -> Option<NowPlayingMetadata> {
+        Some(NowPlayingMetadata {
+            title: "Poolside".to_string(),
+            artist: Some("NTS".to_string()),
+            album: None,
+            artwork_url: None,
+            show_alias: Some("poolside".to_string()),
+            episode_alias: Some("poolside-18th-october-2026".to_string()),
+        })
     }
 
     #[test]
-    fn clear_history_does_not_mutate_memory_when_persist_fails() {
-        let dir = test_dir("clear-fail");
-        let blocking_parent = dir.join("not-a-directory");
-        fs::write(blocking_parent.as_path(), "block").expect("failed to create blocking file");
-        let history_path = blocking_parent.join("history.json");
-
-        let mut history = vec![sample_track()];
-        let result = clear_history_with_persist(history_path.as_path(), &mut history);
-        assert!(result.is_err());
+    fn attempt_records_backend_match_and_skips_duplicates() {
+        let dir = test_dir("mock-match");
+        let (manager, backend, emitted) = mock_manager(&dir, Duration::from_secs(30));
+
+        manager.ingest_audio(&[0.0; 8], 2, 44_100);
+        assert_eq!(backend.fed_frames(), 0);
+
+        manager
+            .identify_now(source())
+            .expect("attempt should start");
+        assert_eq!(backend.fed_frames(), 4);
+        assert!(manager.identify_now(source()).is_err());
+        manager.ingest_audio(&[0.0; 8], 2, 44_100);
+        assert_eq!(backend.fed_frames(), 8);
+
+        backend.emit_match("42", "Track", "Artist");
+        wait_for(|| results(&emitted).len() == 1);
+        let history = manager.get_history();
         assert_eq!(history.len(), 1);
+        assert_eq!(history[0].track.source_title.as_deref(), Some("Poolside"));
+
+        manager
+            .identify_now(None)
+            .expect("second attempt should start");
+        backend.emit_match("42", "Track", "Artist");
+        wait_for(|| results(&emitted).len() == 2);
+        assert_eq!(results(&emitted), vec!["match", "match"]);
+        assert_eq!(manager.get_history().len(), 1);
+        assert_eq!(backend.starts(), 2);
+        assert_eq!(backend.stops(), 2);
+
+        let persisted = HistoryStore::open(
+            dir.join("marconio.sqlite3").as_path(),
+            dir.join("shazam-history.json").as_path(),
+        )
+        .expect("open history")
+        .entries()
+        .expect("entries");
+        assert_eq!(persisted.len(), 1);
+        assert_eq!(persisted[0].show_alias.as_deref(), Some("poolside"));
 
         let _ = fs::remove_dir_all(dir);
     }
 
     #[test]
-    fn clear_history_persists_empty_and_clears_memory_on_success() {
-        let dir = test_dir("clear-success");
-        let history_path = dir.join("history.json");
-
-        let mut history = vec![sample_track()];
-        clear_history_with_persist(history_path.as_path(), &mut history)
-            .expect("expected clear to succeed");
-        assert!(history.is_empty());
-
-        let persisted = load_history_or_empty(history_path.as_path());
-        assert!(persisted.is_empty());
+    fn attempt_starts_with_the_last_seconds_of_audio() {
+        let dir = test_dir("pre-roll");
+        let (manager, backend, _emitted) = mock_manager(&dir, Duration::from_secs(30));
 
-        let _ = fs::remove_dir_all(dir);
-    }
+        // Fifteen seconds of stereo audio in one-second buffers.
+        for _ in 0..15 {
+            manager.ingest_audio(vec![0.0; 2 * 8_000].as_slice(), 2, 8_000);
+        }
+        manager.identify_now(None).expect("attempt should start");
+        assert_eq!(backend.fed_frames(), 10 * 8_000);
 
-    #[test]
-    fn persist_history_round_trip_succeeds() {
-        let dir = test_dir("persist-roundtrip");
-        let history_path = dir.join("history.json");
-        let history = vec![sample_track()];
-        persist_history(history_path.as_path(), history.as_slice()).expect("persist should succeed");
+        manager.ingest_audio(&[0.0; 8], 2, 8_000);
+        assert_eq!(backend.fed_frames(), 10 * 8_000 + 4);
 
-        let loaded = load_history_or_empty(history_path.as_path());
-        assert_eq!(loaded.len(), 1);
-        assert_eq!(loaded[0].title, "Track");
+        // Everything replayed was used up by that attempt.
+        backend.emit(RecognitionEvent::NoMatch);
+        wait_for(|| backend.stops() == 1);
+        manager
+            .identify_now(None)
+            .expect("second attempt should start");
+        assert_eq!(backend.fed_frames(), 10 * 8_000 + 4);
 
         let _ = fs::remove_dir_all(dir);
     }
-}
 
-#[cfg(target_os = "macos")]
-#[derive(Clone, Debug)]
-struct BridgeMatchPayload {
-    shazam_id: Option<String>,
-    title: Option<String>,
-    artist: Option<String>,
-    artwork_url: Option<String>,
-    apple_music_url: Option<String>,
-    web_url: Option<String>,
-}
-
-#[cfg(target_os = "macos")]
-enum BridgeEvent {
-    Match(BridgeMatchPayload),
-    NoMatch,
-    Error(String),
-}
-
-#[cfg(target_os = "macos")]
-struct CallbackContext {
-    tx: std::sync::mpsc::Sender<BridgeEvent>,
-}
-
-#[cfg(target_os = "macos")]
-impl ShazamInner {
-    fn initialize_bridge(self: &Arc<Self>) -> Result<(), String> {
-        let (tx, rx) = std::sync::mpsc::channel::<BridgeEvent>();
-        let callback_context = Box::new(CallbackContext { tx });
-        let user_data = callback_context.as_ref() as *const CallbackContext as *mut std::ffi::c_void;
-        let bridge = unsafe { MacBridge::create(shazam_bridge_callback, user_data)? };
-
-        {
-            let mut context_slot = self
-                .callback_context
-                .lock()
-                .map_err(|_| "Shazam callback context lock poisoned".to_string())?;
-            *context_slot = Some(callback_context);
-        }
-
-        {
-            let mut bridge_slot = self
-                .bridge
-                .lock()
-                .map_err(|_| "Shazam bridge state lock poisoned".to_string())?;
-            *bridge_slot = Some(bridge);
+    #[test]
+    fn tapped_audio_never_waits_on_the_pre_roll_replay() {
+        let dir = test_dir("pre-roll-replay");
+        let (manager, backend, _emitted) = mock_manager(&dir, Duration::from_secs(30));
+        for _ in 0..3 {
+            manager.ingest_audio(vec![0.0; 2 * 8_000].as_slice(), 2, 8_000);
         }
 
-        let weak = Arc::downgrade(self);
-        std::thread::spawn(move || {
-            while let Ok(event) = rx.recv() {
-                let Some(inner) = weak.upgrade() else {
-                    break;
-                };
-
-                match event {
-                    BridgeEvent::Match(payload) => inner.finalize_match(payload),
-                    BridgeEvent::NoMatch => inner.finalize_no_match(),
-                    BridgeEvent::Error(message) => inner.finalize_error(message),
-                }
-            }
+        backend.hold_feeds(true);
+        std::thread::scope(|scope| {
+            let attempt = scope.spawn(|| manager.identify_now(None));
+            wait_for(|| backend.starts() == 1);
+
+            let started = Instant::now();
+            manager.ingest_audio(&[0.0; 8], 2, 8_000);
+            assert!(started.elapsed() < Duration::from_millis(100));
+            assert_eq!(backend.fed_frames(), 0);
+
+            backend.hold_feeds(false);
+            attempt
+                .join()
+                .expect("attempt thread")
+                .expect("attempt should start");
         });
+        // Queued behind the pre-roll, then fed in order.
+        assert_eq!(backend.fed_frames(), 3 * 8_000 + 4);
+        manager.ingest_audio(&[0.0; 8], 2, 8_000);
+        assert_eq!(backend.fed_frames(), 3 * 8_000 + 8);
 
-        Ok(())
+        let _ = fs::remove_dir_all(dir);
     }
 
-    fn with_bridge_mut<T>(
-        &self,
-        f: impl FnOnce(&mut MacBridge) -> Result<T, String>,
-    ) -> Result<T, String> {
-        let mut bridge = self
-            .bridge
-            .lock()
-            .map_err(|_| "Shazam bridge lock poisoned".to_string())?;
-        let bridge = bridge
-            .as_mut()
-            .ok_or_else(|| "Shazam bridge is not initialized".to_string())?;
-        f(bridge)
-    }
+    #[test]
+    fn attempt_times_out_with_no_match() {
+        let dir = test_dir("mock-timeout");
+        let (manager, backend, emitted) = mock_manager(&dir, Duration::from_millis(20));
 
-    fn stop_bridge(&self) {
-        let mut bridge = match self.bridge.lock() {
-            Ok(bridge) => bridge,
-            Err(_) => return,
-        };
-        if let Some(bridge) = bridge.as_mut() {
-            bridge.stop();
-        }
-    }
-}
+        manager.identify_now(None).expect("attempt should start");
+        wait_for(|| results(&emitted) == vec!["noMatch"]);
+        assert_eq!(backend.stops(), 1);
 
-#[cfg(target_os = "macos")]
-unsafe extern "C" fn shazam_bridge_callback(
-    event_type: i32,
-    title: *const std::ffi::c_char,
-    artist: *const std::ffi::c_char,
-    artwork_url: *const std::ffi::c_char,
-    apple_music_url: *const std::ffi::c_char,
-    web_url: *const std::ffi::c_char,
-    error_message: *const std::ffi::c_char,
-    user_data: *mut std::ffi::c_void,
-) {
-    if user_data.is_null() {
-        return;
-    }
-    let context = &*(user_data as *const CallbackContext);
-
-    let event = match event_type {
-        ffi::SHAZAM_BRIDGE_EVENT_MATCH => BridgeEvent::Match(BridgeMatchPayload {
-            shazam_id: None,
-            title: cstring_to_string(title),
-            artist: cstring_to_string(artist),
-            artwork_url: cstring_to_string(artwork_url),
-            apple_music_url: cstring_to_string(apple_music_url),
-            web_url: cstring_to_string(web_url),
-        }),
-        ffi::SHAZAM_BRIDGE_EVENT_NO_MATCH => BridgeEvent::NoMatch,
-        ffi::SHAZAM_BRIDGE_EVENT_ERROR => {
-            let message = cstring_to_string(error_message)
-                .unwrap_or_else(|| "ShazamKit failed to identify the track.".to_string());
-            BridgeEvent::Error(message)
-        }
-        _ => return,
-    };
-
-    let _ = context.tx.send(event);
-}
+        manager.ingest_audio(&[0.0; 8], 2, 44_100);
+        assert_eq!(backend.fed_frames(), 0);
+        manager
+            .identify_now(None)
+            .expect("a new attempt should start after the timeout");
 
-#[cfg(target_os = "macos")]
-fn cstring_to_string(value: *const std::ffi::c_char) -> Option<String> {
-    if value.is_null() {
-        return None;
+        let _ = fs::remove_dir_all(dir);
     }
-    Some(
-        unsafe { std::ffi::CStr::from_ptr(value) }
-            .to_string_lossy()
-            .into_owned(),
-    )
-}
 
-#[cfg(target_os = "macos")]
-struct MacBridge {
-    raw: *mut std::ffi::c_void,
-}
+    #[test]
+    fn backend_start_failure_does_not_leave_attempt_active() {
+        let dir = test_dir("mock-start-fail");
+        let (manager, backend, emitted) = mock_manager(&dir, Duration::from_secs(30));
 
-#[cfg(target_os = "macos")]
-unsafe impl Send for MacBridge {}
+        backend.fail_next_start("microphone unavailable");
+        assert_eq!(
+            manager.identify_now(None),
+            Err("microphone unavailable".to_string())
+        );
+        manager.identify_now(None).expect("retry should start");
 
-#[cfg(target_os = "macos")]
-impl MacBridge {
-    unsafe fn create(
-        callback: ffi::ShazamBridgeCallback,
-        user_data: *mut std::ffi::c_void,
-    ) -> Result<Self, String> {
-        let mut error_ptr: *mut std::ffi::c_char = std::ptr::null_mut();
-        let raw = ffi::shazam_bridge_create(callback, user_data, &mut error_ptr);
-        if raw.is_null() {
-            return Err(consume_bridge_error(error_ptr));
-        }
-        Ok(Self { raw })
-    }
+        backend.emit(RecognitionEvent::Error("network down".to_string()));
+        wait_for(|| results(&emitted) == vec!["error"]);
 
-    fn start(&mut self) -> Result<(), String> {
-        let mut error_ptr: *mut std::ffi::c_char = std::ptr::null_mut();
-        let ok = unsafe { ffi::shazam_bridge_start(self.raw, &mut error_ptr) };
-        if ok {
-            Ok(())
-        } else {
-            Err(consume_bridge_error(error_ptr))
-        }
+        let _ = fs::remove_dir_all(dir);
     }
 
-    fn feed(&mut self, samples: &[f32], channels: u16, sample_rate: u32) -> Result<(), String> {
-        if channels == 0 {
-            return Ok(());
-        }
-        if samples.len() % channels as usize != 0 {
-            return Err("audio frame data was not aligned with channel count".to_string());
-        }
-
-        let frame_count = (samples.len() / channels as usize) as u32;
-        if frame_count == 0 {
-            return Ok(());
-        }
-
-        let mut error_ptr: *mut std::ffi::c_char = std::ptr::null_mut();
-        let ok = unsafe {
-            ffi::shazam_bridge_feed(
-                self.raw,
-                samples.as_ptr(),
-                frame_count,
-                channels as u32,
-                sample_rate as f64,
-                &mut error_ptr,
-            )
-        };
-
-        if ok {
-            Ok(())
-        } else {
-            Err(consume_bridge_error(error_ptr))
+    #[test]
+    fn unanswered_attempt_falls_back_to_fingerprinted_history() {
+        let dir = test_dir("mock-local-match");
+        let (manager, backend, emitted) = mock_manager(&dir, Duration::from_secs(30));
+        let song = synthesized_song(5, 24.0);
+        let (first, second) = song.split_at(song.len() / 2);
+
+        manager.identify_now(None).expect("attempt should start");
+        for chunk in first.chunks(2_304) {
+            manager.ingest_audio(chunk, 2, 44_100);
         }
-    }
-
-    fn stop(&mut self) {
-        unsafe {
-            ffi::shazam_bridge_stop(self.raw);
+        backend.emit_match("7", "Remembered", "Artist");
+        wait_for(|| results(&emitted).len() == 1);
+        assert!(dir.join("shazam-fingerprints.json").exists());
+
+        manager
+            .identify_now(source())
+            .expect("attempt should start");
+        for chunk in with_noise(first, 9, 0.03)[44_100 * 4..].chunks(2_304) {
+            manager.ingest_audio(chunk, 2, 44_100);
         }
-    }
-}
+        backend.emit(RecognitionEvent::Error("offline".to_string()));
+        wait_for(|| results(&emitted).len() == 2);
+        assert_eq!(results(&emitted), vec!["match", "match"]);
+        let history = manager.get_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].track.title, "Remembered");
 
-#[cfg(target_os = "macos")]
-impl Drop for MacBridge {
-    fn drop(&mut self) {
-        unsafe {
-            ffi::shazam_bridge_destroy(self.raw);
+        manager.identify_now(None).expect("attempt should start");
+        for chunk in second.chunks(2_304) {
+            manager.ingest_audio(chunk, 2, 44_100);
         }
-    }
-}
+        backend.emit(RecognitionEvent::NoMatch);
+        wait_for(|| results(&emitted).len() == 3);
+        assert_eq!(results(&emitted)[2], "noMatch");
 
-#[cfg(target_os = "macos")]
-fn consume_bridge_error(error_ptr: *mut std::ffi::c_char) -> String {
-    if error_ptr.is_null() {
-        return "Unknown Shazam bridge error".to_string();
+        let _ = fs::remove_dir_all(dir);
     }
 
-    let message = unsafe { std::ffi::CStr::from_ptr(error_ptr) }
-        .to_string_lossy()
-        .into_owned();
-
-    unsafe {
-        ffi::shazam_bridge_free_error(error_ptr);
-    }
+    #[test]
+    fn auto_id_listens_on_a_schedule_and_builds_tracklists() {
+        let dir = test_dir("auto-id");
+        let (manager, backend, emitted) = mock_manager(&dir, Duration::from_secs(30));
+        manager.set_now_playing_source(Box::new(source));
+        manager.set_auto_id(AutoIdConfig {
+            enabled: true,
+            interval_seconds: 60,
+            on_track_change: true,
+        });
+        let inner = Arc::clone(&manager.inner);
+        let start = Instant::now();
+        let tick_with_audio_at = |seconds: u64| {
+            let now = start + Duration::from_secs(seconds);
+            *inner.last_audio_at.lock().expect("last audio") = Some(now);
+            inner.auto_id_tick(now);
+        };
 
-    message
-}
+        inner.auto_id_tick(start);
+        tick_with_audio_at(0);
+        assert_eq!(backend.starts(), 0, "waits for the track to settle");
+        tick_with_audio_at(AUTO_ID_TRANSITION_DELAY.as_secs());
+        assert_eq!(backend.starts(), 1);
+
+        backend.emit(RecognitionEvent::NoMatch);
+        wait_for(|| backend.stops() == 1);
+        tick_with_audio_at(30);
+        assert_eq!(backend.starts(), 1, "waits out the interval");
+
+        tick_with_audio_at(70);
+        assert_eq!(backend.starts(), 2);
+        backend.emit_match("42", "Track", "Artist");
+        wait_for(|| results(&emitted) == vec!["match"]);
+
+        tick_with_audio_at(130);
+        backend.emit_match("42", "Track", "Artist");
+        wait_for(|| backend.stops() == 3);
+        assert_eq!(results(&emitted), vec!["match"], "repeats stay quiet");
+
+        tick_with_audio_at(190);
+        manager
+            .identify_now(source())
+            .expect("adopts the auto attempt");
+        backend.emit(RecognitionEvent::NoMatch);
+        wait_for(|| results(&emitted) == vec!["match", "noMatch"]);
+        assert_eq!(backend.starts(), 4);
+
+        let tracklists = manager.tracklists();
+        assert_eq!(tracklists.len(), 1);
+        assert_eq!(tracklists[0].key, "poolside/poolside-18th-october-2026");
+        assert_eq!(tracklists[0].tracks.len(), 1);
+        let exported = manager
+            .export_tracklist("poolside/poolside-18th-october-2026")
+            .expect("export");
+        assert!(exported.contains("] Artist — Track"), "{exported}");
+        assert!(emitted
+            .lock()
+            .expect("emitted events")
+            .iter()
+            .any(|(event, _)| event == "shazam-tracklist"));
 
-#[cfg(target_os = "macos")]
-mod ffi {
-    pub const SHAZAM_BRIDGE_EVENT_MATCH: i32 = 1;
-    pub const SHAZAM_BRIDGE_EVENT_NO_MATCH: i32 = 2;
-    pub const SHAZAM_BRIDGE_EVENT_ERROR: i32 = 3;
-
-    pub type ShazamBridgeCallback = unsafe extern "C" fn(
-        event_type: i32,
-        title: *const std::ffi::c_char,
-        artist: *const std::ffi::c_char,
-        artwork_url: *const std::ffi::c_char,
-        apple_music_url: *const std::ffi::c_char,
-        web_url: *const std::ffi::c_char,
-        error_message: *const std::ffi::c_char,
-        user_data: *mut std::ffi::c_void,
-    );
-
-    unsafe extern "C" {
-        pub fn shazam_bridge_create(
-            callback: ShazamBridgeCallback,
-            user_data: *mut std::ffi::c_void,
-            error_out: *mut *mut std::ffi::c_char,
-        ) -> *mut std::ffi::c_void;
-
-        pub fn shazam_bridge_start(
-            bridge: *mut std::ffi::c_void,
-            error_out: *mut *mut std::ffi::c_char,
-        ) -> bool;
-
-        pub fn shazam_bridge_feed(
-            bridge: *mut std::ffi::c_void,
-            samples: *const f32,
-            frame_count: u32,
-            channels: u32,
-            sample_rate: f64,
-            error_out: *mut *mut std::ffi::c_char,
-        ) -> bool;
-
-        pub fn shazam_bridge_stop(bridge: *mut std::ffi::c_void);
-        pub fn shazam_bridge_destroy(bridge: *mut std::ffi::c_void);
-        pub fn shazam_bridge_free_error(error_message: *mut std::ffi::c_char);
+        let _ = fs::remove_dir_all(dir);
     }
 }
@@ -0,0 +1,428 @@
+//! Bar spectrum and level meters for the LCD, computed from the tapped audio.
+//! Everything the audio thread touches is allocated up front; frames go out
+//! through a bounded channel and are emitted from a thread of their own.
+
+use crate::audio_tap::{AudioTapHandle, AudioTapRegistry, TapPoint};
+use crate::fingerprint::fft_in_place;
+use serde::Serialize;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const SPECTRUM_EVENT: &str = "audio-spectrum";
+pub const SPECTRUM_BANDS: usize = 16;
+/// Left and right; mono is shown on both, and channels past two are ignored.
+pub const METER_CHANNELS: usize = 2;
+
+const FFT_SIZE: usize = 2048;
+const FRAMES_PER_SECOND: u32 = 30;
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16_000.0;
+/// Bands read 0 this far below full scale.
+const FLOOR_DB: f32 = -60.0;
+/// How far a band falls per frame, out of 1.
+const BAND_FALL: f32 = 0.06;
+/// Frames a peak marker stays put before falling.
+const PEAK_HOLD_FRAMES: u32 = FRAMES_PER_SECOND;
+const PEAK_FALL: f32 = 0.02;
+/// Frames waiting for the emitter; the newest are dropped past this.
+const QUEUE_DEPTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelLevel {
+    /// Linear, 0–1, since the previous frame.
+    pub peak: f32,
+    pub rms: f32,
+    pub peak_hold: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpectrumFrame {
+    /// Log-spaced from 40 Hz up, each 0–1 over a 60 dB range.
+    pub bands: [f32; SPECTRUM_BANDS],
+    pub band_peaks: [f32; SPECTRUM_BANDS],
+    pub levels: [ChannelLevel; METER_CHANNELS],
+}
+
+#[derive(Clone, Copy, Default)]
+struct Hold {
+    value: f32,
+    frames: u32,
+}
+
+impl Hold {
+    fn update(&mut self, value: f32, fall: f32) -> f32 {
+        if value >= self.value {
+            self.value = value;
+            self.frames = 0;
+        } else if self.frames < PEAK_HOLD_FRAMES {
+            self.frames += 1;
+        } else {
+            self.value = (self.value - fall).max(value);
+        }
+        self.value
+    }
+}
+
+pub struct SpectrumAnalyzer {
+    sample_rate: u32,
+    frames_per_update: usize,
+    frames_since_update: usize,
+    history: Vec<f32>,
+    write_index: usize,
+    window: Vec<f32>,
+    real: Vec<f32>,
+    imag: Vec<f32>,
+    band_bins: [(usize, usize); SPECTRUM_BANDS],
+    bands: [f32; SPECTRUM_BANDS],
+    band_holds: [Hold; SPECTRUM_BANDS],
+    channel_peaks: [f32; METER_CHANNELS],
+    channel_squares: [f32; METER_CHANNELS],
+    level_holds: [Hold; METER_CHANNELS],
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0,
+            frames_per_update: 1,
+            frames_since_update: 0,
+            history: vec![0.0; FFT_SIZE],
+            write_index: 0,
+            window: (0..FFT_SIZE)
+                .map(|index| 0.5 - 0.5 * (2.0 * PI * index as f32 / FFT_SIZE as f32).cos())
+                .collect(),
+            real: vec![0.0; FFT_SIZE],
+            imag: vec![0.0; FFT_SIZE],
+            band_bins: [(0, 0); SPECTRUM_BANDS],
+            bands: [0.0; SPECTRUM_BANDS],
+            band_holds: [Hold::default(); SPECTRUM_BANDS],
+            channel_peaks: [0.0; METER_CHANNELS],
+            channel_squares: [0.0; METER_CHANNELS],
+            level_holds: [Hold::default(); METER_CHANNELS],
+        }
+    }
+}
+
+impl SpectrumAnalyzer {
+    /// Clears everything but the buffers, without reallocating them.
+    pub fn reset(&mut self) {
+        self.sample_rate = 0;
+        self.frames_since_update = 0;
+        self.history.fill(0.0);
+        self.write_index = 0;
+        self.bands = [0.0; SPECTRUM_BANDS];
+        self.band_holds = [Hold::default(); SPECTRUM_BANDS];
+        self.channel_peaks = [0.0; METER_CHANNELS];
+        self.channel_squares = [0.0; METER_CHANNELS];
+        self.level_holds = [Hold::default(); METER_CHANNELS];
+    }
+
+    /// Returns a frame roughly 30 times a second of audio; if one buffer
+    /// spans several, only the last is returned.
+    pub fn push(
+        &mut self,
+        samples: &[f32],
+        channels: u16,
+        sample_rate: u32,
+    ) -> Option<SpectrumFrame> {
+        if sample_rate == 0 || channels == 0 {
+            return None;
+        }
+        if sample_rate != self.sample_rate {
+            self.reset();
+            self.configure(sample_rate);
+        }
+
+        let channels = channels as usize;
+        let mut frame = None;
+        for samples in samples.chunks_exact(channels) {
+            let mut mono = 0.0;
+            for (index, sample) in samples.iter().enumerate() {
+                mono += sample;
+                if index < METER_CHANNELS {
+                    self.channel_peaks[index] = self.channel_peaks[index].max(sample.abs());
+                    self.channel_squares[index] += sample * sample;
+                }
+            }
+            self.history[self.write_index] = mono / channels as f32;
+            self.write_index = (self.write_index + 1) % FFT_SIZE;
+
+            self.frames_since_update += 1;
+            if self.frames_since_update == self.frames_per_update {
+                frame = Some(self.update(channels));
+            }
+        }
+        frame
+    }
+
+    fn configure(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.frames_per_update = (sample_rate / FRAMES_PER_SECOND).max(1) as usize;
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let top = MAX_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let ratio = (top / MIN_FREQUENCY).powf(1.0 / SPECTRUM_BANDS as f32);
+        let mut low = MIN_FREQUENCY;
+        for bins in self.band_bins.iter_mut() {
+            let high = low * ratio;
+            let first = ((low / bin_width).round() as usize).clamp(1, FFT_SIZE / 2 - 1);
+            let last = ((high / bin_width).round() as usize).clamp(first + 1, FFT_SIZE / 2);
+            *bins = (first, last);
+            low = high;
+        }
+    }
+
+    fn update(&mut self, channels: usize) -> SpectrumFrame {
+        // Oldest sample first, so the window lines up with the audio.
+        let (newer, older) = self.history.split_at(self.write_index);
+        for ((target, sample), weight) in self
+            .real
+            .iter_mut()
+            .zip(older.iter().chain(newer.iter()))
+            .zip(self.window.iter())
+        {
+            *target = sample * weight;
+        }
+        self.imag.fill(0.0);
+        fft_in_place(self.real.as_mut_slice(), self.imag.as_mut_slice());
+
+        // A full-scale sine peaks at FFT_SIZE / 4 through the Hann window.
+        let full_scale = FFT_SIZE as f32 / 4.0;
+        let mut frame = SpectrumFrame::default();
+        for (index, &(first, last)) in self.band_bins.iter().enumerate() {
+            let power = (first..last)
+                .map(|bin| self.real[bin] * self.real[bin] + self.imag[bin] * self.imag[bin])
+                .fold(0.0_f32, f32::max);
+            let decibels = 10.0 * (power.max(1e-12) / (full_scale * full_scale)).log10();
+            let value = ((decibels - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+            self.bands[index] = value.max(self.bands[index] - BAND_FALL);
+            frame.bands[index] = self.bands[index];
+            frame.band_peaks[index] = self.band_holds[index].update(self.bands[index], PEAK_FALL);
+        }
+
+        let measured = channels.min(METER_CHANNELS);
+        for index in 0..METER_CHANNELS {
+            // Mono feeds both meters.
+            let source = index.min(measured - 1);
+            let peak = self.channel_peaks[source].min(1.0);
+            let rms = (self.channel_squares[source] / self.frames_since_update as f32)
+                .sqrt()
+                .min(1.0);
+            frame.levels[index] = ChannelLevel {
+                peak,
+                rms,
+                peak_hold: self.level_holds[index].update(peak, PEAK_FALL),
+            };
+        }
+
+        self.channel_peaks = [0.0; METER_CHANNELS];
+        self.channel_squares = [0.0; METER_CHANNELS];
+        self.frames_since_update = 0;
+        frame
+    }
+}
+
+/// Off until the frontend asks for it. Its post-FX tap is only registered
+/// while it's on, so a disabled feed costs the audio thread nothing.
+pub struct SpectrumFeed {
+    enabled: AtomicBool,
+    analyzer: Mutex<SpectrumAnalyzer>,
+    frames: SyncSender<SpectrumFrame>,
+    taps: Arc<AudioTapRegistry>,
+    tap: Mutex<Option<AudioTapHandle>>,
+}
+
+impl SpectrumFeed {
+    pub fn new(
+        taps: Arc<AudioTapRegistry>,
+        emit: Box<dyn Fn(&SpectrumFrame) + Send + 'static>,
+    ) -> Self {
+        let (frames_tx, frames_rx) = mpsc::sync_channel::<SpectrumFrame>(QUEUE_DEPTH);
+        thread::spawn(move || {
+            for frame in frames_rx {
+                emit(&frame);
+            }
+        });
+        Self {
+            enabled: AtomicBool::new(false),
+            analyzer: Mutex::new(SpectrumAnalyzer::default()),
+            frames: frames_tx,
+            taps,
+            tap: Mutex::new(None),
+        }
+    }
+
+    pub fn set_enabled(self: &Arc<Self>, enabled: bool) {
+        let Ok(mut tap) = self.tap.lock() else {
+            return;
+        };
+        match (enabled, tap.as_ref()) {
+            (true, None) => {
+                if let Ok(mut analyzer) = self.analyzer.lock() {
+                    analyzer.reset();
+                }
+                let feed = Arc::downgrade(self);
+                *tap = Some(self.taps.add(
+                    TapPoint::PostFx,
+                    Arc::new(move |samples, channels, sample_rate| {
+                        if let Some(feed) = feed.upgrade() {
+                            feed.push(samples, channels, sample_rate);
+                        }
+                    }),
+                ));
+            }
+            (false, Some(handle)) => {
+                self.taps.remove(*handle);
+                *tap = None;
+            }
+            _ => {}
+        }
+        self.enabled.store(enabled, Ordering::Release);
+    }
+
+    /// Called from the tap's thread. Also checks `enabled`, since the tap
+    /// finishes what it has queued after it's removed.
+    fn push(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        if !self.enabled.load(Ordering::Acquire) {
+            return;
+        }
+        // Never wait in the tap; a skipped buffer only costs a frame.
+        let Ok(mut analyzer) = self.analyzer.try_lock() else {
+            return;
+        };
+        let Some(frame) = analyzer.push(samples, channels, sample_rate) else {
+            return;
+        };
+        if let Err(TrySendError::Disconnected(_)) = self.frames.try_send(frame) {
+            self.enabled.store(false, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SpectrumAnalyzer, SpectrumFeed, SpectrumFrame, SPECTRUM_BANDS};
+    use crate::audio_tap::{AudioTapRegistry, TapPoint};
+    use std::f32::consts::PI;
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// Interleaved stereo: a sine on the left, `right` times it on the right.
+    fn stereo_sine(frequency: f32, amplitude: f32, right: f32, seconds: f32) -> Vec<f32> {
+        let frames = (seconds * SAMPLE_RATE as f32) as usize;
+        (0..frames)
+            .flat_map(|index| {
+                let value =
+                    amplitude * (2.0 * PI * frequency * index as f32 / SAMPLE_RATE as f32).sin();
+                [value, value * right]
+            })
+            .collect()
+    }
+
+    fn analyze(analyzer: &mut SpectrumAnalyzer, samples: &[f32]) -> Vec<SpectrumFrame> {
+        samples
+            .chunks(1_152 * 2)
+            .filter_map(|chunk| analyzer.push(chunk, 2, SAMPLE_RATE))
+            .collect()
+    }
+
+    fn loudest_band(frame: &SpectrumFrame) -> usize {
+        (0..SPECTRUM_BANDS)
+            .max_by(|lhs, rhs| frame.bands[*lhs].total_cmp(&frame.bands[*rhs]))
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn tones_land_in_rising_bands_about_thirty_times_a_second() {
+        let mut analyzer = SpectrumAnalyzer::default();
+        let frames = analyze(&mut analyzer, stereo_sine(100.0, 0.5, 1.0, 1.0).as_slice());
+        assert!((29..=31).contains(&frames.len()), "{}", frames.len());
+        let low = frames.last().copied().unwrap_or_default();
+
+        analyzer.reset();
+        let frames = analyze(
+            &mut analyzer,
+            stereo_sine(1_000.0, 0.5, 1.0, 0.5).as_slice(),
+        );
+        let mid = frames.last().copied().unwrap_or_default();
+        analyzer.reset();
+        let frames = analyze(
+            &mut analyzer,
+            stereo_sine(8_000.0, 0.5, 1.0, 0.5).as_slice(),
+        );
+        let high = frames.last().copied().unwrap_or_default();
+
+        assert!(loudest_band(&low) < loudest_band(&mid));
+        assert!(loudest_band(&mid) < loudest_band(&high));
+        // -6 dBFS reads 0.9 on a 60 dB scale.
+        let level = mid.bands[loudest_band(&mid)];
+        assert!((level - 0.9).abs() < 0.05, "{level}");
+    }
+
+    #[test]
+    fn meters_follow_each_channel_and_hold_peaks() {
+        let mut analyzer = SpectrumAnalyzer::default();
+        let frames = analyze(&mut analyzer, stereo_sine(440.0, 0.8, 0.25, 0.5).as_slice());
+        let frame = frames.last().copied().unwrap_or_default();
+        let [left, right] = frame.levels;
+        assert!((left.peak - 0.8).abs() < 0.01, "{left:?}");
+        assert!((left.rms - 0.8 / 2.0_f32.sqrt()).abs() < 0.02, "{left:?}");
+        assert!((right.peak - 0.2).abs() < 0.01, "{right:?}");
+
+        // Half a second of silence: the meters drop, the markers stay.
+        let frames = analyze(&mut analyzer, vec![0.0; SAMPLE_RATE as usize].as_slice());
+        let frame = frames.last().copied().unwrap_or_default();
+        assert_eq!(frame.levels[0].peak, 0.0);
+        assert!((frame.levels[0].peak_hold - 0.8).abs() < 0.01);
+
+        // Another second and a half and they have fallen too.
+        let frames = analyze(
+            &mut analyzer,
+            vec![0.0; SAMPLE_RATE as usize * 3].as_slice(),
+        );
+        let frame = frames.last().copied().unwrap_or_default();
+        assert!(frame.levels[0].peak_hold < 0.5, "{:?}", frame.levels[0]);
+        assert!(frame.bands.iter().all(|band| *band == 0.0));
+    }
+
+    #[test]
+    fn feed_only_emits_while_enabled() {
+        let (tx, rx) = mpsc::channel();
+        let taps = Arc::new(AudioTapRegistry::default());
+        let mut reader = taps.reader();
+        let feed = Arc::new(SpectrumFeed::new(
+            Arc::clone(&taps),
+            Box::new(move |frame| {
+                let _ = tx.send(*frame);
+            }),
+        ));
+        let samples = stereo_sine(1_000.0, 0.5, 1.0, 0.1);
+        let mut play = || {
+            for buffer in samples.chunks(1_152 * 2) {
+                reader.dispatch(TapPoint::PostFx, buffer, 2, SAMPLE_RATE);
+            }
+        };
+
+        play();
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        feed.set_enabled(true);
+        play();
+        assert!(rx.recv_timeout(Duration::from_secs(2)).is_ok());
+
+        // Off means no tap at all, not one that ignores what it's fed.
+        let handle = feed.tap.lock().unwrap().expect("tap");
+        feed.set_enabled(false);
+        assert!(taps.stats(handle).is_none());
+        while rx.recv_timeout(Duration::from_millis(100)).is_ok() {}
+        play();
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
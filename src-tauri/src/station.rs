@@ -0,0 +1,327 @@
+use crate::audio_engine::NowPlayingMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const ICY_METADATA_MAX_BYTES: usize = 256 * 1024;
+const METADATA_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Covers reading up to the first ICY block, not just the headers.
+const METADATA_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Station {
+    pub id: String,
+    pub name: String,
+    pub stream_url: String,
+    pub artwork_url: Option<String>,
+    pub homepage_url: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<StationMetadataSource>,
+}
+
+/// Where the "now playing" line for a station comes from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StationMetadataSource {
+    /// In-band Shoutcast/Icecast `StreamTitle` metadata.
+    Icy,
+    /// A JSON endpoint with the title and artist at the given JSON pointers.
+    #[serde(rename_all = "camelCase")]
+    Json {
+        url: String,
+        title_pointer: String,
+        artist_pointer: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StationInput {
+    pub id: Option<String>,
+    pub name: String,
+    pub stream_url: String,
+    pub artwork_url: Option<String>,
+    pub homepage_url: Option<String>,
+    pub metadata: Option<StationMetadataSource>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StationNowPlaying {
+    pub title: String,
+    pub artist: Option<String>,
+}
+
+impl Station {
+    pub fn from_input(input: StationInput) -> Result<Self, String> {
+        let name = input.name.trim();
+        if name.is_empty() {
+            return Err("Station name cannot be empty.".to_string());
+        }
+
+        let stream_url = input.stream_url.trim();
+        let parsed = reqwest::Url::parse(stream_url)
+            .map_err(|error| format!("invalid station stream URL {stream_url}: {error}"))?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err(format!(
+                "unsupported station stream URL scheme: {}",
+                parsed.scheme()
+            ));
+        }
+
+        let id = input
+            .id
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(generate_station_id);
+
+        Ok(Self {
+            id,
+            name: name.to_string(),
+            stream_url: stream_url.to_string(),
+            artwork_url: non_empty(input.artwork_url),
+            homepage_url: non_empty(input.homepage_url),
+            metadata: input.metadata,
+        })
+    }
+
+    pub fn now_playing(&self) -> NowPlayingMetadata {
+        NowPlayingMetadata {
+            title: self.name.clone(),
+            artist: None,
+            album: Some(self.name.clone()),
+            artwork_url: self.artwork_url.clone(),
+            show_alias: None,
+            episode_alias: None,
+        }
+    }
+}
+
+pub async fn fetch_now_playing(station: &Station) -> Result<Option<StationNowPlaying>, String> {
+    match station.metadata.as_ref() {
+        None => Ok(None),
+        Some(StationMetadataSource::Icy) => {
+            fetch_icy_now_playing(station.stream_url.as_str()).await
+        }
+        Some(StationMetadataSource::Json {
+            url,
+            title_pointer,
+            artist_pointer,
+        }) => {
+            let response = metadata_client()?
+                .get(url)
+                .send()
+                .await
+                .map_err(|error| format!("station metadata request failed: {error}"))?;
+            if !response.status().is_success() {
+                return Err(format!(
+                    "station metadata request failed with status {}",
+                    response.status().as_u16()
+                ));
+            }
+            let json = response
+                .json::<Value>()
+                .await
+                .map_err(|error| format!("station metadata was not valid JSON: {error}"))?;
+            Ok(now_playing_from_json(
+                &json,
+                title_pointer.as_str(),
+                artist_pointer.as_deref(),
+            ))
+        }
+    }
+}
+
+fn metadata_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .connect_timeout(METADATA_CONNECT_TIMEOUT)
+        .timeout(METADATA_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|error| format!("unable to build station metadata client: {error}"))
+}
+
+async fn fetch_icy_now_playing(stream_url: &str) -> Result<Option<StationNowPlaying>, String> {
+    let mut response = metadata_client()?
+        .get(stream_url)
+        .header("Icy-MetaData", "1")
+        .send()
+        .await
+        .map_err(|error| format!("station stream request failed: {error}"))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "station stream request failed with status {}",
+            response.status().as_u16()
+        ));
+    }
+
+    let Some(metaint) = response
+        .headers()
+        .get("icy-metaint")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<usize>().ok())
+    else {
+        return Ok(None);
+    };
+    if metaint > ICY_METADATA_MAX_BYTES {
+        return Ok(None);
+    }
+
+    let mut buffer = Vec::with_capacity(metaint + 1);
+    loop {
+        if let Some(block) = icy_metadata_block(buffer.as_slice(), metaint) {
+            return Ok(parse_icy_stream_title(block));
+        }
+        if buffer.len() > metaint + 1 + 255 * 16 {
+            return Ok(None);
+        }
+        match response
+            .chunk()
+            .await
+            .map_err(|error| format!("station stream read failed: {error}"))?
+        {
+            Some(chunk) => buffer.extend_from_slice(&chunk),
+            None => return Ok(None),
+        }
+    }
+}
+
+fn icy_metadata_block(buffer: &[u8], metaint: usize) -> Option<&[u8]> {
+    let length = *buffer.get(metaint)? as usize * 16;
+    let start = metaint + 1;
+    buffer.get(start..start + length)
+}
+
+fn parse_icy_stream_title(block: &[u8]) -> Option<StationNowPlaying> {
+    let text = String::from_utf8_lossy(block);
+    let start = text.find("StreamTitle='")? + "StreamTitle='".len();
+    let rest = &text[start..];
+    let end = rest.find("';").or_else(|| rest.rfind('\''))?;
+    let value = rest[..end].trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    match value.split_once(" - ") {
+        Some((artist, title)) if !artist.trim().is_empty() && !title.trim().is_empty() => {
+            Some(StationNowPlaying {
+                title: title.trim().to_string(),
+                artist: Some(artist.trim().to_string()),
+            })
+        }
+        _ => Some(StationNowPlaying {
+            title: value.to_string(),
+            artist: None,
+        }),
+    }
+}
+
+fn now_playing_from_json(
+    json: &Value,
+    title_pointer: &str,
+    artist_pointer: Option<&str>,
+) -> Option<StationNowPlaying> {
+    let title = json.pointer(title_pointer)?.as_str()?.trim();
+    if title.is_empty() {
+        return None;
+    }
+    let artist = artist_pointer
+        .and_then(|pointer| json.pointer(pointer))
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string);
+
+    Some(StationNowPlaying {
+        title: title.to_string(),
+        artist,
+    })
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+}
+
+fn generate_station_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_nanos())
+        .unwrap_or(0);
+    let sequence = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("station-{nanos:x}-{sequence}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        icy_metadata_block, now_playing_from_json, parse_icy_stream_title, Station, StationInput,
+        StationNowPlaying,
+    };
+    use serde_json::json;
+
+    fn input(stream_url: &str) -> StationInput {
+        StationInput {
+            id: None,
+            name: "  Dublab  ".to_string(),
+            stream_url: stream_url.to_string(),
+            artwork_url: Some(" ".to_string()),
+            homepage_url: None,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn from_input_trims_fields_and_generates_id() {
+        let station = Station::from_input(input("https://dublab.out.airtime.pro/dublab_a"))
+            .expect("station should be valid");
+        assert_eq!(station.name, "Dublab");
+        assert!(station.id.starts_with("station-"));
+        assert_eq!(station.artwork_url, None);
+    }
+
+    #[test]
+    fn from_input_rejects_non_http_urls() {
+        assert!(Station::from_input(input("file:///etc/passwd")).is_err());
+        assert!(Station::from_input(input("not a url")).is_err());
+    }
+
+    #[test]
+    fn parse_icy_stream_title_splits_artist_and_title() {
+        let block = b"StreamTitle='Alice Coltrane - Turiya & Ramakrishna';StreamUrl='';\0\0";
+        assert_eq!(
+            parse_icy_stream_title(block),
+            Some(StationNowPlaying {
+                title: "Turiya & Ramakrishna".to_string(),
+                artist: Some("Alice Coltrane".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn icy_metadata_block_reads_length_prefixed_block() {
+        let mut buffer = vec![0_u8; 8];
+        buffer.push(1);
+        buffer.extend_from_slice(b"StreamTitle='x';");
+        assert_eq!(
+            icy_metadata_block(buffer.as_slice(), 8),
+            Some(&b"StreamTitle='x';"[..])
+        );
+        assert_eq!(icy_metadata_block(&buffer[..12], 8), None);
+    }
+
+    #[test]
+    fn now_playing_from_json_uses_pointers() {
+        let json = json!({ "now": { "title": "Song", "artist": "" } });
+        assert_eq!(
+            now_playing_from_json(&json, "/now/title", Some("/now/artist")),
+            Some(StationNowPlaying {
+                title: "Song".to_string(),
+                artist: None,
+            })
+        );
+    }
+}
@@ -0,0 +1,435 @@
+//! Spots likely song boundaries in the tapped audio: the harmonic content
+//! (chroma) before and after a point differs, ideally around a dip in level.
+//! The buffers the audio thread touches are allocated up front, and
+//! boundaries are handed to a thread of their own to be announced.
+
+use crate::audio_samples::downmix_mono;
+use crate::fingerprint::fft_in_place;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const TRACK_BOUNDARY_EVENT: &str = "track-boundary";
+/// A gap this long between tapped buffers means playback stopped or moved to
+/// another stream.
+const TRACK_BOUNDARY_RESET_AFTER: Duration = Duration::from_secs(2);
+
+const ANALYSIS_RATE: u32 = 11_025;
+const FRAME_SIZE: usize = 1024;
+const CHROMA_BANDS: usize = 12;
+const MIN_FREQUENCY: f32 = 110.0;
+const MAX_FREQUENCY: f32 = 2_000.0;
+/// Seconds of audio compared on each side of a candidate boundary.
+const WINDOW_SECONDS: f32 = 6.0;
+/// Seconds around a candidate searched for a dip in level.
+const DIP_SECONDS: f32 = 0.5;
+/// Chroma distance between two stretches of the same song, from chord changes.
+const BASE_NOVELTY: f32 = 0.3;
+/// Chroma distance that alone counts as a certain change of song.
+const FULL_NOVELTY: f32 = 0.6;
+const MIN_CONFIDENCE: f32 = 0.5;
+const MIN_TRACK_SECONDS: f32 = 20.0;
+/// Boundaries waiting to be announced; they're rare, so this never fills.
+const QUEUE_DEPTH: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackBoundary {
+    /// Milliseconds of audio analyzed since the detector was last reset.
+    pub position_ms: u64,
+    pub confidence: f32,
+}
+
+struct Frame {
+    chroma: [f32; CHROMA_BANDS],
+    energy: f32,
+}
+
+/// Streaming detector; feed it every tapped buffer. Boundaries are reported
+/// `WINDOW_SECONDS` after they happen, once the audio after them is known.
+pub struct TrackBoundaryDetector {
+    sample_rate: u32,
+    decimation: usize,
+    pending: Vec<f32>,
+    accumulator: f32,
+    accumulated: usize,
+    frames: VecDeque<Frame>,
+    frame_count: u64,
+    window: Vec<f32>,
+    real: Vec<f32>,
+    imag: Vec<f32>,
+    energies: Vec<f32>,
+    peak: Option<(u64, f32)>,
+    last_boundary: Option<u64>,
+}
+
+impl Default for TrackBoundaryDetector {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0,
+            decimation: 1,
+            pending: Vec::with_capacity(FRAME_SIZE),
+            accumulator: 0.0,
+            accumulated: 0,
+            frames: VecDeque::new(),
+            frame_count: 0,
+            window: (0..FRAME_SIZE)
+                .map(|index| 0.5 - 0.5 * (2.0 * PI * index as f32 / FRAME_SIZE as f32).cos())
+                .collect(),
+            real: vec![0.0; FRAME_SIZE],
+            imag: vec![0.0; FRAME_SIZE],
+            energies: Vec::new(),
+            peak: None,
+            last_boundary: None,
+        }
+    }
+}
+
+impl TrackBoundaryDetector {
+    /// Clears everything but the buffers, without reallocating them.
+    pub fn reset(&mut self) {
+        self.sample_rate = 0;
+        self.decimation = 1;
+        self.pending.clear();
+        self.accumulator = 0.0;
+        self.accumulated = 0;
+        self.frames.clear();
+        self.frame_count = 0;
+        self.peak = None;
+        self.last_boundary = None;
+    }
+
+    pub fn push(
+        &mut self,
+        samples: &[f32],
+        channels: u16,
+        sample_rate: u32,
+    ) -> Option<TrackBoundary> {
+        if sample_rate == 0 || channels == 0 {
+            return None;
+        }
+        if sample_rate != self.sample_rate {
+            self.reset();
+            self.sample_rate = sample_rate;
+            self.decimation = (sample_rate as f32 / ANALYSIS_RATE as f32).round().max(1.0) as usize;
+        }
+
+        let mut found = None;
+        for sample in downmix_mono(samples, channels) {
+            // Averaging blocks of samples is a cheap low-pass before decimating.
+            self.accumulator += sample;
+            self.accumulated += 1;
+            if self.accumulated < self.decimation {
+                continue;
+            }
+            self.pending
+                .push(self.accumulator / self.accumulated as f32);
+            self.accumulator = 0.0;
+            self.accumulated = 0;
+
+            if self.pending.len() == FRAME_SIZE {
+                let frame = self.analyze_frame();
+                self.pending.clear();
+                if let Some(boundary) = self.push_frame(frame) {
+                    found = Some(boundary);
+                }
+            }
+        }
+        found
+    }
+
+    fn frame_rate(&self) -> f32 {
+        self.sample_rate as f32 / self.decimation as f32 / FRAME_SIZE as f32
+    }
+
+    fn analyze_frame(&mut self) -> Frame {
+        for ((target, sample), weight) in self
+            .real
+            .iter_mut()
+            .zip(self.pending.iter())
+            .zip(self.window.iter())
+        {
+            *target = sample * weight;
+        }
+        self.imag.fill(0.0);
+        let real = self.real.as_mut_slice();
+        let imag = self.imag.as_mut_slice();
+        let energy = real.iter().map(|value| value * value).sum::<f32>() / FRAME_SIZE as f32;
+        fft_in_place(real, imag);
+
+        let rate = self.sample_rate as f32 / self.decimation as f32;
+        let mut chroma = [0.0_f32; CHROMA_BANDS];
+        for bin in 1..FRAME_SIZE / 2 {
+            let frequency = bin as f32 * rate / FRAME_SIZE as f32;
+            if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+                continue;
+            }
+            let pitch = (12.0 * (frequency / 261.63).log2()).round() as i32;
+            chroma[pitch.rem_euclid(CHROMA_BANDS as i32) as usize] +=
+                real[bin] * real[bin] + imag[bin] * imag[bin];
+        }
+        Frame { chroma, energy }
+    }
+
+    fn push_frame(&mut self, frame: Frame) -> Option<TrackBoundary> {
+        let half = (WINDOW_SECONDS * self.frame_rate()).round() as usize;
+        self.frames.push_back(frame);
+        self.frame_count += 1;
+        if self.frames.len() > half * 2 {
+            self.frames.pop_front();
+        }
+        if self.frames.len() < half * 2 {
+            return None;
+        }
+
+        // The candidate sits between the two halves of the window.
+        let candidate = self.frame_count - half as u64;
+        let confidence = self.confidence_at(half);
+        if confidence >= MIN_CONFIDENCE && self.peak.is_none_or(|(_, best)| confidence > best) {
+            self.peak = Some((candidate, confidence));
+        }
+
+        // Report the strongest candidate once the score has had a moment to
+        // fall away from it.
+        let (position, confidence) = self.peak?;
+        let settle = (DIP_SECONDS * 2.0 * self.frame_rate()).ceil() as u64;
+        if candidate < position + settle {
+            return None;
+        }
+        self.peak = None;
+
+        let min_gap = (MIN_TRACK_SECONDS * self.frame_rate()) as u64;
+        if self
+            .last_boundary
+            .is_some_and(|last| position < last + min_gap)
+        {
+            return None;
+        }
+        self.last_boundary = Some(position);
+        Some(TrackBoundary {
+            position_ms: (position as f32 / self.frame_rate() * 1_000.0) as u64,
+            confidence,
+        })
+    }
+
+    fn confidence_at(&mut self, half: usize) -> f32 {
+        let mut before = [0.0_f32; CHROMA_BANDS];
+        let mut after = [0.0_f32; CHROMA_BANDS];
+        for (index, frame) in self.frames.iter().enumerate() {
+            let target = if index < half {
+                &mut before
+            } else {
+                &mut after
+            };
+            for (sum, value) in target.iter_mut().zip(frame.chroma.iter()) {
+                *sum += value;
+            }
+        }
+        let novelty = 1.0 - cosine_similarity(&before, &after);
+
+        let radius = (DIP_SECONDS * self.frame_rate()).ceil() as usize;
+        let energies = &mut self.energies;
+        energies.clear();
+        energies.extend(self.frames.iter().map(|frame| frame.energy));
+        let trough = energies[half.saturating_sub(radius)..(half + radius).min(energies.len())]
+            .iter()
+            .copied()
+            .fold(f32::MAX, f32::min);
+        let middle = energies.len() / 2;
+        let (_, &mut median, _) = energies.select_nth_unstable_by(middle, f32::total_cmp);
+        let dip = if median > 0.0 {
+            (1.0 - trough / median).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        ((novelty - BASE_NOVELTY) / (FULL_NOVELTY - BASE_NOVELTY)).clamp(0.0, 1.0)
+            * (0.6 + 0.4 * dip)
+    }
+}
+
+/// Runs a detector over the tapped audio, starting it over whenever the
+/// audio stops for a while.
+pub struct TrackBoundaryFeed {
+    detector: Mutex<(TrackBoundaryDetector, Option<Instant>)>,
+    boundaries: SyncSender<TrackBoundary>,
+}
+
+impl TrackBoundaryFeed {
+    pub fn new(on_boundary: Box<dyn Fn(&TrackBoundary) + Send + 'static>) -> Self {
+        let (boundaries_tx, boundaries_rx) = mpsc::sync_channel::<TrackBoundary>(QUEUE_DEPTH);
+        thread::spawn(move || {
+            for boundary in boundaries_rx {
+                on_boundary(&boundary);
+            }
+        });
+        Self {
+            detector: Mutex::new((TrackBoundaryDetector::default(), None)),
+            boundaries: boundaries_tx,
+        }
+    }
+
+    pub fn push(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        let Ok(mut detector) = self.detector.lock() else {
+            return;
+        };
+        let (detector, last_tap_at) = &mut *detector;
+        let now = Instant::now();
+        // A pause or a new stream starts the analysis over.
+        if last_tap_at
+            .is_some_and(|at| now.saturating_duration_since(at) > TRACK_BOUNDARY_RESET_AFTER)
+        {
+            detector.reset();
+        }
+        *last_tap_at = Some(now);
+        if let Some(boundary) = detector.push(samples, channels, sample_rate) {
+            let _ = self.boundaries.try_send(boundary);
+        }
+    }
+}
+
+fn cosine_similarity(lhs: &[f32; CHROMA_BANDS], rhs: &[f32; CHROMA_BANDS]) -> f32 {
+    let dot = lhs.iter().zip(rhs.iter()).map(|(a, b)| a * b).sum::<f32>();
+    let norm = lhs.iter().map(|a| a * a).sum::<f32>().sqrt()
+        * rhs.iter().map(|b| b * b).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        dot / norm
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrackBoundary, TrackBoundaryDetector, TrackBoundaryFeed};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const SAMPLE_RATE: u32 = 44_100;
+    const MAJOR_SCALE: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+    const PROGRESSION: [usize; 4] = [0, 5, 3, 4];
+
+    /// A mono "song": a I–vi–IV–V progression in one major key, two beats
+    /// per chord.
+    fn song_in_key(root_semitone: i32, bpm: f32, seconds: f32) -> Vec<f32> {
+        let beat = (60.0 / bpm * SAMPLE_RATE as f32) as usize;
+        let total = (seconds * SAMPLE_RATE as f32) as usize;
+        let mut samples = Vec::with_capacity(total);
+        let mut beats = 0;
+        while samples.len() < total {
+            let degree = PROGRESSION[beats / 2 % PROGRESSION.len()];
+            let chord = [0, 2, 4].map(|step| {
+                let index = degree + step;
+                let semitone = root_semitone + MAJOR_SCALE[index % 7] + 12 * (index / 7) as i32;
+                261.63 * 2.0_f32.powf(semitone as f32 / 12.0)
+            });
+            for index in 0..beat {
+                let time = index as f32 / SAMPLE_RATE as f32;
+                let envelope = (-2.0 * time).exp();
+                let value = chord
+                    .iter()
+                    .map(|frequency| (2.0 * std::f32::consts::PI * frequency * time).sin())
+                    .sum::<f32>();
+                samples.push(value * envelope * 0.2);
+            }
+            beats += 1;
+        }
+        samples.truncate(total);
+        samples
+    }
+
+    fn fade_out(samples: &mut [f32], seconds: f32) {
+        let length = ((seconds * SAMPLE_RATE as f32) as usize).min(samples.len());
+        let start = samples.len() - length;
+        for (index, sample) in samples[start..].iter_mut().enumerate() {
+            *sample *= 1.0 - index as f32 / length as f32;
+        }
+    }
+
+    fn detect(samples: &[f32]) -> Vec<TrackBoundary> {
+        let mut detector = TrackBoundaryDetector::default();
+        samples
+            .chunks(1_152)
+            .filter_map(|chunk| detector.push(chunk, 1, SAMPLE_RATE))
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_gap_between_two_songs() {
+        let mut first = song_in_key(0, 120.0, 25.0);
+        fade_out(first.as_mut_slice(), 1.5);
+        let mut mix = first;
+        mix.extend(vec![0.0; SAMPLE_RATE as usize / 2]);
+        mix.extend(song_in_key(6, 96.0, 25.0));
+
+        let boundaries = detect(mix.as_slice());
+        assert_eq!(boundaries.len(), 1, "{boundaries:?}");
+        assert!(
+            (24_000..=26_500).contains(&boundaries[0].position_ms),
+            "{boundaries:?}"
+        );
+        assert!(boundaries[0].confidence > 0.8, "{boundaries:?}");
+    }
+
+    #[test]
+    fn finds_a_hard_cut_into_a_new_key() {
+        let mut mix = song_in_key(2, 128.0, 25.0);
+        mix.extend(song_in_key(8, 128.0, 25.0));
+
+        let boundaries = detect(mix.as_slice());
+        assert_eq!(boundaries.len(), 1, "{boundaries:?}");
+        assert!(
+            (23_500..=26_500).contains(&boundaries[0].position_ms),
+            "{boundaries:?}"
+        );
+    }
+
+    #[test]
+    fn ignores_breaks_within_a_song() {
+        let mut song = song_in_key(4, 110.0, 20.0);
+        fade_out(song.as_mut_slice(), 0.5);
+        song.extend(vec![0.0; SAMPLE_RATE as usize]);
+        song.extend(song_in_key(4, 110.0, 25.0));
+
+        assert_eq!(detect(song.as_slice()), Vec::new());
+    }
+
+    #[test]
+    fn feed_announces_boundaries_off_the_audio_thread() {
+        let (tx, rx) = mpsc::channel();
+        let feed = TrackBoundaryFeed::new(Box::new(move |boundary| {
+            let _ = tx.send(*boundary);
+        }));
+        let mut mix = song_in_key(2, 128.0, 25.0);
+        mix.extend(song_in_key(8, 128.0, 25.0));
+        for chunk in mix.chunks(1_152) {
+            feed.push(chunk, 1, SAMPLE_RATE);
+        }
+
+        let boundary = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no boundary announced");
+        assert!(
+            (23_500..=26_500).contains(&boundary.position_ms),
+            "{boundary:?}"
+        );
+    }
+
+    #[test]
+    fn reset_keeps_the_analysis_buffers() {
+        let mut detector = TrackBoundaryDetector::default();
+        let song = song_in_key(0, 120.0, 15.0);
+        for chunk in song.chunks(1_152) {
+            detector.push(chunk, 1, SAMPLE_RATE);
+        }
+        let frames_capacity = detector.frames.capacity();
+        let energies = detector.energies.as_ptr();
+        detector.reset();
+        assert!(detector.frames.is_empty() && detector.frames.capacity() == frames_capacity);
+        assert_eq!(detector.energies.as_ptr(), energies);
+    }
+}
@@ -0,0 +1,304 @@
+//! Per-broadcast tracklists built from the tracks recognized while a show
+//! was playing.
+
+use crate::audio_engine::NowPlayingMetadata;
+use crate::datetime::format_rfc3339;
+use crate::shazam::{is_duplicate, RecognizedTrack};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const TRACKLIST_LIMIT: usize = 100;
+const NTS_WEB_BASE: &str = "https://www.nts.live";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tracklist {
+    pub key: String,
+    pub show_alias: String,
+    pub episode_alias: Option<String>,
+    pub source_title: String,
+    pub source_artist: Option<String>,
+    pub started_at: u64,
+    pub updated_at: u64,
+    /// In the order they were heard.
+    pub tracks: Vec<RecognizedTrack>,
+}
+
+/// Identifies the broadcast a recognition belongs to, when the source knows
+/// which show (and episode) is on air.
+pub fn broadcast_key(source: &NowPlayingMetadata) -> Option<String> {
+    let show_alias = source
+        .show_alias
+        .as_deref()
+        .map(str::trim)
+        .filter(|alias| !alias.is_empty())?;
+    match source
+        .episode_alias
+        .as_deref()
+        .map(str::trim)
+        .filter(|alias| !alias.is_empty())
+    {
+        Some(episode_alias) => Some(format!("{show_alias}/{episode_alias}")),
+        None => Some(show_alias.to_string()),
+    }
+}
+
+pub struct TracklistStore {
+    path: PathBuf,
+    tracklists: Vec<Tracklist>,
+}
+
+impl TracklistStore {
+    pub fn open(path: PathBuf) -> Self {
+        let tracklists = load_tracklists(path.as_path()).unwrap_or_else(|error| {
+            eprintln!("[tracklist] unable to load tracklists, starting empty: {error}");
+            Vec::new()
+        });
+        Self { path, tracklists }
+    }
+
+    /// Most recently updated first.
+    pub fn list(&self) -> Vec<Tracklist> {
+        self.tracklists.clone()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Tracklist> {
+        self.tracklists
+            .iter()
+            .find(|tracklist| tracklist.key == key)
+    }
+
+    /// Appends `track` to the tracklist of the broadcast `source` is playing,
+    /// returning the updated tracklist unless it was already listed.
+    pub fn record(
+        &mut self,
+        source: &NowPlayingMetadata,
+        track: &RecognizedTrack,
+    ) -> Result<Option<Tracklist>, String> {
+        let Some(key) = broadcast_key(source) else {
+            return Ok(None);
+        };
+
+        let mut tracklists = self.tracklists.clone();
+        let index = match tracklists.iter().position(|item| item.key == key) {
+            Some(index) => index,
+            None => {
+                let show_alias = key.split('/').next().unwrap_or_default().to_string();
+                tracklists.insert(
+                    0,
+                    Tracklist {
+                        episode_alias: key.split_once('/').map(|(_, episode)| episode.to_string()),
+                        show_alias,
+                        key,
+                        source_title: source.title.clone(),
+                        source_artist: source.artist.clone(),
+                        started_at: track.recognized_at,
+                        updated_at: track.recognized_at,
+                        tracks: Vec::new(),
+                    },
+                );
+                0
+            }
+        };
+
+        if is_duplicate(tracklists[index].tracks.iter().rev(), track) {
+            return Ok(None);
+        }
+
+        let mut tracklist = tracklists.remove(index);
+        tracklist.tracks.push(track.clone());
+        tracklist.updated_at = track.recognized_at;
+        tracklists.insert(0, tracklist.clone());
+        tracklists.truncate(TRACKLIST_LIMIT);
+
+        persist_tracklists(self.path.as_path(), tracklists.as_slice())?;
+        self.tracklists = tracklists;
+        Ok(Some(tracklist))
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<bool, String> {
+        let before = self.tracklists.len();
+        let remaining = self
+            .tracklists
+            .iter()
+            .filter(|tracklist| tracklist.key != key)
+            .cloned()
+            .collect::<Vec<_>>();
+        if remaining.len() == before {
+            return Ok(false);
+        }
+        persist_tracklists(self.path.as_path(), remaining.as_slice())?;
+        self.tracklists = remaining;
+        Ok(true)
+    }
+}
+
+/// A plain-text tracklist with each track's time into the broadcast, in the
+/// shape people paste into show comments.
+pub fn export_text(tracklist: &Tracklist) -> String {
+    let mut lines = Vec::new();
+    match tracklist.source_artist.as_deref() {
+        Some(artist) => lines.push(format!("{artist} — {}", tracklist.source_title)),
+        None => lines.push(tracklist.source_title.clone()),
+    }
+    match tracklist.episode_alias.as_deref() {
+        Some(episode) => lines.push(format!(
+            "{NTS_WEB_BASE}/shows/{}/episodes/{episode}",
+            tracklist.show_alias
+        )),
+        None => lines.push(format!("{NTS_WEB_BASE}/shows/{}", tracklist.show_alias)),
+    }
+    lines.push(format_rfc3339(tracklist.started_at));
+    lines.push(String::new());
+
+    for track in tracklist.tracks.iter() {
+        let offset = track.recognized_at.saturating_sub(tracklist.started_at);
+        let time = format!(
+            "{}:{:02}:{:02}",
+            offset / 3_600,
+            offset % 3_600 / 60,
+            offset % 60
+        );
+        match track.artist.as_deref() {
+            Some(artist) => lines.push(format!("[{time}] {artist} — {}", track.title)),
+            None => lines.push(format!("[{time}] {}", track.title)),
+        }
+    }
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn load_tracklists(path: &Path) -> Result<Vec<Tracklist>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(format!(
+                "unable to read tracklists from {}: {error}",
+                path.display()
+            ))
+        }
+    };
+
+    serde_json::from_str::<Vec<Tracklist>>(contents.as_str()).map_err(|error| {
+        format!(
+            "unable to parse tracklists from {}: {error}",
+            path.display()
+        )
+    })
+}
+
+fn persist_tracklists(path: &Path, tracklists: &[Tracklist]) -> Result<(), String> {
+    let bytes = serde_json::to_vec_pretty(tracklists)
+        .map_err(|error| format!("unable to serialize tracklists: {error}"))?;
+    fs::write(path, bytes)
+        .map_err(|error| format!("unable to write tracklists to {}: {error}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{broadcast_key, export_text, TracklistStore};
+    use crate::audio_engine::NowPlayingMetadata;
+    use crate::shazam::RecognizedTrack;
+    use crate::test_support::test_dir;
+    use std::fs;
+
+    fn source(show: Option<&str>, episode: Option<&str>) -> NowPlayingMetadata {
+        NowPlayingMetadata {
+            title: "Channel 1".to_string(),
+            artist: Some("Early Bird Show".to_string()),
+            album: None,
+            artwork_url: None,
+            show_alias: show.map(str::to_string),
+            episode_alias: episode.map(str::to_string),
+        }
+    }
+
+    fn track(id: &str, title: &str, recognized_at: u64) -> RecognizedTrack {
+        RecognizedTrack {
+            shazam_id: Some(id.to_string()),
+            title: title.to_string(),
+            artist: Some("Artist".to_string()),
+            artwork_url: None,
+            apple_music_url: None,
+            web_url: None,
+            recognized_at,
+            source_title: None,
+            source_artist: None,
+            musicbrainz_recording_id: None,
+            acoustid: None,
+        }
+    }
+
+    #[test]
+    fn broadcast_key_needs_a_show_alias() {
+        assert_eq!(broadcast_key(&source(None, Some("ep"))), None);
+        assert_eq!(
+            broadcast_key(&source(Some("early-bird"), None)).as_deref(),
+            Some("early-bird")
+        );
+        assert_eq!(
+            broadcast_key(&source(
+                Some("early-bird"),
+                Some("early-bird-18th-october-2026")
+            ))
+            .as_deref(),
+            Some("early-bird/early-bird-18th-october-2026")
+        );
+    }
+
+    #[test]
+    fn records_tracks_per_broadcast_without_repeats() {
+        let dir = test_dir("record");
+        let path = dir.join("shazam-tracklists.json");
+        let mut store = TracklistStore::open(path.clone());
+        let morning = source(Some("early-bird"), Some("ep-1"));
+        let evening = source(Some("night-moves"), Some("ep-9"));
+
+        assert!(store
+            .record(&source(None, None), &track("1", "Unkeyed", 10))
+            .expect("record")
+            .is_none());
+        assert!(store
+            .record(&morning, &track("1", "First", 1_000))
+            .expect("record")
+            .is_some());
+        assert!(store
+            .record(&morning, &track("1", "First", 1_090))
+            .expect("record")
+            .is_none());
+        store
+            .record(&evening, &track("3", "Late", 2_000))
+            .expect("record");
+        let updated = store
+            .record(&morning, &track("2", "Second", 1_754))
+            .expect("record")
+            .expect("new track");
+        assert_eq!(updated.tracks.len(), 2);
+
+        let reopened = TracklistStore::open(path);
+        let keys = reopened
+            .list()
+            .into_iter()
+            .map(|tracklist| tracklist.key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["early-bird/ep-1", "night-moves/ep-9"]);
+
+        let tracklist = reopened.get("early-bird/ep-1").expect("tracklist");
+        assert_eq!(tracklist.started_at, 1_000);
+        assert_eq!(
+            export_text(tracklist),
+            "Early Bird Show — Channel 1\n\
+             https://www.nts.live/shows/early-bird/episodes/ep-1\n\
+             1970-01-01T00:16:40Z\n\
+             \n\
+             [0:00:00] Artist — First\n\
+             [0:12:34] Artist — Second\n"
+        );
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
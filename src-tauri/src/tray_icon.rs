@@ -14,63 +14,27 @@ static PRESET_ICONS: OnceLock<[IconRgba; 6]> = OnceLock::new();
 const DIGITS: [[u8; GLYPH_H]; 6] = [
     // 1
     [
-        0b00100,
-        0b01100,
-        0b00100,
-        0b00100,
-        0b00100,
-        0b00100,
-        0b01110,
+        0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
     ],
     // 2
     [
-        0b01110,
-        0b10001,
-        0b00001,
-        0b00110,
-        0b01000,
-        0b10000,
-        0b11111,
+        0b01110, 0b10001, 0b00001, 0b00110, 0b01000, 0b10000, 0b11111,
     ],
     // 3
     [
-        0b01110,
-        0b10001,
-        0b00001,
-        0b00110,
-        0b00001,
-        0b10001,
-        0b01110,
+        0b01110, 0b10001, 0b00001, 0b00110, 0b00001, 0b10001, 0b01110,
     ],
     // 4
     [
-        0b00010,
-        0b00110,
-        0b01010,
-        0b10010,
-        0b11111,
-        0b00010,
-        0b00010,
+        0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
     ],
     // 5
     [
-        0b11111,
-        0b10000,
-        0b11110,
-        0b00001,
-        0b00001,
-        0b10001,
-        0b01110,
+        0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
     ],
     // 6
     [
-        0b01110,
-        0b10001,
-        0b10000,
-        0b11110,
-        0b10001,
-        0b10001,
-        0b01110,
+        0b01110, 0b10001, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
     ],
 ];
 
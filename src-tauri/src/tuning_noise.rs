@@ -0,0 +1,339 @@
+//! Procedural radio static: colored noise with AM crackle, and a dial sweep
+//! that whistles as it passes carriers. Seeded, so the same settings always
+//! make the same sound.
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+
+/// Impulses per second in the crackle.
+const CRACKLE_RATE: f32 = 9.0;
+const CRACKLE_DECAY_SECONDS: f32 = 0.004;
+/// Fade in and out of the buffering hiss.
+const HISS_RAMP_SECONDS: f32 = 0.03;
+const SWEEP_CARRIERS: usize = 3;
+/// How far either side of a carrier its whistle is heard, in sweep fractions.
+const CARRIER_WIDTH: f32 = 0.09;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TuningNoiseConfig {
+    pub color: NoiseColor,
+    /// Loudness, 0–1.
+    pub level: f32,
+    /// Length of the dial sweep played by the static crossfade and on a hard
+    /// cut; zero leaves it out.
+    pub sweep_ms: u32,
+    /// Hiss while a stream connects or rebuffers instead of going silent.
+    pub while_buffering: bool,
+}
+
+impl Default for TuningNoiseConfig {
+    fn default() -> Self {
+        Self {
+            color: NoiseColor::Pink,
+            level: 0.2,
+            sweep_ms: 700,
+            while_buffering: true,
+        }
+    }
+}
+
+impl TuningNoiseConfig {
+    pub const MAX_SWEEP_MS: u32 = 5_000;
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.level) {
+            return Err(format!(
+                "noise level must be between 0 and 1: {}",
+                self.level
+            ));
+        }
+        if self.sweep_ms > Self::MAX_SWEEP_MS {
+            return Err(format!("sweep can be at most {}ms", Self::MAX_SWEEP_MS));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NoiseColor {
+    /// Flat, like FM between stations.
+    White,
+    Pink,
+    /// Dark rumble, like AM.
+    Brown,
+}
+
+pub struct TuningNoise {
+    rng: u64,
+    color: NoiseColor,
+    color_state: [f32; 3],
+    level: f32,
+    sample_rate: f32,
+    crackle: f32,
+    crackle_decay: f32,
+    elapsed: usize,
+    shape: Shape,
+}
+
+enum Shape {
+    /// Swells and fades over `total` samples, whistling past each carrier.
+    Sweep {
+        total: usize,
+        carriers: [Carrier; SWEEP_CARRIERS],
+    },
+    /// Runs until released, then ramps out over `ramp` samples.
+    Hiss {
+        ramp: usize,
+        released_at: Option<usize>,
+    },
+}
+
+#[derive(Clone, Copy, Default)]
+struct Carrier {
+    /// Where on the sweep the carrier sits, 0–1.
+    position: f32,
+    /// Beat frequency per unit of detuning.
+    spread_hz: f32,
+    phase: f32,
+}
+
+impl TuningNoise {
+    pub fn sweep(config: &TuningNoiseConfig, seed: u64, sample_rate: u32) -> Self {
+        let mut noise = Self::new(
+            config,
+            seed,
+            sample_rate,
+            Shape::Hiss {
+                ramp: 0,
+                released_at: None,
+            },
+        );
+        let mut carriers = [Carrier::default(); SWEEP_CARRIERS];
+        for (index, carrier) in carriers.iter_mut().enumerate() {
+            // One carrier in each third, so the whistles don't pile up.
+            carrier.position =
+                (index as f32 + 0.2 + noise.next_unit() * 0.6) / SWEEP_CARRIERS as f32;
+            carrier.spread_hz = 6_000.0 + noise.next_unit() * 10_000.0;
+        }
+        let total = (config.sweep_ms.min(TuningNoiseConfig::MAX_SWEEP_MS) as f32 / 1_000.0
+            * noise.sample_rate) as usize;
+        noise.shape = Shape::Sweep {
+            total: total.max(1),
+            carriers,
+        };
+        noise
+    }
+
+    pub fn hiss(config: &TuningNoiseConfig, seed: u64, sample_rate: u32) -> Self {
+        let ramp = (HISS_RAMP_SECONDS * sample_rate.max(1) as f32) as usize;
+        Self::new(
+            config,
+            seed,
+            sample_rate,
+            Shape::Hiss {
+                ramp: ramp.max(1),
+                released_at: None,
+            },
+        )
+    }
+
+    fn new(config: &TuningNoiseConfig, seed: u64, sample_rate: u32, shape: Shape) -> Self {
+        let sample_rate = sample_rate.max(1) as f32;
+        Self {
+            // Xorshift gets stuck on zero.
+            rng: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            color: config.color,
+            color_state: [0.0; 3],
+            level: config.level.clamp(0.0, 1.0),
+            sample_rate,
+            crackle: 0.0,
+            crackle_decay: (-1.0 / (CRACKLE_DECAY_SECONDS * sample_rate)).exp(),
+            elapsed: 0,
+            shape,
+        }
+    }
+
+    /// Starts the hiss ramping out; sweeps end on their own.
+    pub fn release(&mut self) {
+        if let Shape::Hiss { released_at, .. } = &mut self.shape {
+            released_at.get_or_insert(self.elapsed);
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        match self.shape {
+            Shape::Sweep { total, .. } => self.elapsed >= total,
+            Shape::Hiss {
+                ramp,
+                released_at: Some(released_at),
+            } => self.elapsed >= released_at + ramp,
+            Shape::Hiss { .. } => false,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        if self.is_done() {
+            return 0.0;
+        }
+        let white = self.next_unit() * 2.0 - 1.0;
+        let mut sample = self.colored(white);
+
+        if self.next_unit() < CRACKLE_RATE / self.sample_rate {
+            self.crackle = 0.25 + self.next_unit() * 0.35;
+        }
+        sample += white * self.crackle;
+        self.crackle *= self.crackle_decay;
+
+        let elapsed = self.elapsed;
+        self.elapsed += 1;
+        let sample_rate = self.sample_rate;
+        let envelope = match &mut self.shape {
+            Shape::Sweep { total, carriers } => {
+                let dial = elapsed as f32 / *total as f32;
+                for carrier in carriers.iter_mut() {
+                    let detune = dial - carrier.position;
+                    let frequency = 40.0 + carrier.spread_hz * detune.abs();
+                    carrier.phase = (carrier.phase + frequency / sample_rate).fract();
+                    let loudness = (-(detune / CARRIER_WIDTH).powi(2)).exp();
+                    sample += (carrier.phase * TAU).sin() * loudness * 0.3;
+                }
+                (dial * PI).sin()
+            }
+            Shape::Hiss { ramp, released_at } => {
+                let ramp_in = (elapsed as f32 / *ramp as f32).min(1.0);
+                let ramp_out = released_at.map_or(1.0, |released_at| {
+                    1.0 - ((elapsed - released_at) as f32 / *ramp as f32).min(1.0)
+                });
+                ramp_in * ramp_out
+            }
+        };
+        (sample * envelope * self.level).clamp(-1.0, 1.0)
+    }
+
+    /// Uniform in 0–1, from xorshift64*.
+    fn next_unit(&mut self) -> f32 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let value = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (value >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Each color comes out at roughly the same loudness.
+    fn colored(&mut self, white: f32) -> f32 {
+        let state = &mut self.color_state;
+        match self.color {
+            NoiseColor::White => white * 0.35,
+            // Paul Kellet's economy pink filter.
+            NoiseColor::Pink => {
+                state[0] = 0.997_65 * state[0] + white * 0.099_046;
+                state[1] = 0.963 * state[1] + white * 0.296_516_4;
+                state[2] = 0.57 * state[2] + white * 1.052_691_3;
+                (state[0] + state[1] + state[2] + white * 0.1848) * 0.12
+            }
+            NoiseColor::Brown => {
+                state[0] = (state[0] + white * 0.02) / 1.02;
+                state[0] * 3.5
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWEEP_SNAPSHOT: &str = include_str!("../fixtures/tuning_noise/sweep.txt");
+    const HISS_SNAPSHOT: &str = include_str!("../fixtures/tuning_noise/hiss.txt");
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// RMS and peak of each 10ms block, rounded so the snapshot doesn't hang
+    /// on the last bit of a float.
+    fn snapshot(noise: &mut TuningNoise, blocks: usize) -> String {
+        let block_len = SAMPLE_RATE as usize / 100;
+        let mut text = String::new();
+        for block in 0..blocks {
+            let samples = (0..block_len)
+                .map(|_| noise.next_sample())
+                .collect::<Vec<_>>();
+            let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>()
+                / block_len as f32)
+                .sqrt();
+            let peak = samples
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            text.push_str(format!("{block:02} {rms:.3} {peak:.3}\n").as_str());
+        }
+        text
+    }
+
+    #[test]
+    fn sweep_matches_snapshot() {
+        let config = TuningNoiseConfig {
+            color: NoiseColor::Pink,
+            level: 0.5,
+            sweep_ms: 300,
+            while_buffering: true,
+        };
+        let mut noise = TuningNoise::sweep(&config, 7, SAMPLE_RATE);
+        assert_eq!(snapshot(&mut noise, 30), SWEEP_SNAPSHOT);
+        assert!(noise.is_done());
+    }
+
+    #[test]
+    fn hiss_matches_snapshot_and_ramps_out_once_released() {
+        let config = TuningNoiseConfig {
+            color: NoiseColor::Brown,
+            level: 0.5,
+            ..TuningNoiseConfig::default()
+        };
+        let mut noise = TuningNoise::hiss(&config, 11, SAMPLE_RATE);
+        let mut text = snapshot(&mut noise, 20);
+        noise.release();
+        text.push_str(snapshot(&mut noise, 4).as_str());
+        assert_eq!(text, HISS_SNAPSHOT);
+        assert!(noise.is_done());
+    }
+
+    #[test]
+    fn the_seed_decides_the_sound() {
+        let config = TuningNoiseConfig::default();
+        let render = |seed| {
+            let mut noise = TuningNoise::sweep(&config, seed, SAMPLE_RATE);
+            (0..4_410).map(|_| noise.next_sample()).collect::<Vec<_>>()
+        };
+        assert_eq!(render(3), render(3));
+        assert_ne!(render(3), render(4));
+    }
+
+    #[test]
+    fn colors_differ_in_brightness() {
+        // Mean absolute difference between neighbours tracks high-frequency energy.
+        let roughness = |color| {
+            let config = TuningNoiseConfig {
+                color,
+                level: 1.0,
+                ..TuningNoiseConfig::default()
+            };
+            let mut noise = TuningNoise::hiss(&config, 5, SAMPLE_RATE);
+            let samples = (0..SAMPLE_RATE)
+                .map(|_| noise.next_sample())
+                .collect::<Vec<_>>();
+            let rms = (samples.iter().map(|sample| sample * sample).sum::<f32>()
+                / samples.len() as f32)
+                .sqrt();
+            samples
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).abs())
+                .sum::<f32>()
+                / samples.len() as f32
+                / rms
+        };
+        let white = roughness(NoiseColor::White);
+        let pink = roughness(NoiseColor::Pink);
+        let brown = roughness(NoiseColor::Brown);
+        assert!(white > pink && pink > brown, "{white} {pink} {brown}");
+    }
+}
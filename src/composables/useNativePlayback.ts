@@ -1,6 +1,6 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import { onBeforeUnmount, onMounted, ref, type Ref } from "vue";
+import { onBeforeUnmount, onMounted, ref, watch, type Ref } from "vue";
 import type { MediaPlayable } from "../nts";
 
 type NativeMediaControlAction = "play" | "pause" | "stop" | "toggle";
@@ -26,11 +26,17 @@ export function useNativePlayback(options: UseNativePlaybackOptions) {
       ? `NTS ${playable.source.value.channelName}`
       : "NTS Mixtape";
 
+    const details = playable.source.kind === "channel"
+      ? playable.source.value.now.embeds.details
+      : undefined;
+
     return {
       title: playable.title,
       artist: playable.subtitle ?? "NTS Radio",
       album,
       artworkUrl: playable.artworkUrl,
+      showAlias: details?.showAlias ?? null,
+      episodeAlias: details?.episodeAlias ?? null,
     };
   }
 
@@ -76,6 +82,21 @@ export function useNativePlayback(options: UseNativePlaybackOptions) {
     }
   }
 
+  // Live channels move on to the next show without a new stream.
+  watch(options.currentPlayable, (playable, previous) => {
+    if (!isPlaying.value || !playable || playable.streamUrl !== previous?.streamUrl) {
+      return;
+    }
+    if (!options.canUseTauriInvoke()) {
+      return;
+    }
+    void invoke("update_native_now_playing", {
+      nowPlaying: nowPlayingFromPlayable(playable),
+    }).catch((error) => {
+      console.warn("[audio] Unable to update now playing", error);
+    });
+  });
+
   async function handleNativeMediaControl(action: NativeMediaControlAction) {
     if (action === "pause" || action === "stop") {
       if (isPlaying.value) {
@@ -10,6 +10,37 @@ export type RecognizedTrack = {
   sourceArtist?: string | null;
 };
 
+export type HistoryEntry = RecognizedTrack & {
+  id: string;
+  showAlias?: string | null;
+  favorite: boolean;
+  tags: string[];
+  note?: string | null;
+};
+
+export type HistoryQuery = {
+  text?: string;
+  from?: number;
+  to?: number;
+  show?: string;
+  tag?: string;
+  favoritesOnly?: boolean;
+};
+
+export type HistoryExportFormat =
+  | "csv"
+  | "jsonLines"
+  | "m3u"
+  | "xspf"
+  | "rekordboxXml"
+  | "seratoCrate";
+
+export type HistoryImportSummary = {
+  added: number;
+  updated: number;
+  skipped: number;
+};
+
 export type ShazamStatusPayload = {
   status: "idle" | "listening" | string;
 };
@@ -21,7 +52,7 @@ export type ShazamResultPayload = {
 };
 
 export type ShazamHistoryPayload = {
-  history: RecognizedTrack[];
+  history: HistoryEntry[];
 };
 
 export type ToastItem = {